@@ -1,4 +1,4 @@
-// Vertex shader
+#include "textured.wgsl"
 
 struct CameraUniform {
     view_proj: mat4x4<f32>,
@@ -6,16 +6,6 @@ struct CameraUniform {
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-}
-
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -25,15 +15,3 @@ fn vs_main(
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
     return out;
 }
-
-// Fragment shader
-
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0)@binding(1)
-var s_diffuse: sampler;
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
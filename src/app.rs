@@ -1,92 +1,491 @@
-use crate::graphics::{create_graphics, Graphics, Rc};
+use crate::{
+    camera::{Camera, CameraController, CameraUniform},
+    config::GraphicsConfig,
+    graphics::{create_graphics, Graphics, GraphicsError, Rc, SurfaceEvent, SurfaceRecovery},
+    render::{DepthBuffer, UniformBuffer},
+};
+use cgmath::{Point3, Vector3};
+use std::time::Duration;
+use web_time::Instant;
+use wgpu::{ShaderStages, SurfaceError};
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
-    event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy},
+    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowId},
 };
 
 #[derive(Debug)]
 enum State {
-    Ready(Graphics),
-    Init(Option<EventLoopProxy<Graphics>>),
+    Ready(Box<Graphics>),
+    Init(EventLoopProxy<Result<Graphics, GraphicsError>>),
+    /// The window exists and graphics are being created for it.
+    Pending {
+        window: Rc<Window>,
+        proxy: EventLoopProxy<Result<Graphics, GraphicsError>>,
+    },
+    Failed,
+}
+
+type SurfaceEventCallback = Box<dyn FnMut(&SurfaceEvent)>;
+
+/// Whether the app keeps running after handling an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Exit,
+}
+
+/// Tracks whether the window can be drawn to and the size it should have.
+///
+/// Drawing is suspended while the window is minimised (reported as a zero
+/// size on most platforms) or occluded. Resizes are only recorded here and
+/// applied once per frame, so a burst of `Resized` events while dragging the
+/// window border causes a single surface reconfiguration.
+#[derive(Debug, Default)]
+pub struct WindowState {
+    minimized: bool,
+    occluded: bool,
+    pending_resize: Option<PhysicalSize<u32>>,
+}
+
+impl WindowState {
+    /// Updates the state from `event`, returns whether drawing can resume.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let was_suspended = self.is_suspended();
+        match event {
+            WindowEvent::Resized(size) => {
+                self.minimized = size.width == 0 || size.height == 0;
+                if !self.minimized {
+                    self.pending_resize = Some(*size);
+                }
+            }
+            WindowEvent::Occluded(occluded) => self.occluded = *occluded,
+            _ => {}
+        }
+        was_suspended && !self.is_suspended()
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.minimized || self.occluded
+    }
+
+    /// The latest size the window was resized to since the last call.
+    pub fn take_resize(&mut self) -> Option<PhysicalSize<u32>> {
+        self.pending_resize.take()
+    }
 }
 
+/// Runs the window's event loop: draws the render graph, flies the camera
+/// around and handles the debugging keys.
+///
+/// Clicking into the window grabs the cursor for mouse look and Escape
+/// releases it, see [`CameraController`] for the movement keys. V cycles
+/// through the present modes, F12 saves a screenshot and Escape dismisses
+/// the errors shown by the error overlay once the cursor is released.
 pub struct App {
     state: State,
+    config: GraphicsConfig,
+    window_state: WindowState,
+    camera: Camera,
+    camera_controller: CameraController,
+    /// The camera's matrix, uploaded before each frame once graphics exist.
+    camera_uniform: Option<UniformBuffer<CameraUniform>>,
+    /// When the last frame was drawn, `None` after drawing was suspended.
+    last_frame: Option<Instant>,
+    frames_drawn: u64,
+    /// Control flow to restore once drawing resumes.
+    suspended_control_flow: Option<ControlFlow>,
+    surface_event_callback: Option<SurfaceEventCallback>,
 }
 
 impl App {
-    pub fn new(event_loop: &EventLoop<Graphics>) -> Self {
+    pub fn new(
+        event_loop: &EventLoop<Result<Graphics, GraphicsError>>,
+        config: GraphicsConfig,
+    ) -> Self {
+        Self::with_state(State::Init(event_loop.create_proxy()), config)
+    }
+
+    /// An app drawing with already created graphics, e.g. headless ones.
+    pub fn with_graphics(graphics: Graphics) -> Self {
+        Self::with_state(State::Ready(Box::new(graphics)), GraphicsConfig::default())
+    }
+
+    fn with_state(state: State, config: GraphicsConfig) -> Self {
+        let camera = Camera {
+            eye: Point3::new(0.0, 0.0, 2.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            reverse_z: config.reverse_z,
+        };
         Self {
-            state: State::Init(Some(event_loop.create_proxy())),
+            state,
+            config,
+            window_state: WindowState::default(),
+            camera_controller: CameraController::new(&camera),
+            camera,
+            camera_uniform: None,
+            last_frame: None,
+            frames_drawn: 0,
+            suspended_control_flow: None,
+            surface_event_callback: None,
+        }
+    }
+
+    /// Sets a callback notified whenever a frame can't be drawn because of a
+    /// surface error, after the error has been dealt with.
+    pub fn on_surface_event(&mut self, callback: impl FnMut(&SurfaceEvent) + 'static) {
+        self.surface_event_callback = Some(Box::new(callback));
+    }
+
+    pub fn graphics(&self) -> Option<&Graphics> {
+        match &self.state {
+            State::Ready(gfx) => Some(gfx.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn window_state(&self) -> &WindowState {
+        &self.window_state
+    }
+
+    /// The camera as of the last frame.
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_controller(&self) -> &CameraController {
+        &self.camera_controller
+    }
+
+    /// Speeds and limits of the camera can be changed at any time.
+    pub fn camera_controller_mut(&mut self) -> &mut CameraController {
+        &mut self.camera_controller
+    }
+
+    /// The camera's matrix for binding in passes, created with the first
+    /// frame.
+    pub fn camera_uniform(&self) -> Option<&UniformBuffer<CameraUniform>> {
+        self.camera_uniform.as_ref()
+    }
+
+    /// Number of frames successfully drawn so far.
+    pub fn frames_drawn(&self) -> u64 {
+        self.frames_drawn
+    }
+
+    /// Handles an event for the app's window.
+    pub fn handle_window_event(&mut self, event: WindowEvent) -> Control {
+        if self.window_state.handle_event(&event) {
+            if let State::Ready(gfx) = &self.state {
+                gfx.request_redraw();
+            }
+        }
+
+        self.camera_controller.process_window_event(&event);
+
+        match event {
+            WindowEvent::RedrawRequested => return self.draw(),
+            WindowEvent::CloseRequested => return Control::Exit,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(key),
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.handle_key_press(key),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if !self.camera_controller.mouse_look() => self.grab_cursor(true),
+            // The controller stopped mouse look, the cursor has to follow
+            WindowEvent::Focused(false) => self.grab_cursor(false),
+            _ => {}
+        }
+        Control::Continue
+    }
+
+    /// Handles raw input, which moves the camera while the cursor is grabbed.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        self.camera_controller.process_device_event(event);
+    }
+
+    /// Handles a key pressed in the window, repeats aside.
+    pub fn handle_key_press(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::KeyV => self.cycle_present_mode(),
+            #[cfg(not(target_arch = "wasm32"))]
+            KeyCode::F12 => self.screenshot(),
+            KeyCode::Escape if self.camera_controller.mouse_look() => self.grab_cursor(false),
+            KeyCode::Escape => self.dismiss_errors(),
+            _ => {}
+        }
+    }
+
+    /// Grabs the cursor for mouse look, or releases it.
+    ///
+    /// Without a window, e.g. for headless graphics, only mouse look changes.
+    fn grab_cursor(&mut self, grab: bool) {
+        let State::Ready(gfx) = &self.state else {
+            return;
+        };
+        let Some(window) = gfx.window() else {
+            self.camera_controller.set_mouse_look(grab);
+            return;
+        };
+        if let Err(err) = self.camera_controller.grab_cursor(window, grab) {
+            log::warn!("Failed to grab the cursor: {err}");
+        }
+    }
+
+    fn draw(&mut self) -> Control {
+        let State::Ready(gfx) = &mut self.state else {
+            return Control::Continue;
+        };
+        if self.window_state.is_suspended() {
+            // The time spent suspended isn't flown through
+            self.last_frame = None;
+            return Control::Continue;
+        }
+        if let Some(size) = self.window_state.take_resize() {
+            gfx.resize(size);
+        }
+
+        let now = Instant::now();
+        let elapsed = self
+            .last_frame
+            .replace(now)
+            .map_or(Duration::ZERO, |last| now - last);
+        let size = gfx.size();
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.camera.reverse_z = gfx.depth_buffer().is_some_and(DepthBuffer::reverse_z);
+        self.camera_controller
+            .update_camera(&mut self.camera, elapsed);
+        let mut matrix = CameraUniform::new();
+        matrix.update_view_proj(&self.camera);
+        let uniform = self.camera_uniform.get_or_insert_with(|| {
+            UniformBuffer::new(gfx.device(), Some("camera"), ShaderStages::VERTEX, matrix)
+                .expect("camera uniform is padded")
+        });
+        uniform.set(matrix);
+        uniform.write(gfx.queue());
+
+        match gfx.draw() {
+            Ok(()) => {
+                self.frames_drawn += 1;
+                Control::Continue
+            }
+            Err(error) => self.handle_surface_error(error),
+        }
+    }
+
+    /// Deals with a frame that couldn't be drawn because of `error`, notifies
+    /// the surface event callback and requests another frame unless the
+    /// surface can't be recovered.
+    pub fn handle_surface_error(&mut self, error: SurfaceError) -> Control {
+        let State::Ready(gfx) = &mut self.state else {
+            return Control::Continue;
+        };
+        let event = gfx.recover(error);
+        log::warn!("Surface error: {} ({:?})", event.error, event.recovery);
+        if let Some(callback) = &mut self.surface_event_callback {
+            callback(&event);
+        }
+        match event.recovery {
+            // Try again with the next frame
+            SurfaceRecovery::Reconfigured | SurfaceRecovery::SkippedFrame => {
+                gfx.request_redraw();
+                Control::Continue
+            }
+            SurfaceRecovery::Exit => Control::Exit,
         }
     }
 
-    fn draw(&mut self) {
+    /// Clears the errors shown by the error overlay.
+    fn dismiss_errors(&mut self) {
+        if let State::Ready(gfx) = &self.state {
+            if !gfx.errors().is_empty() {
+                gfx.errors().clear();
+                gfx.request_redraw();
+            }
+        }
+    }
+
+    /// Switches to the next present mode supported by the surface.
+    fn cycle_present_mode(&mut self) {
         if let State::Ready(gfx) = &mut self.state {
-            gfx.draw();
+            let modes = gfx.supported_present_modes();
+            let next = modes
+                .iter()
+                .position(|mode| *mode == gfx.present_mode())
+                .map_or(0, |i| (i + 1) % modes.len());
+            if let Some(mode) = modes.get(next) {
+                let mode = gfx.set_present_mode(*mode);
+                log::info!("Present mode: {mode:?}");
+                gfx.request_redraw();
+            }
         }
     }
 
-    fn resized(&mut self, size: PhysicalSize<u32>) {
+    /// Saves the next frame as a PNG in the working directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn screenshot(&mut self) {
         if let State::Ready(gfx) = &mut self.state {
-            gfx.resize(size);
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let path = format!("screenshot-{secs}.png");
+            match gfx.capture_frame() {
+                Ok(image) => match image.save(&path) {
+                    Ok(()) => log::info!("Saved screenshot to {path}"),
+                    Err(err) => log::error!("Failed to save screenshot: {err}"),
+                },
+                Err(err) => log::error!("Failed to capture screenshot: {err}"),
+            }
         }
     }
 }
 
-impl ApplicationHandler<Graphics> for App {
+impl ApplicationHandler<Result<Graphics, GraphicsError>> for App {
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
         _window_id: WindowId,
         event: WindowEvent,
     ) {
-        match event {
-            WindowEvent::Resized(size) => self.resized(size),
-            WindowEvent::RedrawRequested => self.draw(),
-            WindowEvent::CloseRequested => event_loop.exit(),
-            _ => {}
+        if self.handle_window_event(event) == Control::Exit {
+            event_loop.exit();
         }
     }
 
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if let State::Init(proxy) = &mut self.state {
-            if let Some(proxy) = proxy.take() {
-                let mut win_attr = Window::default_attributes();
-
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    win_attr = win_attr
-                        .with_title("WebGPU example")
-                        .with_inner_size(PhysicalSize::new(450, 450));
-                }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window_state.is_suspended() {
+            // Nothing can be drawn, sleep until the window changes
+            if self.suspended_control_flow.is_none() {
+                self.suspended_control_flow = Some(event_loop.control_flow());
+                event_loop.set_control_flow(ControlFlow::Wait);
+            }
+            return;
+        }
+        if let Some(control_flow) = self.suspended_control_flow.take() {
+            event_loop.set_control_flow(control_flow);
+        }
+        // Polling apps draw continuously
+        if let (State::Ready(gfx), ControlFlow::Poll) = (&self.state, event_loop.control_flow()) {
+            gfx.request_redraw();
+        }
+    }
 
-                #[cfg(target_arch = "wasm32")]
-                {
-                    use winit::platform::web::WindowAttributesExtWebSys;
-                    win_attr = win_attr.with_append(true);
-                }
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        self.handle_device_event(&event);
+    }
 
-                let window = Rc::new(
-                    event_loop
-                        .create_window(win_attr)
-                        .expect("create window err."),
-                );
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let State::Ready(gfx) = &self.state {
+            match gfx.save_pipeline_cache() {
+                Ok(Some(path)) => log::info!("Saved pipeline cache to {}", path.display()),
+                Ok(None) => {}
+                Err(err) => log::warn!("Failed to save pipeline cache: {err}"),
+            }
+        }
+    }
 
-                #[cfg(target_arch = "wasm32")]
-                wasm_bindgen_futures::spawn_local(create_graphics(window, proxy));
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if let State::Init(proxy) = &self.state {
+            let proxy = proxy.clone();
+            let mut win_attr = Window::default_attributes();
 
-                #[cfg(not(target_arch = "wasm32"))]
-                pollster::block_on(create_graphics(window, proxy));
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                win_attr = win_attr
+                    .with_title("WebGPU example")
+                    .with_inner_size(PhysicalSize::new(450, 450));
             }
+
+            #[cfg(target_arch = "wasm32")]
+            {
+                use winit::platform::web::WindowAttributesExtWebSys;
+                win_attr = win_attr.with_append(true);
+            }
+
+            let window = Rc::new(
+                event_loop
+                    .create_window(win_attr)
+                    .expect("create window err."),
+            );
+
+            self.state = State::Pending {
+                window: Rc::clone(&window),
+                proxy: proxy.clone(),
+            };
+            spawn_create_graphics(window, proxy, self.config.clone());
         }
     }
 
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, graphics: Graphics) {
-        graphics.request_redraw();
-        self.state = State::Ready(graphics);
+    fn user_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        graphics: Result<Graphics, GraphicsError>,
+    ) {
+        let err = match graphics {
+            Ok(graphics) => {
+                graphics.request_redraw();
+                self.state = State::Ready(Box::new(graphics));
+                return;
+            }
+            Err(err) => err,
+        };
+
+        if let State::Pending { window, proxy } = &self.state {
+            if !self.config.force_fallback_adapter && err.can_retry_with_fallback() {
+                log::warn!("{err}, retrying with a fallback adapter");
+                self.config.force_fallback_adapter = true;
+                self.config.adapter = None;
+                spawn_create_graphics(Rc::clone(window), proxy.clone(), self.config.clone());
+                return;
+            }
+        }
+
+        let mut message = format!("Could not initialize graphics: {err}");
+        let mut source = std::error::Error::source(&err);
+        while let Some(cause) = source {
+            message.push_str(&format!("\n  caused by: {cause}"));
+            source = cause.source();
+        }
+        message.push_str("\nRun with RUST_LOG=wgpu_core=info,wgpu_hal=info for more details.");
+        log::error!("{message}");
+
+        self.state = State::Failed;
+        event_loop.exit();
     }
 }
+
+fn spawn_create_graphics(
+    window: Rc<Window>,
+    proxy: EventLoopProxy<Result<Graphics, GraphicsError>>,
+    config: GraphicsConfig,
+) {
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(create_graphics(window, proxy, config));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pollster::block_on(create_graphics(window, proxy, config));
+}
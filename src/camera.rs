@@ -0,0 +1,341 @@
+use std::time::Duration;
+
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3, Zero};
+use winit::{
+    error::ExternalError,
+    event::{DeviceEvent, ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{CursorGrabMode, Window},
+};
+
+/// Maps the -1..1 depth range of cgmath's OpenGL style projections to the
+/// 0..1 one of wgpu.
+///
+/// `Matrix4::new` takes columns, depth becomes `z / 2 + w / 2`.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// Flips wgpu's depth range so the near plane is at 1 and the far plane at 0.
+///
+/// Depth becomes `w - z`.
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+/// A perspective camera looking from `eye` at `target`, in a right-handed
+/// world with `up` as up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub aspect: f32,
+    /// Vertical field of view in degrees.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Project for a reverse-Z depth buffer, see
+    /// [`DepthBuffer`](crate::render::DepthBuffer).
+    pub reverse_z: bool,
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// The projection into wgpu's clip space.
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let projection =
+            cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        if self.reverse_z {
+            REVERSE_Z_MATRIX * OPENGL_TO_WGPU_MATRIX * projection
+        } else {
+            OPENGL_TO_WGPU_MATRIX * projection
+        }
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+}
+
+/// The camera's matrix laid out for a uniform buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        Self {
+            view_proj: Matrix4::identity().into(),
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_proj = camera.build_view_projection_matrix().into();
+    }
+}
+
+/// The largest pitch, short of straight up where yaw is lost.
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 1e-3;
+
+/// Movement keys currently held down.
+#[derive(Clone, Copy, Debug, Default)]
+struct Keys {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    sprint: bool,
+}
+
+/// A first-person fly camera: WASD or the arrows move along the view, Space
+/// or E rise, Q or Ctrl sink, Shift sprints, and the mouse looks around while
+/// the cursor is grabbed.
+///
+/// Movement depends on the time passed to
+/// [`CameraController::update_camera`] rather than on the number of frames.
+/// Velocity approaches the one the keys ask for exponentially, so the same
+/// total time moves the camera the same distance at any frame rate.
+///
+/// The camera's `up` is the world's up, yaw turns around it.
+#[derive(Clone, Debug)]
+pub struct CameraController {
+    /// Units per second at full speed.
+    pub speed: f32,
+    /// Speed factor while sprinting.
+    pub sprint_multiplier: f32,
+    /// Rate per second at which the velocity approaches the one the keys ask
+    /// for, `f32::INFINITY` to reach it at once. Zero or less leaves the
+    /// velocity as it is.
+    pub acceleration: f32,
+    /// Like `acceleration`, for slowing down once no key is held.
+    pub damping: f32,
+    /// Degrees turned per unit of mouse motion.
+    pub sensitivity: f32,
+    /// How far the camera can look up or down. Limited to just under 90
+    /// degrees, where looking straight up would leave no way to tell left
+    /// from right.
+    pub max_pitch: Deg<f32>,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+    velocity: Vector3<f32>,
+    keys: Keys,
+    /// Mouse motion since the last update.
+    look: (f64, f64),
+    mouse_look: bool,
+}
+
+impl CameraController {
+    /// A controller looking in the direction `camera` currently looks.
+    pub fn new(camera: &Camera) -> Self {
+        let up = camera.up.normalize();
+        let direction = (camera.target - camera.eye).normalize();
+        // Yaw is measured from the forward axis perpendicular to up
+        let (forward, right) = basis(up);
+        let pitch = Rad(direction.dot(up).clamp(-1.0, 1.0).asin());
+        let yaw = Rad(direction.dot(right).atan2(direction.dot(forward)));
+        Self {
+            speed: 4.0,
+            sprint_multiplier: 3.0,
+            acceleration: 10.0,
+            damping: 8.0,
+            sensitivity: 0.1,
+            max_pitch: Deg(89.0),
+            yaw,
+            pitch,
+            velocity: Vector3::zero(),
+            keys: Keys::default(),
+            look: (0.0, 0.0),
+            mouse_look: false,
+        }
+    }
+
+    /// Angle around the camera's up, relative to where it looks at yaw 0.
+    pub fn yaw(&self) -> Rad<f32> {
+        self.yaw
+    }
+
+    /// Angle above the horizon.
+    pub fn pitch(&self) -> Rad<f32> {
+        self.pitch
+    }
+
+    /// Units per second the camera currently moves at.
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    pub fn mouse_look(&self) -> bool {
+        self.mouse_look
+    }
+
+    /// Turns mouse motion into looking around or not, without touching the
+    /// cursor. See [`CameraController::grab_cursor`].
+    pub fn set_mouse_look(&mut self, enabled: bool) {
+        self.mouse_look = enabled;
+        self.look = (0.0, 0.0);
+    }
+
+    /// Hides and locks the cursor to `window` for mouse look, or releases it.
+    ///
+    /// Platforms that can't lock the cursor in place confine it to the window
+    /// instead. Mouse look stays off if neither is possible.
+    pub fn grab_cursor(&mut self, window: &Window, grab: bool) -> Result<(), ExternalError> {
+        if grab {
+            window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))?;
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)?;
+        }
+        window.set_cursor_visible(!grab);
+        self.set_mouse_look(grab);
+        Ok(())
+    }
+
+    /// Tracks movement keys, returns whether the event was used.
+    ///
+    /// Losing focus releases all keys and stops mouse look, as the window no
+    /// longer sees them being released.
+    pub fn process_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state,
+                        physical_key: PhysicalKey::Code(key),
+                        ..
+                    },
+                ..
+            } => self.process_key(*key, *state),
+            WindowEvent::Focused(false) => {
+                self.keys = Keys::default();
+                self.set_mouse_look(false);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Tracks a movement key, returns whether `key` is one.
+    pub fn process_key(&mut self, key: KeyCode, state: ElementState) -> bool {
+        let pressed = state == ElementState::Pressed;
+        let held = match key {
+            KeyCode::KeyW | KeyCode::ArrowUp => &mut self.keys.forward,
+            KeyCode::KeyS | KeyCode::ArrowDown => &mut self.keys.backward,
+            KeyCode::KeyA | KeyCode::ArrowLeft => &mut self.keys.left,
+            KeyCode::KeyD | KeyCode::ArrowRight => &mut self.keys.right,
+            KeyCode::Space | KeyCode::KeyE => &mut self.keys.up,
+            KeyCode::KeyQ | KeyCode::ControlLeft | KeyCode::ControlRight => &mut self.keys.down,
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => &mut self.keys.sprint,
+            _ => return false,
+        };
+        *held = pressed;
+        true
+    }
+
+    /// Collects raw mouse motion while mouse look is on, returns whether the
+    /// event was used.
+    ///
+    /// Device events keep coming when the cursor is locked and aren't
+    /// affected by pointer acceleration, unlike `CursorMoved`.
+    pub fn process_device_event(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::MouseMotion { delta: (x, y) } if self.mouse_look => {
+                self.look.0 += x;
+                self.look.1 += y;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Turns by the mouse motion collected so far and moves `camera` by
+    /// `elapsed` worth of movement.
+    pub fn update_camera(&mut self, camera: &mut Camera, elapsed: Duration) {
+        let dt = elapsed.as_secs_f32();
+        let (x, y) = std::mem::take(&mut self.look);
+        self.yaw += Deg(x as f32 * self.sensitivity).into();
+        let max_pitch = Rad::from(self.max_pitch).0.clamp(0.0, PITCH_LIMIT);
+        let pitch = self.pitch.0 - Rad::from(Deg(y as f32 * self.sensitivity)).0;
+        self.pitch = Rad(pitch.clamp(-max_pitch, max_pitch));
+
+        let up = camera.up.normalize();
+        let (forward, right) = basis(up);
+        let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
+        let horizontal = forward * cos_yaw + right * sin_yaw;
+        let direction = horizontal * cos_pitch + up * sin_pitch;
+        let side = direction.cross(up).normalize();
+        // Nothing moves, and an infinite rate would give NaN below
+        if dt == 0.0 {
+            camera.target = camera.eye + direction;
+            return;
+        }
+
+        let axis = |positive: bool, negative: bool| f32::from(positive) - f32::from(negative);
+        let keys = self.keys;
+        let input = direction * axis(keys.forward, keys.backward)
+            + side * axis(keys.right, keys.left)
+            + up * axis(keys.up, keys.down);
+        let (wanted, rate) = if input.is_zero() {
+            (Vector3::zero(), self.damping)
+        } else {
+            let speed = self.speed
+                * if keys.sprint {
+                    self.sprint_multiplier
+                } else {
+                    1.0
+                };
+            (input.normalize() * speed, self.acceleration)
+        };
+
+        // Nothing changes the velocity, the camera keeps drifting
+        if rate <= 0.0 {
+            camera.eye += self.velocity * dt;
+            camera.target = camera.eye + direction;
+            return;
+        }
+
+        // The velocity moves from v towards w as w + (v - w) * e^(-rate * t),
+        // integrating that over the frame gives the exact distance travelled
+        let remaining = (-rate * dt).exp();
+        let difference = self.velocity - wanted;
+        camera.eye += wanted * dt + difference * ((1.0 - remaining) / rate);
+        self.velocity = wanted + difference * remaining;
+        camera.target = camera.eye + direction;
+    }
+}
+
+/// Horizontal axes perpendicular to `up`: the one the camera looks along at
+/// yaw 0, -Z for a Y up, and the one to its right.
+fn basis(up: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let reference = if up.z.abs() < 0.9 {
+        -Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let forward = (reference - up * reference.dot(up)).normalize();
+    (forward, forward.cross(up))
+}
@@ -0,0 +1,192 @@
+use std::{fmt, sync::mpsc};
+
+use image::RgbaImage;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, Device, Extent3d, Maintain, MapMode, Origin3d, Queue, SurfaceError,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
+    TextureFormat, TextureUsages, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The frame texture was not created with `TextureUsages::COPY_SRC`,
+    /// which some surfaces don't support.
+    NotCopyable,
+    /// Only 8-bit RGBA and BGRA frames can be converted into an image.
+    UnsupportedFormat(TextureFormat),
+    /// The readback buffer could not be mapped.
+    Map(BufferAsyncError),
+    /// The device finished its work without mapping the readback buffer.
+    MapPending,
+    /// No frame could be acquired from the surface.
+    Surface(SurfaceError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NotCopyable => write!(f, "frame texture cannot be copied from"),
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "cannot capture frames with format {format:?}")
+            }
+            CaptureError::Map(err) => write!(f, "failed to map readback buffer: {err}"),
+            CaptureError::MapPending => write!(f, "readback buffer was never mapped"),
+            CaptureError::Surface(err) => write!(f, "failed to acquire frame: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Map(err) => Some(err),
+            CaptureError::Surface(err) => Some(err),
+            CaptureError::NotCopyable
+            | CaptureError::UnsupportedFormat(_)
+            | CaptureError::MapPending => None,
+        }
+    }
+}
+
+/// Reads back the first mip level of an 8-bit RGBA or BGRA texture.
+pub fn read_texture(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+) -> Result<RgbaImage, CaptureError> {
+    read_texture_level(device, queue, texture, 0)
+}
+
+/// Reads back mip level `level` of an 8-bit RGBA or BGRA texture.
+pub fn read_texture_level(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+    level: u32,
+) -> Result<RgbaImage, CaptureError> {
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("texture readback"),
+    });
+    let readback = FrameReadback::with_level(device, &mut encoder, texture, level)?;
+    queue.submit(Some(encoder.finish()));
+    readback.read(device)
+}
+
+/// A pending copy of a frame texture into a CPU-readable buffer.
+pub(crate) struct FrameReadback {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+impl FrameReadback {
+    /// Records a copy of `texture` into a new readback buffer.
+    ///
+    /// The copy only happens once `encoder` has been submitted.
+    pub(crate) fn new(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) -> Result<Self, CaptureError> {
+        Self::with_level(device, encoder, texture, 0)
+    }
+
+    /// Records a copy of mip level `level` of `texture`.
+    fn with_level(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        level: u32,
+    ) -> Result<Self, CaptureError> {
+        if !texture.usage().contains(TextureUsages::COPY_SRC) {
+            return Err(CaptureError::NotCopyable);
+        }
+        let bgra = match texture.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(CaptureError::UnsupportedFormat(format)),
+        };
+
+        let size = texture.size().mip_level_size(level, texture.dimension());
+        let (width, height) = (size.width, size.height);
+        // Rows of a texture to buffer copy have to be 256 byte aligned
+        let padded_bytes_per_row =
+            (width * 4).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("frame readback"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture,
+                mip_level: level,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        })
+    }
+
+    /// Waits for the copy to finish and converts the result into an image.
+    ///
+    /// Must be called after the encoder passed to [`FrameReadback::new`] has
+    /// been submitted. Blocking on the GPU isn't possible in browsers, so this
+    /// only works on native targets.
+    pub(crate) fn read(self, device: &Device) -> Result<RgbaImage, CaptureError> {
+        let slice = self.buffer.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(Maintain::Wait);
+        rx.try_recv()
+            .map_err(|_| CaptureError::MapPending)?
+            .map_err(CaptureError::Map)?;
+
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("readback buffer matches the image size"))
+    }
+}
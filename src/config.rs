@@ -0,0 +1,499 @@
+use std::{fmt, path::PathBuf};
+
+use wgpu::{
+    Adapter, Backends, CompositeAlphaMode, Features, Instance, PowerPreference, PresentMode,
+    TextureFormat, TextureFormatFeatureFlags,
+};
+
+/// Picks one adapter out of those enumerated by [`list_adapters`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// Position in the list of adapters.
+    Index(usize),
+    /// Case-insensitive substring of the adapter name.
+    Name(String),
+}
+
+impl AdapterSelector {
+    /// An index if `value` is a number, a name otherwise.
+    pub fn parse(value: &str) -> Self {
+        match value.parse() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(value.to_string()),
+        }
+    }
+
+    pub fn matches(&self, index: usize, adapter: &Adapter) -> bool {
+        match self {
+            AdapterSelector::Index(i) => *i == index,
+            AdapterSelector::Name(name) => adapter
+                .get_info()
+                .name
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdapterSelector::Index(index) => write!(f, "#{index}"),
+            AdapterSelector::Name(name) => write!(f, "\"{name}\""),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownBackend(String),
+    UnknownPowerPreference(String),
+    UnknownPresentMode(String),
+    UnknownAlphaMode(String),
+    UnknownDepthFormat(String),
+    InvalidNumber {
+        arg: String,
+        value: String,
+    },
+    /// A boolean option got something else than a boolean.
+    InvalidValue {
+        arg: String,
+        value: String,
+    },
+    MissingValue(String),
+    UnknownArgument(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownBackend(name) => write!(
+                f,
+                "unknown backend \"{name}\", expected vulkan, gl, metal, dx12, webgpu, primary, all or software"
+            ),
+            ConfigError::UnknownPowerPreference(name) => {
+                write!(f, "unknown power preference \"{name}\", expected low, high or none")
+            }
+            ConfigError::UnknownPresentMode(name) => write!(
+                f,
+                "unknown present mode \"{name}\", expected fifo, fifo-relaxed, mailbox, immediate, auto-vsync or auto-no-vsync"
+            ),
+            ConfigError::UnknownAlphaMode(name) => write!(
+                f,
+                "unknown alpha mode \"{name}\", expected auto, opaque, pre-multiplied, post-multiplied or inherit"
+            ),
+            ConfigError::UnknownDepthFormat(name) => write!(
+                f,
+                "unknown depth format \"{name}\", expected depth32float, depth24plus-stencil8 or none"
+            ),
+            ConfigError::InvalidNumber { arg, value } => {
+                write!(f, "{arg} expects a number, got \"{value}\"")
+            }
+            ConfigError::InvalidValue { arg, value } => write!(
+                f,
+                "{arg} expects true, false, yes, no, on, off, 1 or 0, got \"{value}\""
+            ),
+            ConfigError::MissingValue(arg) => write!(f, "{arg} requires a value"),
+            ConfigError::UnknownArgument(arg) => write!(f, "unknown argument {arg}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Command line options understood by [`GraphicsConfig::apply_args`].
+pub const USAGE: &str = "\
+Graphics options (flags also take =true/false, =yes/no, =on/off or =1/0):
+  --backend <list>           Comma separated backends: vulkan, gl, metal, dx12, webgpu,
+                             primary, all or software (fallback adapter only)
+  --power-preference <pref>  low, high or none
+  --adapter <name|index>     Use a specific adapter, see --list-adapters
+  --fallback-adapter         Only use a fallback (software) adapter
+  --present-mode <mode>      fifo, fifo-relaxed, mailbox, immediate, auto-vsync or auto-no-vsync
+  --vsync <on|off>           Shorthand for --present-mode auto-vsync/auto-no-vsync
+  --alpha-mode <mode>        auto, opaque, pre-multiplied, post-multiplied or inherit
+  --frame-latency <frames>   Maximum number of frames queued for presentation
+  --depth <format|none>      Depth buffer format: depth32float or depth24plus-stencil8
+  --reverse-z                Map the near plane to depth 1 and the far plane to 0
+  --msaa <samples>           Multisample anti-aliasing with 1, 2, 4 or 8 samples per pixel
+  --pipeline-cache <dir|off> Where compiled pipelines are kept between runs
+  --hot-reload               Rebuild pipelines when their shader files change
+  --shader-dir <dir>         Where hot reloaded shaders are read from, assets/shaders by default
+  --error-overlay            Show wgpu and shader errors on screen
+  --list-adapters            Print all available adapters and exit";
+
+/// How the graphics backend and adapter are chosen.
+///
+/// Settings are taken from the defaults, then the `WGPU_BACKEND`,
+/// `WGPU_POWER_PREF`, `WGPU_ADAPTER_NAME` and `WGPU_FORCE_FALLBACK_ADAPTER`
+/// env vars, then the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphicsConfig {
+    pub backends: Backends,
+    pub power_preference: PowerPreference,
+    /// Only use a fallback ("software") adapter.
+    pub force_fallback_adapter: bool,
+    /// Use this adapter instead of letting wgpu choose one.
+    pub adapter: Option<AdapterSelector>,
+    /// How frames are presented, see [`choose_present_mode`] for the
+    /// fallbacks. `None` keeps the surface's default.
+    pub present_mode: Option<PresentMode>,
+    /// How the window is composited. `None` keeps the surface's default.
+    pub alpha_mode: Option<CompositeAlphaMode>,
+    /// Desired maximum number of frames queued for presentation.
+    pub frame_latency: u32,
+    /// Format of the depth buffer kept with the frame. `None` draws without one.
+    pub depth_format: Option<TextureFormat>,
+    /// Clear depth to 0 and test with `Greater`, for better precision far
+    /// from the camera.
+    pub reverse_z: bool,
+    /// Samples per pixel of the frame, see [`choose_sample_count`] for the
+    /// fallbacks. 1 disables multisampling.
+    pub sample_count: u32,
+    /// Directory the driver's pipeline cache is saved to, on adapters that
+    /// support one. `None` disables saving it.
+    pub pipeline_cache_dir: Option<PathBuf>,
+    /// Watch shader files and rebuild the pipelines using them on changes.
+    pub hot_reload: bool,
+    /// Directory hot reloaded shaders are read from, see
+    /// [`GraphicsConfig::shader_path`].
+    pub shader_dir: PathBuf,
+    /// Draw wgpu and shader errors over the frame.
+    pub error_overlay: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        Self {
+            // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
+            #[cfg(not(target_arch = "wasm32"))]
+            backends: Backends::PRIMARY,
+            #[cfg(target_arch = "wasm32")]
+            backends: Backends::GL,
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
+            adapter: None,
+            present_mode: None,
+            alpha_mode: None,
+            // Same as the default of `Surface::get_default_config`
+            frame_latency: 2,
+            depth_format: Some(TextureFormat::Depth32Float),
+            reverse_z: false,
+            sample_count: 1,
+            pipeline_cache_dir: default_pipeline_cache_dir(),
+            hot_reload: false,
+            shader_dir: PathBuf::from("assets/shaders"),
+            error_overlay: false,
+        }
+    }
+}
+
+impl GraphicsConfig {
+    /// Defaults for rendering without a window, where any backend will do.
+    pub fn headless() -> Self {
+        Self {
+            backends: Backends::all(),
+            pipeline_cache_dir: None,
+            ..Default::default()
+        }
+    }
+
+    /// The defaults overridden by env vars.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(backends) = std::env::var("WGPU_BACKEND") {
+            self.set_backends(&backends)?;
+        }
+        if let Ok(power_preference) = std::env::var("WGPU_POWER_PREF") {
+            self.power_preference = parse_power_preference(&power_preference)?;
+        }
+        if let Ok(adapter) = std::env::var("WGPU_ADAPTER_NAME") {
+            self.adapter = Some(AdapterSelector::parse(&adapter));
+        }
+        if let Ok(fallback) = std::env::var("WGPU_FORCE_FALLBACK_ADAPTER") {
+            self.force_fallback_adapter = parse_flag("WGPU_FORCE_FALLBACK_ADAPTER", &fallback)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the graphics options in `args` and returns the arguments that
+    /// aren't graphics options, in order.
+    pub fn apply_args(
+        &mut self,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Vec<String>, ConfigError> {
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Accept both `--option value` and `--option=value`, flags only
+            // take the latter
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => {
+                    (name.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::MissingValue(name.clone()))
+            };
+            let flag = || match &inline_value {
+                Some(value) => parse_flag(&name, value),
+                None => Ok(true),
+            };
+            match name.as_str() {
+                "--backend" => self.set_backends(&value()?)?,
+                "--power-preference" => self.power_preference = parse_power_preference(&value()?)?,
+                "--adapter" => self.adapter = Some(AdapterSelector::parse(&value()?)),
+                "--fallback-adapter" => self.force_fallback_adapter = flag()?,
+                "--present-mode" => self.present_mode = Some(parse_present_mode(&value()?)?),
+                "--vsync" => {
+                    self.present_mode = Some(match value()?.to_lowercase().as_str() {
+                        "on" | "true" | "1" => PresentMode::AutoVsync,
+                        "off" | "false" | "0" => PresentMode::AutoNoVsync,
+                        other => return Err(ConfigError::UnknownPresentMode(other.to_string())),
+                    })
+                }
+                "--alpha-mode" => self.alpha_mode = Some(parse_alpha_mode(&value()?)?),
+                "--frame-latency" => {
+                    let frames = value()?;
+                    self.frame_latency = frames.parse().map_err(|_| ConfigError::InvalidNumber {
+                        arg: name.clone(),
+                        value: frames,
+                    })?
+                }
+                "--depth" => self.depth_format = parse_depth_format(&value()?)?,
+                "--reverse-z" => self.reverse_z = flag()?,
+                "--msaa" => {
+                    let samples = value()?;
+                    self.sample_count = samples.parse().map_err(|_| ConfigError::InvalidNumber {
+                        arg: name.clone(),
+                        value: samples,
+                    })?
+                }
+                "--pipeline-cache" => {
+                    self.pipeline_cache_dir = match value()?.as_str() {
+                        "off" | "none" => None,
+                        dir => Some(PathBuf::from(dir)),
+                    }
+                }
+                "--hot-reload" => self.hot_reload = flag()?,
+                "--shader-dir" => self.shader_dir = PathBuf::from(value()?),
+                "--error-overlay" => self.error_overlay = flag()?,
+                _ => rest.push(arg),
+            }
+        }
+        Ok(rest)
+    }
+
+    /// The path of the shader file `name` in [`GraphicsConfig::shader_dir`].
+    ///
+    /// A relative directory is looked up in the working directory first, then
+    /// next to the executable, so running from the project and running an
+    /// installed binary both find it. If neither has it, the path is relative
+    /// to the working directory.
+    pub fn shader_path(&self, name: &str) -> PathBuf {
+        let path = self.shader_dir.join(name);
+        if path.is_absolute() || path.exists() {
+            return path;
+        }
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(&path)))
+            .filter(|beside_exe| beside_exe.exists())
+            .unwrap_or(path)
+    }
+
+    fn set_backends(&mut self, list: &str) -> Result<(), ConfigError> {
+        let mut backends = Backends::empty();
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            backends |= match name.to_lowercase().as_str() {
+                "vulkan" | "vk" => Backends::VULKAN,
+                "gl" | "gles" | "opengl" | "webgl" => Backends::GL,
+                "metal" | "mtl" => Backends::METAL,
+                "dx12" | "d3d12" => Backends::DX12,
+                "webgpu" => Backends::BROWSER_WEBGPU,
+                "primary" => Backends::PRIMARY,
+                "all" => Backends::all(),
+                // Software rendering is an adapter type rather than a backend,
+                // any backend can provide it.
+                "software" | "fallback" => {
+                    self.force_fallback_adapter = true;
+                    Backends::all()
+                }
+                _ => return Err(ConfigError::UnknownBackend(name.to_string())),
+            };
+        }
+        self.backends = backends;
+        Ok(())
+    }
+}
+
+/// Whether the boolean option `arg` is on, an empty value turns it on.
+fn parse_flag(arg: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "" | "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(ConfigError::InvalidValue {
+            arg: arg.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+fn parse_power_preference(value: &str) -> Result<PowerPreference, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "low" | "low-power" => Ok(PowerPreference::LowPower),
+        "high" | "high-performance" => Ok(PowerPreference::HighPerformance),
+        "none" => Ok(PowerPreference::None),
+        _ => Err(ConfigError::UnknownPowerPreference(value.to_string())),
+    }
+}
+
+fn parse_present_mode(value: &str) -> Result<PresentMode, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "fifo" => Ok(PresentMode::Fifo),
+        "fifo-relaxed" => Ok(PresentMode::FifoRelaxed),
+        "mailbox" => Ok(PresentMode::Mailbox),
+        "immediate" => Ok(PresentMode::Immediate),
+        "auto-vsync" => Ok(PresentMode::AutoVsync),
+        "auto-no-vsync" => Ok(PresentMode::AutoNoVsync),
+        _ => Err(ConfigError::UnknownPresentMode(value.to_string())),
+    }
+}
+
+fn parse_depth_format(value: &str) -> Result<Option<TextureFormat>, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "depth32float" | "32" => Ok(Some(TextureFormat::Depth32Float)),
+        "depth24plus-stencil8" | "24-8" => Ok(Some(TextureFormat::Depth24PlusStencil8)),
+        "none" | "off" => Ok(None),
+        _ => Err(ConfigError::UnknownDepthFormat(value.to_string())),
+    }
+}
+
+/// The per-user cache directory, `None` on the web or if it can't be found.
+fn default_pipeline_cache_dir() -> Option<PathBuf> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        var("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+    } else {
+        var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base.map(|base| base.join("render-rs"))
+}
+
+fn parse_alpha_mode(value: &str) -> Result<CompositeAlphaMode, ConfigError> {
+    match value.to_lowercase().as_str() {
+        "auto" => Ok(CompositeAlphaMode::Auto),
+        "opaque" => Ok(CompositeAlphaMode::Opaque),
+        "pre-multiplied" => Ok(CompositeAlphaMode::PreMultiplied),
+        "post-multiplied" => Ok(CompositeAlphaMode::PostMultiplied),
+        "inherit" => Ok(CompositeAlphaMode::Inherit),
+        _ => Err(ConfigError::UnknownAlphaMode(value.to_string())),
+    }
+}
+
+/// Picks the closest mode to `requested` out of the `supported` ones.
+///
+/// Modes without vsync fall back to each other before settling on `Fifo`,
+/// which every surface supports. The `Auto*` modes are resolved by wgpu
+/// itself and are returned unchanged.
+pub fn choose_present_mode(requested: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    let chain: &[PresentMode] = match requested {
+        PresentMode::AutoVsync | PresentMode::AutoNoVsync => return requested,
+        PresentMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Immediate],
+        PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+        PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+        PresentMode::Fifo => &[],
+    };
+    chain
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// `requested` if it's supported, otherwise `Auto` so wgpu picks one.
+pub fn choose_alpha_mode(
+    requested: CompositeAlphaMode,
+    supported: &[CompositeAlphaMode],
+) -> CompositeAlphaMode {
+    if supported.contains(&requested) {
+        requested
+    } else {
+        CompositeAlphaMode::Auto
+    }
+}
+
+/// The highest sample count up to `requested` that `adapter` supports for
+/// all of `formats`, 1 if there is none.
+pub fn choose_sample_count(requested: u32, adapter: &Adapter, formats: &[TextureFormat]) -> u32 {
+    let features = adapter.features();
+    // Only the guaranteed counts are usable without this feature
+    let adapter_specific = features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    [16, 8, 4, 2]
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| {
+            formats.iter().all(|&format| {
+                let flags = if adapter_specific {
+                    adapter.get_texture_format_features(format).flags
+                } else {
+                    format.guaranteed_format_features(features).flags
+                };
+                // Color targets are resolved into the frame
+                flags.sample_count_supported(count)
+                    && (!format.has_color_aspect()
+                        || flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE))
+            })
+        })
+        .unwrap_or(1)
+}
+
+/// All adapters available for `backends`, in the order used by [`AdapterSelector::Index`].
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters(instance: &Instance, backends: Backends) -> Vec<Adapter> {
+    instance.enumerate_adapters(backends)
+}
+
+/// Prints every adapter available for the configured backends with its
+/// info, features and limits.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn print_adapters(config: &GraphicsConfig) {
+    let instance = Instance::new(&wgpu::InstanceDescriptor {
+        backends: config.backends,
+        ..Default::default()
+    });
+    let adapters = list_adapters(&instance, config.backends);
+    if adapters.is_empty() {
+        println!("No adapters found for backends {:?}", config.backends);
+    }
+    for (index, adapter) in adapters.iter().enumerate() {
+        let info = adapter.get_info();
+        println!("Adapter #{index}: {}", info.name);
+        println!("  Backend:     {}", info.backend);
+        println!("  Device type: {:?}", info.device_type);
+        println!("  Vendor/ID:   {:#06x}/{:#06x}", info.vendor, info.device);
+        println!("  Driver:      {} {}", info.driver, info.driver_info);
+        println!("  Features:    {:?}", adapter.features());
+        println!("  Limits:      {:#?}", adapter.limits());
+    }
+}
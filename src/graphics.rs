@@ -1,46 +1,359 @@
-use std::borrow::Cow;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::{fmt, sync::OnceLock};
 
+use image::RgbaImage;
 use wgpu::{
-    util::RenderEncoder, Adapter, Color, CommandEncoderDescriptor, Device, DeviceDescriptor,
-    Features, FragmentState, Instance, Limits, LoadOp, MemoryHints, Operations, PowerPreference,
-    Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource, StoreOp,
-    Surface, SurfaceConfiguration, TextureFormat, TextureViewDescriptor, VertexState,
+    Adapter, CommandEncoder, CommandEncoderDescriptor, CompositeAlphaMode, CreateSurfaceError,
+    Device, DeviceDescriptor, ErrorFilter, Extent3d, Features, Instance, Limits, MemoryHints,
+    PresentMode, Queue, RequestAdapterOptions, RequestDeviceError, Surface, SurfaceCapabilities,
+    SurfaceConfiguration, SurfaceError, SurfaceTexture, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
 };
 use winit::{dpi::PhysicalSize, event_loop::EventLoopProxy, window::Window};
 
+use crate::{
+    capture::{CaptureError, FrameReadback},
+    config::{
+        choose_alpha_mode, choose_present_mode, choose_sample_count, AdapterSelector,
+        GraphicsConfig,
+    },
+    render::{
+        DepthBuffer, ErrorLog, ErrorOverlay, FrameTarget, MipmapGenerator, PipelineBuilder,
+        RenderGraph, RenderGraphBuilder, RenderPipelineCache, TextureDesc, TrianglePass,
+    },
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{config::list_adapters, render::ShaderHotReload};
+
 #[cfg(target_arch = "wasm32")]
 pub type Rc<T> = std::rc::Rc<T>;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub type Rc<T> = std::sync::Arc<T>;
 
-pub async fn create_graphics(window: Rc<Window>, proxy: EventLoopProxy<Graphics>) {
+/// Format of the texture rendered into by headless graphics.
+pub const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug)]
+pub enum GraphicsError {
+    /// The window's surface could not be created.
+    CreateSurface(CreateSurfaceError),
+    /// No adapter matching the request is available.
+    AdapterNotFound { force_fallback_adapter: bool },
+    /// None of the enumerated adapters matches the configured selector.
+    AdapterNotMatched(AdapterSelector),
+    /// The adapter was found but refused to open a device.
+    RequestDevice(RequestDeviceError),
+    /// The adapter can't present to the window's surface.
+    SurfaceUnsupported { adapter: String },
+    /// The surface rejected its configuration.
+    ConfigureSurface(wgpu::Error),
+}
+
+impl GraphicsError {
+    /// Whether requesting a fallback ("software") adapter might avoid this
+    /// error, which is only the case when no other adapter was found.
+    pub fn can_retry_with_fallback(&self) -> bool {
+        matches!(
+            self,
+            GraphicsError::AdapterNotFound {
+                force_fallback_adapter: false
+            }
+        )
+    }
+}
+
+impl fmt::Display for GraphicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphicsError::CreateSurface(err) => {
+                write!(f, "failed to create a surface for the window: {err}")
+            }
+            GraphicsError::AdapterNotFound {
+                force_fallback_adapter: false,
+            } => write!(
+                f,
+                "no graphics adapter found, make sure up to date GPU drivers are installed"
+            ),
+            GraphicsError::AdapterNotFound {
+                force_fallback_adapter: true,
+            } => write!(
+                f,
+                "no software adapter found, install Mesa (llvmpipe/lavapipe) or WARP"
+            ),
+            GraphicsError::AdapterNotMatched(selector) => write!(
+                f,
+                "no graphics adapter matches {selector}, run with --list-adapters to see the available ones"
+            ),
+            GraphicsError::RequestDevice(err) => {
+                write!(f, "the graphics adapter failed to open a device: {err}")
+            }
+            GraphicsError::SurfaceUnsupported { adapter } => {
+                write!(f, "adapter \"{adapter}\" can't present to the window")
+            }
+            GraphicsError::ConfigureSurface(err) => {
+                write!(f, "failed to configure the window surface: {err}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphicsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GraphicsError::CreateSurface(err) => Some(err),
+            GraphicsError::RequestDevice(err) => Some(err),
+            GraphicsError::ConfigureSurface(err) => Some(err),
+            GraphicsError::AdapterNotFound { .. }
+            | GraphicsError::AdapterNotMatched(_)
+            | GraphicsError::SurfaceUnsupported { .. } => None,
+        }
+    }
+}
+
+/// Creates graphics for `window` and sends the result through `proxy`.
+pub async fn create_graphics(
+    window: Rc<Window>,
+    proxy: EventLoopProxy<Result<Graphics, GraphicsError>>,
+    config: GraphicsConfig,
+) {
+    let gfx = create_window_graphics(window, &config).await;
+    let _ = proxy.send_event(gfx);
+}
+
+async fn create_window_graphics(
+    window: Rc<Window>,
+    config: &GraphicsConfig,
+) -> Result<Graphics, GraphicsError> {
     // The instance is a handle to our GPU
-    // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
     let instance_desc = wgpu::InstanceDescriptor {
+        backends: config.backends,
+        ..Default::default()
+    };
+    let instance = wgpu::Instance::new(&instance_desc);
+    let surface = instance
+        .create_surface(Rc::clone(&window))
+        .map_err(GraphicsError::CreateSurface)?;
+    let adapter = select_adapter(&instance, config, Some(&surface)).await?;
+
+    let (device, queue) = request_device(&adapter).await?;
+    let errors = create_error_log(&device);
+
+    // Get physical pixel dimensiosn inside the window
+    let size = window.inner_size();
+    // Make the dimensions at least size 1, otherwise wgpu would panic
+    let width = size.width.max(1);
+    let height = size.height.max(1);
+    let mut surface_config = surface
+        .get_default_config(&adapter, width, height)
+        .ok_or_else(|| GraphicsError::SurfaceUnsupported {
+            adapter: adapter.get_info().name,
+        })?;
+    let surface_caps = surface.get_capabilities(&adapter);
+    // Allow frames to be read back for screenshots when the surface supports it
+    if surface_caps.usages.contains(TextureUsages::COPY_SRC) {
+        surface_config.usage |= TextureUsages::COPY_SRC;
+    }
+    if let Some(present_mode) = config.present_mode {
+        surface_config.present_mode =
+            choose_present_mode(present_mode, &surface_caps.present_modes);
+    }
+    if let Some(alpha_mode) = config.alpha_mode {
+        surface_config.alpha_mode = choose_alpha_mode(alpha_mode, &surface_caps.alpha_modes);
+    }
+    surface_config.desired_maximum_frame_latency = config.frame_latency;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        device.push_error_scope(ErrorFilter::Validation);
+        surface.configure(&device, &surface_config);
+        if let Some(err) = device.pop_error_scope().await {
+            return Err(GraphicsError::ConfigureSurface(err));
+        }
+    }
+
+    let sample_count = frame_sample_count(&adapter, config, surface_config.format);
+    let depth = create_depth_buffer(&device, config, &surface_config, sample_count);
+    let mut pipeline_cache = create_pipeline_cache(&device, &adapter, config);
+    pipeline_cache.set_sample_count(sample_count);
+    #[cfg(not(target_arch = "wasm32"))]
+    let hot_reload = create_hot_reload(config, &errors);
+    let render_graph = triangle_graph(
+        &device,
+        &pipeline_cache,
         #[cfg(not(target_arch = "wasm32"))]
-        backends: wgpu::Backends::PRIMARY,
-        #[cfg(target_arch = "wasm32")]
-        backends: wgpu::Backends::GL,
+        hot_reload.as_ref(),
+        #[cfg(not(target_arch = "wasm32"))]
+        &config.shader_path("triangle.wgsl"),
+        surface_config.format,
+    );
+    let error_overlay =
+        (config.error_overlay).then(|| ErrorOverlay::new(&device, surface_config.format));
+
+    Ok(Graphics {
+        target: RenderTarget::Window { window, surface },
+        instance,
+        surface_config,
+        depth,
+        adapter,
+        device,
+        queue,
+        pipeline_cache,
+        mipmaps: OnceLock::new(),
+        #[cfg(not(target_arch = "wasm32"))]
+        hot_reload,
+        render_graph,
+        errors,
+        error_overlay,
+    })
+}
+
+/// Creates graphics that render into an owned texture instead of a window surface.
+///
+/// No window or display server is needed, which makes this usable in CI and on
+/// machines without a GPU: unless an adapter is selected explicitly, a fallback
+/// ("software") adapter is requested when no hardware adapter is available.
+pub async fn create_headless_graphics(
+    config: &GraphicsConfig,
+    width: u32,
+    height: u32,
+) -> Result<Graphics, GraphicsError> {
+    let instance_desc = wgpu::InstanceDescriptor {
+        backends: config.backends,
         ..Default::default()
     };
     let instance = wgpu::Instance::new(&instance_desc);
-    let surface = instance.create_surface(Rc::clone(&window)).unwrap();
-    let adapter = instance
+
+    let mut adapter = select_adapter(&instance, config, None).await;
+    if adapter
+        .as_ref()
+        .is_err_and(GraphicsError::can_retry_with_fallback)
+    {
+        let fallback = GraphicsConfig {
+            force_fallback_adapter: true,
+            ..config.clone()
+        };
+        adapter = select_adapter(&instance, &fallback, None).await;
+    }
+    let adapter = adapter?;
+
+    let (device, queue) = request_device(&adapter).await?;
+    let errors = create_error_log(&device);
+
+    // There is no surface to configure, but the configuration still describes
+    // the target so that resizing and pipeline creation work the same way.
+    let surface_config = SurfaceConfiguration {
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        format: OFFSCREEN_FORMAT,
+        width: width.max(1),
+        height: height.max(1),
+        present_mode: PresentMode::Fifo,
+        desired_maximum_frame_latency: config.frame_latency,
+        alpha_mode: CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+    };
+    let texture = create_offscreen_texture(&device, &surface_config);
+
+    let sample_count = frame_sample_count(&adapter, config, surface_config.format);
+    let depth = create_depth_buffer(&device, config, &surface_config, sample_count);
+    let mut pipeline_cache = create_pipeline_cache(&device, &adapter, config);
+    pipeline_cache.set_sample_count(sample_count);
+    #[cfg(not(target_arch = "wasm32"))]
+    let hot_reload = create_hot_reload(config, &errors);
+    let render_graph = triangle_graph(
+        &device,
+        &pipeline_cache,
+        #[cfg(not(target_arch = "wasm32"))]
+        hot_reload.as_ref(),
+        #[cfg(not(target_arch = "wasm32"))]
+        &config.shader_path("triangle.wgsl"),
+        surface_config.format,
+    );
+    let error_overlay =
+        (config.error_overlay).then(|| ErrorOverlay::new(&device, surface_config.format));
+
+    Ok(Graphics {
+        target: RenderTarget::Offscreen { texture },
+        instance,
+        surface_config,
+        depth,
+        adapter,
+        device,
+        queue,
+        pipeline_cache,
+        mipmaps: OnceLock::new(),
+        #[cfg(not(target_arch = "wasm32"))]
+        hot_reload,
+        render_graph,
+        errors,
+        error_overlay,
+    })
+}
+
+/// Finds the adapter picked by `config` that can present to `surface`, if any.
+async fn select_adapter(
+    instance: &Instance,
+    config: &GraphicsConfig,
+    surface: Option<&Surface<'_>>,
+) -> Result<Adapter, GraphicsError> {
+    if let Some(selector) = &config.adapter {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            // The selector names the adapter, leaving nothing to prefer
+            if config.power_preference != wgpu::PowerPreference::None {
+                log::warn!(
+                    "Ignoring power preference {:?}, adapter {selector} was selected",
+                    config.power_preference
+                );
+            }
+            if config.force_fallback_adapter {
+                log::warn!("Ignoring the fallback adapter option, adapter {selector} was selected");
+            }
+            let (_, adapter) = list_adapters(instance, config.backends)
+                .into_iter()
+                .enumerate()
+                .find(|(index, adapter)| selector.matches(*index, adapter))
+                .ok_or_else(|| GraphicsError::AdapterNotMatched(selector.clone()))?;
+            if surface.is_some_and(|surface| !adapter.is_surface_supported(surface)) {
+                return Err(GraphicsError::SurfaceUnsupported {
+                    adapter: adapter.get_info().name,
+                });
+            }
+            return Ok(adapter);
+        }
+
+        // Browsers only hand out the adapter they choose
+        #[cfg(target_arch = "wasm32")]
+        log::warn!("Ignoring adapter selection {selector}, not supported on the web");
+    }
+
+    instance
         .request_adapter(&RequestAdapterOptions {
-            power_preference: PowerPreference::default(), // Power preference for the device
-            force_fallback_adapter: false, // Indicates that only a fallback ("software") adapter can be used
-            compatible_surface: Some(&surface), // Guarantee that the adapter can render to this surface
+            power_preference: config.power_preference, // Power preference for the device
+            force_fallback_adapter: config.force_fallback_adapter, // Indicates that only a fallback ("software") adapter can be used
+            compatible_surface: surface, // Guarantee that the adapter can render to this surface
         })
         .await
-        .expect("Could not get an adapter (GPU).");
+        .ok_or(GraphicsError::AdapterNotFound {
+            force_fallback_adapter: config.force_fallback_adapter,
+        })
+}
 
-    let (device, queue) = adapter
+async fn request_device(adapter: &Adapter) -> Result<(Device, Queue), GraphicsError> {
+    adapter
         .request_device(
             &DeviceDescriptor {
                 label: None,
-                required_features: Features::empty(), // Specifies the required features by the device request. Fails if the adapt er can't provide them.
+                // Specifies the required features by the device request. Fails if the adapter can't provide them.
+                // Compressed textures are decompressed on the CPU without theirs.
+                required_features: adapter.features()
+                    & (Features::PIPELINE_CACHE
+                        | Features::FLOAT32_FILTERABLE
+                        | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | Features::TEXTURE_COMPRESSION_BC
+                        | Features::TEXTURE_COMPRESSION_ETC2
+                        | Features::TEXTURE_COMPRESSION_ASTC),
                 required_limits: Limits::downlevel_webgl2_defaults()
                     .using_resolution(adapter.limits()),
                 memory_hints: MemoryHints::Performance,
@@ -48,119 +361,462 @@ pub async fn create_graphics(window: Rc<Window>, proxy: EventLoopProxy<Graphics>
             None,
         )
         .await
-        .expect("Failed to get device");
+        .map_err(GraphicsError::RequestDevice)
+}
 
-    // Get physical pixel dimensiosn inside the window
-    let size = window.inner_size();
-    // Make the dimensions at least size 1, otherwise wgpu would panic
-    let width = size.width.max(1);
-    let height = size.height.max(1);
-    let surface_config = surface.get_default_config(&adapter, width, height).unwrap();
+fn create_offscreen_texture(device: &Device, config: &SurfaceConfiguration) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("offscreen target"),
+        size: Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
+}
 
-    #[cfg(not(target_arch = "wasm32"))]
-    surface.configure(&device, &surface_config);
+/// The configured sample count, or the closest lower one the adapter supports
+/// for the frame's color and depth formats.
+fn frame_sample_count(adapter: &Adapter, config: &GraphicsConfig, format: TextureFormat) -> u32 {
+    let formats: Vec<_> = std::iter::once(format).chain(config.depth_format).collect();
+    let count = choose_sample_count(config.sample_count, adapter, &formats);
+    if count != config.sample_count {
+        log::warn!(
+            "{}x MSAA is not supported for {formats:?}, using {count}x",
+            config.sample_count
+        );
+    }
+    count
+}
 
-    let render_pipeline = create_pipeline(&device, surface_config.format);
+fn create_depth_buffer(
+    device: &Device,
+    config: &GraphicsConfig,
+    surface_config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<DepthBuffer> {
+    let format = config.depth_format?;
+    Some(DepthBuffer::new(
+        device,
+        format,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+        config.reverse_z,
+    ))
+}
 
-    let gfx = Graphics {
-        window: window.clone(),
-        instance,
-        surface,
-        surface_config,
-        adapter,
+fn create_pipeline_cache(
+    device: &Device,
+    adapter: &Adapter,
+    config: &GraphicsConfig,
+) -> RenderPipelineCache {
+    #[cfg(not(target_arch = "wasm32"))]
+    return RenderPipelineCache::with_driver_cache(
         device,
-        queue,
-        render_pipeline,
-    };
+        adapter,
+        config.pipeline_cache_dir.as_deref(),
+    );
 
-    let _ = proxy.send_event(gfx);
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (device, adapter, config);
+        RenderPipelineCache::new()
+    }
 }
 
-fn create_pipeline(device: &Device, swap_chain_format: TextureFormat) -> RenderPipeline {
-    let shader = device.create_shader_module(ShaderModuleDescriptor {
-        label: None,
-        source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+/// Logs errors wgpu can't return to a caller and collects them for the error
+/// overlay, instead of panicking like wgpu does by default.
+fn create_error_log(device: &Device) -> ErrorLog {
+    let errors = ErrorLog::new();
+    let uncaptured = errors.clone();
+    device.on_uncaptured_error(Box::new(move |error| {
+        log::error!("Uncaptured wgpu error: {error}");
+        uncaptured.report("wgpu", error.to_string());
+    }));
+    errors
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn create_hot_reload(config: &GraphicsConfig, errors: &ErrorLog) -> Option<ShaderHotReload> {
+    if !config.hot_reload {
+        return None;
+    }
+    let mut hot_reload = ShaderHotReload::new()
+        .inspect_err(|err| log::warn!("Shader hot reload is unavailable: {err}"))
+        .ok()?;
+    hot_reload.set_error_log(errors.clone());
+    Some(hot_reload)
+}
+
+/// Draws the red triangle of `triangle.wgsl` on a green background.
+///
+/// With hot reload the shader is read from `shader_path` instead of the copy
+/// built into the binary, which is only drawn while the file doesn't build.
+fn triangle_graph(
+    device: &Device,
+    cache: &RenderPipelineCache,
+    #[cfg(not(target_arch = "wasm32"))] hot_reload: Option<&ShaderHotReload>,
+    #[cfg(not(target_arch = "wasm32"))] shader_path: &Path,
+    format: TextureFormat,
+) -> RenderGraph {
+    let mut graph = RenderGraphBuilder::new();
+    let color = graph.create_texture(TextureDesc::surface("scene color", format));
+    #[cfg(not(target_arch = "wasm32"))]
+    let triangle = hot_reload.map(|hot_reload| {
+        TrianglePass::from_file(device, cache, hot_reload, shader_path, format, color)
+            .expect("triangle pipeline is valid")
     });
+    #[cfg(target_arch = "wasm32")]
+    let triangle = None;
+    graph
+        .add_pass(triangle.unwrap_or_else(|| TrianglePass::new(device, cache, format, color)))
+        .set_output(color);
+    graph.build().expect("triangle graph is valid")
+}
 
-    device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: None,
-        layout: None,
-        vertex: VertexState {
-            module: &shader,
-            entry_point: Some("vs_main"),
-            buffers: &[],
-            compilation_options: Default::default(),
-        },
-        fragment: Some(FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
-            targets: &[Some(swap_chain_format.into())],
-            compilation_options: Default::default(),
-        }),
-        primitive: Default::default(),
-        depth_stencil: None,
-        multisample: Default::default(),
-        multiview: None,
-        cache: None,
-    })
+/// How [`Graphics::recover`] dealt with a failure to acquire a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceRecovery {
+    /// The surface was reconfigured and the next frame should succeed.
+    Reconfigured,
+    /// The frame was dropped, drawing can simply continue.
+    SkippedFrame,
+    /// The surface can't be recovered and the app should exit.
+    Exit,
+}
+
+/// A surface error together with what was done about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SurfaceEvent {
+    pub error: SurfaceError,
+    pub recovery: SurfaceRecovery,
+}
+
+/// Where the frames drawn by [`Graphics`] end up.
+#[derive(Debug)]
+enum RenderTarget {
+    Window {
+        window: Rc<Window>,
+        surface: Surface<'static>,
+    },
+    Offscreen {
+        texture: Texture,
+    },
 }
 
 #[derive(Debug)]
 pub struct Graphics {
-    window: Rc<Window>,
+    target: RenderTarget,
     instance: Instance,
-    surface: Surface<'static>,
     surface_config: SurfaceConfiguration,
+    depth: Option<DepthBuffer>,
     adapter: Adapter,
     device: Device,
     queue: Queue,
-    render_pipeline: RenderPipeline,
+    pipeline_cache: RenderPipelineCache,
+    mipmaps: OnceLock<MipmapGenerator>,
+    #[cfg(not(target_arch = "wasm32"))]
+    hot_reload: Option<ShaderHotReload>,
+    render_graph: RenderGraph,
+    errors: ErrorLog,
+    error_overlay: Option<ErrorOverlay>,
 }
 
 impl Graphics {
+    pub fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    pub fn adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// Format of the texture frames are drawn into.
+    pub fn format(&self) -> TextureFormat {
+        self.surface_config.format
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.surface_config.width, self.surface_config.height)
+    }
+
+    /// Samples per pixel passes draw the frame with, 1 without MSAA.
+    pub fn sample_count(&self) -> u32 {
+        self.pipeline_cache.sample_count()
+    }
+
+    /// A pipeline builder for passes of the frame, building pipelines with
+    /// its sample count unless they set their own.
+    pub fn pipeline_builder(&self) -> PipelineBuilder<'_> {
+        PipelineBuilder::with_sample_count(&self.device, self.sample_count())
+    }
+
+    /// The depth buffer passes draw with, resized along with the frame.
+    ///
+    /// `None` when [`GraphicsConfig::depth_format`] is.
+    pub fn depth_buffer(&self) -> Option<&DepthBuffer> {
+        self.depth.as_ref()
+    }
+
+    /// The texture rendered into when running headless.
+    pub fn offscreen_texture(&self) -> Option<&Texture> {
+        match &self.target {
+            RenderTarget::Offscreen { texture } => Some(texture),
+            RenderTarget::Window { .. } => None,
+        }
+    }
+
+    fn surface_capabilities(&self) -> Option<SurfaceCapabilities> {
+        match &self.target {
+            RenderTarget::Window { surface, .. } => Some(surface.get_capabilities(&self.adapter)),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+
+    /// Present modes the surface supports, empty when running headless.
+    pub fn supported_present_modes(&self) -> Vec<PresentMode> {
+        self.surface_capabilities()
+            .map(|caps| caps.present_modes)
+            .unwrap_or_default()
+    }
+
+    /// Alpha modes the surface supports, empty when running headless.
+    pub fn supported_alpha_modes(&self) -> Vec<CompositeAlphaMode> {
+        self.surface_capabilities()
+            .map(|caps| caps.alpha_modes)
+            .unwrap_or_default()
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.surface_config.present_mode
+    }
+
+    /// Switches to `mode`, or the closest supported one, and returns the mode used.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> PresentMode {
+        if let Some(caps) = self.surface_capabilities() {
+            self.surface_config.present_mode = choose_present_mode(mode, &caps.present_modes);
+            self.reconfigure();
+        }
+        self.surface_config.present_mode
+    }
+
+    pub fn alpha_mode(&self) -> CompositeAlphaMode {
+        self.surface_config.alpha_mode
+    }
+
+    /// Switches to `mode` if supported, `Auto` otherwise, and returns the mode used.
+    pub fn set_alpha_mode(&mut self, mode: CompositeAlphaMode) -> CompositeAlphaMode {
+        if let Some(caps) = self.surface_capabilities() {
+            self.surface_config.alpha_mode = choose_alpha_mode(mode, &caps.alpha_modes);
+            self.reconfigure();
+        }
+        self.surface_config.alpha_mode
+    }
+
+    pub fn frame_latency(&self) -> u32 {
+        self.surface_config.desired_maximum_frame_latency
+    }
+
+    /// Sets the desired maximum number of frames queued for presentation.
+    ///
+    /// This is a hint, backends may queue more or fewer frames.
+    pub fn set_frame_latency(&mut self, frames: u32) {
+        self.surface_config.desired_maximum_frame_latency = frames;
+        self.reconfigure();
+    }
+
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         self.surface_config.width = new_size.width.max(1);
         self.surface_config.height = new_size.height.max(1);
-        self.surface.configure(&self.device, &self.surface_config);
+        self.reconfigure();
     }
 
-    pub fn request_redraw(&self)
-    {
-        self.window.request_redraw();
+    /// Applies the current configuration to the surface, recreating its swap
+    /// chain and the depth buffer.
+    pub fn reconfigure(&mut self) {
+        if let Some(depth) = &mut self.depth {
+            depth.resize(
+                &self.device,
+                self.surface_config.width,
+                self.surface_config.height,
+            );
+        }
+        match &mut self.target {
+            RenderTarget::Window { surface, .. } => {
+                surface.configure(&self.device, &self.surface_config)
+            }
+            RenderTarget::Offscreen { texture } => {
+                *texture = create_offscreen_texture(&self.device, &self.surface_config)
+            }
+        }
+    }
+
+    /// Decides how to continue after a frame failed to be acquired.
+    ///
+    /// Lost and outdated surfaces are reconfigured so the next frame can be
+    /// drawn, timeouts and unknown errors skip the frame, and running out of
+    /// memory is unrecoverable.
+    pub fn recover(&mut self, error: SurfaceError) -> SurfaceEvent {
+        let recovery = match error {
+            SurfaceError::Lost | SurfaceError::Outdated => {
+                self.reconfigure();
+                SurfaceRecovery::Reconfigured
+            }
+            SurfaceError::Timeout | SurfaceError::Other => SurfaceRecovery::SkippedFrame,
+            SurfaceError::OutOfMemory => SurfaceRecovery::Exit,
+        };
+        SurfaceEvent { error, recovery }
+    }
+
+    /// Pipelines built through this cache are shared between passes.
+    pub fn pipeline_cache(&self) -> &RenderPipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// The mipmap generator textures of the device share, created on first
+    /// use.
+    pub fn mipmap_generator(&self) -> &MipmapGenerator {
+        self.mipmaps
+            .get_or_init(|| MipmapGenerator::new(&self.device, self.adapter.get_info().backend))
     }
 
-    pub fn draw(&mut self) {
-        let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to aquire next swap chain texture.");
+    /// Saves the driver's pipeline cache so the next run starts with compiled
+    /// pipelines, returns the file written if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_pipeline_cache(&self) -> std::io::Result<Option<std::path::PathBuf>> {
+        self.pipeline_cache.save()
+    }
 
-        let view = frame.texture.create_view(&TextureViewDescriptor::default());
+    /// Watches shader files when enabled by [`GraphicsConfig::hot_reload`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn hot_reload(&self) -> Option<&ShaderHotReload> {
+        self.hot_reload.as_ref()
+    }
+
+    /// Errors wgpu reported outside of error scopes, and shaders that failed
+    /// to reload.
+    pub fn errors(&self) -> &ErrorLog {
+        &self.errors
+    }
+
+    pub fn render_graph(&self) -> &RenderGraph {
+        &self.render_graph
+    }
+
+    /// Replaces the passes drawn each frame.
+    pub fn set_render_graph(&mut self, graph: RenderGraph) {
+        self.render_graph = graph;
+    }
+
+    /// The window drawn to, `None` for offscreen graphics.
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            RenderTarget::Window { window, .. } => Some(window),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+
+    pub fn request_redraw(&self) {
+        if let RenderTarget::Window { window, .. } = &self.target {
+            window.request_redraw();
+        }
+    }
+
+    pub fn draw(&mut self) -> Result<(), SurfaceError> {
+        let frame = self.acquire_frame()?;
 
         let mut encoder = self
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        self.encode(&mut encoder, &frame.view);
 
-        {
-            let mut r_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::GREEN),
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            r_pass.set_pipeline(&self.render_pipeline);
-            r_pass.draw(0..3, 0..1);
-        } // `r_pass` dropped here
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        Ok(())
+    }
+
+    /// Draws a frame and reads it back from the GPU before it is presented.
+    pub fn capture_frame(&mut self) -> Result<RgbaImage, CaptureError> {
+        let frame = self.acquire_frame().map_err(CaptureError::Surface)?;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        self.encode(&mut encoder, &frame.view);
+        let readback = FrameReadback::new(&self.device, &mut encoder, &frame.texture);
 
         self.queue.submit(Some(encoder.finish()));
+        let image = readback.and_then(|readback| readback.read(&self.device));
         frame.present();
+        image
+    }
+
+    fn acquire_frame(&self) -> Result<Frame, SurfaceError> {
+        let (surface_texture, texture) = match &self.target {
+            RenderTarget::Window { surface, .. } => {
+                let frame = surface.get_current_texture()?;
+                let texture = frame.texture.clone();
+                (Some(frame), texture)
+            }
+            RenderTarget::Offscreen { texture } => (None, texture.clone()),
+        };
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Ok(Frame {
+            surface_texture,
+            texture,
+            view,
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, view: &TextureView) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(hot_reload) = &self.hot_reload {
+            hot_reload.poll(&self.device, &self.pipeline_cache);
+        }
+
+        let sample_count = self.sample_count();
+        let target = || FrameTarget {
+            view,
+            format: self.surface_config.format,
+            width: self.surface_config.width,
+            height: self.surface_config.height,
+            depth: self.depth.as_ref(),
+            sample_count,
+        };
+        self.render_graph
+            .execute(&self.device, &self.queue, encoder, target());
+        if let Some(overlay) = &mut self.error_overlay {
+            overlay.draw(&self.device, &self.queue, encoder, &target(), &self.errors);
+        }
+    }
+}
+
+/// A texture being drawn into, presented afterwards if it belongs to a surface.
+struct Frame {
+    surface_texture: Option<SurfaceTexture>,
+    texture: Texture,
+    view: TextureView,
+}
+
+impl Frame {
+    fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
     }
 }
@@ -0,0 +1,7 @@
+pub mod app;
+pub mod camera;
+pub mod capture;
+pub mod config;
+pub mod graphics;
+pub mod render;
+pub mod texture;
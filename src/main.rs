@@ -1,11 +1,12 @@
-mod app;
-mod graphics;
-
-use crate::{app::App, graphics::Graphics};
+use render_rs::{
+    app::App,
+    config::GraphicsConfig,
+    graphics::{Graphics, GraphicsError},
+};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 #[cfg(target_arch = "wasm32")]
-fn run_app(event_loop: EventLoop<Graphics>, app: App) {
+fn run_app(event_loop: EventLoop<Result<Graphics, GraphicsError>>, app: App) {
     // Sets up panics to go to the console.error in browser environments
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
     console_log::init_with_level(log::Level::Error).expect("Couldn't initialize logger");
@@ -18,7 +19,7 @@ fn run_app(event_loop: EventLoop<Graphics>, app: App) {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn run_app(event_loop: EventLoop<Graphics>, mut app: App) {
+fn run_app(event_loop: EventLoop<Result<Graphics, GraphicsError>>, mut app: App) {
     // Allows the setting of the log level through RUST_LOG env var.
     // It also allows wgpu logs to be seen.
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("error")).init();
@@ -27,11 +28,97 @@ fn run_app(event_loop: EventLoop<Graphics>, mut app: App) {
     let _ = event_loop.run_app(&mut app);
 }
 
+/// Reads the graphics config from env vars and the command line.
+///
+/// Returns `None` if the app shouldn't start, e.g. after printing the adapters.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_config() -> Option<GraphicsConfig> {
+    use render_rs::config::{print_adapters, ConfigError, USAGE};
+
+    let mut config = GraphicsConfig::default();
+    let result = config
+        .apply_env()
+        .and_then(|()| config.apply_args(std::env::args().skip(1)));
+    let rest = match result {
+        Ok(rest) => rest,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    match rest.first().map(String::as_str) {
+        None => Some(config),
+        Some("--list-adapters") => {
+            print_adapters(&config);
+            None
+        }
+        Some("validate-shaders") => std::process::exit(validate_shaders(&config, &rest[1..])),
+        Some("-h" | "--help") => {
+            println!(
+                "Usage: render-rs [options]\n       render-rs validate-shaders [paths]\n\n{USAGE}"
+            );
+            None
+        }
+        Some(arg) => {
+            eprintln!(
+                "{}\n\n{USAGE}",
+                ConfigError::UnknownArgument(arg.to_string())
+            );
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Validates the shaders at `paths`, or those in the shader directory of
+/// `config`, printing diagnostics. Returns the exit code.
+///
+/// Relative paths, including the shader directory, are resolved against the
+/// working directory. The shaders built into the binary are checked by the
+/// tests instead.
+#[cfg(not(target_arch = "wasm32"))]
+fn validate_shaders(config: &GraphicsConfig, paths: &[String]) -> i32 {
+    use render_rs::render::ShaderValidator;
+    use std::path::PathBuf;
+
+    let paths: Vec<PathBuf> = if paths.is_empty() {
+        vec![config.shader_dir.clone()]
+    } else {
+        paths.iter().map(PathBuf::from).collect()
+    };
+    let reports = ShaderValidator::new().validate_files(paths);
+    let failed = reports.iter().filter(|report| !report.is_ok()).count();
+    for report in &reports {
+        if report.is_ok() {
+            println!("{report}");
+        } else {
+            eprintln!("{report}\n");
+        }
+    }
+    println!(
+        "{} of {} shaders valid",
+        reports.len() - failed,
+        reports.len()
+    );
+    i32::from(failed > 0)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn parse_config() -> Option<GraphicsConfig> {
+    Some(GraphicsConfig::default())
+}
+
 fn main() {
+    let Some(config) = parse_config() else {
+        return;
+    };
+
     // <T> (T -> AppEvent) extends regular platform specific events (resize, mouse, etc.).
     // This allows our app to inject custom events and handle them alongside regular ones.
     // let event_loop = EventLoop::<()>::new().unwrap();
-    let event_loop = EventLoop::<Graphics>::with_user_event().build().unwrap();
+    let event_loop = EventLoop::<Result<Graphics, GraphicsError>>::with_user_event()
+        .build()
+        .unwrap();
 
     // ControlFlow::Poll continuously runs the event loop, even if the OS hasn't
     // dispatched any events. This is ideal for games and similar applications.
@@ -42,6 +129,6 @@ fn main() {
     // input, and uses significantly less power/CPU time than ControlFlow::Poll.
     //event_loop.set_control_flow(ControlFlow::Wait);
 
-    let app = App::new(&event_loop);
+    let app = App::new(&event_loop, config);
     run_app(event_loop, app);
 }
@@ -0,0 +1,492 @@
+use std::{fmt, marker::PhantomData, mem, num::NonZeroU64};
+
+use bytemuck::Pod;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Device, Queue,
+    ShaderStages,
+};
+
+use super::{reflection::binding_compatible, LayoutMismatch, ShaderBinding, ShaderReflection};
+
+/// Array elements in the uniform address space are aligned to 16 bytes.
+const UNIFORM_ARRAY_ALIGNMENT: usize = 16;
+/// WGSL types are made of 4 byte scalars, `f16` aside.
+const SCALAR_ALIGNMENT: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BufferError {
+    /// `T` is zero sized or no values were given.
+    Empty,
+    /// The size of `T` isn't padded to the alignment WGSL gives it in the
+    /// buffer.
+    Alignment { size: usize, align: usize },
+    /// The values don't fit in a binding of this kind on the device.
+    TooLarge { size: u64, max: u64 },
+    /// The device has no storage buffers, like on WebGL2.
+    StorageUnsupported,
+    /// The shader declares the binding as another type or doesn't use it.
+    Layout(LayoutMismatch),
+    /// The shader's array elements have another size than `T`, usually
+    /// because `T` lacks padding WGSL adds.
+    ArrayStride {
+        group: u32,
+        binding: u32,
+        shader: u32,
+        size: usize,
+    },
+    /// The shader's type has another size than the values.
+    ShaderSize {
+        group: u32,
+        binding: u32,
+        shader: u64,
+        buffer: u64,
+    },
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferError::Empty => write!(f, "buffer has no values"),
+            BufferError::Alignment { size, align } => write!(
+                f,
+                "values of {size} bytes must be padded to a multiple of {align} bytes"
+            ),
+            BufferError::TooLarge { size, max } => {
+                write!(f, "buffer of {size} bytes is larger than the {max} bytes allowed")
+            }
+            BufferError::StorageUnsupported => {
+                write!(f, "the device doesn't support storage buffers")
+            }
+            BufferError::Layout(mismatch) => mismatch.fmt(f),
+            BufferError::ArrayStride {
+                group,
+                binding,
+                shader,
+                size,
+            } => write!(
+                f,
+                "@group({group}) @binding({binding}) has elements of {shader} bytes in the shader but of {size} bytes in the buffer"
+            ),
+            BufferError::ShaderSize {
+                group,
+                binding,
+                shader,
+                buffer,
+            } => write!(
+                f,
+                "@group({group}) @binding({binding}) is {shader} bytes in the shader but the buffer holds {buffer} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {}
+
+/// Marks a [`TypedBuffer`] bound as `var<uniform>`.
+#[derive(Debug)]
+pub enum Uniform {}
+
+/// Marks a [`TypedBuffer`] bound as `var<storage>`.
+#[derive(Debug)]
+pub enum Storage {}
+
+/// A buffer holding a `T`, or an array of them, bound as a uniform.
+pub type UniformBuffer<T> = TypedBuffer<T, Uniform>;
+
+/// A buffer holding an array of `T` bound as a storage buffer.
+pub type StorageBuffer<T> = TypedBuffer<T, Storage>;
+
+/// A buffer of `T` values kept on the CPU and uploaded when they change,
+/// with a bind group layout and bind group of its own at binding 0.
+///
+/// The values are only written to the buffer by [`TypedBuffer::write`], and
+/// only when they changed since.
+#[derive(Debug)]
+pub struct TypedBuffer<T, K> {
+    label: Option<String>,
+    values: Vec<T>,
+    /// Whether the values are the elements of an array rather than one value.
+    array: bool,
+    dirty: bool,
+    buffer: Buffer,
+    entry: BindGroupLayoutEntry,
+    layout: BindGroupLayout,
+    bind_group: BindGroup,
+    kind: PhantomData<K>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    /// Creates a uniform buffer holding `value`, visible to `visibility`.
+    ///
+    /// Only the 4 byte alignment of `T` can be checked here: a struct with a
+    /// `vec3` or `vec4` field is rounded up to 16 bytes in WGSL, which a `T`
+    /// lacking that padding goes unnoticed until
+    /// [`TypedBuffer::check_shader`]. Call it to catch struct padding.
+    pub fn new(
+        device: &Device,
+        label: Option<&str>,
+        visibility: ShaderStages,
+        value: T,
+    ) -> Result<Self, BufferError> {
+        Self::create(device, label, visibility, vec![value], false)
+    }
+
+    /// Creates a uniform buffer holding `array<T, N>` with the `N` `values`.
+    ///
+    /// Uniform arrays have a stride of a multiple of 16 bytes, `T` has to be
+    /// padded to it. Padding inside `T` is only caught by
+    /// [`TypedBuffer::check_shader`].
+    pub fn new_array(
+        device: &Device,
+        label: Option<&str>,
+        visibility: ShaderStages,
+        values: &[T],
+    ) -> Result<Self, BufferError> {
+        Self::create(device, label, visibility, values.to_vec(), true)
+    }
+
+    fn create(
+        device: &Device,
+        label: Option<&str>,
+        visibility: ShaderStages,
+        values: Vec<T>,
+        array: bool,
+    ) -> Result<Self, BufferError> {
+        let align = if array {
+            UNIFORM_ARRAY_ALIGNMENT
+        } else {
+            SCALAR_ALIGNMENT
+        };
+        let max = device.limits().max_uniform_buffer_binding_size.into();
+        let size = check_values::<T>(values.len(), align, max)?;
+        let entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(size),
+            },
+            count: None,
+        };
+        Ok(Self::with_entry(
+            device,
+            label,
+            values,
+            array,
+            entry,
+            BufferUsages::UNIFORM,
+        ))
+    }
+
+    /// The value, or the first one of an array.
+    pub fn get(&self) -> &T {
+        &self.values[0]
+    }
+
+    /// Replaces the value, or the first one of an array. The buffer is only
+    /// marked as changed if `value` differs.
+    pub fn set(&mut self, value: T) {
+        if bytemuck::bytes_of(&self.values[0]) != bytemuck::bytes_of(&value) {
+            self.values[0] = value;
+            self.dirty = true;
+        }
+    }
+
+    /// The value for updating in place, marking the buffer as changed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.values[0]
+    }
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    /// Creates a storage buffer holding `array<T>` with `values`, read-write
+    /// unless `read_only`.
+    ///
+    /// Elements are at least aligned to 4 bytes, [`TypedBuffer::check_shader`]
+    /// compares the stride with the one in the shader.
+    pub fn new(
+        device: &Device,
+        label: Option<&str>,
+        visibility: ShaderStages,
+        read_only: bool,
+        values: &[T],
+    ) -> Result<Self, BufferError> {
+        let max = max_storage_size(device)?;
+        check_values::<T>(values.len(), SCALAR_ALIGNMENT, max)?;
+        let entry = BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                // The length may change, it is checked when drawing
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        Ok(Self::with_entry(
+            device,
+            label,
+            values.to_vec(),
+            true,
+            entry,
+            BufferUsages::STORAGE,
+        ))
+    }
+
+    /// Replaces the values, which may change their number.
+    ///
+    /// The buffer is sized to the values so `arrayLength` sees their number,
+    /// a new one and a new bind group are created when it changes. The bind
+    /// group has to be fetched again afterwards.
+    pub fn set_values(&mut self, device: &Device, values: &[T]) -> Result<(), BufferError> {
+        let max = max_storage_size(device)?;
+        let size = check_values::<T>(values.len(), SCALAR_ALIGNMENT, max)?;
+        if size != self.buffer.size() {
+            self.buffer = create_buffer(
+                device,
+                self.label.as_deref(),
+                bytemuck::cast_slice(values),
+                self.buffer.usage(),
+            );
+            self.bind_group =
+                create_bind_group(device, self.label.as_deref(), &self.layout, &self.buffer);
+            self.values = values.to_vec();
+            self.dirty = false;
+        } else if bytemuck::cast_slice::<T, u8>(&self.values) != bytemuck::cast_slice(values) {
+            self.values = values.to_vec();
+            self.dirty = true;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Pod, K> TypedBuffer<T, K> {
+    fn with_entry(
+        device: &Device,
+        label: Option<&str>,
+        values: Vec<T>,
+        array: bool,
+        entry: BindGroupLayoutEntry,
+        usage: BufferUsages,
+    ) -> Self {
+        let buffer = create_buffer(
+            device,
+            label,
+            bytemuck::cast_slice(&values),
+            // Copyable from so the values can be read back
+            usage | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        );
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label,
+            entries: &[entry],
+        });
+        let bind_group = create_bind_group(device, label, &layout, &buffer);
+        Self {
+            label: label.map(str::to_string),
+            values,
+            array,
+            dirty: false,
+            buffer,
+            entry,
+            layout,
+            bind_group,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// The values for updating in place, marking the buffer as changed.
+    ///
+    /// Their number is fixed, see [`StorageBuffer::set_values`] to change it.
+    pub fn values_mut(&mut self) -> &mut [T] {
+        self.dirty = true;
+        &mut self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Always false, buffers hold at least one value.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether the values changed since they were last written.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Uploads the values if they changed, returns whether they did.
+    ///
+    /// The write happens before the next submission on `queue`.
+    pub fn write(&mut self, queue: &Queue) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.values));
+        self.dirty = false;
+        true
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// The layout entry of the buffer, for putting it in a bind group with
+    /// other resources. Its binding is 0.
+    pub fn layout_entry(&self) -> BindGroupLayoutEntry {
+        self.entry
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    /// A bind group with the buffer at binding 0.
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Checks that the shader reads `@group(group) @binding(binding)` as a
+    /// buffer of this kind, with the size and padding of the values.
+    ///
+    /// Arrays have to have elements the size of `T`. Uniforms have to match
+    /// the size of the shader's type exactly, storage buffers have to be at
+    /// least as large as it.
+    pub fn check_shader(
+        &self,
+        reflection: &ShaderReflection,
+        group: u32,
+        binding: u32,
+    ) -> Result<(), BufferError> {
+        let shader = reflection
+            .binding(group, binding)
+            .ok_or(BufferError::Layout(LayoutMismatch::MissingBinding {
+                group,
+                binding,
+            }))?;
+        self.check_binding(shader)
+    }
+
+    fn check_binding(&self, shader: &ShaderBinding) -> Result<(), BufferError> {
+        let (group, binding) = (shader.group, shader.entry.binding);
+        if !binding_compatible(&shader.entry.ty, &self.entry.ty) {
+            return Err(BufferError::Layout(LayoutMismatch::BindingType {
+                group,
+                binding,
+                shader: shader.entry.ty,
+                layout: self.entry.ty,
+            }));
+        }
+        if !self.entry.visibility.contains(shader.entry.visibility) {
+            return Err(BufferError::Layout(LayoutMismatch::Visibility {
+                group,
+                binding,
+                shader: shader.entry.visibility,
+                layout: self.entry.visibility,
+            }));
+        }
+        let BindingType::Buffer {
+            ty,
+            min_binding_size: Some(shader_size),
+            ..
+        } = shader.entry.ty
+        else {
+            return Ok(());
+        };
+        if let Some(stride) = shader.array_stride.filter(|_| self.array) {
+            if stride as usize != mem::size_of::<T>() {
+                return Err(BufferError::ArrayStride {
+                    group,
+                    binding,
+                    shader: stride,
+                    size: mem::size_of::<T>(),
+                });
+            }
+        }
+        let shader_size = shader_size.get();
+        let size = (mem::size_of::<T>() * self.values.len()) as u64;
+        let matches = match ty {
+            BufferBindingType::Uniform => size == shader_size,
+            // Runtime sized arrays take as many elements as there are
+            _ => size >= shader_size,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(BufferError::ShaderSize {
+                group,
+                binding,
+                shader: shader_size,
+                buffer: size,
+            })
+        }
+    }
+}
+
+/// Returns the size of `len` values of `T`, checking that `T` is padded to
+/// `align` and that they fit in `max` bytes.
+fn check_values<T>(len: usize, align: usize, max: u64) -> Result<u64, BufferError> {
+    let size = mem::size_of::<T>();
+    if size == 0 || len == 0 {
+        return Err(BufferError::Empty);
+    }
+    if !size.is_multiple_of(align) {
+        return Err(BufferError::Alignment { size, align });
+    }
+    // A size that doesn't fit in memory doesn't fit in a binding either
+    match size.checked_mul(len).map(|size| size as u64) {
+        Some(size) if size <= max => Ok(size),
+        size => Err(BufferError::TooLarge {
+            size: size.unwrap_or(u64::MAX),
+            max,
+        }),
+    }
+}
+
+/// The largest storage buffer binding of the device.
+fn max_storage_size(device: &Device) -> Result<u64, BufferError> {
+    let limits = device.limits();
+    if limits.max_storage_buffers_per_shader_stage == 0 {
+        return Err(BufferError::StorageUnsupported);
+    }
+    Ok(limits.max_storage_buffer_binding_size.into())
+}
+
+fn create_buffer(
+    device: &Device,
+    label: Option<&str>,
+    contents: &[u8],
+    usage: BufferUsages,
+) -> Buffer {
+    device.create_buffer_init(&BufferInitDescriptor {
+        label,
+        contents,
+        usage,
+    })
+}
+
+fn create_bind_group(
+    device: &Device,
+    label: Option<&str>,
+    layout: &BindGroupLayout,
+    buffer: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label,
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    })
+}
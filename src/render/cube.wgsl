@@ -0,0 +1,16 @@
+// Directions through the faces of a cube map.
+
+// Direction through a point of a face, in the +X, -X, +Y, -Y, +Z, -Z order
+// of cube map layers, with v growing down the face's rows.
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let s = uv.x * 2.0 - 1.0;
+    let t = uv.y * 2.0 - 1.0;
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -t, -s); }
+        case 1u: { return vec3<f32>(-1.0, -t, s); }
+        case 2u: { return vec3<f32>(s, 1.0, t); }
+        case 3u: { return vec3<f32>(s, -1.0, -t); }
+        case 4u: { return vec3<f32>(s, -t, 1.0); }
+        default: { return vec3<f32>(-s, -t, -1.0); }
+    }
+}
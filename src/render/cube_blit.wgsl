@@ -0,0 +1,33 @@
+// Copies the faces of a cube map onto the faces of another level, scaling
+// them.
+//
+// Vertices 3n to 3n + 2 draw face n. Sampling through a cube view works on
+// backends that can't view a face of a cube map on its own.
+
+#include "cube.wgsl"
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) face: u32,
+}
+
+@group(0) @binding(0) var source: texture_cube<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the whole face
+    let corner = index % 3u;
+    let uv = vec2<f32>(f32((corner << 1u) & 2u), f32(corner & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.face = index / 3u;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, cube_direction(in.face, in.uv));
+}
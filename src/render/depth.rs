@@ -0,0 +1,167 @@
+use wgpu::{
+    CompareFunction, DepthStencilState, Device, Extent3d, LoadOp, Operations,
+    RenderPassDepthStencilAttachment, StoreOp, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// A depth texture the size of the frame, recreated when the frame is resized.
+///
+/// With reverse-Z the near plane is at depth 1 and the far plane at 0, which
+/// spreads float precision much more evenly over the view. Cameras drawing
+/// into the buffer need [`Camera::reverse_z`](crate::camera::Camera::reverse_z)
+/// set to match, and pipelines compare with [`DepthBuffer::compare`].
+#[derive(Debug)]
+pub struct DepthBuffer {
+    texture: Texture,
+    view: TextureView,
+    reverse_z: bool,
+}
+
+impl DepthBuffer {
+    /// Creates a `width` x `height` buffer of a depth `format`, with as many
+    /// samples as the color targets drawn along with it.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        reverse_z: bool,
+    ) -> Self {
+        assert!(
+            format.has_depth_aspect(),
+            "{format:?} is not a depth format"
+        );
+        let texture = create_texture(device, format, width, height, sample_count);
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            reverse_z,
+        }
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.texture.sample_count()
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    /// Depth of the far plane, what the buffer is cleared to.
+    pub fn clear_value(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// `compare` written for depths growing away from the camera, flipped
+    /// when the buffer uses reverse-Z.
+    pub fn compare(&self, compare: CompareFunction) -> CompareFunction {
+        if !self.reverse_z {
+            return compare;
+        }
+        match compare {
+            CompareFunction::Less => CompareFunction::Greater,
+            CompareFunction::LessEqual => CompareFunction::GreaterEqual,
+            CompareFunction::Greater => CompareFunction::Less,
+            CompareFunction::GreaterEqual => CompareFunction::LessEqual,
+            other => other,
+        }
+    }
+
+    /// Depth state testing against this buffer, without stencil or depth bias.
+    pub fn depth_stencil_state(
+        &self,
+        write_enabled: bool,
+        compare: CompareFunction,
+    ) -> DepthStencilState {
+        DepthStencilState {
+            format: self.format(),
+            depth_write_enabled: write_enabled,
+            depth_compare: self.compare(compare),
+            stencil: Default::default(),
+            bias: Default::default(),
+        }
+    }
+
+    /// Attaches the buffer to a render pass, keeping what earlier passes drew.
+    pub fn attachment(&self) -> RenderPassDepthStencilAttachment<'_> {
+        self.attachment_with(LoadOp::Load, LoadOp::Load)
+    }
+
+    /// Attaches the buffer to a render pass, clearing depth to the far plane
+    /// and stencil to 0.
+    pub fn clear_attachment(&self) -> RenderPassDepthStencilAttachment<'_> {
+        self.attachment_with(LoadOp::Clear(self.clear_value()), LoadOp::Clear(0))
+    }
+
+    fn attachment_with(
+        &self,
+        depth: LoadOp<f32>,
+        stencil: LoadOp<u32>,
+    ) -> RenderPassDepthStencilAttachment<'_> {
+        RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(Operations {
+                load: depth,
+                store: StoreOp::Store,
+            }),
+            stencil_ops: self.format().has_stencil_aspect().then_some(Operations {
+                load: stencil,
+                store: StoreOp::Store,
+            }),
+        }
+    }
+
+    /// Recreates the texture if the size changed, the contents are lost.
+    ///
+    /// Sizes are at least 1x1, so a minimized window keeps its texture.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        let size = self.texture.size();
+        if size.width == width && size.height == height {
+            return;
+        }
+        self.texture = create_texture(device, self.format(), width, height, self.sample_count());
+        self.view = self.texture.create_view(&TextureViewDescriptor::default());
+    }
+}
+
+fn create_texture(
+    device: &Device,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("depth buffer"),
+        size: Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
@@ -0,0 +1,37 @@
+// Renders the faces of a cube map from an equirectangular panorama.
+//
+// Vertices 3n to 3n + 2 draw face n, in the +X, -X, +Y, -Y, +Z, -Z order of
+// cube map layers.
+
+#include "cube.wgsl"
+
+const PI: f32 = 3.14159265358979;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) face: u32,
+}
+
+@group(0) @binding(0) var panorama: texture_2d<f32>;
+@group(0) @binding(1) var panorama_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the whole face
+    let corner = index % 3u;
+    let uv = vec2<f32>(f32((corner << 1u) & 2u), f32(corner & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.face = index / 3u;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let d = normalize(cube_direction(in.face, in.uv));
+    // The middle of the panorama looks towards -Z, its top towards +Y
+    let uv = vec2<f32>(atan2(d.x, -d.z) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+    return textureSampleLevel(panorama, panorama_sampler, uv, 0.0);
+}
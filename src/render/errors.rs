@@ -0,0 +1,109 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use wgpu::{Device, ErrorFilter};
+
+/// Errors kept by an [`ErrorLog`], the oldest is dropped beyond this.
+const MAX_ERRORS: usize = 16;
+
+/// Runs `f` in a validation error scope, returning the error wgpu reports
+/// instead of passing it to the uncaptured error handler.
+///
+/// Native backends report errors right away. In browsers the scope only
+/// resolves later, the error is then logged and `f`'s result returned.
+pub fn catch_validation_errors<T>(
+    device: &Device,
+    f: impl FnOnce() -> T,
+) -> Result<T, wgpu::Error> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let value = f();
+    // Boxed so the future can still be awaited if it isn't ready
+    let mut error = Box::pin(device.pop_error_scope());
+    match error.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(Some(error)) => Err(error),
+        Poll::Ready(None) => Ok(value),
+        Poll::Pending => {
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Some(error) = error.await {
+                    log::error!("{error}");
+                }
+            });
+            Ok(value)
+        }
+    }
+}
+
+/// Errors reported while the app runs, for showing them in the error overlay.
+///
+/// Clones share the same log, so one can be handed to the device's uncaptured
+/// error handler and to shader hot reload. Errors are reported by source, a
+/// new error replaces the previous one of the same source, and are removed
+/// once their source is fixed.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorLog {
+    inner: Arc<Mutex<LogInner>>,
+}
+
+#[derive(Debug, Default)]
+struct LogInner {
+    /// Sources and messages, oldest first.
+    errors: Vec<(String, String)>,
+    generation: u64,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LogInner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Records `message` as the current error of `source`.
+    pub fn report(&self, source: &str, message: impl Into<String>) {
+        let mut inner = self.lock();
+        inner.errors.retain(|(existing, _)| existing != source);
+        if inner.errors.len() == MAX_ERRORS {
+            inner.errors.remove(0);
+        }
+        inner.errors.push((source.to_string(), message.into()));
+        inner.generation += 1;
+    }
+
+    /// Removes the error of `source`, if any.
+    pub fn resolve(&self, source: &str) {
+        let mut inner = self.lock();
+        let count = inner.errors.len();
+        inner.errors.retain(|(existing, _)| existing != source);
+        if inner.errors.len() != count {
+            inner.generation += 1;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        if !inner.errors.is_empty() {
+            inner.errors.clear();
+            inner.generation += 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().errors.is_empty()
+    }
+
+    /// The current errors with their sources, oldest first.
+    pub fn errors(&self) -> Vec<(String, String)> {
+        self.lock().errors.clone()
+    }
+
+    /// Changes whenever errors are reported or removed.
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+}
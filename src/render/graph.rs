@@ -0,0 +1,930 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt,
+};
+
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource,
+    Buffer, BufferDescriptor, BufferUsages, Color, CommandEncoder, Device, Extent3d, FilterMode,
+    LoadOp, Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    Sampler, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, StoreOp, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor,
+};
+
+use super::{
+    depth::DepthBuffer,
+    pass::{Pass, PassBuilder, PassContext},
+    pipeline_builder::{PipelineBuilder, ShaderSource},
+    preprocessor::ShaderPreprocessor,
+};
+
+/// A texture declared in a [`RenderGraphBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// A buffer declared in a [`RenderGraphBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSize {
+    /// The size of the frame, textures are recreated when it changes.
+    Surface,
+    Fixed {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Describes a transient texture, allocated by the graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub size: TextureSize,
+    pub format: TextureFormat,
+    /// Usages besides the ones implied by how passes access the texture.
+    pub usage: TextureUsages,
+    pub sample_count: u32,
+}
+
+impl TextureDesc {
+    /// A frame-sized texture with the given format.
+    pub fn surface(label: &'static str, format: TextureFormat) -> Self {
+        Self {
+            label,
+            size: TextureSize::Surface,
+            format,
+            usage: TextureUsages::empty(),
+            sample_count: 1,
+        }
+    }
+}
+
+/// Describes a transient buffer, allocated by the graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BufferDesc {
+    pub label: &'static str,
+    pub size: u64,
+    pub usage: BufferUsages,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// [`RenderGraphBuilder::set_output`] was never called.
+    NoOutput,
+    /// A pass declared a handle that doesn't belong to the graph.
+    UnknownResource { pass: String },
+    /// A pass reads a transient resource no pass writes.
+    UninitializedRead { pass: String, resource: String },
+    /// No pass writes the transient output texture.
+    OutputNotWritten(String),
+    /// The passes depend on each other, so no order satisfies all of them.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NoOutput => write!(f, "render graph has no output texture"),
+            GraphError::UnknownResource { pass } => {
+                write!(f, "pass `{pass}` uses a resource of another render graph")
+            }
+            GraphError::UninitializedRead { pass, resource } => {
+                write!(f, "pass `{pass}` reads `{resource}`, which no pass writes")
+            }
+            GraphError::OutputNotWritten(texture) => {
+                write!(f, "no pass writes the output texture `{texture}`")
+            }
+            GraphError::Cycle(passes) => {
+                write!(f, "passes {} depend on each other", passes.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+enum TextureEntry {
+    Transient(TextureDesc),
+    Imported {
+        label: &'static str,
+        texture: Texture,
+    },
+}
+
+enum BufferEntry {
+    Transient(BufferDesc),
+    Imported { label: &'static str, buffer: Buffer },
+}
+
+struct Node {
+    pass: Box<dyn Pass>,
+    access: PassBuilder,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Resource {
+    Texture(usize),
+    Buffer(usize),
+}
+
+/// Passes accessing a resource, in the order they were added.
+#[derive(Default)]
+struct Users {
+    writers: Vec<usize>,
+    /// Passes reading the resource without writing it.
+    readers: Vec<usize>,
+}
+
+/// Declares the resources and passes making up a frame.
+///
+/// Passes can be added in any order: a pass reading a resource runs after
+/// every pass writing it, and passes writing the same resource run in the
+/// order they were added.
+#[derive(Default)]
+pub struct RenderGraphBuilder {
+    textures: Vec<TextureEntry>,
+    buffers: Vec<BufferEntry>,
+    nodes: Vec<Node>,
+    output: Option<TextureHandle>,
+}
+
+impl RenderGraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a texture that only lives while the frame is recorded.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> TextureHandle {
+        self.textures.push(TextureEntry::Transient(desc));
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// Makes a texture created outside the graph accessible to its passes.
+    pub fn import_texture(&mut self, label: &'static str, texture: Texture) -> TextureHandle {
+        self.textures
+            .push(TextureEntry::Imported { label, texture });
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// Declares a buffer that only lives while the frame is recorded.
+    pub fn create_buffer(&mut self, desc: BufferDesc) -> BufferHandle {
+        self.buffers.push(BufferEntry::Transient(desc));
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Makes a buffer created outside the graph accessible to its passes.
+    pub fn import_buffer(&mut self, label: &'static str, buffer: Buffer) -> BufferHandle {
+        self.buffers.push(BufferEntry::Imported { label, buffer });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        let mut access = PassBuilder::default();
+        pass.setup(&mut access);
+        self.nodes.push(Node {
+            pass: Box::new(pass),
+            access,
+        });
+        self
+    }
+
+    /// Sets the texture blitted to the frame once all passes ran.
+    pub fn set_output(&mut self, texture: TextureHandle) -> &mut Self {
+        self.output = Some(texture);
+        self
+    }
+
+    /// Orders the passes and assigns memory to the transient resources.
+    pub fn build(self) -> Result<RenderGraph, GraphError> {
+        let output = self.output.ok_or(GraphError::NoOutput)?;
+        if output.0 >= self.textures.len() {
+            return Err(GraphError::UnknownResource {
+                pass: "output".to_string(),
+            });
+        }
+        let users = self.collect_users()?;
+        let order = self.sort(&users)?;
+
+        let mut position = vec![0; self.nodes.len()];
+        for (i, &node) in order.iter().enumerate() {
+            position[node] = i;
+        }
+        let lifetime = |users: &Users| {
+            let positions = users.writers.iter().chain(&users.readers);
+            let first = positions.clone().map(|&node| position[node]).min()?;
+            let last = positions.map(|&node| position[node]).max()?;
+            Some((first, last))
+        };
+
+        let mut textures = Vec::new();
+        for (index, entry) in self.textures.iter().enumerate() {
+            let TextureEntry::Transient(desc) = entry else {
+                continue;
+            };
+            let users = users.get(&Resource::Texture(index));
+            let Some((first, mut last)) = users.and_then(lifetime) else {
+                continue;
+            };
+            let mut usage = desc.usage;
+            let users = users.unwrap();
+            if !users.writers.is_empty() {
+                usage |= TextureUsages::RENDER_ATTACHMENT;
+            }
+            if !users.readers.is_empty() || index == output.0 {
+                usage |= TextureUsages::TEXTURE_BINDING;
+            }
+            if index == output.0 {
+                // Read by the final blit
+                last = usize::MAX;
+            }
+            let key = TextureKey {
+                size: desc.size,
+                format: desc.format,
+                usage,
+                sample_count: desc.sample_count,
+            };
+            textures.push((index, key, first, last));
+        }
+        let (texture_slots, texture_keys) = alias(self.textures.len(), textures);
+
+        // Written textures are drawn through multisampled ones when MSAA is
+        // on, which keep their contents from the first pass writing them to
+        // the last. Their sample count is the frame's, set by `prepare`.
+        let mut multisampled = Vec::new();
+        for (index, entry) in self.textures.iter().enumerate() {
+            let (size, format, sample_count) = match entry {
+                TextureEntry::Transient(desc) => (desc.size, desc.format, desc.sample_count),
+                TextureEntry::Imported { texture, .. } => (
+                    TextureSize::Fixed {
+                        width: texture.width(),
+                        height: texture.height(),
+                    },
+                    texture.format(),
+                    texture.sample_count(),
+                ),
+            };
+            let writers = users
+                .get(&Resource::Texture(index))
+                .map_or(&[][..], |users| &users.writers)
+                .iter()
+                .map(|&node| position[node]);
+            let (Some(first), Some(last)) = (writers.clone().min(), writers.max()) else {
+                continue;
+            };
+            if sample_count == 1 {
+                let key = TextureKey {
+                    size,
+                    format,
+                    usage: TextureUsages::RENDER_ATTACHMENT,
+                    sample_count,
+                };
+                multisampled.push((index, key, first, last));
+            }
+        }
+        let (multisampled_slots, multisampled_keys) = alias(self.textures.len(), multisampled);
+
+        let mut buffers = Vec::new();
+        for (index, entry) in self.buffers.iter().enumerate() {
+            let BufferEntry::Transient(desc) = entry else {
+                continue;
+            };
+            if let Some((first, last)) = users.get(&Resource::Buffer(index)).and_then(lifetime) {
+                let key = BufferKey {
+                    size: desc.size,
+                    usage: desc.usage,
+                };
+                buffers.push((index, key, first, last));
+            }
+        }
+        let (buffer_slots, buffer_keys) = alias(self.buffers.len(), buffers);
+
+        let textures = self
+            .textures
+            .into_iter()
+            .zip(texture_slots)
+            .map(|(entry, physical)| match entry {
+                TextureEntry::Transient(desc) => TextureSlot::Transient {
+                    label: desc.label,
+                    physical,
+                },
+                TextureEntry::Imported { label, texture } => TextureSlot::Imported {
+                    label,
+                    view: texture.create_view(&TextureViewDescriptor::default()),
+                    texture,
+                },
+            })
+            .collect();
+        let buffers = self
+            .buffers
+            .into_iter()
+            .zip(buffer_slots)
+            .map(|(entry, physical)| match entry {
+                BufferEntry::Transient(desc) => BufferSlot::Transient {
+                    label: desc.label,
+                    physical,
+                },
+                BufferEntry::Imported { buffer, .. } => BufferSlot::Imported(buffer),
+            })
+            .collect();
+
+        let mut nodes: Vec<Option<Node>> = self.nodes.into_iter().map(Some).collect();
+        let passes = order
+            .iter()
+            .map(|&node| nodes[node].take().unwrap().pass)
+            .collect();
+
+        Ok(RenderGraph {
+            passes,
+            resources: Resources {
+                textures,
+                physical_textures: texture_keys
+                    .into_iter()
+                    .map(|key| PhysicalTexture { key, texture: None })
+                    .collect(),
+                multisampled: multisampled_slots,
+                physical_multisampled: multisampled_keys
+                    .into_iter()
+                    .map(|key| PhysicalTexture { key, texture: None })
+                    .collect(),
+                buffers,
+                physical_buffers: buffer_keys
+                    .into_iter()
+                    .map(|key| PhysicalBuffer { key, buffer: None })
+                    .collect(),
+                size: (0, 0),
+                sample_count: 1,
+            },
+            output,
+            blit: None,
+        })
+    }
+
+    fn collect_users(&self) -> Result<BTreeMap<Resource, Users>, GraphError> {
+        let mut users: BTreeMap<Resource, Users> = BTreeMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let access = &node.access;
+            let writes: Vec<Resource> = (access.texture_writes.iter())
+                .map(|texture| Resource::Texture(texture.0))
+                .chain(access.buffer_writes.iter().map(|b| Resource::Buffer(b.0)))
+                .collect();
+            let reads = (access.texture_reads.iter())
+                .map(|texture| Resource::Texture(texture.0))
+                .chain(access.buffer_reads.iter().map(|b| Resource::Buffer(b.0)));
+
+            for resource in writes.iter().copied().chain(reads.clone()) {
+                let declared = match resource {
+                    Resource::Texture(i) => i < self.textures.len(),
+                    Resource::Buffer(i) => i < self.buffers.len(),
+                };
+                if !declared {
+                    return Err(GraphError::UnknownResource {
+                        pass: node.pass.name().to_string(),
+                    });
+                }
+            }
+            for &resource in &writes {
+                let writers = &mut users.entry(resource).or_default().writers;
+                if writers.last() != Some(&index) {
+                    writers.push(index);
+                }
+            }
+            for resource in reads.filter(|resource| !writes.contains(resource)) {
+                let readers = &mut users.entry(resource).or_default().readers;
+                if readers.last() != Some(&index) {
+                    readers.push(index);
+                }
+            }
+        }
+
+        for (resource, users) in &users {
+            if users.writers.is_empty() && self.is_transient(*resource) {
+                return Err(GraphError::UninitializedRead {
+                    pass: self.nodes[users.readers[0]].pass.name().to_string(),
+                    resource: self.label(*resource).to_string(),
+                });
+            }
+        }
+        let output = self.output.map(|texture| Resource::Texture(texture.0));
+        if let Some(output) = output.filter(|output| self.is_transient(*output)) {
+            if users
+                .get(&output)
+                .is_none_or(|users| users.writers.is_empty())
+            {
+                return Err(GraphError::OutputNotWritten(self.label(output).to_string()));
+            }
+        }
+        Ok(users)
+    }
+
+    /// Topologically sorts the passes, preferring the order they were added in.
+    fn sort(&self, users: &BTreeMap<Resource, Users>) -> Result<Vec<usize>, GraphError> {
+        let mut successors = vec![Vec::new(); self.nodes.len()];
+        let mut predecessors = vec![0; self.nodes.len()];
+        let mut edge = |from: usize, to: usize| {
+            successors[from].push(to);
+            predecessors[to] += 1;
+        };
+        for users in users.values() {
+            for pair in users.writers.windows(2) {
+                edge(pair[0], pair[1]);
+            }
+            if let Some(&writer) = users.writers.last() {
+                for &reader in &users.readers {
+                    edge(writer, reader);
+                }
+            }
+        }
+
+        let mut ready: BinaryHeap<_> = (0..self.nodes.len())
+            .filter(|&node| predecessors[node] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(Reverse(node)) = ready.pop() {
+            order.push(node);
+            for &next in &successors[node] {
+                predecessors[next] -= 1;
+                if predecessors[next] == 0 {
+                    ready.push(Reverse(next));
+                }
+            }
+        }
+
+        if order.len() < self.nodes.len() {
+            let passes = (0..self.nodes.len())
+                .filter(|&node| predecessors[node] > 0)
+                .map(|node| self.nodes[node].pass.name().to_string())
+                .collect();
+            return Err(GraphError::Cycle(passes));
+        }
+        Ok(order)
+    }
+
+    fn is_transient(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Texture(i) => matches!(self.textures[i], TextureEntry::Transient(_)),
+            Resource::Buffer(i) => matches!(self.buffers[i], BufferEntry::Transient(_)),
+        }
+    }
+
+    fn label(&self, resource: Resource) -> &'static str {
+        match resource {
+            Resource::Texture(i) => match &self.textures[i] {
+                TextureEntry::Transient(desc) => desc.label,
+                TextureEntry::Imported { label, .. } => label,
+            },
+            Resource::Buffer(i) => match &self.buffers[i] {
+                BufferEntry::Transient(desc) => desc.label,
+                BufferEntry::Imported { label, .. } => label,
+            },
+        }
+    }
+}
+
+/// Assigns physical resources to transient ones, given as `(index, key,
+/// first use, last use)`, sharing them between resources with the same key
+/// whose lifetimes don't overlap.
+///
+/// Returns the physical resource of each of the `count` logical ones and the
+/// keys of the physical resources.
+fn alias<K: PartialEq>(
+    count: usize,
+    mut resources: Vec<(usize, K, usize, usize)>,
+) -> (Vec<Option<usize>>, Vec<K>) {
+    resources.sort_by_key(|(_, _, first, _)| *first);
+    let mut slots = vec![None; count];
+    let mut physical: Vec<(K, usize)> = Vec::new();
+    for (index, key, first, last) in resources {
+        let free = physical
+            .iter()
+            .position(|(other, busy_until)| *other == key && *busy_until < first);
+        let slot = match free {
+            Some(slot) => {
+                physical[slot].1 = last;
+                slot
+            }
+            None => {
+                physical.push((key, last));
+                physical.len() - 1
+            }
+        };
+        slots[index] = Some(slot);
+    }
+    (slots, physical.into_iter().map(|(key, _)| key).collect())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TextureKey {
+    size: TextureSize,
+    format: TextureFormat,
+    usage: TextureUsages,
+    sample_count: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BufferKey {
+    size: u64,
+    usage: BufferUsages,
+}
+
+struct PhysicalTexture {
+    key: TextureKey,
+    texture: Option<(Texture, TextureView)>,
+}
+
+impl PhysicalTexture {
+    /// Creates the texture if it doesn't exist, `frame` being the size of
+    /// surface-sized ones. Returns whether it was created.
+    fn create(&mut self, device: &Device, label: Option<&str>, frame: (u32, u32)) -> bool {
+        if self.texture.is_some() {
+            return false;
+        }
+        let key = self.key;
+        let (width, height) = match key.size {
+            TextureSize::Surface => frame,
+            TextureSize::Fixed { width, height } => (width, height),
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: key.sample_count,
+            dimension: TextureDimension::D2,
+            format: key.format,
+            usage: key.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        self.texture = Some((texture, view));
+        true
+    }
+}
+
+struct PhysicalBuffer {
+    key: BufferKey,
+    buffer: Option<Buffer>,
+}
+
+enum TextureSlot {
+    /// `physical` is `None` when no pass uses the texture.
+    Transient {
+        label: &'static str,
+        physical: Option<usize>,
+    },
+    Imported {
+        label: &'static str,
+        texture: Texture,
+        view: TextureView,
+    },
+}
+
+enum BufferSlot {
+    Transient {
+        label: &'static str,
+        physical: Option<usize>,
+    },
+    Imported(Buffer),
+}
+
+/// The resources of a built graph.
+pub(super) struct Resources {
+    textures: Vec<TextureSlot>,
+    physical_textures: Vec<PhysicalTexture>,
+    /// Multisampled texture drawn into instead of each texture, `None` for
+    /// the ones no pass writes.
+    multisampled: Vec<Option<usize>>,
+    physical_multisampled: Vec<PhysicalTexture>,
+    buffers: Vec<BufferSlot>,
+    physical_buffers: Vec<PhysicalBuffer>,
+    /// Frame size the surface-sized textures were created with.
+    size: (u32, u32),
+    /// Samples of the frame the multisampled textures were created with.
+    sample_count: u32,
+}
+
+impl Resources {
+    /// Creates the physical resources that don't exist yet or have the wrong
+    /// size, and the multisampled textures when `sample_count` is above 1.
+    ///
+    /// Returns whether a texture passes draw into or sample was created,
+    /// which replaces the views bind groups were created with.
+    fn prepare(&mut self, device: &Device, width: u32, height: u32, sample_count: u32) -> bool {
+        if self.size != (width, height) {
+            self.size = (width, height);
+            let physical = self.physical_textures.iter_mut();
+            for physical in physical.chain(&mut self.physical_multisampled) {
+                if physical.key.size == TextureSize::Surface {
+                    physical.texture = None;
+                }
+            }
+        }
+        if self.sample_count != sample_count {
+            self.sample_count = sample_count;
+            for physical in &mut self.physical_multisampled {
+                physical.key.sample_count = sample_count;
+                physical.texture = None;
+            }
+        }
+
+        let textures = &self.textures;
+        let label = |slots: &[Option<usize>], index| {
+            let logical = slots.iter().position(|slot| *slot == Some(index))?;
+            match &textures[logical] {
+                TextureSlot::Transient { label, .. } | TextureSlot::Imported { label, .. } => {
+                    Some(*label)
+                }
+            }
+        };
+        let slots: Vec<_> = (textures.iter())
+            .map(|slot| match slot {
+                TextureSlot::Transient { physical, .. } => *physical,
+                TextureSlot::Imported { .. } => None,
+            })
+            .collect();
+        let mut created = false;
+        for (index, physical) in self.physical_textures.iter_mut().enumerate() {
+            created |= physical.create(device, label(&slots, index), (width, height));
+        }
+        if sample_count > 1 {
+            for (index, physical) in self.physical_multisampled.iter_mut().enumerate() {
+                physical.create(device, label(&self.multisampled, index), (width, height));
+            }
+        }
+
+        for (index, physical) in self.physical_buffers.iter_mut().enumerate() {
+            if physical.buffer.is_some() {
+                continue;
+            }
+            let label = self.buffers.iter().find_map(|slot| match slot {
+                BufferSlot::Transient {
+                    label,
+                    physical: Some(physical),
+                } if *physical == index => Some(*label),
+                _ => None,
+            });
+            physical.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label,
+                size: physical.key.size,
+                usage: physical.key.usage,
+                mapped_at_creation: false,
+            }));
+        }
+        created
+    }
+
+    fn physical_texture(&self, handle: TextureHandle) -> &(Texture, TextureView) {
+        match &self.textures[handle.0] {
+            TextureSlot::Transient { label, physical } => physical
+                .and_then(|physical| self.physical_textures[physical].texture.as_ref())
+                .unwrap_or_else(|| panic!("texture `{label}` is not used by any pass")),
+            TextureSlot::Imported { .. } => unreachable!(),
+        }
+    }
+
+    pub(super) fn texture(&self, handle: TextureHandle) -> &Texture {
+        match &self.textures[handle.0] {
+            TextureSlot::Imported { texture, .. } => texture,
+            TextureSlot::Transient { .. } => &self.physical_texture(handle).0,
+        }
+    }
+
+    pub(super) fn texture_view(&self, handle: TextureHandle) -> &TextureView {
+        match &self.textures[handle.0] {
+            TextureSlot::Imported { view, .. } => view,
+            TextureSlot::Transient { .. } => &self.physical_texture(handle).1,
+        }
+    }
+
+    /// The multisampled view drawn into instead of `handle` when MSAA is on,
+    /// `None` without MSAA or for multisampled textures.
+    pub(super) fn multisampled_view(&self, handle: TextureHandle) -> Option<&TextureView> {
+        let physical = self.multisampled[handle.0]?;
+        let texture = self.physical_multisampled[physical].texture.as_ref();
+        texture.map(|(_, view)| view)
+    }
+
+    pub(super) fn buffer(&self, handle: BufferHandle) -> &Buffer {
+        match &self.buffers[handle.0] {
+            BufferSlot::Imported(buffer) => buffer,
+            BufferSlot::Transient { label, physical } => physical
+                .and_then(|physical| self.physical_buffers[physical].buffer.as_ref())
+                .unwrap_or_else(|| panic!("buffer `{label}` is not used by any pass")),
+        }
+    }
+
+    fn texture_label(&self, handle: TextureHandle) -> &'static str {
+        match &self.textures[handle.0] {
+            TextureSlot::Transient { label, .. } | TextureSlot::Imported { label, .. } => label,
+        }
+    }
+}
+
+/// The texture a frame is drawn into.
+pub struct FrameTarget<'a> {
+    pub view: &'a TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Depth buffer of the frame, cleared by the first pass attaching it.
+    pub depth: Option<&'a DepthBuffer>,
+    /// Samples per pixel passes draw with, 1 without MSAA.
+    pub sample_count: u32,
+}
+
+/// Passes in the order they run, with the resources they use.
+///
+/// Created with a [`RenderGraphBuilder`].
+pub struct RenderGraph {
+    passes: Vec<Box<dyn Pass>>,
+    resources: Resources,
+    output: TextureHandle,
+    blit: Option<Blit>,
+}
+
+impl RenderGraph {
+    /// Names of the passes in the order they run.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Number of textures allocated for the transient ones, after aliasing.
+    pub fn physical_texture_count(&self) -> usize {
+        self.resources.physical_textures.len()
+    }
+
+    /// Number of buffers allocated for the transient ones, after aliasing.
+    pub fn physical_buffer_count(&self) -> usize {
+        self.resources.physical_buffers.len()
+    }
+
+    /// Number of multisampled textures allocated for the written ones when
+    /// MSAA is on, after aliasing.
+    pub fn multisampled_texture_count(&self) -> usize {
+        self.resources.physical_multisampled.len()
+    }
+
+    /// Whether two transient textures share the same memory.
+    pub fn textures_aliased(&self, a: TextureHandle, b: TextureHandle) -> bool {
+        let physical = |handle: TextureHandle| match &self.resources.textures[handle.0] {
+            TextureSlot::Transient { physical, .. } => *physical,
+            TextureSlot::Imported { .. } => None,
+        };
+        physical(a).is_some() && physical(a) == physical(b)
+    }
+
+    /// Records all passes into `encoder`, followed by a blit of the output to `target`.
+    pub fn execute(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        target: FrameTarget,
+    ) {
+        let (width, height, sample_count) = (target.width, target.height, target.sample_count);
+        let created = self.resources.prepare(device, width, height, sample_count);
+        let depth_cleared = Cell::new(false);
+        for pass in &mut self.passes {
+            encoder.push_debug_group(pass.name());
+            pass.execute(&mut PassContext {
+                device,
+                queue,
+                encoder,
+                resources: &self.resources,
+                depth: target.depth,
+                sample_count: target.sample_count,
+                depth_cleared: &depth_cleared,
+            });
+            encoder.pop_debug_group();
+        }
+
+        let blit = self.blit.get_or_insert_with(|| Blit::new(device));
+        if created {
+            blit.bind_group = None;
+        }
+        blit.record(
+            device,
+            encoder,
+            self.resources.texture_view(self.output),
+            self.resources.texture_label(self.output),
+            &target,
+        );
+    }
+}
+
+impl fmt::Debug for RenderGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderGraph")
+            .field("passes", &self.pass_names())
+            .field("physical_textures", &self.physical_texture_count())
+            .field("physical_buffers", &self.physical_buffer_count())
+            .finish()
+    }
+}
+
+/// Copies the graph's output onto the frame.
+struct Blit {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    shader: ShaderModule,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+    /// Bind group of the output's view, until the graph's textures are
+    /// recreated.
+    bind_group: Option<BindGroup>,
+}
+
+impl Blit {
+    fn new(device: &Device) -> Self {
+        let reflection = ShaderPreprocessor::new()
+            .process("blit.wgsl", include_str!("blit.wgsl"))
+            .and_then(|shader| shader.reflect("vs_main", Some("fs_main")))
+            .expect("blit shader is valid");
+        let layout = reflection
+            .create_bind_group_layouts(device, Some("blit"))
+            .remove(0);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("blit"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("blit"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("blit.wgsl"))),
+        });
+        Self {
+            layout,
+            sampler,
+            shader,
+            pipelines: HashMap::new(),
+            bind_group: None,
+        }
+    }
+
+    fn record(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source: &TextureView,
+        label: &str,
+        target: &FrameTarget,
+    ) {
+        let pipeline = self.pipelines.entry(target.format).or_insert_with(|| {
+            PipelineBuilder::new(device)
+                .set_label("blit")
+                .set_shader_module(
+                    ShaderSource::Module(&self.shader),
+                    "vs_main",
+                    Some("fs_main"),
+                )
+                .add_bind_group_layout(&self.layout)
+                .set_pixel_format(target.format)
+                .build()
+                .expect("blit pipeline is valid")
+        });
+        let bind_group = self.bind_group.get_or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some(label),
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(source),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            })
+        });
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("blit"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &*bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
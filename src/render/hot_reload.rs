@@ -0,0 +1,252 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{mpsc, Weak},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+#[cfg(not(target_arch = "wasm32"))]
+use wgpu::Device;
+use wgpu::RenderPipeline;
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::{pipeline_builder::PipelineRecipe, ErrorLog, PipelineError, RenderPipelineCache};
+
+/// A pipeline that may be replaced by a rebuilt one while in use.
+///
+/// Passes should call [`ReloadablePipeline::get`] every frame instead of
+/// keeping the pipeline around.
+#[derive(Clone, Debug)]
+pub struct ReloadablePipeline {
+    slot: Arc<Slot>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    pipeline: Mutex<RenderPipeline>,
+    #[cfg(not(target_arch = "wasm32"))]
+    source: Option<Source>,
+}
+
+/// How to rebuild a pipeline and the files that trigger it.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct Source {
+    recipe: PipelineRecipe,
+    /// The shader file and the files it includes, which may change on reloads.
+    paths: Mutex<Vec<PathBuf>>,
+}
+
+impl Slot {
+    fn pipeline(&self) -> MutexGuard<'_, RenderPipeline> {
+        self.pipeline.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl ReloadablePipeline {
+    /// The latest pipeline that built successfully.
+    pub fn get(&self) -> RenderPipeline {
+        self.slot.pipeline().clone()
+    }
+}
+
+/// A pipeline that is never reloaded.
+impl From<RenderPipeline> for ReloadablePipeline {
+    fn from(pipeline: RenderPipeline) -> Self {
+        Self {
+            slot: Arc::new(Slot {
+                pipeline: Mutex::new(pipeline),
+                #[cfg(not(target_arch = "wasm32"))]
+                source: None,
+            }),
+        }
+    }
+}
+
+/// Watches shader files and rebuilds the pipelines using them when they change.
+///
+/// Meant for development: a shader that fails to compile is logged and the
+/// last working pipeline is kept, so edits can be fixed without restarting.
+/// Pipelines are registered with
+/// [`PipelineBuilder::build_reloadable`](super::PipelineBuilder::build_reloadable)
+/// and rebuilt by [`ShaderHotReload::poll`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct ShaderHotReload {
+    inner: Mutex<Inner>,
+    errors: ErrorLog,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct Inner {
+    watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    /// Directories are watched rather than files, as editors often save by
+    /// replacing the file, which ends watches on it.
+    watched_dirs: HashSet<PathBuf>,
+    pipelines: Vec<Weak<Slot>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ShaderHotReload {
+    pub fn new() -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                watcher,
+                events,
+                watched_dirs: HashSet::new(),
+                pipelines: Vec::new(),
+            }),
+            errors: ErrorLog::new(),
+        })
+    }
+
+    /// Reports failed reloads to `errors`, until the shader builds again.
+    pub fn set_error_log(&mut self, errors: ErrorLog) {
+        self.errors = errors;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Starts rebuilding `pipeline` with `recipe` when one of `paths` changes.
+    pub(super) fn watch(
+        &self,
+        pipeline: RenderPipeline,
+        paths: Vec<PathBuf>,
+        recipe: PipelineRecipe,
+    ) -> ReloadablePipeline {
+        let mut inner = self.lock();
+        let paths = inner.watch_dirs(paths);
+        let slot = Arc::new(Slot {
+            pipeline: Mutex::new(pipeline),
+            source: Some(Source {
+                recipe,
+                paths: Mutex::new(paths),
+            }),
+        });
+        inner.pipelines.retain(|slot| slot.strong_count() > 0);
+        inner.pipelines.push(Arc::downgrade(&slot));
+        ReloadablePipeline { slot }
+    }
+
+    /// Reports that the shader file at `path` failed to build, until it
+    /// builds again.
+    pub(super) fn report(&self, path: &Path, error: &PipelineError) {
+        log::error!("Failed to build shader {}: {error}", path.display());
+        self.errors
+            .report(&path.display().to_string(), error.to_string());
+    }
+
+    /// Rebuilds the pipelines whose shader files changed since the last call
+    /// through `cache`, returns how many were replaced.
+    pub fn poll(&self, device: &Device, cache: &RenderPipelineCache) -> usize {
+        let (changed, pipelines) = {
+            let mut inner = self.lock();
+            let mut changed = HashSet::new();
+            while let Ok(event) = inner.events.try_recv() {
+                match event {
+                    Ok(event)
+                        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) =>
+                    {
+                        changed.extend(event.paths)
+                    }
+                    Ok(_) => {}
+                    Err(err) => log::warn!("Shader watcher error: {err}"),
+                }
+            }
+            inner.pipelines.retain(|slot| slot.strong_count() > 0);
+            let pipelines: Vec<_> = inner.pipelines.iter().filter_map(Weak::upgrade).collect();
+            (changed, pipelines)
+        };
+        if changed.is_empty() {
+            return 0;
+        }
+
+        let mut reloaded = 0;
+        for slot in pipelines {
+            let Some(source) = &slot.source else {
+                continue;
+            };
+            let paths = source.paths.lock().unwrap_or_else(|err| err.into_inner());
+            let Some(path) = paths.iter().find(|path| changed.contains(*path)).cloned() else {
+                continue;
+            };
+            drop(paths);
+            let (ok, paths) = reload(device, cache, &slot, &path, &source.recipe, &self.errors);
+            // Includes may have been added or removed, even by a broken edit
+            if let Some(paths) = paths {
+                let paths = self.lock().watch_dirs(paths);
+                *source.paths.lock().unwrap_or_else(|err| err.into_inner()) = paths;
+            }
+            reloaded += usize::from(ok);
+        }
+        reloaded
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Inner {
+    /// Watches the directories of `paths`, returns the paths as reported by
+    /// events.
+    fn watch_dirs(&mut self, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        let paths: Vec<_> = (paths.into_iter())
+            // Events report absolute paths with symlinks resolved
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .collect();
+        for dir in paths.iter().filter_map(|path| path.parent()) {
+            if self.watched_dirs.contains(dir) {
+                continue;
+            }
+            match self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.watched_dirs.insert(dir.to_path_buf());
+                }
+                Err(err) => log::warn!("Can't watch {} for changes: {err}", dir.display()),
+            }
+        }
+        paths
+    }
+}
+
+/// Rebuilds a pipeline from its shader files, keeping the old one on errors.
+///
+/// Returns whether the pipeline was replaced and the files the shader was read
+/// from, if it could be preprocessed. `changed` is only used for logging.
+#[cfg(not(target_arch = "wasm32"))]
+fn reload(
+    device: &Device,
+    cache: &RenderPipelineCache,
+    slot: &Slot,
+    changed: &Path,
+    recipe: &PipelineRecipe,
+    errors: &ErrorLog,
+) -> (bool, Option<Vec<PathBuf>>) {
+    let source = recipe.path().display().to_string();
+    let (pipeline, paths) = recipe.build(device, cache);
+    match pipeline {
+        Ok(pipeline) => {
+            *slot.pipeline() = pipeline;
+            errors.resolve(&source);
+            log::info!("Reloaded shader after {} changed", changed.display());
+            (true, paths)
+        }
+        Err(err) => {
+            log::error!(
+                "Failed to reload shader after {} changed: {err}",
+                changed.display()
+            );
+            errors.report(&source, err.to_string());
+            (false, paths)
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{borrow::Cow, fmt};
+
+use wgpu::{
+    AddressMode, Backend, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource,
+    CommandEncoder, Device, FilterMode, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDescriptor, Sampler, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor,
+    StoreOp, Texture, TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension,
+};
+
+use super::{PipelineBuilder, RenderPipelineCache, ShaderPreprocessor, ShaderSource};
+
+/// How each mip level is computed from the one above it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MipFilter {
+    /// Keeps one texel out of four, for pixel art and data textures.
+    Nearest,
+    /// Averages the texels, the usual choice.
+    #[default]
+    Linear,
+    /// A sharper Lanczos filter, only available on the CPU.
+    Lanczos3,
+}
+
+impl MipFilter {
+    /// Whether [`MipmapGenerator`] can use the filter.
+    pub fn gpu_supported(self) -> bool {
+        self != MipFilter::Lanczos3
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MipmapError {
+    /// The filter can only be applied on the CPU.
+    UnsupportedFilter(MipFilter),
+    /// The device can't render to or filter the format.
+    UnsupportedFormat(TextureFormat),
+    /// The texture lacks `TEXTURE_BINDING` or `RENDER_ATTACHMENT` usage.
+    MissingUsage(TextureUsages),
+    /// Only 2D textures and their arrays have their mips generated.
+    UnsupportedDimension(TextureDimension),
+}
+
+impl fmt::Display for MipmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MipmapError::UnsupportedFilter(filter) => {
+                write!(f, "mipmaps can't be generated with {filter:?} on the GPU")
+            }
+            MipmapError::UnsupportedFormat(format) => {
+                write!(f, "mipmaps can't be rendered in format {format:?}")
+            }
+            MipmapError::MissingUsage(usage) => {
+                write!(f, "texture needs {usage:?} usage to generate its mipmaps")
+            }
+            MipmapError::UnsupportedDimension(dimension) => {
+                write!(f, "mipmaps can't be generated for {dimension:?} textures")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MipmapError {}
+
+/// Fills mip levels by blitting each level into the next one.
+///
+/// Cube maps are filtered across their faces. Pipelines are created per
+/// format on first use and kept in a [`RenderPipelineCache`]. Create one
+/// generator per device and share it, see
+/// [`Graphics::mipmap_generator`](crate::graphics::Graphics::mipmap_generator).
+#[derive(Debug)]
+pub struct MipmapGenerator {
+    backend: Backend,
+    layout: BindGroupLayout,
+    cube_layout: BindGroupLayout,
+    linear: Sampler,
+    nearest: Sampler,
+    shader: ShaderModule,
+    cube_shader: ShaderModule,
+}
+
+impl MipmapGenerator {
+    /// A generator for `device`, created from an adapter of `backend`.
+    pub fn new(device: &Device, backend: Backend) -> Self {
+        let reflection = ShaderPreprocessor::new()
+            .process("blit.wgsl", include_str!("blit.wgsl"))
+            .and_then(|shader| shader.reflect("vs_main", Some("fs_main")))
+            .expect("blit shader is valid");
+        let layout = reflection
+            .create_bind_group_layouts(device, Some("mipmaps"))
+            .remove(0);
+        let sampler = |filter| {
+            device.create_sampler(&SamplerDescriptor {
+                label: Some("mipmaps"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                ..Default::default()
+            })
+        };
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("mipmaps"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("blit.wgsl"))),
+        });
+
+        let cube_blit = cube_preprocessor()
+            .process("cube_blit.wgsl", include_str!("cube_blit.wgsl"))
+            .expect("cube blit shader is valid");
+        let cube_layout = cube_blit
+            .reflect("vs_main", Some("fs_main"))
+            .expect("cube blit shader is valid")
+            .create_bind_group_layouts(device, Some("cube mipmaps"))
+            .remove(0);
+        let cube_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("cube mipmaps"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(cube_blit.source())),
+        });
+        Self {
+            backend,
+            layout,
+            cube_layout,
+            linear: sampler(FilterMode::Linear),
+            nearest: sampler(FilterMode::Nearest),
+            shader,
+            cube_shader,
+        }
+    }
+
+    /// Whether textures of `format` can have their mips generated with
+    /// `filter` on `device`.
+    pub fn supports(device: &Device, format: TextureFormat, filter: MipFilter) -> bool {
+        let features = format.guaranteed_format_features(device.features());
+        // The blit shader samples through a filtering sampler, even with
+        // nearest filtering
+        filter.gpu_supported()
+            && features
+                .allowed_usages
+                .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING)
+            && features
+                .flags
+                .contains(TextureFormatFeatureFlags::FILTERABLE)
+    }
+
+    /// Records the generation of the levels below `base_level` of every
+    /// layer of `texture` into `encoder`.
+    ///
+    /// `view_dimension` is how the texture is sampled: the faces of `Cube`
+    /// and `CubeArray` textures are filtered together, the layers of others
+    /// one by one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate(
+        &self,
+        device: &Device,
+        cache: &RenderPipelineCache,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        view_dimension: TextureViewDimension,
+        base_level: u32,
+        filter: MipFilter,
+    ) -> Result<(), MipmapError> {
+        if !filter.gpu_supported() {
+            return Err(MipmapError::UnsupportedFilter(filter));
+        }
+        let format = texture.format();
+        if !Self::supports(device, format, filter) {
+            return Err(MipmapError::UnsupportedFormat(format));
+        }
+        let usage = TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+        if !texture.usage().contains(usage) {
+            return Err(MipmapError::MissingUsage(usage - texture.usage()));
+        }
+        if texture.dimension() != TextureDimension::D2 {
+            return Err(MipmapError::UnsupportedDimension(texture.dimension()));
+        }
+
+        let size = texture.size();
+        // GL makes every square texture of 6n layers a cube map, whose layers
+        // can't be viewed as 2D textures
+        let gl_cube = self.backend == Backend::Gl
+            && size.width == size.height
+            && size.depth_or_array_layers.is_multiple_of(6);
+        let cube = gl_cube
+            || matches!(
+                view_dimension,
+                TextureViewDimension::Cube | TextureViewDimension::CubeArray
+            );
+        let (shader, layout) = if cube {
+            (&self.cube_shader, &self.cube_layout)
+        } else {
+            (&self.shader, &self.layout)
+        };
+        let pipeline = PipelineBuilder::new(device)
+            .set_label("mipmaps")
+            .set_shader_module(ShaderSource::Module(shader), "vs_main", Some("fs_main"))
+            .add_bind_group_layout(layout)
+            .set_pixel_format(format)
+            .build_cached(cache)
+            .expect("mipmap pipeline is valid");
+        let sampler = match filter {
+            MipFilter::Nearest => &self.nearest,
+            _ => &self.linear,
+        };
+        let view = |dimension, layer, layers, level| {
+            texture.create_view(&TextureViewDescriptor {
+                label: Some("mipmaps"),
+                dimension: Some(dimension),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(layers),
+                ..Default::default()
+            })
+        };
+
+        // Each source is a layer, or the 6 faces of a cube
+        let (dimension, layers) = if cube {
+            (TextureViewDimension::Cube, 6)
+        } else {
+            (TextureViewDimension::D2, 1)
+        };
+        for first in (0..size.depth_or_array_layers).step_by(layers as usize) {
+            for level in base_level + 1..texture.mip_level_count() {
+                let source = view(dimension, first, layers, level - 1);
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("mipmaps"),
+                    layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&source),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(sampler),
+                        },
+                    ],
+                });
+                for layer in 0..layers {
+                    let target = view(TextureViewDimension::D2, first + layer, 1, level);
+                    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                        label: Some("mipmaps"),
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: &target,
+                            resolve_target: None,
+                            ops: Operations {
+                                load: LoadOp::Load,
+                                store: StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                    pass.set_pipeline(&pipeline);
+                    pass.set_bind_group(0, &bind_group, &[]);
+                    // The cube blit picks the face from the vertices
+                    pass.draw(layer * 3..layer * 3 + 3, 0..1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A preprocessor able to include `cube.wgsl`.
+pub(crate) fn cube_preprocessor() -> ShaderPreprocessor {
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_source("cube.wgsl", include_str!("cube.wgsl"));
+    preprocessor
+}
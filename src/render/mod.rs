@@ -0,0 +1,42 @@
+mod buffer;
+mod depth;
+mod errors;
+mod graph;
+mod hot_reload;
+mod mipmap;
+mod overlay;
+mod pass;
+mod pipeline_builder;
+mod pipeline_cache;
+mod preprocessor;
+mod reflection;
+mod skybox;
+mod triangle;
+mod validation;
+
+pub use buffer::{BufferError, Storage, StorageBuffer, TypedBuffer, Uniform, UniformBuffer};
+pub use depth::DepthBuffer;
+pub use errors::{catch_validation_errors, ErrorLog};
+pub use graph::{
+    BufferDesc, BufferHandle, FrameTarget, GraphError, RenderGraph, RenderGraphBuilder,
+    TextureDesc, TextureHandle, TextureSize,
+};
+pub use hot_reload::ReloadablePipeline;
+#[cfg(not(target_arch = "wasm32"))]
+pub use hot_reload::ShaderHotReload;
+pub(crate) use mipmap::cube_preprocessor;
+pub use mipmap::{MipFilter, MipmapError, MipmapGenerator};
+pub use overlay::ErrorOverlay;
+pub use pass::{Pass, PassBuilder, PassContext};
+pub use pipeline_builder::{PipelineBuilder, PipelineError, ShaderSource};
+#[cfg(not(target_arch = "wasm32"))]
+pub use pipeline_cache::write_cache_file;
+pub use pipeline_cache::{
+    cache_file_identity, decode_cache_file, encode_cache_file, PipelineCacheStats,
+    RenderPipelineCache,
+};
+pub use preprocessor::{PreprocessedShader, ShaderError, ShaderPreprocessor, SourceLocation};
+pub use reflection::{LayoutMismatch, ShaderBinding, ShaderReflection};
+pub use skybox::SkyboxPass;
+pub use triangle::TrianglePass;
+pub use validation::{ShaderReport, ShaderValidator};
@@ -0,0 +1,364 @@
+use wgpu::{
+    util::{DeviceExt, TextureDataOrder},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource,
+    BlendState, ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode,
+    LoadOp, Operations, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    Sampler, SamplerDescriptor, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureViewDescriptor,
+};
+
+use super::{ErrorLog, FrameTarget, PipelineBuilder, ShaderPreprocessor, ShaderSource};
+
+/// Size of a character cell in texels, glyphs are 5x7 with spacing.
+const CELL_WIDTH: usize = 6;
+const CELL_HEIGHT: usize = 9;
+/// Texels around the text.
+const PADDING: usize = 4;
+/// Texels are drawn as squares of this many pixels.
+const SCALE: u32 = 2;
+/// Pixels between the overlay and the edges of the frame.
+const MARGIN: u32 = 8;
+const BACKGROUND: [u8; 4] = [96, 0, 0, 224];
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// Draws the errors of an [`ErrorLog`] over the frame, so they show up
+/// without looking at the terminal.
+///
+/// The text is only rasterized again when the errors or the frame width
+/// change.
+#[derive(Debug)]
+pub struct ErrorOverlay {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    format: TextureFormat,
+    pipeline: RenderPipeline,
+    text: Option<OverlayText>,
+}
+
+/// The errors rasterized into a texture.
+#[derive(Debug)]
+struct OverlayText {
+    generation: u64,
+    columns: usize,
+    rows: usize,
+    width: u32,
+    height: u32,
+    bind_group: BindGroup,
+}
+
+impl ErrorOverlay {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        // The overlay is a blit of the text confined to a viewport
+        let reflection = ShaderPreprocessor::new()
+            .process("blit.wgsl", include_str!("blit.wgsl"))
+            .and_then(|shader| shader.reflect("vs_main", Some("fs_main")))
+            .expect("blit shader is valid");
+        let layout = reflection
+            .create_bind_group_layouts(device, Some("error overlay"))
+            .remove(0);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("error overlay"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+        let pipeline = create_pipeline(device, &layout, format);
+        Self {
+            layout,
+            sampler,
+            format,
+            pipeline,
+            text: None,
+        }
+    }
+
+    /// Draws the current errors of `errors` onto `target`, if there are any.
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        target: &FrameTarget,
+        errors: &ErrorLog,
+    ) {
+        if errors.is_empty() {
+            self.text = None;
+            return;
+        }
+        let available = |size: u32| (size.saturating_sub(2 * MARGIN) / SCALE) as usize;
+        let columns = available(target.width).saturating_sub(2 * PADDING) / CELL_WIDTH;
+        let rows = available(target.height).saturating_sub(2 * PADDING) / CELL_HEIGHT;
+        if columns == 0 || rows == 0 {
+            return;
+        }
+        if target.format != self.format {
+            self.format = target.format;
+            self.pipeline = create_pipeline(device, &self.layout, target.format);
+        }
+
+        let generation = errors.generation();
+        let outdated = self.text.as_ref().is_none_or(|text| {
+            (text.generation, text.columns, text.rows) != (generation, columns, rows)
+        });
+        if outdated {
+            let lines = layout_text(&errors.errors(), columns, rows);
+            let (width, height, bind_group) = self.rasterize(device, queue, &lines);
+            self.text = Some(OverlayText {
+                generation,
+                columns,
+                rows,
+                width,
+                height,
+                bind_group,
+            });
+        }
+        let Some(text) = &self.text else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("error overlay"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_viewport(
+            MARGIN as f32,
+            MARGIN as f32,
+            (text.width * SCALE) as f32,
+            (text.height * SCALE) as f32,
+            0.0,
+            1.0,
+        );
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &text.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Draws `lines` into a texture, returns its size and the bind group
+    /// sampling it.
+    fn rasterize(&self, device: &Device, queue: &Queue, lines: &[String]) -> (u32, u32, BindGroup) {
+        let used_columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let width = used_columns * CELL_WIDTH + 2 * PADDING;
+        let height = lines.len() * CELL_HEIGHT + 2 * PADDING;
+        let mut texels = BACKGROUND.repeat(width * height);
+        for (row, line) in lines.iter().enumerate() {
+            for (column, c) in line.bytes().enumerate() {
+                let glyph = &FONT[(c - b' ') as usize];
+                for (y, bits) in glyph.iter().enumerate() {
+                    for x in 0..5 {
+                        if bits & (0x10 >> x) == 0 {
+                            continue;
+                        }
+                        let x = PADDING + column * CELL_WIDTH + x;
+                        let y = PADDING + row * CELL_HEIGHT + y;
+                        let index = (y * width + x) * 4;
+                        texels[index..index + 4].copy_from_slice(&FOREGROUND);
+                    }
+                }
+            }
+        }
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("error overlay"),
+                size: Extent3d {
+                    width: width as u32,
+                    height: height as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &texels,
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("error overlay"),
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        (width as u32, height as u32, bind_group)
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    layout: &BindGroupLayout,
+    format: TextureFormat,
+) -> RenderPipeline {
+    PipelineBuilder::new(device)
+        .set_label("error overlay")
+        .set_shader_module(
+            ShaderSource::Wgsl(include_str!("blit.wgsl")),
+            "vs_main",
+            Some("fs_main"),
+        )
+        .add_bind_group_layout(layout)
+        .add_color_target(ColorTargetState {
+            format,
+            blend: Some(BlendState::ALPHA_BLENDING),
+            write_mask: ColorWrites::ALL,
+        })
+        .build()
+        .expect("error overlay pipeline is valid")
+}
+
+/// Wraps the messages into at most `rows` lines of `columns` printable ASCII
+/// characters, with an empty line between messages.
+fn layout_text(errors: &[(String, String)], columns: usize, rows: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (index, (_, message)) in errors.iter().enumerate() {
+        if index > 0 {
+            lines.push(String::new());
+        }
+        for line in message.lines() {
+            let line: Vec<char> = (line.replace('\t', "    ").chars())
+                .map(|c| {
+                    if c == ' ' || c.is_ascii_graphic() {
+                        c
+                    } else {
+                        '?'
+                    }
+                })
+                .collect();
+            if line.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(line.chunks(columns).map(|chunk| chunk.iter().collect()));
+        }
+    }
+    if lines.len() > rows {
+        lines.truncate(rows);
+        if let Some(last) = lines.last_mut() {
+            *last = "...".chars().take(columns).collect();
+        }
+    }
+    lines
+}
+
+/// 5x7 glyphs of the printable ASCII characters, starting at the space. Rows
+/// go from top to bottom, the leftmost pixel is bit 4.
+#[rustfmt::skip]
+const FONT: [[u8; 7]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x04], // !
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // &
+    [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // @
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F], // a
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E], // b
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E], // c
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F], // d
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E], // e
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08], // f
+    [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // g
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // h
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E], // i
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C], // j
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // k
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // l
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11], // m
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // n
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E], // o
+    [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10], // p
+    [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01], // q
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // r
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E], // s
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06], // t
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D], // u
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04], // v
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A], // w
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11], // x
+    [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E], // y
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F], // z
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // {
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // |
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // }
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // ~
+];
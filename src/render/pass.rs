@@ -0,0 +1,142 @@
+use std::cell::Cell;
+
+use wgpu::{
+    Buffer, Color, CommandEncoder, Device, Operations, Queue, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, Texture, TextureView,
+};
+
+use super::{
+    depth::DepthBuffer,
+    graph::{BufferHandle, Resources, TextureHandle},
+};
+
+/// A step of a frame, recorded by a [`RenderGraph`](super::RenderGraph).
+pub trait Pass {
+    /// Name used in logs, debug labels and errors.
+    fn name(&self) -> &str;
+
+    /// Declares the resources the pass reads and writes.
+    ///
+    /// Called once when the pass is added to a graph, the declarations decide
+    /// the order passes run in and how long transient resources live.
+    fn setup(&self, builder: &mut PassBuilder);
+
+    /// Records the pass' commands.
+    fn execute(&mut self, ctx: &mut PassContext);
+}
+
+/// Collects the resource accesses of a pass.
+#[derive(Debug, Default)]
+pub struct PassBuilder {
+    pub(super) texture_reads: Vec<TextureHandle>,
+    pub(super) texture_writes: Vec<TextureHandle>,
+    pub(super) buffer_reads: Vec<BufferHandle>,
+    pub(super) buffer_writes: Vec<BufferHandle>,
+}
+
+impl PassBuilder {
+    /// The pass samples or copies from `texture`.
+    pub fn read_texture(&mut self, texture: TextureHandle) -> &mut Self {
+        self.texture_reads.push(texture);
+        self
+    }
+
+    /// The pass renders into `texture`.
+    ///
+    /// Transient textures may share memory with others that aren't alive at
+    /// the same time, so the first pass writing one should clear it.
+    pub fn write_texture(&mut self, texture: TextureHandle) -> &mut Self {
+        self.texture_writes.push(texture);
+        self
+    }
+
+    pub fn read_buffer(&mut self, buffer: BufferHandle) -> &mut Self {
+        self.buffer_reads.push(buffer);
+        self
+    }
+
+    pub fn write_buffer(&mut self, buffer: BufferHandle) -> &mut Self {
+        self.buffer_writes.push(buffer);
+        self
+    }
+}
+
+/// What a pass can access while recording.
+///
+/// Resources borrow from the graph rather than the context, so they can be
+/// used while recording with `encoder`.
+pub struct PassContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub encoder: &'a mut CommandEncoder,
+    /// The frame's depth buffer, attached with
+    /// [`PassContext::depth_attachment`].
+    pub depth: Option<&'a DepthBuffer>,
+    pub(super) sample_count: u32,
+    pub(super) resources: &'a Resources,
+    /// Whether a pass of the frame attached the depth buffer yet.
+    pub(super) depth_cleared: &'a Cell<bool>,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn texture(&self, handle: TextureHandle) -> &'a Texture {
+        self.resources.texture(handle)
+    }
+
+    /// A view of the whole texture.
+    pub fn texture_view(&self, handle: TextureHandle) -> &'a TextureView {
+        self.resources.texture_view(handle)
+    }
+
+    /// Samples per pixel of the frame's color and depth targets.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Draws into `handle`, through a multisampled texture of the same size
+    /// and format resolving into it when MSAA is on.
+    ///
+    /// The multisampled texture keeps its contents from the first pass
+    /// writing `handle` to the last, so passes loading it see what earlier
+    /// ones drew.
+    ///
+    /// Pipelines drawing with it need [`PassContext::sample_count`] samples,
+    /// which those built with
+    /// [`PipelineBuilder::build_cached`](super::PipelineBuilder::build_cached)
+    /// on the frame's cache have.
+    pub fn color_attachment(
+        &self,
+        handle: TextureHandle,
+        ops: Operations<Color>,
+    ) -> RenderPassColorAttachment<'a> {
+        let view = self.texture_view(handle);
+        match self.resources.multisampled_view(handle) {
+            Some(multisampled) => RenderPassColorAttachment {
+                view: multisampled,
+                resolve_target: Some(view),
+                ops,
+            },
+            None => RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops,
+            },
+        }
+    }
+
+    /// Attaches the frame's depth buffer, cleared by the first pass of the
+    /// frame attaching it and loaded with what earlier passes drew by the
+    /// rest.
+    pub fn depth_attachment(&self) -> Option<RenderPassDepthStencilAttachment<'a>> {
+        let depth = self.depth?;
+        Some(if self.depth_cleared.replace(true) {
+            depth.attachment()
+        } else {
+            depth.clear_attachment()
+        })
+    }
+
+    pub fn buffer(&self, handle: BufferHandle) -> &'a Buffer {
+        self.resources.buffer(handle)
+    }
+}
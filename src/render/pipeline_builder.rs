@@ -0,0 +1,978 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use naga::valid::Capabilities;
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutEntry, BlendState, BufferAddress, ColorTargetState,
+    ColorWrites, CompareFunction, DepthStencilState, Device, Face, Features, FragmentState,
+    FrontFace, IndexFormat, MultisampleState, PipelineCache, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
+    ShaderModuleDescriptor, TextureFormat, TextureFormatFeatureFlags, VertexAttribute,
+    VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::hot_reload::{ReloadablePipeline, ShaderHotReload};
+use super::{
+    catch_validation_errors, DepthBuffer, LayoutMismatch, PreprocessedShader, RenderPipelineCache,
+    ShaderError, ShaderPreprocessor, ShaderReflection,
+};
+
+pub enum ShaderSource<'a> {
+    /// WGSL source code, preprocessed and compiled when the pipeline is built.
+    Wgsl(&'a str),
+    /// A WGSL file, read and preprocessed when the pipeline is built. Not
+    /// supported on the web.
+    Path(&'a Path),
+    /// WGSL that was preprocessed already, shader defs of the builder don't
+    /// apply to it.
+    Preprocessed(&'a PreprocessedShader),
+    /// An already compiled module.
+    Module(&'a ShaderModule),
+}
+
+/// A shader source with files read and preprocessed.
+enum LoadedShader<'a> {
+    Wgsl(Cow<'a, PreprocessedShader>, ShaderReflection),
+    Module(ShaderModule),
+}
+
+/// A vertex buffer layout owning its attributes.
+type OwnedVertexBufferLayout = (BufferAddress, VertexStepMode, Vec<VertexAttribute>);
+
+/// Everything that ends up in a pipeline descriptor except labels, which
+/// [`RenderPipelineCache`] shares pipelines by.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct PipelineKey {
+    shader: ShaderKey,
+    vert_main: String,
+    frag_main: Option<String>,
+    vertex_buffer_layouts: Vec<OwnedVertexBufferLayout>,
+    bind_group_layouts: Vec<BindGroupLayout>,
+    color_targets: Vec<Option<ColorTargetState>>,
+    primitive: PrimitiveState,
+    depth_stencil: Option<DepthStencilState>,
+    multisample: MultisampleState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ShaderKey {
+    /// Preprocessed WGSL source.
+    Wgsl(String),
+    Module(ShaderModule),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// [`PipelineBuilder::set_shader_module`] was never called.
+    MissingShader,
+    /// There is neither a color target nor a depth-stencil state to draw into.
+    NoTargets,
+    /// A color target option was set before any color target was added.
+    NoColorTarget,
+    /// A depth option was set before the depth-stencil state.
+    NoDepthStencil,
+    TooManyColorTargets {
+        count: usize,
+        max: u32,
+    },
+    /// A color target uses a depth or stencil format.
+    NotColorFormat(TextureFormat),
+    /// The depth-stencil state uses a color format.
+    NotDepthFormat(TextureFormat),
+    /// Blending was enabled for a format that can't be blended.
+    NotBlendable(TextureFormat),
+    /// Sample counts have to be supported by every target format.
+    UnsupportedSampleCount {
+        count: u32,
+        format: TextureFormat,
+    },
+    /// Strip index formats only apply to strip topologies.
+    StripIndexFormat(PrimitiveTopology),
+    /// The pipeline state needs a feature the device wasn't created with.
+    MissingFeature(Features),
+    /// The shader couldn't be read, preprocessed or compiled.
+    Shader(ShaderError),
+    /// The shader doesn't match the layouts, vertex buffers or targets.
+    LayoutMismatch(LayoutMismatch),
+    /// wgpu rejected the shader module or pipeline, for problems the checks
+    /// above don't catch.
+    Device {
+        label: Option<String>,
+        message: String,
+    },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::MissingShader => write!(f, "no shader module was set"),
+            PipelineError::NoTargets => {
+                write!(
+                    f,
+                    "pipeline has neither color targets nor a depth-stencil state"
+                )
+            }
+            PipelineError::NoColorTarget => {
+                write!(f, "color target options set before adding a color target")
+            }
+            PipelineError::NoDepthStencil => {
+                write!(f, "depth options set before the depth-stencil state")
+            }
+            PipelineError::TooManyColorTargets { count, max } => {
+                write!(f, "{count} color targets exceed the device limit of {max}")
+            }
+            PipelineError::NotColorFormat(format) => {
+                write!(f, "{format:?} can't be used as a color target")
+            }
+            PipelineError::NotDepthFormat(format) => {
+                write!(f, "{format:?} is not a depth or stencil format")
+            }
+            PipelineError::NotBlendable(format) => write!(f, "{format:?} can't be blended"),
+            PipelineError::UnsupportedSampleCount { count, format } => {
+                write!(f, "{format:?} doesn't support {count} samples")
+            }
+            PipelineError::StripIndexFormat(topology) => {
+                write!(
+                    f,
+                    "strip index format set for non-strip topology {topology:?}"
+                )
+            }
+            PipelineError::MissingFeature(features) => {
+                write!(f, "pipeline requires device features {features:?}")
+            }
+            PipelineError::Shader(error) => write!(f, "{error}"),
+            PipelineError::LayoutMismatch(mismatch) => write!(f, "{mismatch}"),
+            PipelineError::Device {
+                label: Some(label),
+                message,
+            } => write!(f, "pipeline \"{label}\" is invalid: {message}"),
+            PipelineError::Device {
+                label: None,
+                message,
+            } => write!(f, "pipeline is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Shader(error) => Some(error),
+            PipelineError::LayoutMismatch(mismatch) => Some(mismatch),
+            _ => None,
+        }
+    }
+}
+
+/// Builds render pipelines from state set step by step.
+///
+/// Defaults match wgpu's: triangle lists, counter-clockwise front faces, no
+/// culling, no blending, no depth-stencil state and one sample, or the
+/// frame's sample count for builders created with
+/// [`PipelineBuilder::with_sample_count`].
+pub struct PipelineBuilder<'a> {
+    label: Option<&'a str>,
+    shader_source: Option<ShaderSource<'a>>,
+    preprocessor: Option<&'a ShaderPreprocessor>,
+    shader_defs: Vec<(&'a str, &'a str)>,
+    vert_main: String,
+    frag_main: Option<String>,
+    vertex_buffer_layouts: Vec<VertexBufferLayout<'a>>,
+    bind_group_layouts: Vec<&'a BindGroupLayout>,
+    /// Entries of the bind group layouts, if known, to check them against
+    /// the shader.
+    bind_group_entries: Vec<Option<&'a [BindGroupLayoutEntry]>>,
+    color_targets: Vec<Option<ColorTargetState>>,
+    primitive: PrimitiveState,
+    depth_stencil: Option<DepthStencilState>,
+    multisample: MultisampleState,
+    /// Sample count pipelines get unless they set one, kept across builds.
+    sample_count: u32,
+    /// First invalid option set, reported by `build`.
+    error: Option<PipelineError>,
+    device: &'a Device,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn new(device: &'a Device) -> Self {
+        Self::with_sample_count(device, 1)
+    }
+
+    /// A builder whose pipelines draw with `sample_count` samples unless they
+    /// set their own, usually the frame's from
+    /// [`Graphics::pipeline_builder`](crate::graphics::Graphics::pipeline_builder)
+    /// or [`RenderPipelineCache::sample_count`].
+    pub fn with_sample_count(device: &'a Device, sample_count: u32) -> Self {
+        Self {
+            label: None,
+            shader_source: None,
+            preprocessor: None,
+            shader_defs: Vec::new(),
+            vert_main: String::new(),
+            frag_main: None,
+            vertex_buffer_layouts: Vec::new(),
+            bind_group_layouts: Vec::new(),
+            bind_group_entries: Vec::new(),
+            color_targets: Vec::new(),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: sample_count,
+                ..MultisampleState::default()
+            },
+            sample_count,
+            error: None,
+            device,
+        }
+    }
+
+    /// Puts every option back to its default, as after creating the builder.
+    fn reset(&mut self) {
+        *self = Self::with_sample_count(self.device, self.sample_count);
+    }
+
+    pub fn set_label(&mut self, label: &'a str) -> &mut Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn add_vertex_buffer_layout(&mut self, layout: VertexBufferLayout<'a>) -> &mut Self {
+        self.vertex_buffer_layouts.push(layout);
+        self
+    }
+
+    pub fn add_vertex_buffer_layouts(&mut self, layouts: &[VertexBufferLayout<'a>]) -> &mut Self {
+        self.vertex_buffer_layouts.extend_from_slice(layouts);
+        self
+    }
+
+    /// Adds a bind group layout to the pipeline layout.
+    ///
+    /// Without any, the layouts are generated from the bindings of WGSL
+    /// shaders, see [`ShaderReflection`], or derived by wgpu for compiled
+    /// modules.
+    pub fn add_bind_group_layout(&mut self, layout: &'a BindGroupLayout) -> &mut Self {
+        self.bind_group_layouts.push(layout);
+        self.bind_group_entries.push(None);
+        self
+    }
+
+    pub fn add_bind_group_layouts(&mut self, layouts: &[&'a BindGroupLayout]) -> &mut Self {
+        for layout in layouts {
+            self.add_bind_group_layout(layout);
+        }
+        self
+    }
+
+    /// Adds a bind group layout created from `entries`, which are checked
+    /// against the bindings of WGSL shaders when the pipeline is built.
+    pub fn add_bind_group_layout_with_entries(
+        &mut self,
+        layout: &'a BindGroupLayout,
+        entries: &'a [BindGroupLayoutEntry],
+    ) -> &mut Self {
+        self.bind_group_layouts.push(layout);
+        self.bind_group_entries.push(Some(entries));
+        self
+    }
+
+    /// Without a fragment entry point only depth and stencil are written.
+    pub fn set_shader_module(
+        &mut self,
+        shader_source: ShaderSource<'a>,
+        vert_main: &str,
+        frag_main: Option<&str>,
+    ) -> &mut Self {
+        self.shader_source = Some(shader_source);
+        self.vert_main = vert_main.to_string();
+        self.frag_main = frag_main.map(str::to_string);
+        self
+    }
+
+    /// Preprocesses WGSL shaders with the defines, include directories and
+    /// sources of `preprocessor`.
+    pub fn set_preprocessor(&mut self, preprocessor: &'a ShaderPreprocessor) -> &mut Self {
+        self.preprocessor = Some(preprocessor);
+        self
+    }
+
+    /// Defines `name` while preprocessing the shader, on top of the defines of
+    /// the preprocessor. Each set of defs builds a separate permutation.
+    pub fn add_shader_def(&mut self, name: &'a str, value: &'a str) -> &mut Self {
+        self.shader_defs.push((name, value));
+        self
+    }
+
+    /// Replaces the color targets with a single one of `format`.
+    pub fn set_pixel_format(&mut self, format: TextureFormat) -> &mut Self {
+        self.color_targets.clear();
+        self.add_color_target(format)
+    }
+
+    /// Adds a color target, written to by the next fragment shader output location.
+    pub fn add_color_target(&mut self, target: impl Into<ColorTargetState>) -> &mut Self {
+        self.color_targets.push(Some(target.into()));
+        self
+    }
+
+    /// Skips a fragment shader output location.
+    pub fn add_empty_color_target(&mut self) -> &mut Self {
+        self.color_targets.push(None);
+        self
+    }
+
+    /// Sets the blend state of the last added color target.
+    pub fn set_blend(&mut self, blend: Option<BlendState>) -> &mut Self {
+        self.last_color_target(|target| target.blend = blend)
+    }
+
+    /// Sets the channels written to the last added color target.
+    pub fn set_write_mask(&mut self, write_mask: ColorWrites) -> &mut Self {
+        self.last_color_target(|target| target.write_mask = write_mask)
+    }
+
+    fn last_color_target(&mut self, f: impl FnOnce(&mut ColorTargetState)) -> &mut Self {
+        match self.color_targets.last_mut() {
+            Some(Some(target)) => f(target),
+            _ => self.fail(PipelineError::NoColorTarget),
+        }
+        self
+    }
+
+    pub fn set_topology(&mut self, topology: PrimitiveTopology) -> &mut Self {
+        self.primitive.topology = topology;
+        self
+    }
+
+    /// Index format used to restart strips, required for indexed strip draws.
+    pub fn set_strip_index_format(&mut self, format: Option<IndexFormat>) -> &mut Self {
+        self.primitive.strip_index_format = format;
+        self
+    }
+
+    pub fn set_front_face(&mut self, front_face: FrontFace) -> &mut Self {
+        self.primitive.front_face = front_face;
+        self
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<Face>) -> &mut Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    /// Anything other than `Fill` requires `Features::POLYGON_MODE_LINE` or
+    /// `Features::POLYGON_MODE_POINT`.
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) -> &mut Self {
+        self.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Requires `Features::DEPTH_CLIP_CONTROL`.
+    pub fn set_unclipped_depth(&mut self, unclipped_depth: bool) -> &mut Self {
+        self.primitive.unclipped_depth = unclipped_depth;
+        self
+    }
+
+    /// Requires `Features::CONSERVATIVE_RASTERIZATION`.
+    pub fn set_conservative(&mut self, conservative: bool) -> &mut Self {
+        self.primitive.conservative = conservative;
+        self
+    }
+
+    pub fn set_depth_stencil(&mut self, depth_stencil: Option<DepthStencilState>) -> &mut Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// Tests against a depth buffer of `format`, without stencil or depth bias.
+    pub fn set_depth(
+        &mut self,
+        format: TextureFormat,
+        write_enabled: bool,
+        compare: CompareFunction,
+    ) -> &mut Self {
+        self.set_depth_stencil(Some(DepthStencilState {
+            format,
+            depth_write_enabled: write_enabled,
+            depth_compare: compare,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }))
+    }
+
+    /// Tests against `depth`, flipping `compare` if it uses reverse-Z.
+    pub fn set_depth_buffer(
+        &mut self,
+        depth: &DepthBuffer,
+        write_enabled: bool,
+        compare: CompareFunction,
+    ) -> &mut Self {
+        self.set_depth_stencil(Some(depth.depth_stencil_state(write_enabled, compare)))
+    }
+
+    /// Whether passing fragments write their depth, after the depth-stencil
+    /// state was set.
+    pub fn set_depth_write_enabled(&mut self, write_enabled: bool) -> &mut Self {
+        self.depth_state(|state| state.depth_write_enabled = write_enabled)
+    }
+
+    /// Replaces the depth test, after the depth-stencil state was set.
+    pub fn set_depth_compare(&mut self, compare: CompareFunction) -> &mut Self {
+        self.depth_state(|state| state.depth_compare = compare)
+    }
+
+    fn depth_state(&mut self, f: impl FnOnce(&mut DepthStencilState)) -> &mut Self {
+        match &mut self.depth_stencil {
+            Some(state) => f(state),
+            None => self.fail(PipelineError::NoDepthStencil),
+        }
+        self
+    }
+
+    pub fn set_sample_count(&mut self, count: u32) -> &mut Self {
+        self.multisample.count = count;
+        self
+    }
+
+    pub fn set_multisample(&mut self, multisample: MultisampleState) -> &mut Self {
+        self.multisample = multisample;
+        self
+    }
+
+    fn fail(&mut self, error: PipelineError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Checks the state against the device, so invalid pipelines are reported
+    /// here instead of through wgpu's validation errors.
+    fn validate(&self) -> Result<(), PipelineError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        if self.shader_source.is_none() {
+            return Err(PipelineError::MissingShader);
+        }
+        let color_targets: Vec<_> = self.color_targets.iter().flatten().collect();
+        if self.frag_main.is_some() && color_targets.is_empty() && self.depth_stencil.is_none() {
+            return Err(PipelineError::NoTargets);
+        }
+        let max = self.device.limits().max_color_attachments;
+        if self.frag_main.is_some() && self.color_targets.len() > max as usize {
+            return Err(PipelineError::TooManyColorTargets {
+                count: self.color_targets.len(),
+                max,
+            });
+        }
+
+        let features = self.device.features();
+        let count = self.multisample.count;
+        // Counts beyond the guaranteed ones are checked by wgpu against what
+        // the adapter supports
+        let adapter_specific =
+            features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let check_samples = |format: TextureFormat| {
+            let flags = format.guaranteed_format_features(features).flags;
+            let supported = if adapter_specific {
+                count.is_power_of_two() && count <= 16
+            } else {
+                flags.sample_count_supported(count)
+            };
+            if supported {
+                Ok(())
+            } else {
+                Err(PipelineError::UnsupportedSampleCount { count, format })
+            }
+        };
+        for target in &color_targets {
+            if !target.format.has_color_aspect() {
+                return Err(PipelineError::NotColorFormat(target.format));
+            }
+            let flags = target.format.guaranteed_format_features(features).flags;
+            if target.blend.is_some() && !flags.contains(TextureFormatFeatureFlags::BLENDABLE) {
+                return Err(PipelineError::NotBlendable(target.format));
+            }
+            check_samples(target.format)?;
+        }
+        if let Some(depth_stencil) = &self.depth_stencil {
+            if !depth_stencil.format.is_depth_stencil_format() {
+                return Err(PipelineError::NotDepthFormat(depth_stencil.format));
+            }
+            check_samples(depth_stencil.format)?;
+        }
+
+        let primitive = &self.primitive;
+        if primitive.strip_index_format.is_some() && !primitive.topology.is_strip() {
+            return Err(PipelineError::StripIndexFormat(primitive.topology));
+        }
+        let mut required = Features::empty();
+        required.set(
+            Features::POLYGON_MODE_LINE,
+            primitive.polygon_mode == PolygonMode::Line,
+        );
+        required.set(
+            Features::POLYGON_MODE_POINT,
+            primitive.polygon_mode == PolygonMode::Point,
+        );
+        required.set(Features::DEPTH_CLIP_CONTROL, primitive.unclipped_depth);
+        required.set(Features::CONSERVATIVE_RASTERIZATION, primitive.conservative);
+        if !features.contains(required) {
+            return Err(PipelineError::MissingFeature(required - features));
+        }
+        Ok(())
+    }
+
+    /// Errors wgpu reports while creating the shader module and pipeline are
+    /// returned instead of reaching the uncaptured error handler.
+    ///
+    /// Every option is reset to its default afterwards, whether or not the
+    /// build succeeded, so the builder starts over for the next pipeline.
+    pub fn build(&mut self) -> Result<RenderPipeline, PipelineError> {
+        let result = self.prepare().and_then(|shader| {
+            let (shader, reflection) = match shader {
+                LoadedShader::Module(module) => (module, None),
+                LoadedShader::Wgsl(shader, reflection) => {
+                    let module = catch_validation_errors(self.device, || {
+                        self.device.create_shader_module(ShaderModuleDescriptor {
+                            label: self.label,
+                            source: wgpu::ShaderSource::Wgsl(shader.source().into()),
+                        })
+                    })
+                    .map_err(|err| self.device_error(err))?;
+                    (module, Some(reflection))
+                }
+            };
+            self.create(&shader, reflection.as_ref(), None)
+        });
+        self.reset();
+        result
+    }
+
+    /// Like [`PipelineBuilder::build`], but returns the pipeline from `cache`
+    /// if an identical one was built before.
+    ///
+    /// Labels are not part of the description, a cached pipeline keeps the
+    /// label it was first built with. Resets the builder like `build`.
+    pub fn build_cached(
+        &mut self,
+        cache: &RenderPipelineCache,
+    ) -> Result<RenderPipeline, PipelineError> {
+        let result = self.prepare().and_then(|shader| {
+            let key = self.key(&shader);
+            cache.get_or_try_create(key, || {
+                let (shader, reflection) = match shader {
+                    LoadedShader::Module(module) => (module, None),
+                    LoadedShader::Wgsl(shader, reflection) => (
+                        cache
+                            .shader_module(self.device, self.label, shader.source())
+                            .map_err(|err| self.device_error(err))?,
+                        Some(reflection),
+                    ),
+                };
+                self.create(&shader, reflection.as_ref(), cache.driver_cache())
+            })
+        });
+        self.reset();
+        result
+    }
+
+    /// Builds a pipeline through `cache` that is rebuilt by `hot_reload`
+    /// whenever its shader file or a file it includes changes, which requires
+    /// a [`ShaderSource::Path`] shader.
+    ///
+    /// Other shader sources give a pipeline that never changes. Resets the
+    /// builder like `build`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_reloadable(
+        &mut self,
+        cache: &RenderPipelineCache,
+        hot_reload: &ShaderHotReload,
+    ) -> Result<ReloadablePipeline, PipelineError> {
+        self.build_reloadable_with(cache, hot_reload, |_, err| Err(err))
+    }
+
+    /// Like [`PipelineBuilder::build_reloadable`], but a shader file that
+    /// doesn't build is still watched, with the pipeline from `fallback` used
+    /// until an edit fixes it.
+    ///
+    /// The error is logged and reported to the error log of `hot_reload`.
+    /// Invalid builder state is still returned as an error, as editing the
+    /// file can't fix it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_reloadable_or(
+        &mut self,
+        cache: &RenderPipelineCache,
+        hot_reload: &ShaderHotReload,
+        fallback: impl FnOnce() -> RenderPipeline,
+    ) -> Result<ReloadablePipeline, PipelineError> {
+        self.build_reloadable_with(cache, hot_reload, |path, err| {
+            hot_reload.report(path, &err);
+            Ok(fallback())
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn build_reloadable_with(
+        &mut self,
+        cache: &RenderPipelineCache,
+        hot_reload: &ShaderHotReload,
+        on_error: impl FnOnce(&Path, PipelineError) -> Result<RenderPipeline, PipelineError>,
+    ) -> Result<ReloadablePipeline, PipelineError> {
+        let Some(&ShaderSource::Path(path)) = self.shader_source.as_ref() else {
+            return self.build_cached(cache).map(ReloadablePipeline::from);
+        };
+        // Deferred errors aren't part of the recipe
+        let valid = self.validate();
+        let recipe = self.recipe(path);
+        self.reset();
+        valid?;
+        let (pipeline, paths) = recipe.build(self.device, cache);
+        let pipeline = pipeline.or_else(|err| on_error(path, err))?;
+        let paths = paths.unwrap_or_else(|| vec![path.to_path_buf()]);
+        Ok(hot_reload.watch(pipeline, paths, recipe))
+    }
+
+    /// Validates the state and loads the shader.
+    fn prepare(&self) -> Result<LoadedShader<'a>, PipelineError> {
+        self.validate().and_then(|()| self.load_shader())
+    }
+
+    fn load_shader(&self) -> Result<LoadedShader<'a>, PipelineError> {
+        let default = ShaderPreprocessor::new();
+        let preprocessor = self.preprocessor.unwrap_or(&default);
+        let shader = match self.shader_source.as_ref().unwrap() {
+            ShaderSource::Module(module) => return Ok(LoadedShader::Module((*module).clone())),
+            ShaderSource::Preprocessed(shader) => Cow::Borrowed(*shader),
+            ShaderSource::Wgsl(source) => Cow::Owned(
+                preprocessor
+                    .process_with(self.label.unwrap_or("shader"), source, &self.shader_defs)
+                    .map_err(PipelineError::Shader)?,
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            ShaderSource::Path(path) => Cow::Owned(
+                preprocessor
+                    .process_file_with(path, &self.shader_defs)
+                    .map_err(PipelineError::Shader)?,
+            ),
+            // Files can only be fetched asynchronously in browsers
+            #[cfg(target_arch = "wasm32")]
+            ShaderSource::Path(path) => {
+                return Err(PipelineError::Shader(ShaderError::Read {
+                    path: path.to_path_buf(),
+                    error: "reading files is not supported on the web".to_string(),
+                }))
+            }
+        };
+        // Compile errors from wgpu point into the preprocessed source, naga's
+        // point into the original files. Validating with what the device
+        // supports leaves wgpu little to reject.
+        let (module, info) = shader
+            .validate_with(shader_capabilities(self.device.features()))
+            .map_err(PipelineError::Shader)?;
+        let reflection =
+            ShaderReflection::new(&module, &info, &self.vert_main, self.frag_main.as_deref())
+                .map_err(PipelineError::Shader)?;
+        self.check(&reflection)
+            .map_err(PipelineError::LayoutMismatch)?;
+        Ok(LoadedShader::Wgsl(shader, reflection))
+    }
+
+    /// Checks the layouts with known entries, vertex buffers and color
+    /// targets against the shader.
+    fn check(&self, reflection: &ShaderReflection) -> Result<(), LayoutMismatch> {
+        if !self.bind_group_layouts.is_empty() {
+            let groups = self.bind_group_layouts.len() as u32;
+            if reflection.group_count() > groups {
+                return Err(LayoutMismatch::MissingGroup(groups));
+            }
+            for (group, entries) in self.bind_group_entries.iter().enumerate() {
+                if let Some(entries) = entries {
+                    reflection.check_bind_group_layout(group as u32, entries)?;
+                }
+            }
+        }
+        reflection.check_vertex_buffers(&self.vertex_buffer_layouts)?;
+        if self.frag_main.is_some() {
+            reflection.check_color_targets(&self.color_targets)?;
+        }
+        Ok(())
+    }
+
+    /// A copy of the state that doesn't borrow anything, except the shader.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recipe(&self, path: &Path) -> PipelineRecipe {
+        PipelineRecipe {
+            label: self.label.map(str::to_string),
+            path: path.to_path_buf(),
+            preprocessor: self.preprocessor.cloned().unwrap_or_default(),
+            shader_defs: (self.shader_defs.iter())
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            vert_main: self.vert_main.clone(),
+            frag_main: self.frag_main.clone(),
+            vertex_buffer_layouts: self.owned_vertex_buffer_layouts(),
+            bind_group_layouts: self.bind_group_layouts.iter().copied().cloned().collect(),
+            bind_group_entries: (self.bind_group_entries.iter())
+                .map(|entries| entries.map(<[_]>::to_vec))
+                .collect(),
+            color_targets: self.color_targets.clone(),
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: self.multisample,
+        }
+    }
+
+    fn owned_vertex_buffer_layouts(&self) -> Vec<OwnedVertexBufferLayout> {
+        (self.vertex_buffer_layouts.iter())
+            .map(|layout| {
+                (
+                    layout.array_stride,
+                    layout.step_mode,
+                    layout.attributes.to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    fn key(&self, shader: &LoadedShader) -> PipelineKey {
+        PipelineKey {
+            shader: match shader {
+                LoadedShader::Wgsl(shader, _) => ShaderKey::Wgsl(shader.source().to_string()),
+                LoadedShader::Module(module) => ShaderKey::Module(module.clone()),
+            },
+            vert_main: self.vert_main.clone(),
+            frag_main: self.frag_main.clone(),
+            vertex_buffer_layouts: self.owned_vertex_buffer_layouts(),
+            bind_group_layouts: self.bind_group_layouts.iter().copied().cloned().collect(),
+            color_targets: self.color_targets.clone(),
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            multisample: self.multisample,
+        }
+    }
+
+    fn create(
+        &self,
+        shader: &ShaderModule,
+        reflection: Option<&ShaderReflection>,
+        cache: Option<&PipelineCache>,
+    ) -> Result<RenderPipeline, PipelineError> {
+        catch_validation_errors(self.device, || {
+            self.create_pipeline(shader, reflection, cache)
+        })
+        .map_err(|err| self.device_error(err))
+    }
+
+    fn device_error(&self, error: wgpu::Error) -> PipelineError {
+        PipelineError::Device {
+            label: self.label.map(str::to_string),
+            message: error.to_string(),
+        }
+    }
+
+    fn create_pipeline(
+        &self,
+        shader: &ShaderModule,
+        reflection: Option<&ShaderReflection>,
+        cache: Option<&PipelineCache>,
+    ) -> RenderPipeline {
+        let generated = match reflection {
+            Some(reflection) if self.bind_group_layouts.is_empty() => {
+                reflection.create_bind_group_layouts(self.device, self.label)
+            }
+            _ => Vec::new(),
+        };
+        let bind_group_layouts: Vec<_> = (self.bind_group_layouts.iter().copied())
+            .chain(&generated)
+            .collect();
+        // Without reflection or layouts, wgpu derives the layout from the module
+        let layout = (reflection.is_some() || !bind_group_layouts.is_empty()).then(|| {
+            self.device
+                .create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: self.label,
+                    bind_group_layouts: &bind_group_layouts,
+                    push_constant_ranges: &[],
+                })
+        });
+
+        self.device
+            .create_render_pipeline(&RenderPipelineDescriptor {
+                label: self.label,
+                layout: layout.as_ref(),
+                vertex: VertexState {
+                    module: shader,
+                    entry_point: Some(&self.vert_main),
+                    buffers: &self.vertex_buffer_layouts,
+                    compilation_options: Default::default(),
+                },
+                fragment: self.frag_main.as_ref().map(|fs_main| FragmentState {
+                    module: shader,
+                    entry_point: Some(fs_main),
+                    targets: &self.color_targets,
+                    compilation_options: Default::default(),
+                }),
+                primitive: self.primitive,
+                depth_stencil: self.depth_stencil.clone(),
+                multisample: self.multisample,
+                // If the pipeline will be used with a multiview render pass, this
+                // indicates how many array layers the attachments will have.
+                multiview: None,
+                // Useful for optimizing shader compilation on Android
+                cache,
+            })
+    }
+}
+
+/// The shader capabilities wgpu enables for a device with `features`.
+///
+/// Capabilities depending on downlevel flags are allowed, the device has the
+/// last word on those.
+fn shader_capabilities(features: Features) -> Capabilities {
+    let mut capabilities = Capabilities::MULTISAMPLED_SHADING | Capabilities::CUBE_ARRAY_TEXTURES;
+    let mapping = [
+        (Features::PUSH_CONSTANTS, Capabilities::PUSH_CONSTANT),
+        (Features::SHADER_F64, Capabilities::FLOAT64),
+        (
+            Features::SHADER_PRIMITIVE_INDEX,
+            Capabilities::PRIMITIVE_INDEX,
+        ),
+        (
+            Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+            Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                | Capabilities::SAMPLER_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+            Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+        (
+            Features::TEXTURE_FORMAT_16BIT_NORM,
+            Capabilities::STORAGE_TEXTURE_16BIT_NORM_FORMATS,
+        ),
+        (Features::MULTIVIEW, Capabilities::MULTIVIEW),
+        (
+            Features::SHADER_EARLY_DEPTH_TEST,
+            Capabilities::EARLY_DEPTH_TEST,
+        ),
+        (Features::SHADER_INT64, Capabilities::SHADER_INT64),
+        (
+            Features::SHADER_INT64_ATOMIC_MIN_MAX,
+            Capabilities::SHADER_INT64_ATOMIC_MIN_MAX,
+        ),
+        (
+            Features::SHADER_INT64_ATOMIC_ALL_OPS,
+            Capabilities::SHADER_INT64_ATOMIC_MIN_MAX | Capabilities::SHADER_INT64_ATOMIC_ALL_OPS,
+        ),
+        (Features::TEXTURE_ATOMIC, Capabilities::TEXTURE_ATOMIC),
+        (
+            Features::TEXTURE_INT64_ATOMIC,
+            Capabilities::TEXTURE_INT64_ATOMIC,
+        ),
+        (
+            Features::SHADER_FLOAT32_ATOMIC,
+            Capabilities::SHADER_FLOAT32_ATOMIC,
+        ),
+        (
+            Features::DUAL_SOURCE_BLENDING,
+            Capabilities::DUAL_SOURCE_BLENDING,
+        ),
+        (Features::SUBGROUP, Capabilities::SUBGROUP),
+        (
+            Features::SUBGROUP_VERTEX,
+            Capabilities::SUBGROUP | Capabilities::SUBGROUP_VERTEX_STAGE,
+        ),
+        (Features::SUBGROUP_BARRIER, Capabilities::SUBGROUP_BARRIER),
+        (Features::EXPERIMENTAL_RAY_QUERY, Capabilities::RAY_QUERY),
+    ];
+    for (feature, capability) in mapping {
+        if features.contains(feature) {
+            capabilities |= capability;
+        }
+    }
+    capabilities
+}
+
+/// Pipeline builder state owning everything it refers to, so the pipeline can
+/// be rebuilt later with a new shader.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(super) struct PipelineRecipe {
+    label: Option<String>,
+    path: PathBuf,
+    preprocessor: ShaderPreprocessor,
+    shader_defs: Vec<(String, String)>,
+    vert_main: String,
+    frag_main: Option<String>,
+    vertex_buffer_layouts: Vec<OwnedVertexBufferLayout>,
+    bind_group_layouts: Vec<BindGroupLayout>,
+    bind_group_entries: Vec<Option<Vec<BindGroupLayoutEntry>>>,
+    color_targets: Vec<Option<ColorTargetState>>,
+    primitive: PrimitiveState,
+    depth_stencil: Option<DepthStencilState>,
+    multisample: MultisampleState,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PipelineRecipe {
+    /// The shader file the pipeline is built from.
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Builds the pipeline from the current shader file through `cache`,
+    /// returns it with the files the shader was read from.
+    ///
+    /// The files are known even if the pipeline fails to build, unless the
+    /// shader can't be preprocessed.
+    pub(super) fn build(
+        &self,
+        device: &Device,
+        cache: &RenderPipelineCache,
+    ) -> (Result<RenderPipeline, PipelineError>, Option<Vec<PathBuf>>) {
+        let shader_defs: Vec<_> = (self.shader_defs.iter())
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let shader = match self
+            .preprocessor
+            .process_file_with(&self.path, &shader_defs)
+        {
+            Ok(shader) => shader,
+            Err(err) => return (Err(PipelineError::Shader(err)), None),
+        };
+        let vertex_buffer_layouts: Vec<_> = (self.vertex_buffer_layouts.iter())
+            .map(|(array_stride, step_mode, attributes)| VertexBufferLayout {
+                array_stride: *array_stride,
+                step_mode: *step_mode,
+                attributes,
+            })
+            .collect();
+
+        let mut builder = PipelineBuilder::new(device);
+        builder.label = self.label.as_deref();
+        builder.color_targets = self.color_targets.clone();
+        builder.primitive = self.primitive;
+        builder.depth_stencil = self.depth_stencil.clone();
+        builder.multisample = self.multisample;
+        builder
+            .set_shader_module(
+                ShaderSource::Preprocessed(&shader),
+                &self.vert_main,
+                self.frag_main.as_deref(),
+            )
+            .add_vertex_buffer_layouts(&vertex_buffer_layouts);
+        for (layout, entries) in self.bind_group_layouts.iter().zip(&self.bind_group_entries) {
+            match entries {
+                Some(entries) => builder.add_bind_group_layout_with_entries(layout, entries),
+                None => builder.add_bind_group_layout(layout),
+            };
+        }
+        let pipeline = builder.build_cached(cache);
+        (
+            pipeline,
+            Some(shader.paths().map(Path::to_path_buf).collect()),
+        )
+    }
+}
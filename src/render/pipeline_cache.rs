@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{io, path::Path};
+
+#[cfg(not(target_arch = "wasm32"))]
+use wgpu::{Adapter, Features, PipelineCacheDescriptor};
+use wgpu::{
+    AdapterInfo, Device, PipelineCache, RenderPipeline, ShaderModule, ShaderModuleDescriptor,
+};
+
+use super::{catch_validation_errors, pipeline_builder::PipelineKey};
+
+/// Counters of a [`RenderPipelineCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    /// Requests answered with a pipeline built before.
+    pub hits: u64,
+    /// Requests that had to build a new pipeline.
+    pub misses: u64,
+    pub shader_hits: u64,
+    pub shader_misses: u64,
+    /// Time spent building pipelines on cache misses, including their shader
+    /// modules.
+    ///
+    /// Stays zero on the web, where there is no monotonic clock in `std`.
+    pub compile_time: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Modules by their WGSL source.
+    shaders: HashMap<String, ShaderModule>,
+    pipelines: HashMap<PipelineKey, RenderPipeline>,
+    stats: PipelineCacheStats,
+}
+
+/// Shares render pipelines and shader modules between identical requests.
+///
+/// Pipelines are keyed by their full description, see
+/// [`PipelineBuilder::build_cached`](super::PipelineBuilder::build_cached).
+/// Bind group layouts and shader modules are compared by identity, so
+/// layouts have to be shared for pipelines using them to be shared.
+///
+/// The cache is used through shared references, so it can be borrowed
+/// alongside the device it creates pipelines with.
+///
+/// On adapters supporting `Features::PIPELINE_CACHE` it can also hold the
+/// driver's cache of compiled pipelines, which is kept on disk between runs.
+#[derive(Debug, Default)]
+pub struct RenderPipelineCache {
+    inner: Mutex<Inner>,
+    driver: Option<DriverCache>,
+    /// Samples of the frame pipelines are built for, `None` for one.
+    sample_count: Option<u32>,
+}
+
+// Never created on the web, which has no pipeline caches
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Debug)]
+struct DriverCache {
+    cache: PipelineCache,
+    path: PathBuf,
+    /// Identifies the adapter and driver the data was created by.
+    identity: String,
+}
+
+/// Start of pipeline cache files, followed by the adapter identity line.
+const CACHE_FILE_MAGIC: &[u8] = b"render-rs pipeline cache 1\n";
+
+/// Identifies the adapter and driver version pipeline cache data belongs to,
+/// as a single line.
+pub fn cache_file_identity(info: &AdapterInfo) -> String {
+    format!(
+        "{:?} {} {:#x} {:#x} {} {}",
+        info.backend, info.name, info.vendor, info.device, info.driver, info.driver_info
+    )
+    .replace('\n', " ")
+}
+
+/// A pipeline cache file holding `data` created for `identity`.
+pub fn encode_cache_file(identity: &str, data: &[u8]) -> Vec<u8> {
+    let mut file = CACHE_FILE_MAGIC.to_vec();
+    file.extend_from_slice(identity.as_bytes());
+    file.push(b'\n');
+    file.extend_from_slice(data);
+    file
+}
+
+/// The data of a pipeline cache file, `None` if it wasn't written by
+/// [`encode_cache_file`] for `identity`.
+pub fn decode_cache_file<'a>(file: &'a [u8], identity: &str) -> Option<&'a [u8]> {
+    file.strip_prefix(CACHE_FILE_MAGIC)?
+        .strip_prefix(identity.as_bytes())?
+        .strip_prefix(b"\n")
+}
+
+/// Replaces the file at `path` with `file`, creating its directory.
+///
+/// The file is written next to `path` and renamed over it, so a crash never
+/// leaves a torn cache behind.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_cache_file(path: &Path, file: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, file)?;
+    std::fs::rename(&temp, path)
+}
+
+impl RenderPipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache that also loads the driver's pipeline cache for `adapter` from
+    /// `dir`, if the device supports it.
+    ///
+    /// Cache files written for another adapter or driver version are ignored
+    /// and overwritten by [`RenderPipelineCache::save`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_driver_cache(device: &Device, adapter: &Adapter, dir: Option<&Path>) -> Self {
+        let info = adapter.get_info();
+        let (Some(dir), Some(key)) = (dir, wgpu::util::pipeline_cache_key(&info)) else {
+            return Self::new();
+        };
+        if !device.features().contains(Features::PIPELINE_CACHE) {
+            return Self::new();
+        }
+
+        let path = dir.join(key);
+        let identity = cache_file_identity(&info);
+        let data = match std::fs::read(&path) {
+            Ok(file) => {
+                let data = decode_cache_file(&file, &identity);
+                if data.is_none() {
+                    log::info!(
+                        "Discarding pipeline cache {} of another driver",
+                        path.display()
+                    );
+                }
+                data.map(<[u8]>::to_vec)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                log::warn!("Failed to read pipeline cache {}: {err}", path.display());
+                None
+            }
+        };
+
+        // SAFETY: cache files are only ever written by `save`, from
+        // `PipelineCache::get_data` of a cache created for the same identity,
+        // and `decode_cache_file` only returns data following this identity.
+        // `fallback` merely covers data wgpu itself refuses, such as a cache
+        // of another wgpu version; it does not make arbitrary data safe.
+        let cache = unsafe {
+            device.create_pipeline_cache(&PipelineCacheDescriptor {
+                label: Some("pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        Self {
+            inner: Mutex::default(),
+            driver: Some(DriverCache {
+                cache,
+                path,
+                identity,
+            }),
+            sample_count: None,
+        }
+    }
+
+    /// The driver's cache, passed to pipelines built through this cache.
+    pub fn driver_cache(&self) -> Option<&PipelineCache> {
+        self.driver.as_ref().map(|driver| &driver.cache)
+    }
+
+    /// Samples per pixel of the frame pipelines drawing into it are built
+    /// for, see
+    /// [`PipelineBuilder::with_sample_count`](super::PipelineBuilder::with_sample_count).
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count.unwrap_or(1)
+    }
+
+    pub fn set_sample_count(&mut self, count: u32) {
+        self.sample_count = Some(count);
+    }
+
+    /// Writes the driver's pipeline cache to disk, returns the path written to.
+    ///
+    /// Does nothing without a driver cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) -> io::Result<Option<PathBuf>> {
+        let Some(driver) = &self.driver else {
+            return Ok(None);
+        };
+        let Some(data) = driver.cache.get_data() else {
+            return Ok(None);
+        };
+        write_cache_file(&driver.path, &encode_cache_file(&driver.identity, &data))?;
+        Ok(Some(driver.path.clone()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // The maps stay consistent even if a panic poisoned the lock
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn stats(&self) -> PipelineCacheStats {
+        self.lock().stats
+    }
+
+    /// Number of distinct pipelines in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().pipelines.is_empty()
+    }
+
+    /// Drops all cached pipelines and shader modules, keeping the statistics.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.shaders.clear();
+        inner.pipelines.clear();
+    }
+
+    /// Returns the module compiled from `source`, compiling it on first use.
+    ///
+    /// Modules wgpu rejects are not cached. Compiling doesn't lock the
+    /// cache, if two threads compile the same source the first module
+    /// cached is returned to both.
+    pub fn shader_module(
+        &self,
+        device: &Device,
+        label: Option<&str>,
+        source: &str,
+    ) -> Result<ShaderModule, wgpu::Error> {
+        {
+            let mut inner = self.lock();
+            if let Some(module) = inner.shaders.get(source) {
+                let module = module.clone();
+                inner.stats.shader_hits += 1;
+                return Ok(module);
+            }
+        }
+
+        let module = catch_validation_errors(device, || {
+            device.create_shader_module(ShaderModuleDescriptor {
+                label,
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        })?;
+
+        let mut inner = self.lock();
+        inner.stats.shader_misses += 1;
+        let module = inner.shaders.entry(source.to_string()).or_insert(module);
+        Ok(module.clone())
+    }
+
+    /// Returns the pipeline cached for `key`, or creates and caches it if
+    /// `create` succeeds.
+    pub(super) fn get_or_try_create<E>(
+        &self,
+        key: PipelineKey,
+        create: impl FnOnce() -> Result<RenderPipeline, E>,
+    ) -> Result<RenderPipeline, E> {
+        {
+            let mut inner = self.lock();
+            if let Some(pipeline) = inner.pipelines.get(&key) {
+                let pipeline = pipeline.clone();
+                inner.stats.hits += 1;
+                return Ok(pipeline);
+            }
+        }
+
+        // Not locked while creating, `create` may compile shaders through the cache
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = std::time::Instant::now();
+        let pipeline = create()?;
+
+        let mut inner = self.lock();
+        inner.stats.misses += 1;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            inner.stats.compile_time += start.elapsed();
+        }
+        inner.pipelines.insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use naga::valid::{Capabilities, ModuleInfo};
+
+use super::ShaderReflection;
+
+/// Expands includes, defines and conditional blocks in WGSL.
+///
+/// Directives are lines starting with `#`:
+///
+/// - `#include "file.wgsl"` inserts a file, looked up in the sources added
+///   with [`ShaderPreprocessor::add_source`], next to the including file, then
+///   in the include directories. Each file is included once, so shared
+///   declarations can be included from several files.
+/// - `#define NAME [value]` and `#undef NAME` change shader defs. Identifiers
+///   matching a def with a value are replaced by the value.
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep or drop lines
+///   depending on the defs, which gives permutations of one shader.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderPreprocessor {
+    defines: HashMap<String, String>,
+    include_dirs: Vec<PathBuf>,
+    sources: HashMap<String, String>,
+}
+
+impl ShaderPreprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines `name` for all shaders, an empty value only enables `#ifdef`s.
+    pub fn define(&mut self, name: &str, value: &str) -> &mut Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn undefine(&mut self, name: &str) -> &mut Self {
+        self.defines.remove(name);
+        self
+    }
+
+    /// Adds a directory includes are looked up in.
+    pub fn add_include_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Makes `source` available to `#include "name"` without reading a file,
+    /// for shaders built into the binary.
+    pub fn add_source(&mut self, name: &str, source: &str) -> &mut Self {
+        self.sources.insert(name.to_string(), source.to_string());
+        self
+    }
+
+    /// Preprocesses `source`, using `name` for it in error messages.
+    pub fn process(&self, name: &str, source: &str) -> Result<PreprocessedShader, ShaderError> {
+        self.process_with(name, source, &[])
+    }
+
+    /// Reads and preprocesses the shader at `path`.
+    pub fn process_file(&self, path: &Path) -> Result<PreprocessedShader, ShaderError> {
+        self.process_file_with(path, &[])
+    }
+
+    /// Like [`ShaderPreprocessor::process`], with `defines` added to those of
+    /// the preprocessor.
+    pub(super) fn process_with(
+        &self,
+        name: &str,
+        source: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<PreprocessedShader, ShaderError> {
+        self.expand(name, None, source, defines)
+    }
+
+    pub(super) fn process_file_with(
+        &self,
+        path: &Path,
+        defines: &[(&str, &str)],
+    ) -> Result<PreprocessedShader, ShaderError> {
+        let source = read(path)?;
+        self.expand(&path.display().to_string(), Some(path), &source, defines)
+    }
+
+    fn expand(
+        &self,
+        name: &str,
+        path: Option<&Path>,
+        source: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<PreprocessedShader, ShaderError> {
+        let mut expander = Expander {
+            preprocessor: self,
+            defines: self.defines.clone(),
+            shader: PreprocessedShader::default(),
+        };
+        for (name, value) in defines {
+            expander.defines.insert(name.to_string(), value.to_string());
+        }
+        let file = expander.add_file(ShaderFile {
+            name: name.to_string(),
+            path: path.map(file_key),
+        });
+        expander.expand(file, source)?;
+        Ok(expander.shader)
+    }
+
+    /// Finds the file included as `name` from `from`.
+    fn resolve(&self, name: &str, from: Option<&Path>) -> Option<ShaderFile> {
+        if self.sources.contains_key(name) {
+            return Some(ShaderFile {
+                name: name.to_string(),
+                path: None,
+            });
+        }
+        let relative = from.and_then(Path::parent).map(|dir| dir.join(name));
+        let path = relative
+            .into_iter()
+            .chain(self.include_dirs.iter().map(|dir| dir.join(name)))
+            .find(|path| path.is_file())?;
+        Some(ShaderFile {
+            name: path.display().to_string(),
+            path: Some(file_key(&path)),
+        })
+    }
+
+    fn read_file(&self, file: &ShaderFile) -> Result<String, ShaderError> {
+        match &file.path {
+            Some(path) => read(path),
+            None => Ok(self.sources[&file.name].clone()),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, ShaderError> {
+    std::fs::read_to_string(path).map_err(|err| ShaderError::Read {
+        path: path.to_path_buf(),
+        error: err.to_string(),
+    })
+}
+
+/// Identifies files on disk regardless of how they were reached.
+fn file_key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// WGSL produced by a [`ShaderPreprocessor`], with the origin of every line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreprocessedShader {
+    source: String,
+    /// File index and 1-based line of every output line.
+    lines: Vec<(usize, u32)>,
+    files: Vec<ShaderFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ShaderFile {
+    name: String,
+    path: Option<PathBuf>,
+}
+
+impl PreprocessedShader {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The files on disk the shader was read from, including the shader
+    /// itself if it was read from a file.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().filter_map(|file| file.path.as_deref())
+    }
+
+    /// Where the 1-based `line` and `column` of the output came from.
+    ///
+    /// Columns are those of the output, they are off on lines where defines
+    /// were replaced by values of another length.
+    pub fn map_location(&self, line: u32, column: u32) -> Option<SourceLocation> {
+        let &(file, line) = self.lines.get(line.checked_sub(1)? as usize)?;
+        Some(SourceLocation {
+            file: self.files[file].name.clone(),
+            line,
+            column,
+        })
+    }
+
+    /// The output line `location` was expanded to, for quoting it in
+    /// diagnostics.
+    pub fn source_line(&self, location: &SourceLocation) -> Option<&str> {
+        let index = self.lines.iter().position(|&(file, line)| {
+            line == location.line && self.files[file].name == location.file
+        })?;
+        self.source.lines().nth(index)
+    }
+
+    /// Parses and validates the shader with naga, reporting errors at their
+    /// location in the original files.
+    ///
+    /// Validation allows all capabilities, the device may still reject the
+    /// module.
+    pub fn validate(&self) -> Result<(naga::Module, ModuleInfo), ShaderError> {
+        self.validate_with(Capabilities::all())
+    }
+
+    /// Like [`PreprocessedShader::validate`], allowing only `capabilities`.
+    pub(super) fn validate_with(
+        &self,
+        capabilities: Capabilities,
+    ) -> Result<(naga::Module, ModuleInfo), ShaderError> {
+        let module = naga::front::wgsl::parse_str(&self.source)
+            .map_err(|err| self.compile_error(err.message().to_string(), err.labels()))?;
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), capabilities)
+            .validate(&module)
+            .map_err(|err| {
+                let mut message = err.to_string();
+                let mut source = err.source();
+                while let Some(err) = source {
+                    message = format!("{message}: {err}");
+                    source = err.source();
+                }
+                let labels = err.spans().map(|(span, label)| (*span, label.as_str()));
+                self.compile_error(message, labels)
+            })?;
+        Ok((module, info))
+    }
+
+    /// Validates the shader and reflects the entry points of a pipeline.
+    pub fn reflect(
+        &self,
+        vert_main: &str,
+        frag_main: Option<&str>,
+    ) -> Result<ShaderReflection, ShaderError> {
+        let (module, info) = self.validate()?;
+        ShaderReflection::new(&module, &info, vert_main, frag_main)
+    }
+
+    fn compile_error<'l>(
+        &self,
+        message: String,
+        labels: impl Iterator<Item = (naga::Span, &'l str)>,
+    ) -> ShaderError {
+        let labels = self.map_labels(labels);
+        ShaderError::Compile { message, labels }
+    }
+
+    /// Locates naga spans in the original files.
+    pub(super) fn map_labels<'l>(
+        &self,
+        labels: impl Iterator<Item = (naga::Span, &'l str)>,
+    ) -> Vec<(SourceLocation, String)> {
+        labels
+            .filter(|(span, _)| span.is_defined())
+            .filter_map(|(span, label)| {
+                let location = span.location(&self.source);
+                let location = self.map_location(location.line_number, location.line_position)?;
+                Some((location, label.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// A position in a shader file, 1-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderError {
+    /// A shader file couldn't be read.
+    Read { path: PathBuf, error: String },
+    IncludeNotFound {
+        name: String,
+        location: SourceLocation,
+    },
+    UnknownDirective {
+        directive: String,
+        location: SourceLocation,
+    },
+    /// A directive is missing its argument or has a malformed one.
+    InvalidDirective {
+        directive: String,
+        location: SourceLocation,
+    },
+    /// An `#else` or `#endif` doesn't belong to any `#ifdef`, or an `#ifdef`
+    /// has several `#else`.
+    UnmatchedDirective {
+        directive: String,
+        location: SourceLocation,
+    },
+    /// An `#ifdef` or `#ifndef` has no `#endif`.
+    UnterminatedConditional(SourceLocation),
+    /// The shader has no entry point of that name for the stage it is used as.
+    MissingEntryPoint(String),
+    /// The preprocessed shader is invalid WGSL. Labels point to the code at
+    /// fault in the original files.
+    Compile {
+        message: String,
+        labels: Vec<(SourceLocation, String)>,
+    },
+    /// The shader is valid but uses something `target` can't run.
+    Unsupported {
+        target: &'static str,
+        message: String,
+        labels: Vec<(SourceLocation, String)>,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Read { path, error } => {
+                write!(f, "failed to read shader {}: {error}", path.display())
+            }
+            ShaderError::IncludeNotFound { name, location } => {
+                write!(f, "{location}: included file \"{name}\" not found")
+            }
+            ShaderError::UnknownDirective {
+                directive,
+                location,
+            } => write!(f, "{location}: unknown directive #{directive}"),
+            ShaderError::InvalidDirective {
+                directive,
+                location,
+            } => write!(f, "{location}: invalid #{directive} directive"),
+            ShaderError::UnmatchedDirective {
+                directive,
+                location,
+            } => write!(f, "{location}: #{directive} without matching #ifdef"),
+            ShaderError::UnterminatedConditional(location) => {
+                write!(f, "{location}: conditional block without #endif")
+            }
+            ShaderError::MissingEntryPoint(name) => {
+                write!(f, "shader has no entry point {name}")
+            }
+            ShaderError::Compile { message, labels } => write_labelled(f, message, labels),
+            ShaderError::Unsupported {
+                target,
+                message,
+                labels,
+            } => write_labelled(f, &format!("not supported by {target}: {message}"), labels),
+        }
+    }
+}
+
+fn write_labelled(
+    f: &mut fmt::Formatter<'_>,
+    message: &str,
+    labels: &[(SourceLocation, String)],
+) -> fmt::Result {
+    match labels.first() {
+        Some((location, _)) => write!(f, "{location}: {message}")?,
+        None => write!(f, "{message}")?,
+    }
+    for (location, label) in labels {
+        write!(f, "\n  {location}: {label}")?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for ShaderError {}
+
+/// An `#ifdef` or `#ifndef` block being expanded.
+struct Conditional {
+    /// Whether lines are currently kept.
+    active: bool,
+    /// Whether the enclosing block keeps lines.
+    parent_active: bool,
+    has_else: bool,
+    location: SourceLocation,
+}
+
+struct Expander<'p> {
+    preprocessor: &'p ShaderPreprocessor,
+    defines: HashMap<String, String>,
+    shader: PreprocessedShader,
+}
+
+impl Expander<'_> {
+    fn add_file(&mut self, file: ShaderFile) -> usize {
+        self.shader.files.push(file);
+        self.shader.files.len() - 1
+    }
+
+    fn is_included(&self, file: &ShaderFile) -> bool {
+        self.shader.files.iter().any(|included| match &file.path {
+            Some(path) => included.path.as_ref() == Some(path),
+            None => included.path.is_none() && included.name == file.name,
+        })
+    }
+
+    fn expand(&mut self, file: usize, source: &str) -> Result<(), ShaderError> {
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let active = conditionals.last().is_none_or(|block| block.active);
+            let trimmed = line.trim_start();
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active {
+                    let line = substitute(line, &self.defines);
+                    self.shader.source.push_str(&line);
+                    self.shader.source.push('\n');
+                    self.shader.lines.push((file, line_number));
+                }
+                continue;
+            };
+
+            let location = SourceLocation {
+                file: self.shader.files[file].name.clone(),
+                line: line_number,
+                column: (line.len() - trimmed.len()) as u32 + 1,
+            };
+            let (name, argument) = directive
+                .split_once(char::is_whitespace)
+                .unwrap_or((directive, ""));
+            let argument = argument.split("//").next().unwrap_or_default().trim();
+            let invalid = || ShaderError::InvalidDirective {
+                directive: name.to_string(),
+                location: location.clone(),
+            };
+            let unmatched = || ShaderError::UnmatchedDirective {
+                directive: name.to_string(),
+                location: location.clone(),
+            };
+
+            match name {
+                "ifdef" | "ifndef" => {
+                    if !is_identifier(argument) {
+                        return Err(invalid());
+                    }
+                    let defined = self.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: active && defined == (name == "ifdef"),
+                        parent_active: active,
+                        has_else: false,
+                        location,
+                    });
+                }
+                "else" => {
+                    let block = conditionals.last_mut().ok_or_else(unmatched)?;
+                    if block.has_else {
+                        return Err(unmatched());
+                    }
+                    block.has_else = true;
+                    block.active = block.parent_active && !block.active;
+                }
+                "endif" => {
+                    conditionals.pop().ok_or_else(unmatched)?;
+                }
+                // Other directives only apply in kept blocks
+                _ if !active => {}
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((argument, ""));
+                    if !is_identifier(define) {
+                        return Err(invalid());
+                    }
+                    self.defines
+                        .insert(define.to_string(), value.trim().to_string());
+                }
+                "undef" => {
+                    if !is_identifier(argument) {
+                        return Err(invalid());
+                    }
+                    self.defines.remove(argument);
+                }
+                "include" => {
+                    let include = argument
+                        .strip_prefix('"')
+                        .and_then(|rest| rest.strip_suffix('"'))
+                        .filter(|include| !include.is_empty())
+                        .ok_or_else(invalid)?;
+                    let from = self.shader.files[file].path.clone();
+                    let included = self
+                        .preprocessor
+                        .resolve(include, from.as_deref())
+                        .ok_or_else(|| ShaderError::IncludeNotFound {
+                            name: include.to_string(),
+                            location,
+                        })?;
+                    if !self.is_included(&included) {
+                        let source = self.preprocessor.read_file(&included)?;
+                        let included = self.add_file(included);
+                        self.expand(included, &source)?;
+                    }
+                }
+                _ => {
+                    return Err(ShaderError::UnknownDirective {
+                        directive: name.to_string(),
+                        location,
+                    })
+                }
+            }
+        }
+
+        match conditionals.pop() {
+            Some(block) => Err(ShaderError::UnterminatedConditional(block.location)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces identifiers outside of comments by the value of their define.
+fn substitute<'l>(line: &'l str, defines: &HashMap<String, String>) -> Cow<'l, str> {
+    if defines.values().all(String::is_empty) {
+        return Cow::Borrowed(line);
+    }
+    let (code, comment) = match line.find("//") {
+        Some(start) => line.split_at(start),
+        None => (line, ""),
+    };
+
+    let mut output = String::with_capacity(line.len());
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let end = if c.is_ascii_digit() {
+            // Numbers are skipped whole, so their suffixes aren't replaced
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len())
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        } else {
+            c.len_utf8()
+        };
+        let (token, tail) = rest.split_at(end);
+        match defines.get(token) {
+            Some(value) if !value.is_empty() => output.push_str(value),
+            _ => output.push_str(token),
+        }
+        rest = tail;
+    }
+    output.push_str(comment);
+    Cow::Owned(output)
+}
@@ -0,0 +1,575 @@
+use std::{
+    fmt,
+    num::{NonZeroU32, NonZeroU64},
+};
+
+use naga::{
+    valid::ModuleInfo, AddressSpace, ArraySize, Binding, ImageClass, ImageDimension, Module,
+    ScalarKind, ShaderStage, StorageAccess, StorageFormat, Type, TypeInner,
+};
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+    BufferBindingType, ColorTargetState, Device, SamplerBindingType, ShaderStages,
+    StorageTextureAccess, TextureFormat, TextureSampleType, TextureViewDimension,
+    VertexBufferLayout, VertexFormat,
+};
+
+use super::ShaderError;
+
+/// A resource declared with `@group` and `@binding` and used by the shader.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderBinding {
+    pub name: Option<String>,
+    pub group: u32,
+    /// The layout entry the resource needs, visible to the stages using it.
+    pub entry: BindGroupLayoutEntry,
+    /// For buffers holding an array, or a struct ending with one, the stride
+    /// of its elements.
+    pub array_stride: Option<u32>,
+}
+
+/// The resources and interface of a vertex and fragment entry point, read
+/// from the shader.
+///
+/// Used by [`PipelineBuilder`](super::PipelineBuilder) to generate layouts and
+/// to check the ones it is given, but also to create layouts up front for
+/// bind groups shared by several pipelines.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShaderReflection {
+    /// Sorted by group and binding.
+    bindings: Vec<ShaderBinding>,
+    /// Locations and scalar kinds of the vertex inputs.
+    vertex_inputs: Vec<(u32, ScalarKind)>,
+    /// Locations and scalar kinds of the fragment outputs.
+    fragment_outputs: Vec<(u32, ScalarKind)>,
+}
+
+impl ShaderReflection {
+    /// Reflects the entry points `vert_main` and `frag_main` of a validated
+    /// module.
+    pub fn new(
+        module: &Module,
+        info: &ModuleInfo,
+        vert_main: &str,
+        frag_main: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        let mut reflection = Self::default();
+        let entry_points = std::iter::once((vert_main, ShaderStage::Vertex))
+            .chain(frag_main.map(|name| (name, ShaderStage::Fragment)));
+        for (name, stage) in entry_points {
+            let (index, entry_point) = (module.entry_points.iter().enumerate())
+                .find(|(_, entry_point)| entry_point.name == name && entry_point.stage == stage)
+                .ok_or_else(|| ShaderError::MissingEntryPoint(name.to_string()))?;
+            let visibility = match stage {
+                ShaderStage::Vertex => ShaderStages::VERTEX,
+                _ => ShaderStages::FRAGMENT,
+            };
+
+            let uses = info.get_entry_point(index);
+            for (handle, global) in module.global_variables.iter() {
+                let Some(binding) = &global.binding else {
+                    continue;
+                };
+                if uses[handle].is_empty() {
+                    continue;
+                }
+                let existing = (reflection.bindings.iter_mut()).find(|existing| {
+                    existing.group == binding.group && existing.entry.binding == binding.binding
+                });
+                if let Some(existing) = existing {
+                    existing.entry.visibility |= visibility;
+                    continue;
+                }
+                let (ty, count) = binding_type(module, global.space, &module.types[global.ty]);
+                reflection.bindings.push(ShaderBinding {
+                    name: global.name.clone(),
+                    group: binding.group,
+                    entry: BindGroupLayoutEntry {
+                        binding: binding.binding,
+                        visibility,
+                        ty,
+                        count,
+                    },
+                    array_stride: array_stride(module, &module.types[global.ty].inner),
+                });
+            }
+
+            let function = &entry_point.function;
+            match stage {
+                ShaderStage::Vertex => {
+                    for argument in &function.arguments {
+                        locations(
+                            module,
+                            argument.ty,
+                            argument.binding.as_ref(),
+                            &mut reflection.vertex_inputs,
+                        );
+                    }
+                }
+                _ => {
+                    if let Some(result) = &function.result {
+                        locations(
+                            module,
+                            result.ty,
+                            result.binding.as_ref(),
+                            &mut reflection.fragment_outputs,
+                        );
+                    }
+                }
+            }
+        }
+        reflection
+            .bindings
+            .sort_by_key(|binding| (binding.group, binding.entry.binding));
+        Ok(reflection)
+    }
+
+    pub fn bindings(&self) -> &[ShaderBinding] {
+        &self.bindings
+    }
+
+    /// The resource at `@group(group) @binding(binding)`, if the shader uses
+    /// one.
+    pub fn binding(&self, group: u32, binding: u32) -> Option<&ShaderBinding> {
+        (self.bindings.iter())
+            .find(|existing| existing.group == group && existing.entry.binding == binding)
+    }
+
+    /// Number of bind groups the pipeline layout needs, including unused ones
+    /// below the highest group.
+    pub fn group_count(&self) -> u32 {
+        self.bindings.last().map_or(0, |binding| binding.group + 1)
+    }
+
+    /// Layout entries of `group`, empty for groups the shader doesn't use.
+    ///
+    /// Textures of float formats are assumed to be filterable, shaders
+    /// sampling unfilterable formats need layouts written by hand.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Vec<BindGroupLayoutEntry> {
+        (self.bindings.iter())
+            .filter(|binding| binding.group == group)
+            .map(|binding| binding.entry)
+            .collect()
+    }
+
+    /// Creates the layouts of all groups up to [`ShaderReflection::group_count`].
+    pub fn create_bind_group_layouts(
+        &self,
+        device: &Device,
+        label: Option<&str>,
+    ) -> Vec<BindGroupLayout> {
+        (0..self.group_count())
+            .map(|group| {
+                device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label,
+                    entries: &self.bind_group_layout_entries(group),
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that a layout written for `group` has every binding the shader
+    /// uses in it, with a compatible type and visibility.
+    ///
+    /// Entries the shader doesn't use are allowed.
+    pub fn check_bind_group_layout(
+        &self,
+        group: u32,
+        entries: &[BindGroupLayoutEntry],
+    ) -> Result<(), LayoutMismatch> {
+        for binding in self
+            .bindings
+            .iter()
+            .filter(|binding| binding.group == group)
+        {
+            let shader = &binding.entry;
+            let layout = (entries.iter())
+                .find(|entry| entry.binding == shader.binding)
+                .ok_or(LayoutMismatch::MissingBinding {
+                    group,
+                    binding: shader.binding,
+                })?;
+            if !binding_compatible(&shader.ty, &layout.ty) || shader.count != layout.count {
+                return Err(LayoutMismatch::BindingType {
+                    group,
+                    binding: shader.binding,
+                    shader: shader.ty,
+                    layout: layout.ty,
+                });
+            }
+            if !layout.visibility.contains(shader.visibility) {
+                return Err(LayoutMismatch::Visibility {
+                    group,
+                    binding: shader.binding,
+                    shader: shader.visibility,
+                    layout: layout.visibility,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every vertex input is provided by an attribute of a
+    /// matching kind.
+    pub fn check_vertex_buffers(
+        &self,
+        buffers: &[VertexBufferLayout],
+    ) -> Result<(), LayoutMismatch> {
+        for &(location, kind) in &self.vertex_inputs {
+            let attribute = (buffers.iter())
+                .flat_map(|buffer| buffer.attributes)
+                .find(|attribute| attribute.shader_location == location)
+                .ok_or(LayoutMismatch::MissingVertexAttribute(location))?;
+            if vertex_format_kind(attribute.format) != kind {
+                return Err(LayoutMismatch::VertexFormat {
+                    location,
+                    format: attribute.format,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every fragment output written to a color target matches
+    /// the kind of the target's format.
+    pub fn check_color_targets(
+        &self,
+        targets: &[Option<ColorTargetState>],
+    ) -> Result<(), LayoutMismatch> {
+        for &(location, kind) in &self.fragment_outputs {
+            let Some(Some(target)) = targets.get(location as usize) else {
+                continue;
+            };
+            let matches = match target.format.sample_type(None, None) {
+                Some(TextureSampleType::Float { .. }) => kind == ScalarKind::Float,
+                Some(TextureSampleType::Sint) => kind == ScalarKind::Sint,
+                Some(TextureSampleType::Uint) => kind == ScalarKind::Uint,
+                _ => true,
+            };
+            if !matches {
+                return Err(LayoutMismatch::FragmentOutput {
+                    location,
+                    format: target.format,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A difference between a pipeline's shader and the layouts or targets it
+/// was given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutMismatch {
+    /// The shader uses a group beyond the bind group layouts given.
+    MissingGroup(u32),
+    MissingBinding {
+        group: u32,
+        binding: u32,
+    },
+    BindingType {
+        group: u32,
+        binding: u32,
+        shader: BindingType,
+        layout: BindingType,
+    },
+    /// The layout doesn't make a binding visible to every stage using it.
+    Visibility {
+        group: u32,
+        binding: u32,
+        shader: ShaderStages,
+        layout: ShaderStages,
+    },
+    /// No vertex buffer has an attribute for a shader location.
+    MissingVertexAttribute(u32),
+    VertexFormat {
+        location: u32,
+        format: VertexFormat,
+    },
+    FragmentOutput {
+        location: u32,
+        format: TextureFormat,
+    },
+}
+
+impl fmt::Display for LayoutMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutMismatch::MissingGroup(group) => {
+                write!(f, "shader uses @group({group}), which has no layout")
+            }
+            LayoutMismatch::MissingBinding { group, binding } => {
+                write!(
+                    f,
+                    "@group({group}) @binding({binding}) is missing from the layout"
+                )
+            }
+            LayoutMismatch::BindingType {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({group}) @binding({binding}) is {shader:?} in the shader but {layout:?} in the layout"
+            ),
+            LayoutMismatch::Visibility {
+                group,
+                binding,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "@group({group}) @binding({binding}) is used by {shader:?} but only visible to {layout:?}"
+            ),
+            LayoutMismatch::MissingVertexAttribute(location) => {
+                write!(f, "no vertex attribute for @location({location})")
+            }
+            LayoutMismatch::VertexFormat { location, format } => {
+                write!(
+                    f,
+                    "vertex attribute {format:?} doesn't match the type of @location({location})"
+                )
+            }
+            LayoutMismatch::FragmentOutput { location, format } => {
+                write!(
+                    f,
+                    "fragment output @location({location}) doesn't match the target format {format:?}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutMismatch {}
+
+/// Collects the locations of an entry point argument or result, looking into
+/// structs.
+fn locations(
+    module: &Module,
+    ty: naga::Handle<Type>,
+    binding: Option<&Binding>,
+    out: &mut Vec<(u32, ScalarKind)>,
+) {
+    let inner = &module.types[ty].inner;
+    match (binding, inner) {
+        (Some(Binding::Location { location, .. }), _) => {
+            if let Some(scalar) = inner.scalar() {
+                out.push((*location, scalar.kind));
+            }
+        }
+        (None, TypeInner::Struct { members, .. }) => {
+            for member in members {
+                locations(module, member.ty, member.binding.as_ref(), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The layout binding type of a global, and the size of binding arrays.
+fn binding_type(
+    module: &Module,
+    space: AddressSpace,
+    ty: &Type,
+) -> (BindingType, Option<NonZeroU32>) {
+    let (inner, count) = match ty.inner {
+        TypeInner::BindingArray { base, size } => (
+            &module.types[base].inner,
+            match size {
+                ArraySize::Constant(size) => Some(size),
+                // Runtime sized arrays need a count in the layout, which the
+                // shader doesn't say
+                _ => NonZeroU32::new(1),
+            },
+        ),
+        ref inner => (inner, None),
+    };
+
+    let ty = match (space, inner) {
+        (AddressSpace::Uniform, _) => BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(inner.size(module.to_ctx()).into()),
+        },
+        (AddressSpace::Storage { access }, _) => BindingType::Buffer {
+            ty: BufferBindingType::Storage {
+                read_only: !access.contains(StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            // Runtime sized arrays count as one element, like wgpu does
+            min_binding_size: NonZeroU64::new(inner.size(module.to_ctx()).into()),
+        },
+        (_, TypeInner::Sampler { comparison }) => BindingType::Sampler(if *comparison {
+            SamplerBindingType::Comparison
+        } else {
+            SamplerBindingType::Filtering
+        }),
+        (
+            _,
+            &TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = view_dimension(dim, arrayed);
+            match class {
+                ImageClass::Sampled { kind, multi } => BindingType::Texture {
+                    sample_type: match kind {
+                        ScalarKind::Sint => TextureSampleType::Sint,
+                        ScalarKind::Uint => TextureSampleType::Uint,
+                        _ => TextureSampleType::Float { filterable: !multi },
+                    },
+                    view_dimension,
+                    multisampled: multi,
+                },
+                ImageClass::Depth { multi } => BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: multi,
+                },
+                ImageClass::Storage { format, access } => BindingType::StorageTexture {
+                    access: match (
+                        access.contains(StorageAccess::LOAD),
+                        access.contains(StorageAccess::STORE),
+                    ) {
+                        (true, true) => StorageTextureAccess::ReadWrite,
+                        (true, false) => StorageTextureAccess::ReadOnly,
+                        _ => StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(format),
+                    view_dimension,
+                },
+            }
+        }
+        _ => BindingType::AccelerationStructure,
+    };
+    (ty, count)
+}
+
+fn array_stride(module: &Module, inner: &TypeInner) -> Option<u32> {
+    match inner {
+        TypeInner::Array { stride, .. } => Some(*stride),
+        TypeInner::Struct { members, .. } => {
+            let last = members.last()?;
+            match module.types[last.ty].inner {
+                TypeInner::Array { stride, .. } => Some(stride),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> TextureViewDimension {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => TextureViewDimension::D1,
+        (ImageDimension::D2, false) => TextureViewDimension::D2,
+        (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: StorageFormat) -> TextureFormat {
+    match format {
+        StorageFormat::R8Unorm => TextureFormat::R8Unorm,
+        StorageFormat::R8Snorm => TextureFormat::R8Snorm,
+        StorageFormat::R8Uint => TextureFormat::R8Uint,
+        StorageFormat::R8Sint => TextureFormat::R8Sint,
+        StorageFormat::R16Uint => TextureFormat::R16Uint,
+        StorageFormat::R16Sint => TextureFormat::R16Sint,
+        StorageFormat::R16Float => TextureFormat::R16Float,
+        StorageFormat::Rg8Unorm => TextureFormat::Rg8Unorm,
+        StorageFormat::Rg8Snorm => TextureFormat::Rg8Snorm,
+        StorageFormat::Rg8Uint => TextureFormat::Rg8Uint,
+        StorageFormat::Rg8Sint => TextureFormat::Rg8Sint,
+        StorageFormat::R32Uint => TextureFormat::R32Uint,
+        StorageFormat::R32Sint => TextureFormat::R32Sint,
+        StorageFormat::R32Float => TextureFormat::R32Float,
+        StorageFormat::Rg16Uint => TextureFormat::Rg16Uint,
+        StorageFormat::Rg16Sint => TextureFormat::Rg16Sint,
+        StorageFormat::Rg16Float => TextureFormat::Rg16Float,
+        StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        StorageFormat::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        StorageFormat::Rgba8Uint => TextureFormat::Rgba8Uint,
+        StorageFormat::Rgba8Sint => TextureFormat::Rgba8Sint,
+        StorageFormat::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+        StorageFormat::Rgb10a2Uint => TextureFormat::Rgb10a2Uint,
+        StorageFormat::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+        StorageFormat::Rg11b10Ufloat => TextureFormat::Rg11b10Ufloat,
+        StorageFormat::R64Uint => TextureFormat::R64Uint,
+        StorageFormat::Rg32Uint => TextureFormat::Rg32Uint,
+        StorageFormat::Rg32Sint => TextureFormat::Rg32Sint,
+        StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+        StorageFormat::Rgba16Uint => TextureFormat::Rgba16Uint,
+        StorageFormat::Rgba16Sint => TextureFormat::Rgba16Sint,
+        StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        StorageFormat::Rgba32Uint => TextureFormat::Rgba32Uint,
+        StorageFormat::Rgba32Sint => TextureFormat::Rgba32Sint,
+        StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+        StorageFormat::R16Unorm => TextureFormat::R16Unorm,
+        StorageFormat::R16Snorm => TextureFormat::R16Snorm,
+        StorageFormat::Rg16Unorm => TextureFormat::Rg16Unorm,
+        StorageFormat::Rg16Snorm => TextureFormat::Rg16Snorm,
+        StorageFormat::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+        StorageFormat::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+    }
+}
+
+/// Whether a layout entry of type `layout` can hold what the shader declares
+/// as `shader`.
+pub(super) fn binding_compatible(shader: &BindingType, layout: &BindingType) -> bool {
+    match (shader, layout) {
+        (BindingType::Buffer { ty: shader, .. }, BindingType::Buffer { ty: layout, .. }) => {
+            match (shader, layout) {
+                (
+                    BufferBindingType::Storage { read_only },
+                    BufferBindingType::Storage {
+                        read_only: layout_read_only,
+                    },
+                ) => *read_only || !layout_read_only,
+                _ => shader == layout,
+            }
+        }
+        (
+            BindingType::Texture {
+                sample_type: shader_sample_type,
+                view_dimension: shader_dimension,
+                multisampled: shader_multisampled,
+            },
+            BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            },
+        ) => {
+            let sample_type_matches = match (shader_sample_type, sample_type) {
+                (TextureSampleType::Float { .. }, TextureSampleType::Float { .. }) => true,
+                (shader, layout) => shader == layout,
+            };
+            sample_type_matches
+                && shader_dimension == view_dimension
+                && shader_multisampled == multisampled
+        }
+        (BindingType::Sampler(shader), BindingType::Sampler(layout)) => {
+            (*shader == SamplerBindingType::Comparison)
+                == (*layout == SamplerBindingType::Comparison)
+        }
+        (shader, layout) => shader == layout,
+    }
+}
+
+fn vertex_format_kind(format: VertexFormat) -> ScalarKind {
+    use VertexFormat::*;
+    match format {
+        Uint8 | Uint8x2 | Uint8x4 | Uint16 | Uint16x2 | Uint16x4 | Uint32 | Uint32x2 | Uint32x3
+        | Uint32x4 => ScalarKind::Uint,
+        Sint8 | Sint8x2 | Sint8x4 | Sint16 | Sint16x2 | Sint16x4 | Sint32 | Sint32x2 | Sint32x3
+        | Sint32x4 => ScalarKind::Sint,
+        // Normalized, half and double formats are read as floats
+        _ => ScalarKind::Float,
+    }
+}
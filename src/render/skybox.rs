@@ -0,0 +1,135 @@
+use cgmath::{SquareMatrix, Vector4};
+use wgpu::{
+    BindGroup, BindGroupLayout, Color, Device, LoadOp, Operations, RenderPassDescriptor,
+    RenderPipeline, ShaderStages, StoreOp, TextureFormat, TextureViewDimension,
+};
+
+use super::{
+    Pass, PassBuilder, PassContext, PipelineBuilder, RenderPipelineCache, ShaderSource,
+    TextureHandle, UniformBuffer,
+};
+use crate::{camera::Camera, texture::Texture};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+}
+
+impl SkyboxUniform {
+    fn new(camera: &Camera) -> Self {
+        // Only the camera's rotation matters, the sky is infinitely far
+        let mut view = camera.view_matrix();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let inverse = (camera.projection_matrix() * view)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        Self {
+            inverse_view_proj: inverse.into(),
+        }
+    }
+}
+
+/// Draws a cube map behind the scene, as seen by a camera.
+///
+/// The sky covers the whole target, the pass should run before the ones
+/// drawing the scene.
+pub struct SkyboxPass {
+    target: TextureHandle,
+    pipeline: RenderPipeline,
+    camera: UniformBuffer<SkyboxUniform>,
+    sky_layout: BindGroupLayout,
+    sky: BindGroup,
+}
+
+impl SkyboxPass {
+    pub fn new(
+        device: &Device,
+        cache: &RenderPipelineCache,
+        format: TextureFormat,
+        target: TextureHandle,
+        cubemap: &Texture,
+        camera: &Camera,
+    ) -> Self {
+        assert_eq!(
+            cubemap.view_dimension,
+            TextureViewDimension::Cube,
+            "skyboxes are drawn from cube maps"
+        );
+        let camera = UniformBuffer::new(
+            device,
+            Some("skybox camera"),
+            ShaderStages::VERTEX,
+            SkyboxUniform::new(camera),
+        )
+        .expect("skybox uniform is padded");
+        let sky_layout = cubemap.bind_group_layout(device, ShaderStages::FRAGMENT);
+        let pipeline = PipelineBuilder::with_sample_count(device, cache.sample_count())
+            .set_label("skybox")
+            .set_shader_module(
+                ShaderSource::Wgsl(include_str!("skybox.wgsl")),
+                "vs_main",
+                Some("fs_main"),
+            )
+            .add_bind_group_layouts(&[camera.bind_group_layout(), &sky_layout])
+            .set_pixel_format(format)
+            .build_cached(cache)
+            .expect("skybox pipeline is valid");
+        let sky = cubemap.bind_group(device, &sky_layout);
+
+        Self {
+            target,
+            pipeline,
+            camera,
+            sky_layout,
+            sky,
+        }
+    }
+
+    /// Looks through `camera` from the next frame on.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera.set(SkyboxUniform::new(camera));
+    }
+
+    /// Replaces the sky with another cube map of the same format.
+    pub fn set_cubemap(&mut self, device: &Device, cubemap: &Texture) {
+        assert_eq!(
+            cubemap.view_dimension,
+            TextureViewDimension::Cube,
+            "skyboxes are drawn from cube maps"
+        );
+        self.sky = cubemap.bind_group(device, &self.sky_layout);
+    }
+}
+
+impl Pass for SkyboxPass {
+    fn name(&self) -> &str {
+        "skybox"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.write_texture(self.target);
+    }
+
+    fn execute(&mut self, ctx: &mut PassContext) {
+        self.camera.write(ctx.queue);
+        let color = ctx.color_attachment(
+            self.target,
+            Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: StoreOp::Store,
+            },
+        );
+        let mut r_pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("skybox"),
+            color_attachments: &[Some(color)],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        r_pass.set_pipeline(&self.pipeline);
+        r_pass.set_bind_group(0, self.camera.bind_group(), &[]);
+        r_pass.set_bind_group(1, &self.sky, &[]);
+        r_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,30 @@
+// Draws a cube map as seen from the camera, at the far plane.
+
+struct Camera {
+    // Inverse view projection of the camera, without its translation
+    inverse_view_proj: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) direction: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var sky: texture_cube<f32>;
+@group(1) @binding(1) var sky_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    out.direction = camera.inverse_view_proj * out.position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(sky, sky_sampler, in.direction.xyz / in.direction.w);
+}
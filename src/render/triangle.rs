@@ -0,0 +1,105 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use wgpu::{
+    Color, Device, LoadOp, Operations, RenderPassDescriptor, RenderPipeline, StoreOp, TextureFormat,
+};
+
+use super::{
+    Pass, PassBuilder, PassContext, PipelineBuilder, ReloadablePipeline, RenderPipelineCache,
+    ShaderSource, TextureHandle,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{PipelineError, ShaderHotReload};
+
+/// Clears a texture to green and draws a red triangle into it.
+pub struct TrianglePass {
+    target: TextureHandle,
+    pipeline: ReloadablePipeline,
+}
+
+impl TrianglePass {
+    pub fn new(
+        device: &Device,
+        cache: &RenderPipelineCache,
+        format: TextureFormat,
+        target: TextureHandle,
+    ) -> Self {
+        Self {
+            target,
+            pipeline: builtin_pipeline(device, cache, format).into(),
+        }
+    }
+
+    /// Like [`TrianglePass::new`], with a shader read from `path` and
+    /// rebuilt when the file changes.
+    ///
+    /// While the file doesn't build, the shader built into the binary is
+    /// drawn instead and the error is reported to `hot_reload`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_file(
+        device: &Device,
+        cache: &RenderPipelineCache,
+        hot_reload: &ShaderHotReload,
+        path: &Path,
+        format: TextureFormat,
+        target: TextureHandle,
+    ) -> Result<Self, PipelineError> {
+        let pipeline = PipelineBuilder::with_sample_count(device, cache.sample_count())
+            .set_label("triangle")
+            .set_shader_module(ShaderSource::Path(path), "vs_main", Some("fs_main"))
+            .set_pixel_format(format)
+            .build_reloadable_or(cache, hot_reload, || {
+                builtin_pipeline(device, cache, format)
+            })?;
+
+        Ok(Self { target, pipeline })
+    }
+}
+
+/// The pipeline of the shader built into the binary.
+fn builtin_pipeline(
+    device: &Device,
+    cache: &RenderPipelineCache,
+    format: TextureFormat,
+) -> RenderPipeline {
+    PipelineBuilder::with_sample_count(device, cache.sample_count())
+        .set_label("triangle")
+        .set_shader_module(
+            ShaderSource::Wgsl(include_str!("../../assets/shaders/triangle.wgsl")),
+            "vs_main",
+            Some("fs_main"),
+        )
+        .set_pixel_format(format)
+        .build_cached(cache)
+        .expect("triangle pipeline is valid")
+}
+
+impl Pass for TrianglePass {
+    fn name(&self) -> &str {
+        "triangle"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.write_texture(self.target);
+    }
+
+    fn execute(&mut self, ctx: &mut PassContext) {
+        let color = ctx.color_attachment(
+            self.target,
+            Operations {
+                load: LoadOp::Clear(Color::GREEN),
+                store: StoreOp::Store,
+            },
+        );
+        let mut r_pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("triangle"),
+            color_attachments: &[Some(color)],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        r_pass.set_pipeline(&self.pipeline.get());
+        r_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{collections::HashSet, fmt, fs, path::PathBuf};
+
+use naga::{
+    back::glsl, valid::Capabilities, AddressSpace, EntryPoint, ImageClass, Module, ShaderStage,
+    TypeInner,
+};
+use wgpu::{Limits, ShaderStages};
+
+use super::{PreprocessedShader, ShaderError, ShaderPreprocessor, SourceLocation};
+
+const WEBGL2: &str = "WebGL2";
+
+/// Checks WGSL shaders without a device, so mistakes show up before the app
+/// creates its pipelines. Used by `render-rs validate-shaders`.
+///
+/// Shaders are preprocessed, validated with naga and must have the required
+/// entry points, `vs_main` and `fs_main` by default. Unless disabled, they are
+/// also checked against WebGL2: they must validate without optional
+/// capabilities, use no compute or storage resources, and translate to
+/// GLSL ES 3.0 like the GL backend does.
+#[derive(Clone, Debug)]
+pub struct ShaderValidator {
+    preprocessor: ShaderPreprocessor,
+    entry_points: Vec<(String, ShaderStages)>,
+    webgl2: bool,
+}
+
+impl Default for ShaderValidator {
+    fn default() -> Self {
+        Self {
+            preprocessor: ShaderPreprocessor::new(),
+            entry_points: vec![
+                ("vs_main".to_string(), ShaderStages::VERTEX),
+                ("fs_main".to_string(), ShaderStages::FRAGMENT),
+            ],
+            webgl2: true,
+        }
+    }
+}
+
+impl ShaderValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the preprocessor shaders are expanded with, for defines and
+    /// include directories.
+    pub fn set_preprocessor(&mut self, preprocessor: ShaderPreprocessor) -> &mut Self {
+        self.preprocessor = preprocessor;
+        self
+    }
+
+    /// Requires every shader to have an entry point `name` for `stage`.
+    pub fn require_entry_point(&mut self, name: &str, stage: ShaderStages) -> &mut Self {
+        self.entry_points.push((name.to_string(), stage));
+        self
+    }
+
+    /// Removes the required entry points, including the default ones.
+    pub fn clear_entry_points(&mut self) -> &mut Self {
+        self.entry_points.clear();
+        self
+    }
+
+    pub fn set_webgl2(&mut self, webgl2: bool) -> &mut Self {
+        self.webgl2 = webgl2;
+        self
+    }
+
+    /// Checks a preprocessed shader, returning every problem found.
+    pub fn validate(&self, shader: &PreprocessedShader) -> Vec<ShaderError> {
+        let module = match shader.validate() {
+            Ok((module, _)) => module,
+            Err(err) => return vec![err],
+        };
+        let mut errors: Vec<_> = (self.entry_points.iter())
+            .filter(|(name, stage)| {
+                !(module.entry_points.iter()).any(|entry_point| {
+                    entry_point.name == *name && shader_stage(entry_point.stage) == *stage
+                })
+            })
+            .map(|(name, _)| ShaderError::MissingEntryPoint(name.clone()))
+            .collect();
+        if self.webgl2 {
+            errors.extend(webgl2_errors(shader));
+        }
+        errors
+    }
+
+    /// Validates the `.wgsl` files at `paths`, searching directories
+    /// recursively.
+    ///
+    /// Files included by another of the shaders are checked as part of it
+    /// rather than on their own, as they usually lack entry points.
+    pub fn validate_files(&self, paths: impl IntoIterator<Item = PathBuf>) -> Vec<ShaderReport> {
+        let mut files = Vec::new();
+        for path in paths {
+            find_shaders(path, &mut files);
+        }
+        files.sort();
+        files.dedup();
+
+        let shaders: Vec<_> = (files.into_iter())
+            .map(|path| {
+                let shader = self.preprocessor.process_file(&path);
+                (path, shader)
+            })
+            .collect();
+        let included: HashSet<_> = (shaders.iter())
+            .filter_map(|(_, shader)| shader.as_ref().ok())
+            .flat_map(|shader| shader.paths().skip(1))
+            .collect();
+        (shaders.iter())
+            .filter(|(path, _)| {
+                let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                !included.contains(path.as_path())
+            })
+            .map(|(path, shader)| match shader {
+                Ok(shader) => ShaderReport {
+                    path: path.clone(),
+                    errors: self.validate(shader),
+                    shader: Some(shader.clone()),
+                },
+                Err(err) => ShaderReport {
+                    path: path.clone(),
+                    errors: vec![err.clone()],
+                    shader: None,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Adds `path` if it is a file, or the `.wgsl` files below it if it is a
+/// directory.
+fn find_shaders(path: PathBuf, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path);
+        return;
+    }
+    let Ok(entries) = fs::read_dir(&path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() || path.extension().is_some_and(|ext| ext == "wgsl") {
+            find_shaders(path, files);
+        }
+    }
+}
+
+fn shader_stage(stage: ShaderStage) -> ShaderStages {
+    match stage {
+        ShaderStage::Vertex => ShaderStages::VERTEX,
+        ShaderStage::Fragment => ShaderStages::FRAGMENT,
+        ShaderStage::Compute => ShaderStages::COMPUTE,
+    }
+}
+
+/// What keeps a valid shader from running on WebGL2.
+fn webgl2_errors(shader: &PreprocessedShader) -> Vec<ShaderError> {
+    let unsupported =
+        |message: String, labels: Vec<(SourceLocation, String)>| ShaderError::Unsupported {
+            target: WEBGL2,
+            message,
+            labels,
+        };
+    let (module, info) = match shader.validate_with(Capabilities::empty()) {
+        Ok(validated) => validated,
+        Err(ShaderError::Compile { message, labels }) => return vec![unsupported(message, labels)],
+        Err(err) => return vec![err],
+    };
+    let (module, info) = match naga::back::pipeline_constants::process_overrides(
+        &module,
+        &info,
+        &Default::default(),
+    ) {
+        Ok(processed) => processed,
+        Err(err) => return vec![unsupported(err.to_string(), Vec::new())],
+    };
+
+    let limits = Limits::downlevel_webgl2_defaults();
+    let mut errors = Vec::new();
+    for (handle, global) in module.global_variables.iter() {
+        let labels = || {
+            let span = module.global_variables.get_span(handle);
+            shader.map_labels(std::iter::once((span, "declared here")))
+        };
+        let name = global.name.as_deref().unwrap_or("_");
+        if let Some(binding) = &global.binding {
+            if binding.group >= limits.max_bind_groups {
+                errors.push(unsupported(
+                    format!(
+                        "{name} is in bind group {}, only {} are available",
+                        binding.group, limits.max_bind_groups
+                    ),
+                    labels(),
+                ));
+            }
+        }
+        let kind = match (global.space, &module.types[global.ty].inner) {
+            (AddressSpace::Storage { .. }, _) => "storage buffer",
+            (
+                AddressSpace::Handle,
+                TypeInner::Image {
+                    class: ImageClass::Storage { .. },
+                    ..
+                },
+            ) => "storage texture",
+            _ => continue,
+        };
+        let used = (0..module.entry_points.len())
+            .any(|index| !info.get_entry_point(index)[handle].is_empty());
+        if used {
+            errors.push(unsupported(format!("{kind} {name}"), labels()));
+        }
+    }
+
+    for entry_point in &module.entry_points {
+        if entry_point.stage == ShaderStage::Compute {
+            errors.push(unsupported(
+                format!("compute entry point {}", entry_point.name),
+                Vec::new(),
+            ));
+        } else if let Err(err) = write_glsl(&module, &info, entry_point) {
+            errors.push(unsupported(
+                format!("{}: {err}", entry_point.name),
+                Vec::new(),
+            ));
+        }
+    }
+    errors
+}
+
+/// Translates `entry_point` to GLSL ES 3.0 the way the GL backend does on
+/// WebGL2.
+fn write_glsl(
+    module: &Module,
+    info: &naga::valid::ModuleInfo,
+    entry_point: &EntryPoint,
+) -> Result<(), glsl::Error> {
+    let options = glsl::Options {
+        version: glsl::Version::Embedded {
+            version: 300,
+            is_webgl: true,
+        },
+        writer_flags: glsl::WriterFlags::ADJUST_COORDINATE_SPACE
+            | glsl::WriterFlags::FORCE_POINT_SIZE,
+        ..Default::default()
+    };
+    let pipeline_options = glsl::PipelineOptions {
+        shader_stage: entry_point.stage,
+        entry_point: entry_point.name.clone(),
+        multiview: None,
+    };
+    let mut output = String::new();
+    glsl::Writer::new(
+        &mut output,
+        module,
+        info,
+        &options,
+        &pipeline_options,
+        Default::default(),
+    )?
+    .write()?;
+    Ok(())
+}
+
+/// The problems found in one shader file by
+/// [`ShaderValidator::validate_files`].
+///
+/// Displays as diagnostics quoting the lines errors point to.
+#[derive(Clone, Debug)]
+pub struct ShaderReport {
+    pub path: PathBuf,
+    pub errors: Vec<ShaderError>,
+    shader: Option<PreprocessedShader>,
+}
+
+impl ShaderReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Writes `label` under the line `location` points to.
+    fn write_label(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        location: &SourceLocation,
+        label: &str,
+    ) -> fmt::Result {
+        write!(f, "\n  --> {location}")?;
+        let line = (self.shader.as_ref()).and_then(|shader| shader.source_line(location));
+        let Some(line) = line else {
+            return write!(f, "\n      {label}");
+        };
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Keep tabs so the marker lines up with the quoted line
+        let indent: String = (line.chars())
+            .take(location.column.saturating_sub(1) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(
+            f,
+            "\n {gutter} |\n {number} | {line}\n {gutter} | {indent}^ {label}"
+        )
+    }
+}
+
+impl fmt::Display for ShaderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "{}: ok", self.path.display());
+        }
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let (message, labels) = match error {
+                ShaderError::Compile { message, labels } => (message.clone(), labels.as_slice()),
+                ShaderError::Unsupported {
+                    target,
+                    message,
+                    labels,
+                } => (
+                    format!("not supported by {target}: {message}"),
+                    labels.as_slice(),
+                ),
+                ShaderError::MissingEntryPoint(_) => (error.to_string(), &[][..]),
+                _ => {
+                    write!(f, "error: {error}")?;
+                    continue;
+                }
+            };
+            write!(f, "error: {message}")?;
+            if labels.is_empty() {
+                write!(f, "\n  --> {}", self.path.display())?;
+            }
+            for (location, label) in labels {
+                self.write_label(f, location, label)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,612 @@
+//! Decoder for the ASTC block formats, LDR profile only.
+//!
+//! Blocks are 128 bits of any footprint from 4x4 to 12x12 texels. The
+//! decoder writes the texels row by row. Reserved or illegal blocks and
+//! partitions with HDR endpoints decode to magenta like they do on GPUs.
+
+use Packing::{Bits, Quints, Trits};
+
+/// Color of blocks that can't be decoded.
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+pub(super) fn decode(
+    block: &[u8],
+    width: usize,
+    height: usize,
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) {
+    let bits = u128::from_le_bytes(block[..16].try_into().expect("ASTC blocks are 16 bytes"));
+    let texels = &mut texels[..width * height];
+    if decode_block(bits, width, height, srgb, texels).is_none() {
+        texels.fill(ERROR_COLOR);
+    }
+}
+
+/// `count` bits of `bits` from bit `start` up.
+fn field(bits: u128, start: u32, count: u32) -> u32 {
+    ((bits >> start) & ((1 << count) - 1)) as u32
+}
+
+fn decode_block(
+    bits: u128,
+    width: usize,
+    height: usize,
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) -> Option<()> {
+    if field(bits, 0, 9) == 0x1fc {
+        return decode_void_extent(bits, texels);
+    }
+    let mode = BlockMode::parse(field(bits, 0, 11))?;
+    if mode.width > width || mode.height > height {
+        return None;
+    }
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = mode.width * mode.height * planes;
+    let weight_bits = mode.range.sequence_bits(weight_count);
+    if weight_count > 64 || !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    // The configuration that doesn't fit after the block mode is stored
+    // below the weights, which fill the block from the top
+    let partitions = field(bits, 11, 2) as usize + 1;
+    if partitions == 4 && mode.dual_plane {
+        return None;
+    }
+    let mut below_weights = 128 - weight_bits;
+    let (endpoint_modes, color_start) = if partitions == 1 {
+        ([field(bits, 13, 4); 4], 17)
+    } else {
+        let mut selector = field(bits, 23, 6);
+        let mut modes = [(selector >> 2) & 0xf; 4];
+        if selector & 3 != 0 {
+            let extra = 3 * partitions as u32 - 4;
+            below_weights -= extra;
+            selector |= field(bits, below_weights, extra) << 6;
+            let class = (selector & 3) - 1;
+            for (partition, mode) in modes[..partitions].iter_mut().enumerate() {
+                let offset = (selector >> (2 + partition)) & 1;
+                let low = (selector >> (2 + partitions + 2 * partition)) & 3;
+                *mode = ((class + offset) << 2) | low;
+            }
+        }
+        (modes, 29)
+    };
+    let plane_channel = if mode.dual_plane {
+        below_weights -= 2;
+        Some(field(bits, below_weights, 2) as usize)
+    } else {
+        None
+    };
+    let seed = field(bits, 13, 10);
+
+    let endpoint_modes = &endpoint_modes[..partitions];
+    let value_count: usize = endpoint_modes
+        .iter()
+        .map(|&mode| 2 * ((mode as usize >> 2) + 1))
+        .sum();
+    if value_count > 18 || below_weights < color_start {
+        return None;
+    }
+    let range = *COLOR_RANGES
+        .iter()
+        .find(|range| range.sequence_bits(value_count) <= below_weights - color_start)?;
+    let mut values = [0; 18];
+    let values = &mut values[..value_count];
+    decode_sequence(bits, color_start, range, values);
+    let mut endpoints = [([0; 4], [0; 4]); 4];
+    let mut offset = 0;
+    for (endpoint, &mode) in endpoints.iter_mut().zip(endpoint_modes) {
+        let count = 2 * ((mode as usize >> 2) + 1);
+        let colors = unquantize_colors(&values[offset..offset + count], range);
+        *endpoint = decode_endpoints(mode, &colors).unwrap_or((ERROR_COLOR, ERROR_COLOR));
+        offset += count;
+    }
+
+    // Weights are stored with their bits reversed from the top of the block
+    let mut weights = [0; 64];
+    let weights = &mut weights[..weight_count];
+    decode_sequence(bits.reverse_bits(), 0, mode.range, weights);
+    for weight in weights.iter_mut() {
+        *weight = unquantize_weight(*weight, mode.range);
+    }
+
+    let small = width * height < 31;
+    let ds = (1024 + width / 2) / (width - 1);
+    let dt = (1024 + height / 2) / (height - 1);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (s, t) = (i % width, i / width);
+        let gs = (ds * s * (mode.width - 1) + 32) >> 6;
+        let gt = (dt * t * (mode.height - 1) + 32) >> 6;
+        let (js, fs) = (gs >> 4, (gs & 0xf) as u32);
+        let (jt, ft) = (gt >> 4, (gt & 0xf) as u32);
+        let w11 = (fs * ft + 8) >> 4;
+        let factors = [16 + w11 - fs - ft, fs - w11, ft - w11, w11];
+        let first = js + jt * mode.width;
+        let grid = [first, first + 1, first + mode.width, first + mode.width + 1];
+        let weight = |plane: usize| {
+            let sum: u32 = grid
+                .iter()
+                .zip(factors)
+                .map(|(&index, factor)| {
+                    weights.get(index * planes + plane).copied().unwrap_or(0) * factor
+                })
+                .sum();
+            (sum + 8) >> 4
+        };
+        let plane_weights = [weight(0), if mode.dual_plane { weight(1) } else { 0 }];
+
+        let partition = select_partition(seed, s as u32, t as u32, partitions as u32, small);
+        let (e0, e1) = endpoints[partition];
+        for channel in 0..4 {
+            let weight = plane_weights[usize::from(plane_channel == Some(channel))];
+            let expand = |value: u8| {
+                let value = u32::from(value);
+                if srgb {
+                    (value << 8) | 0x80
+                } else {
+                    (value << 8) | value
+                }
+            };
+            let value =
+                (expand(e0[channel]) * (64 - weight) + expand(e1[channel]) * weight + 32) >> 6;
+            texel[channel] = (value >> 8) as u8;
+        }
+    }
+    Some(())
+}
+
+/// Fills a block with a single color.
+fn decode_void_extent(bits: u128, texels: &mut [[u8; 4]]) -> Option<()> {
+    // HDR colors
+    if field(bits, 9, 1) != 0 {
+        return None;
+    }
+    let extent = [
+        field(bits, 12, 13),
+        field(bits, 25, 13),
+        field(bits, 38, 13),
+        field(bits, 51, 13),
+    ];
+    if extent != [0x1fff; 4] && (extent[0] >= extent[1] || extent[2] >= extent[3]) {
+        return None;
+    }
+    let color = [0, 1, 2, 3].map(|channel| (field(bits, 64 + 16 * channel, 16) >> 8) as u8);
+    texels.fill(color);
+    Some(())
+}
+
+/// Size and precision of the weight grid.
+struct BlockMode {
+    width: usize,
+    height: usize,
+    dual_plane: bool,
+    range: Range,
+}
+
+impl BlockMode {
+    fn parse(mode: u32) -> Option<Self> {
+        let bit = |index: u32| (mode >> index) & 1;
+        let a = ((mode >> 5) & 3) as usize;
+        let (width, height, range, high, dual_plane);
+        if mode & 3 != 0 {
+            let b = ((mode >> 7) & 3) as usize;
+            (width, height) = match (mode >> 2) & 3 {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 0 => (a + 2, (b & 1) + 6),
+                _ => ((b & 1) + 2, a + 2),
+            };
+            range = bit(4) | ((mode & 3) << 1);
+            (high, dual_plane) = (bit(9), bit(10) == 1);
+        } else {
+            range = bit(4) | (((mode >> 2) & 3) << 1);
+            (high, dual_plane) = (bit(9), bit(10) == 1);
+            (width, height) = match (mode >> 7) & 3 {
+                0 => (12, a + 2),
+                1 => (a + 2, 12),
+                2 => {
+                    let b = ((mode >> 9) & 3) as usize;
+                    return (range >= 2).then(|| Self {
+                        width: a + 6,
+                        height: b + 6,
+                        dual_plane: false,
+                        range: WEIGHT_RANGES[0][range as usize - 2],
+                    });
+                }
+                _ => match a {
+                    0 => (6, 10),
+                    1 => (10, 6),
+                    _ => return None,
+                },
+            };
+        }
+        (range >= 2).then(|| Self {
+            width,
+            height,
+            dual_plane,
+            range: WEIGHT_RANGES[high as usize][range as usize - 2],
+        })
+    }
+}
+
+/// How the integers of a sequence are packed.
+#[derive(Clone, Copy)]
+enum Packing {
+    Bits,
+    /// Five integers share eight bits holding their base 3 digits.
+    Trits,
+    /// Three integers share seven bits holding their base 5 digits.
+    Quints,
+}
+
+/// Range of the integers of a sequence, `bits` low bits and a trit or quint.
+#[derive(Clone, Copy)]
+struct Range {
+    packing: Packing,
+    bits: u32,
+}
+
+impl Range {
+    const fn new(packing: Packing, bits: u32) -> Self {
+        Self { packing, bits }
+    }
+
+    fn sequence_bits(self, count: usize) -> u32 {
+        let count = count as u32;
+        count * self.bits
+            + match self.packing {
+                Packing::Bits => 0,
+                Packing::Trits => (8 * count).div_ceil(5),
+                Packing::Quints => (7 * count).div_ceil(3),
+            }
+    }
+}
+
+/// Weight ranges by the high precision bit and the range bits minus 2.
+const WEIGHT_RANGES: [[Range; 6]; 2] = [
+    [
+        Range::new(Bits, 1),
+        Range::new(Trits, 0),
+        Range::new(Bits, 2),
+        Range::new(Quints, 0),
+        Range::new(Trits, 1),
+        Range::new(Bits, 3),
+    ],
+    [
+        Range::new(Quints, 1),
+        Range::new(Trits, 2),
+        Range::new(Bits, 4),
+        Range::new(Quints, 2),
+        Range::new(Trits, 3),
+        Range::new(Bits, 5),
+    ],
+];
+
+/// Endpoint ranges from 256 down to 6 values, the first that fits is used.
+const COLOR_RANGES: [Range; 17] = [
+    Range::new(Bits, 8),
+    Range::new(Trits, 6),
+    Range::new(Quints, 5),
+    Range::new(Bits, 7),
+    Range::new(Trits, 5),
+    Range::new(Quints, 4),
+    Range::new(Bits, 6),
+    Range::new(Trits, 4),
+    Range::new(Quints, 3),
+    Range::new(Bits, 5),
+    Range::new(Trits, 3),
+    Range::new(Quints, 2),
+    Range::new(Bits, 4),
+    Range::new(Trits, 2),
+    Range::new(Quints, 1),
+    Range::new(Bits, 3),
+    Range::new(Trits, 1),
+];
+
+/// Decodes the integer sequence of `values.len()` integers at `start`.
+///
+/// The last trit or quint group can be cut short, its missing bits are zero.
+fn decode_sequence(bits: u128, start: u32, range: Range, values: &mut [u32]) {
+    let end = start + range.sequence_bits(values.len());
+    let mut position = start;
+    let mut read = |count: u32| {
+        let value = if position >= end {
+            0
+        } else {
+            field(bits, position, count.min(end - position))
+        };
+        position += count;
+        value
+    };
+    match range.packing {
+        Packing::Bits => values
+            .iter_mut()
+            .for_each(|value| *value = read(range.bits)),
+        Packing::Trits => {
+            for group in values.chunks_mut(5) {
+                let (mut low, mut packed) = ([0; 5], 0);
+                let mut shift = 0;
+                for (i, count) in [2, 2, 1, 2, 1].into_iter().enumerate() {
+                    low[i] = read(range.bits);
+                    packed |= read(count) << shift;
+                    shift += count;
+                }
+                for ((value, trit), low) in group.iter_mut().zip(trits(packed)).zip(low) {
+                    *value = (trit << range.bits) | low;
+                }
+            }
+        }
+        Packing::Quints => {
+            for group in values.chunks_mut(3) {
+                let (mut low, mut packed) = ([0; 3], 0);
+                let mut shift = 0;
+                for (i, count) in [3, 2, 2].into_iter().enumerate() {
+                    low[i] = read(range.bits);
+                    packed |= read(count) << shift;
+                    shift += count;
+                }
+                for ((value, quint), low) in group.iter_mut().zip(quints(packed)).zip(low) {
+                    *value = (quint << range.bits) | low;
+                }
+            }
+        }
+    }
+}
+
+/// The five trits packed in eight bits.
+fn trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, index: u32| (value >> index) & 1;
+    let (c, t3, t4) = if (t >> 2) & 7 == 7 {
+        ((((t >> 5) & 7) << 2) | (t & 3), 2, 2)
+    } else if (t >> 5) & 3 == 3 {
+        (t & 0x1f, bit(t, 7), 2)
+    } else {
+        (t & 0x1f, (t >> 5) & 3, bit(t, 7))
+    };
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (
+            (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1),
+            bit(c, 4),
+            2,
+        )
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (
+            (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1),
+            (c >> 2) & 3,
+            bit(c, 4),
+        )
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+/// The three quints packed in seven bits.
+fn quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, index: u32| (value >> index) & 1;
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let not0 = !q & 1;
+        let q2 = (bit(q, 0) << 2) | ((bit(q, 4) & not0) << 1) | (bit(q, 3) & not0);
+        return [4, 4, q2];
+    }
+    let (c, q2) = if (q >> 1) & 3 == 3 {
+        ((((q >> 3) & 3) << 3) | ((!q >> 5) & 3) << 1 | (q & 1), 4)
+    } else {
+        (q & 0x1f, (q >> 5) & 3)
+    };
+    if c & 7 == 5 {
+        [(c >> 3) & 3, 4, q2]
+    } else {
+        [c & 7, (c >> 3) & 3, q2]
+    }
+}
+
+/// Repeats the `bits` low bits of `value` to fill `to` bits.
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        let shift = to as i32 - filled as i32 - bits as i32;
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+        filled += bits;
+    }
+    result
+}
+
+/// Low bits and the trit or quint of an integer, and its lowest bit spread
+/// over `bits` bits.
+fn split(value: u32, range: Range, bits: u32) -> (u32, u32, u32) {
+    let low = value & ((1 << range.bits) - 1);
+    let spread = if low & 1 != 0 { (1 << bits) - 1 } else { 0 };
+    (value >> range.bits, low >> 1, spread)
+}
+
+/// The endpoint values of a partition in 0..=255, padded with zeros.
+fn unquantize_colors(values: &[u32], range: Range) -> [u8; 8] {
+    let mut colors = [0; 8];
+    for (color, &value) in colors.iter_mut().zip(values) {
+        *color = unquantize_color(value, range);
+    }
+    colors
+}
+
+fn unquantize_color(value: u32, range: Range) -> u8 {
+    let (digit, high, spread) = split(value, range, 9);
+    let (b, c) = match (range.packing, range.bits) {
+        (Packing::Bits, bits) => return replicate(value, bits, 8) as u8,
+        (Packing::Trits, 1) => (0, 204),
+        (Packing::Trits, 2) => ((high << 8) | (high << 4) | (high << 2) | (high << 1), 93),
+        (Packing::Trits, 3) => ((high << 7) | (high << 2) | high, 44),
+        (Packing::Trits, 4) => ((high << 6) | high, 22),
+        (Packing::Trits, 5) => ((high << 5) | (high >> 2), 11),
+        (Packing::Trits, _) => ((high << 4) | (high >> 4), 5),
+        (Packing::Quints, 1) => (0, 113),
+        (Packing::Quints, 2) => ((high << 8) | (high << 3) | (high << 2), 54),
+        (Packing::Quints, 3) => ((high << 7) | (high << 1) | (high >> 1), 26),
+        (Packing::Quints, 4) => ((high << 6) | (high >> 1), 13),
+        (Packing::Quints, _) => ((high << 5) | (high >> 3), 6),
+    };
+    let t = (digit * c + b) ^ spread;
+    ((spread & 0x80) | (t >> 2)) as u8
+}
+
+/// A weight in 0..=64.
+fn unquantize_weight(value: u32, range: Range) -> u32 {
+    let (digit, high, spread) = split(value, range, 7);
+    let weight = match (range.packing, range.bits) {
+        (Packing::Bits, bits) => replicate(value, bits, 6),
+        (Packing::Trits, 0) => [0, 32, 63][digit as usize],
+        (Packing::Quints, 0) => [0, 16, 32, 47, 63][digit as usize],
+        (packing, bits) => {
+            let (b, c) = match (packing, bits) {
+                (Packing::Trits, 1) => (0, 50),
+                (Packing::Trits, 2) => ((high << 6) | (high << 2) | high, 23),
+                (Packing::Trits, _) => ((high << 5) | high, 11),
+                (Packing::Quints, 1) => (0, 28),
+                _ => ((high << 6) | (high << 1), 13),
+            };
+            let t = (digit * c + b) ^ spread;
+            (spread & 0x20) | (t >> 2)
+        }
+    };
+    if weight > 32 {
+        weight + 1
+    } else {
+        weight
+    }
+}
+
+/// The two endpoint colors of an LDR endpoint mode.
+fn decode_endpoints(mode: u32, values: &[u8; 8]) -> Option<([u8; 4], [u8; 4])> {
+    let [v0, v1, v2, v3, v4, v5, v6, v7] = values.map(i32::from);
+    let blue_contract = |[r, g, b, a]: [i32; 4]| [(r + b) >> 1, (g + b) >> 1, b, a];
+    let (e0, e1) = match mode {
+        // Luminance
+        0 => ([v0, v0, v0, 255], [v1, v1, v1, 255]),
+        1 => {
+            let l0 = (v0 >> 2) | (v1 & 0xc0);
+            let l1 = (l0 + (v1 & 0x3f)).min(255);
+            ([l0, l0, l0, 255], [l1, l1, l1, 255])
+        }
+        // Luminance and alpha
+        4 => ([v0, v0, v0, v2], [v1, v1, v1, v3]),
+        5 => {
+            let (v1, v0) = bit_transfer_signed(v1, v0);
+            let (v3, v2) = bit_transfer_signed(v3, v2);
+            let l1 = v0 + v1;
+            ([v0, v0, v0, v2], [l1, l1, l1, v2 + v3])
+        }
+        // RGB with a scaled base
+        6 => (
+            [(v0 * v3) >> 8, (v1 * v3) >> 8, (v2 * v3) >> 8, 255],
+            [v0, v1, v2, 255],
+        ),
+        10 => (
+            [(v0 * v3) >> 8, (v1 * v3) >> 8, (v2 * v3) >> 8, v4],
+            [v0, v1, v2, v5],
+        ),
+        // RGB and RGBA
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v6, v7) } else { (255, 255) };
+            if v1 + v3 + v5 >= v0 + v2 + v4 {
+                ([v0, v2, v4, a0], [v1, v3, v5, a1])
+            } else {
+                (
+                    blue_contract([v1, v3, v5, a1]),
+                    blue_contract([v0, v2, v4, a0]),
+                )
+            }
+        }
+        9 | 13 => {
+            let (v1, v0) = bit_transfer_signed(v1, v0);
+            let (v3, v2) = bit_transfer_signed(v3, v2);
+            let (v5, v4) = bit_transfer_signed(v5, v4);
+            let (a0, a1) = if mode == 13 {
+                let (v7, v6) = bit_transfer_signed(v7, v6);
+                (v6, v6 + v7)
+            } else {
+                (255, 255)
+            };
+            let base = [v0, v2, v4, a0];
+            let offset = [v0 + v1, v2 + v3, v4 + v5, a1];
+            if v1 + v3 + v5 >= 0 {
+                (base, offset)
+            } else {
+                (blue_contract(offset), blue_contract(base))
+            }
+        }
+        // HDR
+        _ => return None,
+    };
+    let clamp = |color: [i32; 4]| color.map(|value| value.clamp(0, 255) as u8);
+    Some((clamp(e0), clamp(e1)))
+}
+
+/// Moves the top bit of `a` to `b` and makes `a` a signed 6-bit offset.
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3f;
+    let a = if a & 0x20 != 0 { a - 0x40 } else { a };
+    (a, b)
+}
+
+/// Partition of texel `x`, `y` with the pattern picked by `seed`.
+fn select_partition(seed: u32, x: u32, y: u32, partitions: u32, small: bool) -> usize {
+    if partitions == 1 {
+        return 0;
+    }
+    let (x, y) = if small { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partitions - 1) * 1024;
+    let random = hash52(seed);
+    // 3D textures also use the nibbles from bit 18 on
+    let mut seeds = [0, 4, 8, 12, 16, 20, 24, 28].map(|shift| {
+        let nibble = (random >> shift) & 0xf;
+        nibble * nibble
+    });
+    let (shift1, shift2) = if seed & 1 != 0 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    for (i, seed) in seeds.iter_mut().enumerate() {
+        *seed >>= if i % 2 == 0 { shift1 } else { shift2 };
+    }
+    let mut scores = [14, 10, 6, 2].map(|shift| random >> shift);
+    for (i, score) in scores.iter_mut().enumerate() {
+        *score = (seeds[2 * i] * x + seeds[2 * i + 1] * y + *score) & 0x3f;
+    }
+    let scores = &scores[..partitions as usize];
+    let best = *scores.iter().max().expect("blocks have a partition");
+    scores
+        .iter()
+        .position(|&score| score == best)
+        .expect("the best score is a score")
+}
+
+fn hash52(mut p: u32) -> u32 {
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
@@ -0,0 +1,802 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use image::{Rgba, RgbaImage};
+use wgpu::{
+    AddressMode, Device, Extent3d, FilterMode, Origin3d, Queue, Sampler, SamplerDescriptor,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+use super::TextureError;
+
+#[derive(Debug)]
+pub enum AtlasError {
+    /// An image with the name is already in the atlas.
+    Duplicate(String),
+    /// The image has no texels.
+    Empty(String),
+    /// The image and its margins don't fit in a page.
+    TooLarge { width: u32, height: u32, max: u32 },
+    /// Every page is full and no more may be added.
+    Full,
+    /// Pages can't be larger than the device's textures.
+    PageSize { size: u32, max: u32 },
+    /// The image could not be decoded.
+    Texture(TextureError),
+    /// A serialized layout is malformed.
+    Layout { line: usize, message: String },
+    /// A restored layout has a different number of pages than images given.
+    PageCount { layout: u32, images: usize },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Duplicate(name) => write!(f, "image `{name}` is already in the atlas"),
+            AtlasError::Empty(name) => write!(f, "image `{name}` is empty"),
+            AtlasError::TooLarge { width, height, max } => write!(
+                f,
+                "image of {width}x{height} with its margins doesn't fit a page of {max}x{max}"
+            ),
+            AtlasError::Full => write!(f, "all atlas pages are full"),
+            AtlasError::PageSize { size, max } => write!(
+                f,
+                "atlas pages of {size}x{size} exceed the maximum texture size of {max}"
+            ),
+            AtlasError::Texture(err) => write!(f, "{err}"),
+            AtlasError::Layout { line, message } => {
+                write!(f, "invalid atlas layout at line {line}: {message}")
+            }
+            AtlasError::PageCount { layout, images } => write!(
+                f,
+                "atlas layout has {layout} pages but {images} page images were given"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtlasError::Texture(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<TextureError> for AtlasError {
+    fn from(err: TextureError) -> Self {
+        AtlasError::Texture(err)
+    }
+}
+
+/// How an atlas lays out and samples its pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasOptions {
+    /// Width and height of each page in texels.
+    pub page_size: u32,
+    /// Transparent texels around each image, beyond its extrusion.
+    pub padding: u32,
+    /// Texels the edges of each image are repeated outwards, so filtering
+    /// at the edges doesn't pick up the neighbours.
+    pub extrude: u32,
+    /// Pages that may be created, `None` for as many as needed.
+    pub max_pages: Option<u32>,
+    /// Whether texels are sRGB encoded colors.
+    pub srgb: bool,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            page_size: 1024,
+            padding: 1,
+            extrude: 1,
+            max_pages: None,
+            srgb: true,
+        }
+    }
+}
+
+impl AtlasOptions {
+    /// Texels between the edge of a cell and its image.
+    fn margin(&self) -> u32 {
+        self.padding + self.extrude
+    }
+
+    fn format(&self) -> TextureFormat {
+        if self.srgb {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        }
+    }
+}
+
+/// A rectangle of texels in an atlas page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Texture coordinates of an image in its page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+/// Where an image was placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasEntry {
+    pub page: u32,
+    /// The image's texels, without padding and extrusion.
+    pub rect: AtlasRect,
+    pub uv: UvRect,
+}
+
+impl AtlasEntry {
+    fn new(page: u32, rect: AtlasRect, page_size: u32) -> Self {
+        let size = page_size as f32;
+        Self {
+            page,
+            rect,
+            uv: UvRect {
+                min: [rect.x as f32 / size, rect.y as f32 / size],
+                max: [
+                    (rect.x + rect.width) as f32 / size,
+                    (rect.y + rect.height) as f32 / size,
+                ],
+            },
+        }
+    }
+}
+
+/// A row of cells of at most its height, with the spans that are free.
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Start and width of free spans, ordered and never adjacent.
+    free: Vec<(u32, u32)>,
+}
+
+impl Shelf {
+    fn new(y: u32, height: u32, width: u32) -> Self {
+        Self {
+            y,
+            height,
+            free: vec![(0, width)],
+        }
+    }
+
+    /// Takes the first free span wide enough.
+    fn allocate(&mut self, width: u32) -> Option<u32> {
+        let index = self.free.iter().position(|&(_, free)| free >= width)?;
+        let (x, free) = self.free[index];
+        if free == width {
+            self.free.remove(index);
+        } else {
+            self.free[index] = (x + width, free - width);
+        }
+        Some(x)
+    }
+
+    /// Takes a given span, failing if any of it is in use.
+    fn reserve(&mut self, x: u32, width: u32) -> bool {
+        let Some(index) = self
+            .free
+            .iter()
+            .position(|&(start, free)| start <= x && x + width <= start + free)
+        else {
+            return false;
+        };
+        let (start, free) = self.free.remove(index);
+        let after = start + free - (x + width);
+        if after > 0 {
+            self.free.insert(index, (x + width, after));
+        }
+        if x > start {
+            self.free.insert(index, (start, x - start));
+        }
+        true
+    }
+
+    fn release(&mut self, x: u32, width: u32) {
+        let index = self.free.partition_point(|&(start, _)| start < x);
+        self.free.insert(index, (x, width));
+        // Merge with the following span, then the preceding one
+        if index + 1 < self.free.len() && x + width == self.free[index + 1].0 {
+            self.free[index].1 += self.free.remove(index + 1).1;
+        }
+        if index > 0 {
+            let (start, free) = self.free[index - 1];
+            if start + free == x {
+                self.free[index - 1].1 += self.free.remove(index).1;
+            }
+        }
+    }
+
+    fn is_empty(&self, page_size: u32) -> bool {
+        self.free == [(0, page_size)]
+    }
+}
+
+/// Shelf packing of the cells of one page.
+///
+/// Cells go on the shelf they waste the least height on, or a new shelf at
+/// the bottom. Released cells free their span for cells of at most the
+/// shelf's height, and empty shelves at the bottom are dropped.
+#[derive(Debug)]
+struct ShelfPacker {
+    size: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            shelves: Vec::new(),
+        }
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut candidates: Vec<usize> = (0..self.shelves.len())
+            .filter(|&i| self.shelves[i].height >= height)
+            .collect();
+        candidates.sort_by_key(|&i| self.shelves[i].height - height);
+        for i in candidates {
+            if let Some(x) = self.shelves[i].allocate(width) {
+                return Some((x, self.shelves[i].y));
+            }
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        if y + height > self.size {
+            return None;
+        }
+        let mut shelf = Shelf::new(y, height, self.size);
+        let x = shelf.allocate(width)?;
+        self.shelves.push(shelf);
+        Some((x, y))
+    }
+
+    /// Takes a cell at a given position, for restoring a layout.
+    fn reserve(&mut self, cell: AtlasRect) -> bool {
+        if cell.x + cell.width > self.size || cell.y + cell.height > self.size {
+            return false;
+        }
+        let index = self.shelves.partition_point(|shelf| shelf.y < cell.y);
+        if self
+            .shelves
+            .get(index)
+            .is_none_or(|shelf| shelf.y != cell.y)
+        {
+            self.shelves
+                .insert(index, Shelf::new(cell.y, cell.height, self.size));
+        }
+        let shelf = &mut self.shelves[index];
+        shelf.height = shelf.height.max(cell.height);
+        let overlaps_next = self
+            .shelves
+            .get(index + 1)
+            .is_some_and(|next| cell.y + cell.height > next.y);
+        let overlaps_previous = index > 0 && {
+            let previous = &self.shelves[index - 1];
+            previous.y + previous.height > cell.y
+        };
+        !overlaps_next && !overlaps_previous && self.shelves[index].reserve(cell.x, cell.width)
+    }
+
+    fn release(&mut self, cell: AtlasRect) {
+        let Some(shelf) = self.shelves.iter_mut().find(|shelf| shelf.y == cell.y) else {
+            return;
+        };
+        shelf.release(cell.x, cell.width);
+        while self
+            .shelves
+            .last()
+            .is_some_and(|shelf| shelf.is_empty(self.size))
+        {
+            self.shelves.pop();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AtlasPage {
+    texture: wgpu::Texture,
+    view: TextureView,
+    packer: ShelfPacker,
+}
+
+/// Many images packed into a few textures, so they can be drawn without
+/// switching bind groups.
+///
+/// Images are placed on pages of a fixed size with shelf packing, padded and
+/// extruded to keep filtering from bleeding between them. Pages have no
+/// mips, which would blend neighbouring images.
+#[derive(Debug)]
+pub struct TextureAtlas {
+    options: AtlasOptions,
+    pages: Vec<AtlasPage>,
+    entries: HashMap<String, AtlasEntry>,
+    sampler: Sampler,
+    label: Option<String>,
+}
+
+impl TextureAtlas {
+    pub fn new(
+        device: &Device,
+        label: Option<&str>,
+        options: AtlasOptions,
+    ) -> Result<Self, AtlasError> {
+        let max = device.limits().max_texture_dimension_2d;
+        if options.page_size > max {
+            return Err(AtlasError::PageSize {
+                size: options.page_size,
+                max,
+            });
+        }
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Self {
+            options,
+            pages: Vec::new(),
+            entries: HashMap::new(),
+            sampler,
+            label: label.map(str::to_string),
+        })
+    }
+
+    /// Recreates an atlas from a layout and images of its pages, such as
+    /// ones baked offline.
+    pub fn from_layout(
+        device: &Device,
+        queue: &Queue,
+        label: Option<&str>,
+        layout: &AtlasLayout,
+        pages: &[RgbaImage],
+        srgb: bool,
+    ) -> Result<Self, AtlasError> {
+        let margin = layout_margin(layout.padding, layout.extrude)?;
+        if pages.len() != layout.pages as usize {
+            return Err(AtlasError::PageCount {
+                layout: layout.pages,
+                images: pages.len(),
+            });
+        }
+        let options = AtlasOptions {
+            page_size: layout.page_size,
+            padding: layout.padding,
+            extrude: layout.extrude,
+            max_pages: None,
+            srgb,
+        };
+        let mut atlas = Self::new(device, label, options)?;
+        for image in pages {
+            if image.dimensions() != (layout.page_size, layout.page_size) {
+                return Err(AtlasError::Layout {
+                    line: 1,
+                    message: format!(
+                        "page images must be {0}x{0}, found {1}x{2}",
+                        layout.page_size,
+                        image.width(),
+                        image.height()
+                    ),
+                });
+            }
+            let page = atlas.add_page(device);
+            write_rect(queue, &page.texture, 0, 0, image);
+        }
+        for (index, (name, entry)) in layout.entries.iter().enumerate() {
+            // Parsed layouts were checked with the lines they came from, the
+            // entries of others are on the lines they would be written to
+            let line = index + 2;
+            if atlas.entries.contains_key(name) {
+                return Err(AtlasError::Layout {
+                    line,
+                    message: format!("`{name}` is listed twice"),
+                });
+            }
+            let page = atlas.pages.get_mut(entry.page as usize);
+            let packer = page.map(|page| &mut page.packer);
+            reserve_cell(packer, margin, line, name, entry.page, entry.rect)?;
+            atlas.entries.insert(name.clone(), *entry);
+        }
+        Ok(atlas)
+    }
+
+    pub fn options(&self) -> &AtlasOptions {
+        &self.options
+    }
+
+    /// Decodes an image file and inserts it.
+    pub fn insert_bytes(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<AtlasEntry, AtlasError> {
+        let image = image::load_from_memory(bytes).map_err(TextureError::from)?;
+        self.insert(device, queue, name, &image.to_rgba8())
+    }
+
+    /// Packs an image into the first page with room, adding a page when none
+    /// has any.
+    pub fn insert(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        name: &str,
+        image: &RgbaImage,
+    ) -> Result<AtlasEntry, AtlasError> {
+        if self.entries.contains_key(name) {
+            return Err(AtlasError::Duplicate(name.to_string()));
+        }
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Err(AtlasError::Empty(name.to_string()));
+        }
+        let margin = self.options.margin();
+        let (cell_width, cell_height) = (width + 2 * margin, height + 2 * margin);
+        if cell_width > self.options.page_size || cell_height > self.options.page_size {
+            return Err(AtlasError::TooLarge {
+                width,
+                height,
+                max: self.options.page_size,
+            });
+        }
+
+        let placed = self.pages.iter_mut().enumerate().find_map(|(index, page)| {
+            let (x, y) = page.packer.allocate(cell_width, cell_height)?;
+            Some((index, x, y))
+        });
+        let (page, x, y) = match placed {
+            Some(placed) => placed,
+            None => {
+                if self
+                    .options
+                    .max_pages
+                    .is_some_and(|max| self.pages.len() as u32 >= max)
+                {
+                    return Err(AtlasError::Full);
+                }
+                let page = self.add_page(device);
+                let (x, y) = page
+                    .packer
+                    .allocate(cell_width, cell_height)
+                    .expect("an empty page fits a cell of at most its size");
+                (self.pages.len() - 1, x, y)
+            }
+        };
+
+        let cell = extrude(image, self.options.extrude, self.options.padding);
+        write_rect(queue, &self.pages[page].texture, x, y, &cell);
+        let rect = AtlasRect {
+            x: x + margin,
+            y: y + margin,
+            width,
+            height,
+        };
+        let entry = AtlasEntry::new(page as u32, rect, self.options.page_size);
+        self.entries.insert(name.to_string(), entry);
+        Ok(entry)
+    }
+
+    /// Frees the space of an image for later insertions.
+    ///
+    /// Its texels stay in the page until they are overwritten.
+    pub fn remove(&mut self, name: &str) -> Option<AtlasEntry> {
+        let entry = self.entries.remove(name)?;
+        let cell = cell(entry.rect, self.options.margin()).expect("entries fit their page");
+        self.pages[entry.page as usize].packer.release(cell);
+        Some(entry)
+    }
+
+    /// Removes every image, keeping the pages.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        for page in &mut self.pages {
+            page.packer = ShelfPacker::new(self.options.page_size);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AtlasEntry> {
+        self.entries.get(name)
+    }
+
+    pub fn uv(&self, name: &str) -> Option<UvRect> {
+        self.get(name).map(|entry| entry.uv)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &AtlasEntry)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    pub fn page_texture(&self, page: u32) -> Option<&wgpu::Texture> {
+        self.pages.get(page as usize).map(|page| &page.texture)
+    }
+
+    pub fn page_view(&self, page: u32) -> Option<&TextureView> {
+        self.pages.get(page as usize).map(|page| &page.view)
+    }
+
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    /// The placement of every image, to save with the page images.
+    pub fn layout(&self) -> AtlasLayout {
+        let mut entries: Vec<(String, AtlasEntry)> = self
+            .entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), *entry))
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        AtlasLayout {
+            page_size: self.options.page_size,
+            padding: self.options.padding,
+            extrude: self.options.extrude,
+            pages: self.page_count(),
+            entries,
+        }
+    }
+
+    fn add_page(&mut self, device: &Device) -> &mut AtlasPage {
+        let size = self.options.page_size;
+        let texture = device.create_texture(&TextureDescriptor {
+            label: self.label.as_deref(),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: self.options.format(),
+            // Copyable from so pages can be saved
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        self.pages.push(AtlasPage {
+            texture,
+            view,
+            packer: ShelfPacker::new(size),
+        });
+        self.pages.last_mut().expect("page was just added")
+    }
+}
+
+/// The cell of an image at `rect` with its margins, `None` when it would
+/// reach outside of the range of texel coordinates.
+fn cell(rect: AtlasRect, margin: u32) -> Option<AtlasRect> {
+    let cell = AtlasRect {
+        x: rect.x.checked_sub(margin)?,
+        y: rect.y.checked_sub(margin)?,
+        width: rect.width.checked_add(margin.checked_mul(2)?)?,
+        height: rect.height.checked_add(margin.checked_mul(2)?)?,
+    };
+    cell.x.checked_add(cell.width)?;
+    cell.y.checked_add(cell.height)?;
+    Some(cell)
+}
+
+/// Texels between the edge of a cell and its image in a layout.
+fn layout_margin(padding: u32, extrude: u32) -> Result<u32, AtlasError> {
+    padding
+        .checked_add(extrude)
+        .ok_or_else(|| AtlasError::Layout {
+            line: 1,
+            message: "padding and extrusion are too large".to_string(),
+        })
+}
+
+/// Takes the cell of an image of a layout on line `line` from the packer of
+/// its page.
+fn reserve_cell(
+    packer: Option<&mut ShelfPacker>,
+    margin: u32,
+    line: usize,
+    name: &str,
+    page: u32,
+    rect: AtlasRect,
+) -> Result<(), AtlasError> {
+    let error = |message| Err(AtlasError::Layout { line, message });
+    let Some(packer) = packer else {
+        return error(format!("page {page} doesn't exist"));
+    };
+    if rect.width == 0 || rect.height == 0 {
+        return error(format!("`{name}` is empty"));
+    }
+    match cell(rect, margin) {
+        Some(cell) if packer.reserve(cell) => Ok(()),
+        _ => error(format!("`{name}` overlaps another image or its page")),
+    }
+}
+
+/// The image surrounded by `extrude` copies of its edge texels and `padding`
+/// transparent ones.
+fn extrude(image: &RgbaImage, extrude: u32, padding: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let margin = extrude + padding;
+    RgbaImage::from_fn(width + 2 * margin, height + 2 * margin, |x, y| {
+        let inside = |c: u32, size: u32| c >= padding && c < size + 2 * extrude + padding;
+        if !inside(x, width) || !inside(y, height) {
+            return Rgba([0; 4]);
+        }
+        let source = |c: u32, size: u32| (c - padding).saturating_sub(extrude).min(size - 1);
+        *image.get_pixel(source(x, width), source(y, height))
+    })
+}
+
+fn write_rect(queue: &Queue, texture: &wgpu::Texture, x: u32, y: u32, image: &RgbaImage) {
+    let (width, height) = image.dimensions();
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d { x, y, z: 0 },
+            aspect: TextureAspect::All,
+        },
+        image,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Where the images of an atlas are, saved as text.
+///
+/// The first line holds the page size, padding, extrusion and page count,
+/// each following one the page, position and size of an image, then its
+/// name up to the end of the line:
+///
+/// ```text
+/// atlas 256 1 1 1
+/// 0 2 2 16 16 icons/close
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct AtlasLayout {
+    pub page_size: u32,
+    pub padding: u32,
+    pub extrude: u32,
+    pub pages: u32,
+    pub entries: Vec<(String, AtlasEntry)>,
+}
+
+impl fmt::Display for AtlasLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "atlas {} {} {} {}",
+            self.page_size, self.padding, self.extrude, self.pages
+        )?;
+        for (name, entry) in &self.entries {
+            let AtlasRect {
+                x,
+                y,
+                width,
+                height,
+            } = entry.rect;
+            writeln!(f, "{} {x} {y} {width} {height} {name}", entry.page)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AtlasLayout {
+    type Err = AtlasError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |line: usize, message: String| AtlasError::Layout { line, message };
+        let number = |line: usize, field: &str| {
+            field
+                .parse::<u32>()
+                .map_err(|_| error(line, format!("`{field}` isn't a number")))
+        };
+
+        let mut lines = s.lines().enumerate().map(|(index, line)| (index + 1, line));
+        let header = lines.next().map_or("", |(_, line)| line);
+        let header: Vec<&str> = header.split_whitespace().collect();
+        let ["atlas", page_size, padding, extrude, pages] = header[..] else {
+            return Err(error(
+                1,
+                "expected `atlas <page size> <padding> <extrude> <pages>`".to_string(),
+            ));
+        };
+        let mut layout = AtlasLayout {
+            page_size: number(1, page_size)?,
+            padding: number(1, padding)?,
+            extrude: number(1, extrude)?,
+            pages: number(1, pages)?,
+            entries: Vec::new(),
+        };
+        // Entries are placed as they are read, so errors point at their line
+        let margin = layout_margin(layout.padding, layout.extrude)?;
+        let mut packers = HashMap::new();
+        let mut names = HashSet::new();
+
+        for (line, text) in lines {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let mut fields = text.splitn(6, ' ');
+            let mut next = || {
+                fields.next().ok_or_else(|| {
+                    error(
+                        line,
+                        "expected `<page> <x> <y> <width> <height> <name>`".to_string(),
+                    )
+                })
+            };
+            let page = number(line, next()?)?;
+            let rect = AtlasRect {
+                x: number(line, next()?)?,
+                y: number(line, next()?)?,
+                width: number(line, next()?)?,
+                height: number(line, next()?)?,
+            };
+            let name = next()?;
+            if !names.insert(name) {
+                return Err(error(line, format!("`{name}` is listed twice")));
+            }
+            let packer = (page < layout.pages).then(|| {
+                packers
+                    .entry(page)
+                    .or_insert_with(|| ShelfPacker::new(layout.page_size))
+            });
+            reserve_cell(packer, margin, line, name, page, rect)?;
+            let entry = AtlasEntry::new(page, rect, layout.page_size);
+            layout.entries.push((name.to_string(), entry));
+        }
+        Ok(layout)
+    }
+}
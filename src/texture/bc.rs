@@ -0,0 +1,798 @@
+//! Decoders for the BC1–BC7 block formats.
+//!
+//! Each decoder writes the 16 texels of a 4x4 block row by row. Snorm
+//! channels are written as the bits of an `i8`, BC6H texels as half floats.
+
+use half::f16;
+
+pub(super) fn decode_bc1(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(&block[..8], true, texels);
+}
+
+pub(super) fn decode_bc2(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(&block[8..16], false, texels);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (4 * i)) & 0xf) as u8 * 17;
+    }
+}
+
+pub(super) fn decode_bc3(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(&block[8..16], false, texels);
+    for (texel, alpha) in texels.iter_mut().zip(decode_channel(&block[..8], false)) {
+        texel[3] = alpha as u8;
+    }
+}
+
+pub(super) fn decode_bc4_unorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, false, texels);
+}
+
+pub(super) fn decode_bc4_snorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, true, texels);
+}
+
+pub(super) fn decode_bc5_unorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, false, texels);
+}
+
+pub(super) fn decode_bc5_snorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, true, texels);
+}
+
+/// Decodes one BC4 block per 8 bytes into consecutive channels.
+fn decode_channels(block: &[u8], signed: bool, texels: &mut [[u8; 4]; 16]) {
+    for (channel, block) in block.chunks_exact(8).enumerate() {
+        for (texel, value) in texels.iter_mut().zip(decode_channel(block, signed)) {
+            texel[channel] = value as u8;
+        }
+    }
+}
+
+/// Decodes a BC1 color block. Blocks of BC2 and BC3 always use four colors,
+/// BC1 blocks with `color0 <= color1` have three and a transparent black.
+fn decode_color(block: &[u8], punchthrough: bool, texels: &mut [[u8; 4]; 16]) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let rgb = |color: u16| {
+        let (r, g, b) = (color >> 11, (color >> 5) & 0x3f, color & 0x1f);
+        [
+            (r << 3) | (r >> 2),
+            (g << 2) | (g >> 4),
+            (b << 3) | (b >> 2),
+        ]
+    };
+    let (c0, c1) = (rgb(color0), rgb(color1));
+    let mix = |w0: u16, w1: u16, sum: u16| {
+        let c = |i: usize| ((w0 * c0[i] + w1 * c1[i]) / sum) as u8;
+        [c(0), c(1), c(2), 255]
+    };
+    let palette = if color0 > color1 || !punchthrough {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(1, 1, 2), [0; 4]]
+    };
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+}
+
+/// Decodes a BC4 block, the alpha block of BC3, into unorm or snorm values.
+fn decode_channel(block: &[u8], signed: bool) -> [i32; 16] {
+    let (e0, e1) = if signed {
+        // -128 means -1.0 like -127 does
+        let e = |b: u8| (b as i8).max(-127) as i32;
+        (e(block[0]), e(block[1]))
+    } else {
+        (block[0] as i32, block[1] as i32)
+    };
+    let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+    let mut palette = [e0, e1, 0, 0, 0, 0, min, max];
+    if e0 > e1 {
+        for i in 1..7 {
+            palette[i + 1] = div_round((7 - i as i32) * e0 + i as i32 * e1, 7);
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = div_round((5 - i as i32) * e0 + i as i32 * e1, 5);
+        }
+    }
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (3 * i)) & 7) as usize])
+}
+
+fn div_round(value: i32, divisor: i32) -> i32 {
+    if value < 0 {
+        (value - divisor / 2) / divisor
+    } else {
+        (value + divisor / 2) / divisor
+    }
+}
+
+/// Reads the bits of a block from the least significant one up.
+struct Bits {
+    bits: u128,
+    position: u32,
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u8 {
+        let value = (self.bits >> self.position) & ((1 << count) - 1);
+        self.position += count;
+        value as u8
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+pub(super) fn decode_bc7(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    let mut bits = Bits::new(block);
+    let Some(mode) = (0..8).find(|_| bits.read(1) == 1) else {
+        // Reserved mode
+        *texels = [[0; 4]; 16];
+        return;
+    };
+    let mode = &BC7_MODES[mode];
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoints = mode.subsets * 2;
+    let mut colors = [[0u8; 4]; 6];
+    for channel in 0..3 {
+        for color in &mut colors[..endpoints] {
+            color[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for color in &mut colors[..endpoints] {
+        color[3] = bits.read(mode.alpha_bits);
+    }
+    let p_bits: [u8; 6] = if mode.endpoint_p_bits {
+        std::array::from_fn(|i| if i < endpoints { bits.read(1) } else { 0 })
+    } else if mode.shared_p_bits {
+        // One per subset, only mode 1 has them
+        let shared = [bits.read(1), bits.read(1)];
+        std::array::from_fn(|i| shared[(i / 2).min(1)])
+    } else {
+        [0; 6]
+    };
+    let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    for (color, p_bit) in colors[..endpoints].iter_mut().zip(p_bits) {
+        for (channel, value) in color.iter_mut().enumerate() {
+            let bits = if channel < 3 {
+                mode.color_bits
+            } else {
+                mode.alpha_bits
+            };
+            if bits == 0 {
+                *value = 255;
+                continue;
+            }
+            let (value_bits, bits) = if has_p_bits {
+                (((*value as u16) << 1) | p_bit as u16, bits + 1)
+            } else {
+                (*value as u16, bits)
+            };
+            *value = ((value_bits << (8 - bits)) | (value_bits >> (2 * bits - 8))) as u8;
+        }
+    }
+
+    let subset = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => ((PARTITIONS_2[partition] >> texel) & 1) as usize,
+        _ => PARTITIONS_3[partition][texel] as usize,
+    };
+    let is_anchor = |texel: usize| {
+        texel == 0
+            || match mode.subsets {
+                1 => false,
+                2 => texel == ANCHORS_2[partition] as usize,
+                _ => {
+                    texel == ANCHORS_3_SECOND[partition] as usize
+                        || texel == ANCHORS_3_THIRD[partition] as usize
+                }
+            }
+    };
+    let mut read_indices = |index_bits: u32| -> [u8; 16] {
+        std::array::from_fn(|texel| {
+            if index_bits == 0 {
+                0
+            } else if is_anchor(texel) {
+                bits.read(index_bits - 1)
+            } else {
+                bits.read(index_bits)
+            }
+        })
+    };
+    let indices = read_indices(mode.index_bits);
+    let secondary = read_indices(mode.secondary_index_bits);
+
+    for (texel, out) in texels.iter_mut().enumerate() {
+        let subset = subset(texel);
+        let (e0, e1) = (colors[2 * subset], colors[2 * subset + 1]);
+        let interpolate = |channel: usize, index: u8, index_bits: u32| {
+            let weight = match index_bits {
+                2 => WEIGHTS_2[index as usize],
+                3 => WEIGHTS_3[index as usize],
+                _ => WEIGHTS_4[index as usize],
+            } as u32;
+            let (a, b) = (e0[channel] as u32, e1[channel] as u32);
+            (((64 - weight) * a + weight * b + 32) >> 6) as u8
+        };
+        let (color_index, color_bits, alpha_index, alpha_bits) = if mode.secondary_index_bits == 0 {
+            (
+                indices[texel],
+                mode.index_bits,
+                indices[texel],
+                mode.index_bits,
+            )
+        } else if index_selection == 0 {
+            let alpha = (secondary[texel], mode.secondary_index_bits);
+            (indices[texel], mode.index_bits, alpha.0, alpha.1)
+        } else {
+            let color = (secondary[texel], mode.secondary_index_bits);
+            (color.0, color.1, indices[texel], mode.index_bits)
+        };
+        *out = [
+            interpolate(0, color_index, color_bits),
+            interpolate(1, color_index, color_bits),
+            interpolate(2, color_index, color_bits),
+            interpolate(3, alpha_index, alpha_bits),
+        ];
+        if rotation > 0 {
+            out.swap(rotation as usize - 1, 3);
+        }
+    }
+}
+
+pub(super) fn decode_bc6h_ufloat(block: &[u8], texels: &mut [[f16; 4]; 16]) {
+    decode_bc6h(block, false, texels);
+}
+
+pub(super) fn decode_bc6h_float(block: &[u8], texels: &mut [[f16; 4]; 16]) {
+    decode_bc6h(block, true, texels);
+}
+
+/// Fields of the BC6H endpoints, `W` to `Z` for the two endpoints of each
+/// region, and the partition.
+const RW: u8 = 0;
+const GW: u8 = 1;
+const BW: u8 = 2;
+const RX: u8 = 3;
+const GX: u8 = 4;
+const BX: u8 = 5;
+const RY: u8 = 6;
+const GY: u8 = 7;
+const BY: u8 = 8;
+const RZ: u8 = 9;
+const GZ: u8 = 10;
+const BZ: u8 = 11;
+const D: u8 = 12;
+
+struct Bc6hMode {
+    /// Value of the 2 or 5 mode bits.
+    bits: u8,
+    regions: usize,
+    /// Whether the endpoints after the first are stored as differences.
+    transformed: bool,
+    endpoint_bits: u32,
+    /// Bits of the other endpoints, per channel.
+    delta_bits: [u32; 3],
+    /// Where the bits following the mode go, as a field and its bits from
+    /// `b` towards `a` like the spec's `field[a:b]`.
+    layout: &'static [(u8, u8, u8)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        bits: 0,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0),
+            (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+            (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 1,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 6, 0), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4),
+            (GW, 6, 0), (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 6, 0), (BZ, 3, 3), (BZ, 5, 5),
+            (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0),
+            (RY, 5, 0), (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 2,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (RW, 10, 10), (GY, 3, 0), (GX, 3, 0),
+            (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0),
+            (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 6,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (GZ, 4, 4), (GY, 3, 0),
+            (GX, 4, 0), (GW, 10, 10), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0),
+            (RY, 3, 0), (BZ, 0, 0), (BZ, 2, 2), (RZ, 3, 0), (GY, 4, 4), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 10,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (BY, 4, 4), (GY, 3, 0),
+            (GX, 3, 0), (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BW, 10, 10), (BY, 3, 0),
+            (RY, 3, 0), (BZ, 1, 1), (BZ, 2, 2), (RZ, 3, 0), (BZ, 4, 4), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 14,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (RW, 8, 0), (BY, 4, 4), (GW, 8, 0), (GY, 4, 4), (BW, 8, 0), (BZ, 4, 4), (RX, 4, 0),
+            (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+            (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 18,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        layout: &[
+            (RW, 7, 0), (GZ, 4, 4), (BY, 4, 4), (GW, 7, 0), (BZ, 2, 2), (GY, 4, 4), (BW, 7, 0),
+            (BZ, 3, 3), (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0),
+            (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 22,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        layout: &[
+            (RW, 7, 0), (BZ, 0, 0), (BY, 4, 4), (GW, 7, 0), (GY, 5, 5), (GY, 4, 4), (BW, 7, 0),
+            (GZ, 5, 5), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0),
+            (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+            (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 26,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        layout: &[
+            (RW, 7, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 7, 0), (BY, 5, 5), (GY, 4, 4), (BW, 7, 0),
+            (BZ, 5, 5), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0),
+            (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+            (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 30,
+        regions: 2,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (RW, 5, 0), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 5, 0), (GY, 5, 5),
+            (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 5, 0), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5),
+            (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0),
+            (RY, 5, 0), (RZ, 5, 0), (D, 4, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 3,
+        regions: 1,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 9, 0), (GX, 9, 0), (BX, 9, 0),
+        ],
+    },
+    Bc6hMode {
+        bits: 7,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 8, 0), (RW, 10, 10), (GX, 8, 0), (GW, 10, 10),
+            (BX, 8, 0), (BW, 10, 10),
+        ],
+    },
+    // The high bits of the first endpoint are stored reversed
+    Bc6hMode {
+        bits: 11,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 7, 0), (RW, 10, 11), (GX, 7, 0), (GW, 10, 11),
+            (BX, 7, 0), (BW, 10, 11),
+        ],
+    },
+    Bc6hMode {
+        bits: 15,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        layout: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 15), (GX, 3, 0), (GW, 10, 15),
+            (BX, 3, 0), (BW, 10, 15),
+        ],
+    },
+];
+
+fn decode_bc6h(block: &[u8], signed: bool, texels: &mut [[f16; 4]; 16]) {
+    let mut bits = Bits::new(block);
+    let mut mode_bits = bits.read(2);
+    if mode_bits > 1 {
+        mode_bits |= bits.read(3) << 2;
+    }
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.bits == mode_bits) else {
+        // Reserved modes
+        *texels = [[f16::ZERO, f16::ZERO, f16::ZERO, f16::ONE]; 16];
+        return;
+    };
+    let mut fields = [0i32; 13];
+    for &(field, a, b) in mode.layout {
+        let mut bit = b;
+        loop {
+            fields[field as usize] |= (bits.read(1) as i32) << bit;
+            if bit == a {
+                break;
+            }
+            if a > b {
+                bit += 1;
+            } else {
+                bit -= 1;
+            }
+        }
+    }
+
+    // Endpoints W and X of the first region, Y and Z of the second
+    let endpoints = mode.regions * 2;
+    let mut colors = [[0i32; 3]; 4];
+    for (endpoint, color) in colors[..endpoints].iter_mut().enumerate() {
+        color.copy_from_slice(&fields[endpoint * 3..endpoint * 3 + 3]);
+    }
+    let endpoint_bits = mode.endpoint_bits;
+    let (first, rest) = colors.split_at_mut(1);
+    if signed {
+        for value in &mut first[0] {
+            *value = extend_sign(*value, endpoint_bits);
+        }
+    }
+    for color in &mut rest[..endpoints - 1] {
+        for (channel, value) in color.iter_mut().enumerate() {
+            if mode.transformed || signed {
+                *value = extend_sign(*value, mode.delta_bits[channel]);
+            }
+            if mode.transformed {
+                *value = (first[0][channel] + *value) & ((1 << endpoint_bits) - 1);
+                if signed {
+                    *value = extend_sign(*value, endpoint_bits);
+                }
+            }
+        }
+    }
+    for value in colors[..endpoints].iter_mut().flatten() {
+        *value = unquantize_bc6h(*value, endpoint_bits, signed);
+    }
+
+    let partition = fields[D as usize] as usize;
+    let index_bits = if mode.regions == 2 { 3 } else { 4 };
+    for (texel, out) in texels.iter_mut().enumerate() {
+        let anchor = texel == 0 || (mode.regions == 2 && texel == ANCHORS_2[partition] as usize);
+        let index = bits.read(index_bits - u32::from(anchor)) as usize;
+        let region = if mode.regions == 2 {
+            ((PARTITIONS_2[partition] >> texel) & 1) as usize
+        } else {
+            0
+        };
+        let weight = if mode.regions == 2 {
+            WEIGHTS_3[index]
+        } else {
+            WEIGHTS_4[index]
+        } as i32;
+        let (e0, e1) = (colors[2 * region], colors[2 * region + 1]);
+        let channel = |c: usize| {
+            let value = ((64 - weight) * e0[c] + weight * e1[c] + 32) >> 6;
+            finish_bc6h(value, signed)
+        };
+        *out = [channel(0), channel(1), channel(2), f16::ONE];
+    }
+}
+
+fn extend_sign(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Spreads an endpoint of `bits` bits over the range of the interpolation.
+fn unquantize_bc6h(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xffff
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        unquantized * value.signum()
+    }
+}
+
+/// Scales an interpolated value to the bits of a half float.
+fn finish_bc6h(value: i32, signed: bool) -> f16 {
+    if !signed {
+        f16::from_bits(((value * 31) >> 6) as u16)
+    } else if value < 0 {
+        f16::from_bits(0x8000 | (((-value) * 31) >> 5) as u16)
+    } else {
+        f16::from_bits(((value * 31) >> 5) as u16)
+    }
+}
+
+const WEIGHTS_2: [u8; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u8; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u8; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Subsets of two subset partitions, bit `i` for texel `i`.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Anchor texel of the second subset of two subset partitions.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+    15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5,
+    10, 8, 13, 15, 12, 3, 3,
+];
+
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+    10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
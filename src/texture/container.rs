@@ -0,0 +1,321 @@
+//! Reading KTX2 and DDS containers.
+
+use std::io::Read;
+
+use ddsfile::{Caps2, D3DFormat, Dds, DxgiFormat, MiscFlag};
+use ktx2::{Format, SupercompressionScheme};
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use super::{data::level_bytes, TextureData, TextureError};
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+pub(super) fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_MAGIC)
+}
+
+pub(super) fn is_dds(bytes: &[u8]) -> bool {
+    bytes.starts_with(&DDS_MAGIC)
+}
+
+fn ktx2_error(message: impl ToString) -> TextureError {
+    TextureError::Container {
+        container: "KTX2",
+        message: message.to_string(),
+    }
+}
+
+fn dds_error(message: impl ToString) -> TextureError {
+    TextureError::Container {
+        container: "DDS",
+        message: message.to_string(),
+    }
+}
+
+/// Reads a KTX2 file, undoing Zstandard or zlib supercompression.
+pub(super) fn read_ktx2(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    let reader = ktx2::Reader::new(bytes).map_err(ktx2_error)?;
+    let header = reader.header();
+    if header.face_count > 1 {
+        return Err(TextureError::UnsupportedLayout("cube maps"));
+    }
+    if header.layer_count > 1 {
+        return Err(TextureError::UnsupportedLayout("array textures"));
+    }
+    if header.pixel_depth > 1 {
+        return Err(TextureError::UnsupportedLayout("3D textures"));
+    }
+    let format = match header.format {
+        Some(format) => ktx2_format(format)
+            .ok_or_else(|| TextureError::UnsupportedFormat(format!("{format:?}")))?,
+        // Basis Universal payloads are transcoded, not uploaded
+        None => {
+            return Err(TextureError::UnsupportedFormat(
+                "Basis Universal".to_string(),
+            ))
+        }
+    };
+
+    let levels = reader
+        .levels()
+        .map(|level| match header.supercompression_scheme {
+            None => Ok(level.data.to_vec()),
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut decoder =
+                    ruzstd::decoding::StreamingDecoder::new(level.data).map_err(ktx2_error)?;
+                let mut bytes = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                decoder.read_to_end(&mut bytes).map_err(ktx2_error)?;
+                Ok(bytes)
+            }
+            Some(SupercompressionScheme::ZLIB) => {
+                miniz_oxide::inflate::decompress_to_vec_zlib(level.data).map_err(ktx2_error)
+            }
+            Some(scheme) => Err(TextureError::UnsupportedSupercompression(format!(
+                "{scheme:?}"
+            ))),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(TextureData {
+        format,
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        levels,
+    })
+}
+
+fn ktx2_format(format: Format) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    let astc = |block, srgb_or_hdr: Option<bool>| F::Astc {
+        block,
+        channel: match srgb_or_hdr {
+            None => AstcChannel::Hdr,
+            Some(true) => AstcChannel::UnormSrgb,
+            Some(false) => AstcChannel::Unorm,
+        },
+    };
+    Some(match format {
+        Format::R8_UNORM => F::R8Unorm,
+        Format::R8_SNORM => F::R8Snorm,
+        Format::R8G8_UNORM => F::Rg8Unorm,
+        Format::R8G8_SNORM => F::Rg8Snorm,
+        Format::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        Format::R8G8B8A8_SNORM => F::Rgba8Snorm,
+        Format::B8G8R8A8_UNORM => F::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => F::Bgra8UnormSrgb,
+        Format::A2B10G10R10_UNORM_PACK32 => F::Rgb10a2Unorm,
+        Format::B10G11R11_UFLOAT_PACK32 => F::Rg11b10Ufloat,
+        Format::E5B9G9R9_UFLOAT_PACK32 => F::Rgb9e5Ufloat,
+        Format::R16_UNORM => F::R16Unorm,
+        Format::R16_SNORM => F::R16Snorm,
+        Format::R16_SFLOAT => F::R16Float,
+        Format::R16G16_UNORM => F::Rg16Unorm,
+        Format::R16G16_SNORM => F::Rg16Snorm,
+        Format::R16G16_SFLOAT => F::Rg16Float,
+        Format::R16G16B16A16_UNORM => F::Rgba16Unorm,
+        Format::R16G16B16A16_SNORM => F::Rgba16Snorm,
+        Format::R16G16B16A16_SFLOAT => F::Rgba16Float,
+        Format::R32_SFLOAT => F::R32Float,
+        Format::R32G32_SFLOAT => F::Rg32Float,
+        Format::R32G32B32A32_SFLOAT => F::Rgba32Float,
+        // Without alpha, BC1 is uploaded as if it had it
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => F::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => F::Etc2Rgb8UnormSrgb,
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => F::Etc2Rgb8A1Unorm,
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => F::Etc2Rgb8A1UnormSrgb,
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => F::Etc2Rgba8Unorm,
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => F::Etc2Rgba8UnormSrgb,
+        Format::EAC_R11_UNORM_BLOCK => F::EacR11Unorm,
+        Format::EAC_R11_SNORM_BLOCK => F::EacR11Snorm,
+        Format::EAC_R11G11_UNORM_BLOCK => F::EacRg11Unorm,
+        Format::EAC_R11G11_SNORM_BLOCK => F::EacRg11Snorm,
+        Format::ASTC_4x4_UNORM_BLOCK => astc(AstcBlock::B4x4, Some(false)),
+        Format::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4, Some(true)),
+        Format::ASTC_4x4_SFLOAT_BLOCK => astc(AstcBlock::B4x4, None),
+        Format::ASTC_5x4_UNORM_BLOCK => astc(AstcBlock::B5x4, Some(false)),
+        Format::ASTC_5x4_SRGB_BLOCK => astc(AstcBlock::B5x4, Some(true)),
+        Format::ASTC_5x4_SFLOAT_BLOCK => astc(AstcBlock::B5x4, None),
+        Format::ASTC_5x5_UNORM_BLOCK => astc(AstcBlock::B5x5, Some(false)),
+        Format::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5, Some(true)),
+        Format::ASTC_5x5_SFLOAT_BLOCK => astc(AstcBlock::B5x5, None),
+        Format::ASTC_6x5_UNORM_BLOCK => astc(AstcBlock::B6x5, Some(false)),
+        Format::ASTC_6x5_SRGB_BLOCK => astc(AstcBlock::B6x5, Some(true)),
+        Format::ASTC_6x5_SFLOAT_BLOCK => astc(AstcBlock::B6x5, None),
+        Format::ASTC_6x6_UNORM_BLOCK => astc(AstcBlock::B6x6, Some(false)),
+        Format::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6, Some(true)),
+        Format::ASTC_6x6_SFLOAT_BLOCK => astc(AstcBlock::B6x6, None),
+        Format::ASTC_8x5_UNORM_BLOCK => astc(AstcBlock::B8x5, Some(false)),
+        Format::ASTC_8x5_SRGB_BLOCK => astc(AstcBlock::B8x5, Some(true)),
+        Format::ASTC_8x5_SFLOAT_BLOCK => astc(AstcBlock::B8x5, None),
+        Format::ASTC_8x6_UNORM_BLOCK => astc(AstcBlock::B8x6, Some(false)),
+        Format::ASTC_8x6_SRGB_BLOCK => astc(AstcBlock::B8x6, Some(true)),
+        Format::ASTC_8x6_SFLOAT_BLOCK => astc(AstcBlock::B8x6, None),
+        Format::ASTC_8x8_UNORM_BLOCK => astc(AstcBlock::B8x8, Some(false)),
+        Format::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8, Some(true)),
+        Format::ASTC_8x8_SFLOAT_BLOCK => astc(AstcBlock::B8x8, None),
+        Format::ASTC_10x5_UNORM_BLOCK => astc(AstcBlock::B10x5, Some(false)),
+        Format::ASTC_10x5_SRGB_BLOCK => astc(AstcBlock::B10x5, Some(true)),
+        Format::ASTC_10x5_SFLOAT_BLOCK => astc(AstcBlock::B10x5, None),
+        Format::ASTC_10x6_UNORM_BLOCK => astc(AstcBlock::B10x6, Some(false)),
+        Format::ASTC_10x6_SRGB_BLOCK => astc(AstcBlock::B10x6, Some(true)),
+        Format::ASTC_10x6_SFLOAT_BLOCK => astc(AstcBlock::B10x6, None),
+        Format::ASTC_10x8_UNORM_BLOCK => astc(AstcBlock::B10x8, Some(false)),
+        Format::ASTC_10x8_SRGB_BLOCK => astc(AstcBlock::B10x8, Some(true)),
+        Format::ASTC_10x8_SFLOAT_BLOCK => astc(AstcBlock::B10x8, None),
+        Format::ASTC_10x10_UNORM_BLOCK => astc(AstcBlock::B10x10, Some(false)),
+        Format::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10, Some(true)),
+        Format::ASTC_10x10_SFLOAT_BLOCK => astc(AstcBlock::B10x10, None),
+        Format::ASTC_12x10_UNORM_BLOCK => astc(AstcBlock::B12x10, Some(false)),
+        Format::ASTC_12x10_SRGB_BLOCK => astc(AstcBlock::B12x10, Some(true)),
+        Format::ASTC_12x10_SFLOAT_BLOCK => astc(AstcBlock::B12x10, None),
+        Format::ASTC_12x12_UNORM_BLOCK => astc(AstcBlock::B12x12, Some(false)),
+        Format::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12, Some(true)),
+        Format::ASTC_12x12_SFLOAT_BLOCK => astc(AstcBlock::B12x12, None),
+        _ => return None,
+    })
+}
+
+/// Reads a DDS file.
+///
+/// Legacy DDS files can't say whether colors are sRGB encoded, `srgb` picks
+/// the format for them. DX10 ones name it.
+pub(super) fn read_dds(bytes: &[u8], srgb: bool) -> Result<TextureData, TextureError> {
+    let dds = Dds::read(bytes).map_err(dds_error)?;
+    let cube = dds.header.caps2.contains(Caps2::CUBEMAP)
+        || dds
+            .header10
+            .as_ref()
+            .is_some_and(|header| header.misc_flag.contains(MiscFlag::TEXTURECUBE));
+    if cube {
+        return Err(TextureError::UnsupportedLayout("cube maps"));
+    }
+    if dds.get_num_array_layers() > 1 {
+        return Err(TextureError::UnsupportedLayout("array textures"));
+    }
+    if dds.get_depth() > 1 {
+        return Err(TextureError::UnsupportedLayout("3D textures"));
+    }
+    let format = if let Some(format) = dds.get_dxgi_format() {
+        dxgi_format(format).ok_or_else(|| TextureError::UnsupportedFormat(format!("{format:?}")))?
+    } else if let Some(format) = dds.get_d3d_format() {
+        let format = d3d_format(format)
+            .ok_or_else(|| TextureError::UnsupportedFormat(format!("{format:?}")))?;
+        if srgb {
+            format.add_srgb_suffix()
+        } else {
+            format
+        }
+    } else {
+        return Err(TextureError::UnsupportedFormat(
+            "unknown DDS pixel format".to_string(),
+        ));
+    };
+
+    let mut texture = TextureData {
+        format,
+        width: dds.get_width(),
+        height: dds.get_height(),
+        levels: Vec::new(),
+    };
+    let mut data = dds.data.as_slice();
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+        let (width, height) = texture.level_size(level);
+        let size = level_bytes(format, width, height);
+        if data.len() < size {
+            return Err(TextureError::LevelSize {
+                level,
+                expected: size,
+                found: data.len(),
+            });
+        }
+        let (bytes, rest) = data.split_at(size);
+        texture.levels.push(bytes.to_vec());
+        data = rest;
+    }
+    Ok(texture)
+}
+
+fn dxgi_format(format: DxgiFormat) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    Some(match format {
+        DxgiFormat::R8_UNorm => F::R8Unorm,
+        DxgiFormat::R8_SNorm => F::R8Snorm,
+        DxgiFormat::R8G8_UNorm => F::Rg8Unorm,
+        DxgiFormat::R8G8_SNorm => F::Rg8Snorm,
+        DxgiFormat::R8G8B8A8_UNorm => F::Rgba8Unorm,
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+        DxgiFormat::R8G8B8A8_SNorm => F::Rgba8Snorm,
+        DxgiFormat::B8G8R8A8_UNorm => F::Bgra8Unorm,
+        DxgiFormat::B8G8R8A8_UNorm_sRGB => F::Bgra8UnormSrgb,
+        DxgiFormat::R10G10B10A2_UNorm => F::Rgb10a2Unorm,
+        DxgiFormat::R11G11B10_Float => F::Rg11b10Ufloat,
+        DxgiFormat::R9G9B9E5_SharedExp => F::Rgb9e5Ufloat,
+        DxgiFormat::R16_UNorm => F::R16Unorm,
+        DxgiFormat::R16_SNorm => F::R16Snorm,
+        DxgiFormat::R16_Float => F::R16Float,
+        DxgiFormat::R16G16_UNorm => F::Rg16Unorm,
+        DxgiFormat::R16G16_SNorm => F::Rg16Snorm,
+        DxgiFormat::R16G16_Float => F::Rg16Float,
+        DxgiFormat::R16G16B16A16_UNorm => F::Rgba16Unorm,
+        DxgiFormat::R16G16B16A16_SNorm => F::Rgba16Snorm,
+        DxgiFormat::R16G16B16A16_Float => F::Rgba16Float,
+        DxgiFormat::R32_Float => F::R32Float,
+        DxgiFormat::R32G32_Float => F::Rg32Float,
+        DxgiFormat::R32G32B32A32_Float => F::Rgba32Float,
+        DxgiFormat::BC1_UNorm => F::Bc1RgbaUnorm,
+        DxgiFormat::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+        DxgiFormat::BC2_UNorm => F::Bc2RgbaUnorm,
+        DxgiFormat::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+        DxgiFormat::BC3_UNorm => F::Bc3RgbaUnorm,
+        DxgiFormat::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+        DxgiFormat::BC4_UNorm => F::Bc4RUnorm,
+        DxgiFormat::BC4_SNorm => F::Bc4RSnorm,
+        DxgiFormat::BC5_UNorm => F::Bc5RgUnorm,
+        DxgiFormat::BC5_SNorm => F::Bc5RgSnorm,
+        DxgiFormat::BC6H_UF16 => F::Bc6hRgbUfloat,
+        DxgiFormat::BC6H_SF16 => F::Bc6hRgbFloat,
+        DxgiFormat::BC7_UNorm => F::Bc7RgbaUnorm,
+        DxgiFormat::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Formats of legacy DDS files, all linear.
+fn d3d_format(format: D3DFormat) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    Some(match format {
+        D3DFormat::L8 => F::R8Unorm,
+        D3DFormat::A8L8 => F::Rg8Unorm,
+        D3DFormat::A8B8G8R8 => F::Rgba8Unorm,
+        D3DFormat::A8R8G8B8 => F::Bgra8Unorm,
+        D3DFormat::G16R16 => F::Rg16Unorm,
+        D3DFormat::A16B16G16R16 => F::Rgba16Unorm,
+        D3DFormat::R16F => F::R16Float,
+        D3DFormat::G16R16F => F::Rg16Float,
+        D3DFormat::A16B16G16R16F => F::Rgba16Float,
+        D3DFormat::R32F => F::R32Float,
+        D3DFormat::G32R32F => F::Rg32Float,
+        D3DFormat::A32B32G32R32F => F::Rgba32Float,
+        D3DFormat::DXT1 => F::Bc1RgbaUnorm,
+        D3DFormat::DXT3 => F::Bc2RgbaUnorm,
+        D3DFormat::DXT5 => F::Bc3RgbaUnorm,
+        _ => return None,
+    })
+}
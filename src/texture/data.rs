@@ -0,0 +1,427 @@
+use std::sync::OnceLock;
+
+use half::f16;
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, GenericImageView, Rgba, Rgba32FImage,
+};
+use wgpu::{AstcChannel, Extent3d, TextureDimension, TextureFormat};
+
+use super::{astc, bc, etc, TextureError};
+use crate::render::MipFilter;
+
+/// Texels of a 2D texture in a wgpu format, with some or all of its mip
+/// levels.
+///
+/// Levels are tightly packed rows of texel blocks, largest level first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureData {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// Picks the format from the image's channels and precision.
+    ///
+    /// 16-bit images become half floats, with a single or two channels for
+    /// grey ones that aren't sRGB. HDR and EXR images become `Rgba16Float`
+    /// and are always linear. 8-bit grey images that aren't sRGB keep their
+    /// channel count, everything else is `Rgba8`.
+    pub fn from_image(image: &DynamicImage, srgb: bool) -> Self {
+        let (width, height) = image.dimensions();
+        let (format, bytes) = match image {
+            DynamicImage::ImageLuma8(image) if !srgb => {
+                (TextureFormat::R8Unorm, image.as_raw().clone())
+            }
+            DynamicImage::ImageLumaA8(image) if !srgb => {
+                (TextureFormat::Rg8Unorm, image.as_raw().clone())
+            }
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => {
+                let (format, channels): (_, &[usize]) = match image {
+                    DynamicImage::ImageLuma16(_) if !srgb => (TextureFormat::R16Float, &[0]),
+                    DynamicImage::ImageLumaA16(_) if !srgb => (TextureFormat::Rg16Float, &[0, 3]),
+                    _ => (TextureFormat::Rgba16Float, &[0, 1, 2, 3]),
+                };
+                let mut texels = Vec::with_capacity((width * height) as usize * channels.len());
+                for pixel in image.to_rgba16().pixels() {
+                    for &channel in channels {
+                        let value = pixel[channel] as f32 / 65535.0;
+                        let value = if srgb && channel < 3 {
+                            srgb_to_linear(value)
+                        } else {
+                            value
+                        };
+                        texels.push(f16::from_f32(value));
+                    }
+                }
+                (format, bytemuck::cast_slice(&texels).to_vec())
+            }
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let texels: Vec<f16> = image
+                    .to_rgba32f()
+                    .as_raw()
+                    .iter()
+                    .map(|&value| f16::from_f32(value))
+                    .collect();
+                (
+                    TextureFormat::Rgba16Float,
+                    bytemuck::cast_slice(&texels).to_vec(),
+                )
+            }
+            _ => {
+                let format = if srgb {
+                    TextureFormat::Rgba8UnormSrgb
+                } else {
+                    TextureFormat::Rgba8Unorm
+                };
+                (format, image.to_rgba8().into_raw())
+            }
+        };
+        Self {
+            format,
+            width,
+            height,
+            levels: vec![bytes],
+        }
+    }
+
+    pub fn size(&self) -> Extent3d {
+        Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Size in texels of mip level `level`.
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        let size = self.size().mip_level_size(level, TextureDimension::D2);
+        (size.width, size.height)
+    }
+
+    /// Checks there are as many levels as the size allows at most, each of
+    /// the size of its texel blocks.
+    pub fn validate(&self) -> Result<(), TextureError> {
+        if self.levels.is_empty() {
+            return Err(TextureError::NoLevels);
+        }
+        let max = self.size().max_mips(TextureDimension::D2);
+        let count = self.levels.len() as u32;
+        if count > max {
+            return Err(TextureError::TooManyLevels { count, max });
+        }
+        for (level, bytes) in self.levels.iter().enumerate() {
+            let (width, height) = self.level_size(level as u32);
+            let expected = level_bytes(self.format, width, height);
+            if bytes.len() != expected {
+                return Err(TextureError::LevelSize {
+                    level: level as u32,
+                    expected,
+                    found: bytes.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes block compressed texels into an uncompressed format with the
+    /// same channels, rounded to 8 bits or half floats for BC6H.
+    ///
+    /// BC1–BC7, ETC2, EAC and LDR ASTC can be decoded; HDR ASTC can't.
+    pub fn decompress(&self) -> Result<Self, TextureError> {
+        let (format, decoder) = decoder(self.format).ok_or(TextureError::NoDecoder(self.format))?;
+        let texel_size = format
+            .block_copy_size(None)
+            .expect("decoded formats have a block size") as usize;
+        let block_size = self.format.block_copy_size(None).unwrap() as usize;
+        let (block_width, block_height) = self.format.block_dimensions();
+        let (block_width, block_height) = (block_width as usize, block_height as usize);
+        let mut block_texels = vec![0; block_width * block_height * texel_size];
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, bytes)| {
+                let (width, height) = self.level_size(level as u32);
+                let (width, height) = (width as usize, height as usize);
+                let blocks_wide = width.div_ceil(block_width);
+                let mut texels = vec![0; width * height * texel_size];
+                for (i, block) in bytes.chunks_exact(block_size).enumerate() {
+                    decoder.decode(block, block_width, block_height, &mut block_texels);
+                    let (bx, by) = (
+                        i % blocks_wide * block_width,
+                        i / blocks_wide * block_height,
+                    );
+                    for (j, texel) in block_texels.chunks_exact(texel_size).enumerate() {
+                        let (x, y) = (bx + j % block_width, by + j / block_width);
+                        if x < width && y < height {
+                            let offset = (y * width + x) * texel_size;
+                            texels[offset..offset + texel_size].copy_from_slice(texel);
+                        }
+                    }
+                }
+                texels
+            })
+            .collect();
+        Ok(Self {
+            format,
+            width: self.width,
+            height: self.height,
+            levels,
+        })
+    }
+}
+
+/// Decodes the texels of a block row by row.
+#[derive(Clone, Copy)]
+enum BlockDecoder {
+    /// 4x4 blocks of up to four 8-bit channels.
+    Bytes(fn(&[u8], &mut [[u8; 4]; 16])),
+    /// 4x4 blocks of half floats.
+    Half(fn(&[u8], &mut [[f16; 4]; 16])),
+    /// ASTC blocks of any size, to sRGB when set.
+    Astc(bool),
+}
+
+impl BlockDecoder {
+    /// Writes the texels of a `width` by `height` block to `out`, packed in
+    /// the decoded format.
+    fn decode(self, block: &[u8], width: usize, height: usize, out: &mut [u8]) {
+        match self {
+            Self::Bytes(decode) => {
+                let mut texels = [[0; 4]; 16];
+                decode(block, &mut texels);
+                let channels = out.len() / texels.len();
+                for (out, texel) in out.chunks_exact_mut(channels).zip(&texels) {
+                    out.copy_from_slice(&texel[..channels]);
+                }
+            }
+            Self::Half(decode) => {
+                let mut texels = [[f16::ZERO; 4]; 16];
+                decode(block, &mut texels);
+                out.copy_from_slice(bytemuck::cast_slice(&texels));
+            }
+            Self::Astc(srgb) => {
+                astc::decode(block, width, height, srgb, bytemuck::cast_slice_mut(out));
+            }
+        }
+    }
+}
+
+/// Format of decoded texels and the block decoder of a compressed format.
+fn decoder(format: TextureFormat) -> Option<(TextureFormat, BlockDecoder)> {
+    use BlockDecoder::{Astc, Bytes, Half};
+    use TextureFormat as F;
+    let rgba = |srgb| {
+        if srgb {
+            F::Rgba8UnormSrgb
+        } else {
+            F::Rgba8Unorm
+        }
+    };
+    let srgb = format.is_srgb();
+    Some(match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => (rgba(srgb), Bytes(bc::decode_bc1)),
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => (rgba(srgb), Bytes(bc::decode_bc2)),
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => (rgba(srgb), Bytes(bc::decode_bc3)),
+        F::Bc4RUnorm => (F::R8Unorm, Bytes(bc::decode_bc4_unorm)),
+        F::Bc4RSnorm => (F::R8Snorm, Bytes(bc::decode_bc4_snorm)),
+        F::Bc5RgUnorm => (F::Rg8Unorm, Bytes(bc::decode_bc5_unorm)),
+        F::Bc5RgSnorm => (F::Rg8Snorm, Bytes(bc::decode_bc5_snorm)),
+        F::Bc6hRgbUfloat => (F::Rgba16Float, Half(bc::decode_bc6h_ufloat)),
+        F::Bc6hRgbFloat => (F::Rgba16Float, Half(bc::decode_bc6h_float)),
+        F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => (rgba(srgb), Bytes(bc::decode_bc7)),
+        F::Etc2Rgb8Unorm | F::Etc2Rgb8UnormSrgb => (rgba(srgb), Bytes(etc::decode_etc2_rgb)),
+        F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb => (rgba(srgb), Bytes(etc::decode_etc2_rgb_a1)),
+        F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb => (rgba(srgb), Bytes(etc::decode_etc2_rgba)),
+        F::EacR11Unorm => (F::R8Unorm, Bytes(etc::decode_eac_r11_unorm)),
+        F::EacR11Snorm => (F::R8Snorm, Bytes(etc::decode_eac_r11_snorm)),
+        F::EacRg11Unorm => (F::Rg8Unorm, Bytes(etc::decode_eac_rg11_unorm)),
+        F::EacRg11Snorm => (F::Rg8Snorm, Bytes(etc::decode_eac_rg11_snorm)),
+        F::Astc {
+            channel: AstcChannel::Unorm,
+            ..
+        } => (F::Rgba8Unorm, Astc(false)),
+        F::Astc {
+            channel: AstcChannel::UnormSrgb,
+            ..
+        } => (F::Rgba8UnormSrgb, Astc(true)),
+        _ => return None,
+    })
+}
+
+/// Bytes of a level of `width` by `height` texels.
+pub(super) fn level_bytes(format: TextureFormat, width: u32, height: u32) -> usize {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format
+        .block_copy_size(None)
+        .expect("color formats have a block size");
+    width.div_ceil(block_width) as usize
+        * height.div_ceil(block_height) as usize
+        * block_size as usize
+}
+
+/// How the channels of a format mips can be computed for on the CPU are
+/// stored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Unorm8,
+    Srgb8,
+    Snorm8,
+    Half,
+    Float,
+}
+
+/// Channel count, encoding and whether red and blue are swapped.
+fn texel_layout(format: TextureFormat) -> Option<(usize, Encoding, bool)> {
+    use TextureFormat as F;
+    Some(match format {
+        F::R8Unorm => (1, Encoding::Unorm8, false),
+        F::Rg8Unorm => (2, Encoding::Unorm8, false),
+        F::Rgba8Unorm => (4, Encoding::Unorm8, false),
+        F::Rgba8UnormSrgb => (4, Encoding::Srgb8, false),
+        F::Bgra8Unorm => (4, Encoding::Unorm8, true),
+        F::Bgra8UnormSrgb => (4, Encoding::Srgb8, true),
+        F::R8Snorm => (1, Encoding::Snorm8, false),
+        F::Rg8Snorm => (2, Encoding::Snorm8, false),
+        F::Rgba8Snorm => (4, Encoding::Snorm8, false),
+        F::R16Float => (1, Encoding::Half, false),
+        F::Rg16Float => (2, Encoding::Half, false),
+        F::Rgba16Float => (4, Encoding::Half, false),
+        F::R32Float => (1, Encoding::Float, false),
+        F::Rg32Float => (2, Encoding::Float, false),
+        F::Rgba32Float => (4, Encoding::Float, false),
+        _ => return None,
+    })
+}
+
+/// Whether [`generate_levels`] handles `format`.
+pub(super) fn cpu_mipmaps_supported(format: TextureFormat) -> bool {
+    texel_layout(format).is_some()
+}
+
+/// Computes `count` levels below one of `width` by `height` texels, each
+/// half the size of the one before.
+///
+/// sRGB colors are filtered in linear space like the GPU does, alpha always
+/// is. Returns `None` for formats [`cpu_mipmaps_supported`] rejects.
+pub(super) fn generate_levels(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+    count: u32,
+    filter: MipFilter,
+) -> Option<Vec<Vec<u8>>> {
+    let layout = texel_layout(format)?;
+    let filter = match filter {
+        MipFilter::Nearest => FilterType::Nearest,
+        MipFilter::Linear => FilterType::Triangle,
+        MipFilter::Lanczos3 => FilterType::Lanczos3,
+    };
+    let mut level = decode_texels(layout, width, height, bytes);
+    Some(
+        (0..count)
+            .map(|_| {
+                let (width, height) = level.dimensions();
+                level = imageops::resize(&level, (width / 2).max(1), (height / 2).max(1), filter);
+                encode_texels(layout, &level)
+            })
+            .collect(),
+    )
+}
+
+fn decode_texels(
+    (channels, encoding, bgra): (usize, Encoding, bool),
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+) -> Rgba32FImage {
+    let component = |bytes: &[u8], channel: usize| -> f32 {
+        match encoding {
+            Encoding::Unorm8 => bytes[channel] as f32 / 255.0,
+            Encoding::Srgb8 if channel < 3 => srgb_table()[bytes[channel] as usize],
+            Encoding::Srgb8 => bytes[channel] as f32 / 255.0,
+            Encoding::Snorm8 => (bytes[channel] as i8 as f32 / 127.0).max(-1.0),
+            Encoding::Half => {
+                f16::from_le_bytes([bytes[2 * channel], bytes[2 * channel + 1]]).into()
+            }
+            Encoding::Float => f32::from_le_bytes(bytes[4 * channel..][..4].try_into().unwrap()),
+        }
+    };
+    let texel_size = channels * encoding_size(encoding);
+    let mut image = Rgba32FImage::new(width, height);
+    for (pixel, bytes) in image.pixels_mut().zip(bytes.chunks_exact(texel_size)) {
+        let mut texel = [0.0, 0.0, 0.0, 1.0];
+        for (channel, value) in texel.iter_mut().enumerate().take(channels) {
+            *value = component(bytes, channel);
+        }
+        if bgra {
+            texel.swap(0, 2);
+        }
+        *pixel = Rgba(texel);
+    }
+    image
+}
+
+fn encode_texels(
+    (channels, encoding, bgra): (usize, Encoding, bool),
+    image: &Rgba32FImage,
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(image.len() / 4 * channels * encoding_size(encoding));
+    let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for pixel in image.pixels() {
+        let mut texel = pixel.0;
+        if bgra {
+            texel.swap(0, 2);
+        }
+        for (channel, &value) in texel.iter().enumerate().take(channels) {
+            match encoding {
+                Encoding::Unorm8 => bytes.push(quantize(value)),
+                Encoding::Srgb8 if channel < 3 => bytes.push(quantize(linear_to_srgb(value))),
+                Encoding::Srgb8 => bytes.push(quantize(value)),
+                Encoding::Snorm8 => {
+                    bytes.push((value.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8)
+                }
+                Encoding::Half => bytes.extend(f16::from_f32(value).to_le_bytes()),
+                Encoding::Float => bytes.extend(value.to_le_bytes()),
+            }
+        }
+    }
+    bytes
+}
+
+fn encoding_size(encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::Unorm8 | Encoding::Srgb8 | Encoding::Snorm8 => 1,
+        Encoding::Half => 2,
+        Encoding::Float => 4,
+    }
+}
+
+/// Linear values of the 256 sRGB encoded ones.
+fn srgb_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|c| srgb_to_linear(c as f32 / 255.0)))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,308 @@
+//! Decoders for the ETC2 and EAC block formats.
+//!
+//! Blocks are big endian and list their texels column by column, the
+//! decoders write them row by row like the BC ones. Snorm channels are
+//! written as the bits of an `i8`.
+
+pub(super) fn decode_etc2_rgb(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(block, false, texels);
+}
+
+pub(super) fn decode_etc2_rgb_a1(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(block, true, texels);
+}
+
+pub(super) fn decode_etc2_rgba(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color(&block[8..16], false, texels);
+    let alpha = decode_alpha(&block[..8]);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+}
+
+pub(super) fn decode_eac_r11_unorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, false, texels);
+}
+
+pub(super) fn decode_eac_r11_snorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, true, texels);
+}
+
+pub(super) fn decode_eac_rg11_unorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, false, texels);
+}
+
+pub(super) fn decode_eac_rg11_snorm(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_channels(block, true, texels);
+}
+
+/// Position in the row by row output of the `i`th texel of a block.
+fn texel(i: usize) -> usize {
+    (i % 4) * 4 + i / 4
+}
+
+/// Modifiers of the individual and differential modes, indexed by table
+/// codeword and texel index.
+const MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+/// Distances of the T and H modes.
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+fn extend4(value: u64) -> i32 {
+    (value as i32) * 17
+}
+
+fn extend5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+fn extend6(value: u64) -> i32 {
+    let value = value as i32;
+    (value << 2) | (value >> 4)
+}
+
+fn extend7(value: u64) -> i32 {
+    let value = value as i32;
+    (value << 1) | (value >> 6)
+}
+
+fn rgba(color: [i32; 3]) -> [u8; 4] {
+    let c = |i: usize| color[i].clamp(0, 255) as u8;
+    [c(0), c(1), c(2), 255]
+}
+
+/// Decodes an ETC2 RGB block. With punchthrough alpha, bit 33 tells whether
+/// the block is opaque instead of selecting the differential mode.
+fn decode_color(block: &[u8], punchthrough: bool, texels: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let bit = |position: u32| (bits >> position) & 1;
+    let field = |high: u32, count: u32| (bits >> (high + 1 - count)) & ((1 << count) - 1);
+    let (differential, opaque) = if punchthrough {
+        (true, bit(33) == 1)
+    } else {
+        (bit(33) == 1, true)
+    };
+    let index = |i: usize| (bit(16 + i as u32) << 1 | bit(i as u32)) as usize;
+    // The second index value is transparent black in non opaque blocks
+    let transparent = |index: usize| !opaque && index == 2;
+
+    if !differential {
+        let base = [
+            [
+                extend4(field(63, 4)),
+                extend4(field(55, 4)),
+                extend4(field(47, 4)),
+            ],
+            [
+                extend4(field(59, 4)),
+                extend4(field(51, 4)),
+                extend4(field(43, 4)),
+            ],
+        ];
+        decode_subblocks(bits, base, opaque, texels);
+        return;
+    }
+
+    let r = field(63, 5) as i32;
+    let g = field(55, 5) as i32;
+    let b = field(47, 5) as i32;
+    let delta = |high: u32| ((field(high, 3) as i32) << 29) >> 29;
+    let (r2, g2, b2) = (r + delta(58), g + delta(50), b + delta(42));
+
+    if !(0..32).contains(&r2) {
+        // T mode
+        let base1 = [
+            extend4(field(60, 2) << 2 | field(57, 2)),
+            extend4(field(55, 4)),
+            extend4(field(51, 4)),
+        ];
+        let base2 = [
+            extend4(field(47, 4)),
+            extend4(field(43, 4)),
+            extend4(field(39, 4)),
+        ];
+        let distance = DISTANCES[(field(35, 2) << 1 | bit(32)) as usize];
+        let offset = |color: [i32; 3], d: i32| color.map(|c| c + d);
+        let paint = [
+            base1,
+            offset(base2, distance),
+            base2,
+            offset(base2, -distance),
+        ];
+        for i in 0..16 {
+            let index = index(i);
+            texels[texel(i)] = if transparent(index) {
+                [0; 4]
+            } else {
+                rgba(paint[index])
+            };
+        }
+    } else if !(0..32).contains(&g2) {
+        // H mode
+        let base1 = [
+            extend4(field(62, 4)),
+            extend4(field(58, 3) << 1 | bit(52)),
+            extend4(bit(51) << 3 | field(49, 3)),
+        ];
+        let base2 = [
+            extend4(field(46, 4)),
+            extend4(field(42, 4)),
+            extend4(field(38, 4)),
+        ];
+        let value = |color: [i32; 3]| (color[0] << 16) | (color[1] << 8) | color[2];
+        let order = (value(base1) >= value(base2)) as u64;
+        let distance = DISTANCES[(bit(34) << 2 | bit(32) << 1 | order) as usize];
+        let offset = |color: [i32; 3], d: i32| color.map(|c| c + d);
+        let paint = [
+            offset(base1, distance),
+            offset(base1, -distance),
+            offset(base2, distance),
+            offset(base2, -distance),
+        ];
+        for i in 0..16 {
+            let index = index(i);
+            texels[texel(i)] = if transparent(index) {
+                [0; 4]
+            } else {
+                rgba(paint[index])
+            };
+        }
+    } else if !(0..32).contains(&b2) {
+        // Planar mode, always opaque
+        let origin = [
+            extend6(field(62, 6)),
+            extend7(bit(56) << 6 | field(54, 6)),
+            extend6(bit(48) << 5 | field(44, 2) << 3 | field(41, 3)),
+        ];
+        let horizontal = [
+            extend6(field(38, 5) << 1 | bit(32)),
+            extend7(field(31, 7)),
+            extend6(field(24, 6)),
+        ];
+        let vertical = [
+            extend6(field(18, 6)),
+            extend7(field(12, 7)),
+            extend6(field(5, 6)),
+        ];
+        for y in 0..4 {
+            for x in 0..4 {
+                let color: [i32; 3] = std::array::from_fn(|c| {
+                    (x * (horizontal[c] - origin[c])
+                        + y * (vertical[c] - origin[c])
+                        + 4 * origin[c]
+                        + 2)
+                        >> 2
+                });
+                texels[(y * 4 + x) as usize] = rgba(color);
+            }
+        }
+    } else {
+        let base = [
+            [extend5(r), extend5(g), extend5(b)],
+            [extend5(r2), extend5(g2), extend5(b2)],
+        ];
+        decode_subblocks(bits, base, opaque, texels);
+    }
+}
+
+/// Decodes the two subblocks of the individual and differential modes.
+fn decode_subblocks(bits: u64, base: [[i32; 3]; 2], opaque: bool, texels: &mut [[u8; 4]; 16]) {
+    let bit = |position: u32| (bits >> position) & 1;
+    let tables = [(bits >> 37) & 7, (bits >> 34) & 7];
+    let flip = bit(32) == 1;
+    for i in 0..16 {
+        let (x, y) = (i / 4, i % 4);
+        let subblock = if flip {
+            (y >= 2) as usize
+        } else {
+            (x >= 2) as usize
+        };
+        let index = (bit(16 + i as u32) << 1 | bit(i as u32)) as usize;
+        let modifier = match index {
+            // Non opaque blocks have no small modifiers, the second one is
+            // transparent black
+            0 if !opaque => 0,
+            2 if !opaque => {
+                texels[texel(i)] = [0; 4];
+                continue;
+            }
+            _ => MODIFIERS[tables[subblock] as usize][index],
+        };
+        texels[texel(i)] = rgba(base[subblock].map(|c| c + modifier));
+    }
+}
+
+/// Modifiers of EAC blocks, indexed by table and texel index.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Base codeword, multiplier, modifier table and the 16 texel indices of an
+/// EAC block.
+fn eac_fields(block: &[u8]) -> (u8, i32, &'static [i32; 8], [usize; 16]) {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let indices = std::array::from_fn(|i| ((bits >> (45 - 3 * i)) & 7) as usize);
+    (
+        block[0],
+        (block[1] >> 4) as i32,
+        &EAC_MODIFIERS[(block[1] & 0xf) as usize],
+        indices,
+    )
+}
+
+fn decode_alpha(block: &[u8]) -> [u8; 16] {
+    let (base, multiplier, modifiers, indices) = eac_fields(block);
+    let mut alpha = [0; 16];
+    for (i, index) in indices.into_iter().enumerate() {
+        alpha[texel(i)] = (base as i32 + modifiers[index] * multiplier).clamp(0, 255) as u8;
+    }
+    alpha
+}
+
+/// Decodes one 11-bit EAC block per 8 bytes into consecutive channels,
+/// rounded to 8 bits.
+fn decode_channels(block: &[u8], signed: bool, texels: &mut [[u8; 4]; 16]) {
+    for (channel, block) in block.chunks_exact(8).enumerate() {
+        let (base, multiplier, modifiers, indices) = eac_fields(block);
+        for (i, index) in indices.into_iter().enumerate() {
+            let modifier = if multiplier == 0 {
+                modifiers[index]
+            } else {
+                modifiers[index] * multiplier * 8
+            };
+            texels[texel(i)][channel] = if signed {
+                // -128 means -1.0 like -127 does
+                let base = (base as i8).max(-127) as i32 * 8;
+                let value = (base + modifier).clamp(-1023, 1023);
+                ((value * 127 + value.signum() * 511) / 1023) as i8 as u8
+            } else {
+                let value = (base as i32 * 8 + 4 + modifier).clamp(0, 2047);
+                ((value * 255 + 1023) / 2047) as u8
+            };
+        }
+    }
+}
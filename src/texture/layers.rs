@@ -0,0 +1,313 @@
+//! Array textures and cube maps.
+//!
+//! Cube map faces are in the order of their layers: +X, -X, +Y, -Y, +Z,
+//! -Z. Each is seen from inside the cube, with +Y up on the side faces.
+
+use image::DynamicImage;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindingResource, CommandEncoderDescriptor,
+    Device, Extent3d, FilterMode, LoadOp, Operations, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, SamplerDescriptor, StoreOp, TextureDescriptor, TextureDimension,
+    TextureUsages, TextureViewDescriptor, TextureViewDimension,
+};
+
+use super::{sample_type, sampler, Texture, TextureData, TextureError, TextureOptions};
+use crate::render::{
+    cube_preprocessor, MipmapGenerator, PipelineBuilder, RenderPipelineCache, ShaderSource,
+};
+
+impl Texture {
+    /// Uploads images of the same size as the layers of an array texture.
+    ///
+    /// The images have to be of color types [`TextureData::from_image`]
+    /// picks the same format for.
+    pub fn from_layers(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        images: &[DynamicImage],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let layers: Vec<_> = images
+            .iter()
+            .map(|image| TextureData::from_image(image, options.srgb))
+            .collect();
+        Self::from_layer_data(device, queue, cache, mipmaps, &layers, label, options)
+    }
+
+    /// Uploads the layers of an array texture, of the same format, size and
+    /// mip level count.
+    pub fn from_layer_data(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        layers: &[TextureData],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        Self::upload(
+            device,
+            queue,
+            cache,
+            mipmaps,
+            layers,
+            TextureViewDimension::D2Array,
+            label,
+            options,
+        )
+    }
+
+    /// Uploads the six square faces of a cube map.
+    pub fn from_faces(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        faces: &[DynamicImage; 6],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let faces = faces
+            .each_ref()
+            .map(|face| TextureData::from_image(face, options.srgb));
+        Self::from_face_data(device, queue, cache, mipmaps, &faces, label, options)
+    }
+
+    /// Uploads the six square faces of a cube map, of the same format, size
+    /// and mip level count.
+    pub fn from_face_data(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        faces: &[TextureData; 6],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let (width, height) = (faces[0].width, faces[0].height);
+        if width != height {
+            return Err(TextureError::NotSquare { width, height });
+        }
+        Self::upload(
+            device,
+            queue,
+            cache,
+            mipmaps,
+            faces,
+            TextureViewDimension::Cube,
+            label,
+            options,
+        )
+    }
+
+    /// Uploads a cube map laid out as a cross, with +Y above and -Y below
+    /// +Z.
+    ///
+    /// A horizontal cross of 4x3 faces has -X, +Z, +X and -Z in its middle
+    /// row. A vertical one of 3x4 faces has -X, +Z and +X in its second row
+    /// and -Z upside down below -Y.
+    pub fn from_cross(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        image: &DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let (width, height) = (image.width(), image.height());
+        let (size, negative_z) = if width * 3 == height * 4 {
+            (width / 4, (3, 1))
+        } else if width * 4 == height * 3 {
+            (width / 3, (1, 3))
+        } else {
+            return Err(TextureError::CrossLayout { width, height });
+        };
+        if size == 0 {
+            return Err(TextureError::CrossLayout { width, height });
+        }
+        let face =
+            |(column, row): (u32, u32)| image.crop_imm(column * size, row * size, size, size);
+        let mut faces = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), negative_z].map(face);
+        if negative_z == (1, 3) {
+            faces[5] = faces[5].rotate180();
+        }
+        Self::from_faces(device, queue, cache, mipmaps, &faces, label, options)
+    }
+
+    /// Converts an equirectangular panorama into a cube map with faces of
+    /// `face_size`, on the GPU.
+    ///
+    /// The middle of the panorama becomes the -Z side, the one a camera
+    /// looks at by default, and its top +Y. Mips are only generated when
+    /// the GPU can.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_equirect(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        image: &DynamicImage,
+        face_size: u32,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let panorama = Self::from_image(
+            device,
+            queue,
+            cache,
+            mipmaps,
+            image,
+            Some("panorama"),
+            &TextureOptions {
+                mipmaps: false,
+                ..*options
+            },
+        )?;
+        let format = panorama.texture.format();
+        let features = format.guaranteed_format_features(device.features());
+        if !features
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT)
+        {
+            return Err(TextureError::NotRenderable(format));
+        }
+        let max = device.limits().max_texture_dimension_2d;
+        if face_size > max {
+            return Err(TextureError::TooLarge {
+                width: face_size,
+                height: face_size,
+                max,
+            });
+        }
+
+        let size = Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        };
+        let on_gpu = MipmapGenerator::supports(device, format, options.mip_filter);
+        let mip_level_count = if options.mipmaps && on_gpu {
+            size.max_mips(TextureDimension::D2)
+        } else {
+            if options.mipmaps {
+                log::warn!("mipmaps can't be generated for format {format:?}");
+            }
+            1
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let shader = cube_preprocessor()
+            .process("equirect.wgsl", include_str!("../render/equirect.wgsl"))
+            .expect("equirect shader is valid");
+        // The layout is reflected from the shader, the same for every
+        // conversion to the format, so the pipeline is only built once
+        let pipeline = PipelineBuilder::new(device)
+            .set_label("equirect")
+            .set_shader_module(
+                ShaderSource::Preprocessed(&shader),
+                "vs_main",
+                Some("fs_main"),
+            )
+            .set_pixel_format(format)
+            .build_cached(cache)
+            .expect("equirect pipeline is valid");
+        let layout = pipeline.get_bind_group_layout(0);
+        // The panorama wraps around horizontally
+        let panorama_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("panorama"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("equirect"),
+            layout: &layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&panorama.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&panorama_sampler),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("equirect"),
+        });
+        for face in 0..6 {
+            let target = texture.create_view(&TextureViewDescriptor {
+                label: Some("equirect"),
+                dimension: Some(TextureViewDimension::D2),
+                mip_level_count: Some(1),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("equirect"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(face * 3..face * 3 + 3, 0..1);
+        }
+        if mip_level_count > 1 {
+            mipmaps
+                .generate(
+                    device,
+                    cache,
+                    &mut encoder,
+                    &texture,
+                    TextureViewDimension::Cube,
+                    0,
+                    options.mip_filter,
+                )
+                .expect("mipmap generation is supported");
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sample_type = sample_type(device, &texture);
+        Ok(Self {
+            texture,
+            view,
+            sampler: sampler(device, label, options, sample_type),
+            view_dimension: TextureViewDimension::Cube,
+            sample_type,
+        })
+    }
+}
@@ -0,0 +1,642 @@
+mod astc;
+mod atlas;
+mod bc;
+mod container;
+mod data;
+mod etc;
+mod layers;
+
+use std::{borrow::Cow, fmt};
+
+use image::{DynamicImage, RgbaImage};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    CommandEncoderDescriptor, Device, Extent3d, FilterMode, Origin3d, Queue, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+pub use atlas::{
+    AtlasEntry, AtlasError, AtlasLayout, AtlasOptions, AtlasRect, TextureAtlas, UvRect,
+};
+pub use data::TextureData;
+
+use crate::render::{MipFilter, MipmapGenerator, RenderPipelineCache};
+
+#[derive(Debug)]
+pub enum TextureError {
+    /// The file isn't an image the `image` crate can decode.
+    Decode(image::ImageError),
+    /// A KTX2 or DDS file is malformed.
+    Container {
+        container: &'static str,
+        message: String,
+    },
+    /// The container holds texels in a format that can't be uploaded.
+    UnsupportedFormat(String),
+    /// The KTX2 supercompression scheme can't be undone.
+    UnsupportedSupercompression(String),
+    /// Only single 2D textures are read from containers, not cube maps,
+    /// arrays or volumes.
+    UnsupportedLayout(&'static str),
+    /// The device lacks the feature an uncompressed format needs.
+    MissingFeature(TextureFormat),
+    /// A compressed format the device lacks the feature for has no CPU
+    /// decoder.
+    NoDecoder(TextureFormat),
+    /// No mip levels were given.
+    NoLevels,
+    /// A precomputed mip level isn't half the size of the level above it.
+    MipSize {
+        level: u32,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// A mip level has more or fewer bytes than its size needs.
+    LevelSize {
+        level: u32,
+        expected: usize,
+        found: usize,
+    },
+    /// More mip levels were given than the size allows.
+    TooManyLevels { count: u32, max: u32 },
+    /// The image is larger than the device's textures can be.
+    TooLarge { width: u32, height: u32, max: u32 },
+    /// No layers were given for an array texture.
+    NoLayers,
+    /// A layer differs in format, size or mip level count from the first one.
+    LayerMismatch { layer: u32 },
+    /// More layers were given than the device's array textures can have.
+    TooManyLayers { count: u32, max: u32 },
+    /// Cube map faces have to be square.
+    NotSquare { width: u32, height: u32 },
+    /// A cube cross is neither 4:3 nor 3:4 faces.
+    CrossLayout { width: u32, height: u32 },
+    /// The device can't render to the format, which converting a panorama
+    /// into a cube map needs.
+    NotRenderable(TextureFormat),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Decode(err) => write!(f, "failed to decode the image: {err}"),
+            TextureError::Container { container, message } => {
+                write!(f, "failed to read the {container} file: {message}")
+            }
+            TextureError::UnsupportedFormat(format) => {
+                write!(f, "texture format {format} isn't supported")
+            }
+            TextureError::UnsupportedSupercompression(scheme) => {
+                write!(f, "{scheme} supercompression isn't supported")
+            }
+            TextureError::UnsupportedLayout(layout) => write!(f, "{layout} aren't supported"),
+            TextureError::MissingFeature(format) => {
+                write!(f, "the device lacks the feature for format {format:?}")
+            }
+            TextureError::NoDecoder(format) => {
+                write!(f, "format {format:?} can't be decompressed on the CPU")
+            }
+            TextureError::NoLevels => write!(f, "no mip levels were given"),
+            TextureError::MipSize {
+                level,
+                expected: (width, height),
+                found: (found_width, found_height),
+            } => write!(
+                f,
+                "mip level {level} is {found_width}x{found_height}, expected {width}x{height}"
+            ),
+            TextureError::LevelSize {
+                level,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mip level {level} has {found} bytes, expected {expected}"
+            ),
+            TextureError::TooManyLevels { count, max } => {
+                write!(f, "{count} mip levels given, the size allows {max}")
+            }
+            TextureError::TooLarge { width, height, max } => write!(
+                f,
+                "image of {width}x{height} exceeds the maximum texture size of {max}"
+            ),
+            TextureError::NoLayers => write!(f, "no layers were given"),
+            TextureError::LayerMismatch { layer } => write!(
+                f,
+                "layer {layer} differs in format, size or mip levels from the first one"
+            ),
+            TextureError::TooManyLayers { count, max } => {
+                write!(f, "{count} layers given, array textures can have {max}")
+            }
+            TextureError::NotSquare { width, height } => {
+                write!(
+                    f,
+                    "cube map faces have to be square, found {width}x{height}"
+                )
+            }
+            TextureError::CrossLayout { width, height } => write!(
+                f,
+                "a cube cross has to be 4x3 or 3x4 faces, found an image of {width}x{height}"
+            ),
+            TextureError::NotRenderable(format) => {
+                write!(f, "format {format:?} can't be rendered to")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Decode(err)
+    }
+}
+
+/// How images are uploaded and sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureOptions {
+    /// Fill the whole mip chain, generating the levels the source lacks.
+    ///
+    /// Block compressed textures only have the levels stored in the file.
+    pub mipmaps: bool,
+    pub mip_filter: MipFilter,
+    /// Generate mips on the CPU even when the GPU could.
+    pub cpu_mipmaps: bool,
+    /// Decompress block compressed textures on the CPU even when the device
+    /// supports their format.
+    pub cpu_decompression: bool,
+    /// Whether texels are sRGB encoded colors, rather than data like normals.
+    ///
+    /// KTX2 and DX10 DDS files name their format, this only applies to
+    /// images and legacy DDS files.
+    pub srgb: bool,
+    pub address_mode: AddressMode,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            mipmaps: true,
+            mip_filter: MipFilter::Linear,
+            cpu_mipmaps: false,
+            cpu_decompression: false,
+            srgb: true,
+            address_mode: AddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl TextureOptions {
+    fn format(&self) -> TextureFormat {
+        if self.srgb {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        }
+    }
+}
+
+/// A 2D texture, array texture or cube map with a view of all its layers
+/// and mips, and a sampler.
+///
+/// Missing mip levels are generated on upload: on the GPU when it can render
+/// the format with the filter, using the given [`MipmapGenerator`] and
+/// caching its pipelines in the given [`RenderPipelineCache`], on the CPU
+/// otherwise. Block compressed
+/// texels are uploaded as they are when the device has the feature for
+/// their format, and decompressed on the CPU otherwise.
+#[derive(Debug)]
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    pub sampler: Sampler,
+    /// How `view` is bound: `D2`, `D2Array` or `Cube`.
+    pub view_dimension: TextureViewDimension,
+    /// How `view` is sampled, filterable when the device can filter the
+    /// format.
+    pub sample_type: TextureSampleType,
+}
+
+impl Texture {
+    /// Reads a KTX2 or DDS container, or decodes a PNG, Radiance HDR or
+    /// OpenEXR image, and uploads it.
+    pub fn from_bytes(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        bytes: &[u8],
+        label: &str,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let data = if container::is_ktx2(bytes) {
+            container::read_ktx2(bytes)?
+        } else if container::is_dds(bytes) {
+            container::read_dds(bytes, options.srgb)?
+        } else {
+            TextureData::from_image(&image::load_from_memory(bytes)?, options.srgb)
+        };
+        Self::from_data(device, queue, cache, mipmaps, &data, Some(label), options)
+    }
+
+    /// Uploads an image in the format [`TextureData::from_image`] picks.
+    pub fn from_image(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        image: &DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let data = TextureData::from_image(image, options.srgb);
+        Self::from_data(device, queue, cache, mipmaps, &data, label, options)
+    }
+
+    /// Uploads precomputed mip levels, largest first, each half the size of
+    /// the one before.
+    ///
+    /// With [`TextureOptions::mipmaps`], the levels below the last one given
+    /// are generated from it.
+    pub fn from_mips(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        levels: &[RgbaImage],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let base = levels.first().ok_or(TextureError::NoLevels)?;
+        let (width, height) = base.dimensions();
+        let data = TextureData {
+            format: options.format(),
+            width,
+            height,
+            levels: levels.iter().map(|level| level.as_raw().clone()).collect(),
+        };
+        for (level, image) in levels.iter().enumerate().skip(1) {
+            let expected = data.level_size(level as u32);
+            if image.dimensions() != expected {
+                return Err(TextureError::MipSize {
+                    level: level as u32,
+                    expected,
+                    found: image.dimensions(),
+                });
+            }
+        }
+        Self::from_data(device, queue, cache, mipmaps, &data, label, options)
+    }
+
+    /// Uploads texels in any format the device supports, decompressing
+    /// block compressed ones it lacks the feature for.
+    pub fn from_data(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        data: &TextureData,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let layers = std::slice::from_ref(data);
+        Self::upload(
+            device,
+            queue,
+            cache,
+            mipmaps,
+            layers,
+            TextureViewDimension::D2,
+            label,
+            options,
+        )
+    }
+
+    /// Uploads layers of the same format, size and mip level count into one
+    /// texture, viewed with `view_dimension`.
+    #[allow(clippy::too_many_arguments)]
+    fn upload(
+        device: &Device,
+        queue: &Queue,
+        cache: &RenderPipelineCache,
+        mipmaps: &MipmapGenerator,
+        layers: &[TextureData],
+        view_dimension: TextureViewDimension,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let first = layers.first().ok_or(TextureError::NoLayers)?;
+        for (index, data) in layers.iter().enumerate() {
+            data.validate()?;
+            if data.format != first.format
+                || data.size() != first.size()
+                || data.levels.len() != first.levels.len()
+            {
+                return Err(TextureError::LayerMismatch {
+                    layer: index as u32,
+                });
+            }
+        }
+        let (width, height) = (first.width, first.height);
+        let limits = device.limits();
+        let max = limits.max_texture_dimension_2d;
+        if width > max || height > max {
+            return Err(TextureError::TooLarge { width, height, max });
+        }
+        let count = layers.len() as u32;
+        if count > limits.max_texture_array_layers {
+            return Err(TextureError::TooManyLayers {
+                count,
+                max: limits.max_texture_array_layers,
+            });
+        }
+        let compressed = first.format.is_compressed();
+        let layers = if compressed && !Self::upload_compressed(device, first, options) {
+            let decompressed = layers.iter().map(TextureData::decompress);
+            Cow::Owned(decompressed.collect::<Result<Vec<_>, _>>()?)
+        } else {
+            Cow::Borrowed(layers)
+        };
+        let data = &layers[0];
+        let format = data.format;
+        if !device.features().contains(format.required_features()) {
+            return Err(TextureError::MissingFeature(format));
+        }
+
+        let size = Extent3d {
+            depth_or_array_layers: count,
+            ..data.size()
+        };
+        let given = data.levels.len() as u32;
+        let mut mip_level_count = if options.mipmaps && !compressed {
+            size.max_mips(TextureDimension::D2)
+        } else {
+            given
+        };
+        let generate = mip_level_count > given;
+        let on_gpu = generate
+            && !options.cpu_mipmaps
+            && MipmapGenerator::supports(device, format, options.mip_filter);
+        if generate && !on_gpu && !data::cpu_mipmaps_supported(format) {
+            log::warn!("mipmaps can't be generated for format {format:?}");
+            mip_level_count = given;
+        }
+        // Copyable from so it can be read back
+        let mut usage =
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC;
+        if on_gpu {
+            usage |= TextureUsages::RENDER_ATTACHMENT;
+        }
+        let texture = device.create_texture(&TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+
+        for (layer, data) in layers.iter().enumerate() {
+            let generated = if mip_level_count > given && !on_gpu {
+                let last = given - 1;
+                let (width, height) = data.level_size(last);
+                data::generate_levels(
+                    format,
+                    width,
+                    height,
+                    &data.levels[last as usize],
+                    mip_level_count - given,
+                    options.mip_filter,
+                )
+                .expect("format is supported")
+            } else {
+                Vec::new()
+            };
+            for (level, bytes) in data.levels.iter().chain(&generated).enumerate() {
+                write_level(queue, &texture, level as u32, layer as u32, bytes);
+            }
+        }
+        if on_gpu {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("mipmaps"),
+            });
+            mipmaps
+                .generate(
+                    device,
+                    cache,
+                    &mut encoder,
+                    &texture,
+                    view_dimension,
+                    given - 1,
+                    options.mip_filter,
+                )
+                .expect("mipmap generation is supported");
+            queue.submit(Some(encoder.finish()));
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(view_dimension),
+            ..Default::default()
+        });
+        let sample_type = sample_type(device, &texture);
+        Ok(Self {
+            texture,
+            view,
+            sampler: sampler(device, label, options, sample_type),
+            view_dimension,
+            sample_type,
+        })
+    }
+
+    /// Whether compressed texels can be uploaded as they are, which needs
+    /// the feature and a size made of whole blocks.
+    fn upload_compressed(device: &Device, data: &TextureData, options: &TextureOptions) -> bool {
+        let (block_width, block_height) = data.format.block_dimensions();
+        !options.cpu_decompression
+            && device.features().contains(data.format.required_features())
+            && data.width.is_multiple_of(block_width)
+            && data.height.is_multiple_of(block_height)
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// Layers of an array texture, 6 for a cube map and 1 otherwise.
+    pub fn layer_count(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
+
+    /// Layout entries of the view at `binding` and the sampler at
+    /// `binding + 1`, for putting them in a bind group with other resources.
+    pub fn layout_entries(
+        &self,
+        binding: u32,
+        visibility: ShaderStages,
+    ) -> [BindGroupLayoutEntry; 2] {
+        let filtering = matches!(
+            self.sample_type,
+            TextureSampleType::Float { filterable: true }
+        );
+        [
+            BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: BindingType::Texture {
+                    sample_type: self.sample_type,
+                    view_dimension: self.view_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility,
+                ty: BindingType::Sampler(if filtering {
+                    SamplerBindingType::Filtering
+                } else {
+                    SamplerBindingType::NonFiltering
+                }),
+                count: None,
+            },
+        ]
+    }
+
+    /// A layout with the view at binding 0 and the sampler at binding 1.
+    pub fn bind_group_layout(&self, device: &Device, visibility: ShaderStages) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &self.layout_entries(0, visibility),
+        })
+    }
+
+    /// A bind group of a layout like [`Texture::bind_group_layout`]'s.
+    pub fn bind_group(&self, device: &Device, layout: &BindGroupLayout) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&self.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+/// How a texture is sampled on `device`, whose features decide whether
+/// 32-bit floats are filterable.
+fn sample_type(device: &Device, texture: &wgpu::Texture) -> TextureSampleType {
+    texture
+        .format()
+        .sample_type(None, Some(device.features()))
+        .expect("color formats can be sampled")
+}
+
+/// A linear sampler, or a nearest one for formats that can't be filtered.
+fn sampler(
+    device: &Device,
+    label: Option<&str>,
+    options: &TextureOptions,
+    sample_type: TextureSampleType,
+) -> Sampler {
+    let filter = if matches!(sample_type, TextureSampleType::Float { filterable: true }) {
+        FilterMode::Linear
+    } else {
+        FilterMode::Nearest
+    };
+    device.create_sampler(&SamplerDescriptor {
+        label,
+        address_mode_u: options.address_mode,
+        address_mode_v: options.address_mode,
+        address_mode_w: options.address_mode,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter: filter,
+        ..Default::default()
+    })
+}
+
+fn write_level(queue: &Queue, texture: &wgpu::Texture, level: u32, layer: u32, bytes: &[u8]) {
+    let format = texture.format();
+    let size = texture
+        .size()
+        .mip_level_size(level, TextureDimension::D2)
+        .physical_size(format);
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format
+        .block_copy_size(None)
+        .expect("color formats have a block size");
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: level,
+            origin: Origin3d {
+                z: layer,
+                ..Origin3d::ZERO
+            },
+            aspect: TextureAspect::All,
+        },
+        bytes,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width / block_width * block_size),
+            rows_per_image: Some(size.height / block_height),
+        },
+        Extent3d {
+            depth_or_array_layers: 1,
+            ..size
+        },
+    );
+}
+
+/// Computes `count` mip levels below `base`, each half the size of the one
+/// before.
+///
+/// Colors of sRGB images are filtered in linear space like the GPU does,
+/// alpha always is.
+pub fn generate_mipmaps(
+    base: &RgbaImage,
+    count: u32,
+    filter: MipFilter,
+    srgb: bool,
+) -> Vec<RgbaImage> {
+    let format = if srgb {
+        TextureFormat::Rgba8UnormSrgb
+    } else {
+        TextureFormat::Rgba8Unorm
+    };
+    let (width, height) = base.dimensions();
+    data::generate_levels(format, width, height, base, count, filter)
+        .expect("RGBA is supported")
+        .into_iter()
+        .enumerate()
+        .map(|(level, bytes)| {
+            let size = Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            }
+            .mip_level_size(level as u32 + 1, TextureDimension::D2);
+            RgbaImage::from_raw(size.width, size.height, bytes).expect("level matches its size")
+        })
+        .collect()
+}
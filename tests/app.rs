@@ -0,0 +1,160 @@
+//! Feeds synthetic window events to `App` and checks when it draws.
+
+mod common;
+
+use render_rs::{
+    app::{App, Control},
+    camera::CameraUniform,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, ElementState, MouseButton, WindowEvent},
+    keyboard::KeyCode,
+};
+
+fn headless_app() -> App {
+    App::with_graphics(common::headless(64, 64))
+}
+
+fn send(app: &mut App, event: WindowEvent) {
+    assert_eq!(app.handle_window_event(event), Control::Continue);
+}
+
+fn size(app: &App) -> PhysicalSize<u32> {
+    app.graphics().unwrap().size()
+}
+
+#[test]
+fn draws_on_redraw_request() {
+    let mut app = headless_app();
+    send(&mut app, WindowEvent::RedrawRequested);
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 2);
+}
+
+#[test]
+fn minimised_window_suspends_drawing() {
+    let mut app = headless_app();
+    send(&mut app, WindowEvent::Resized(PhysicalSize::new(0, 0)));
+    assert!(app.window_state().is_suspended());
+
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 0);
+    // The zero size is never applied to the surface
+    assert_eq!(size(&app), PhysicalSize::new(64, 64));
+
+    send(&mut app, WindowEvent::Resized(PhysicalSize::new(32, 16)));
+    assert!(!app.window_state().is_suspended());
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 1);
+    assert_eq!(size(&app), PhysicalSize::new(32, 16));
+}
+
+#[test]
+fn occluded_window_suspends_drawing() {
+    let mut app = headless_app();
+    send(&mut app, WindowEvent::Occluded(true));
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 0);
+
+    send(&mut app, WindowEvent::Occluded(false));
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 1);
+}
+
+#[test]
+fn minimised_and_occluded_resumes_only_when_both_clear() {
+    let mut app = headless_app();
+    send(&mut app, WindowEvent::Occluded(true));
+    send(&mut app, WindowEvent::Resized(PhysicalSize::new(0, 0)));
+    send(&mut app, WindowEvent::Resized(PhysicalSize::new(64, 64)));
+    assert!(app.window_state().is_suspended());
+    send(&mut app, WindowEvent::Occluded(false));
+    assert!(!app.window_state().is_suspended());
+}
+
+#[test]
+fn resizes_are_coalesced_until_the_next_frame() {
+    let mut app = headless_app();
+    for width in [10, 20, 30, 40] {
+        send(&mut app, WindowEvent::Resized(PhysicalSize::new(width, 24)));
+    }
+    assert_eq!(size(&app), PhysicalSize::new(64, 64));
+
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(size(&app), PhysicalSize::new(40, 24));
+    let texture = app.graphics().unwrap().offscreen_texture().unwrap();
+    assert_eq!((texture.width(), texture.height()), (40, 24));
+}
+
+#[test]
+fn close_requested_exits() {
+    let mut app = headless_app();
+    assert_eq!(
+        app.handle_window_event(WindowEvent::CloseRequested),
+        Control::Exit
+    );
+}
+
+fn click(app: &mut App) {
+    send(
+        app,
+        WindowEvent::MouseInput {
+            device_id: DeviceId::dummy(),
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+        },
+    );
+}
+
+#[test]
+fn clicking_grabs_the_cursor_for_mouse_look() {
+    let mut app = headless_app();
+    send(&mut app, WindowEvent::RedrawRequested);
+    let direction = app.camera().target - app.camera().eye;
+
+    // Motion is ignored until the cursor is grabbed
+    app.handle_device_event(&DeviceEvent::MouseMotion { delta: (40.0, 0.0) });
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.camera().target - app.camera().eye, direction);
+
+    click(&mut app);
+    assert!(app.camera_controller().mouse_look());
+    app.handle_device_event(&DeviceEvent::MouseMotion { delta: (40.0, 0.0) });
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_ne!(app.camera().target - app.camera().eye, direction);
+
+    // Losing focus releases the cursor
+    send(&mut app, WindowEvent::Focused(false));
+    assert!(!app.camera_controller().mouse_look());
+}
+
+#[test]
+fn escape_releases_the_cursor_before_dismissing_errors() {
+    let mut app = headless_app();
+    let errors = app.graphics().unwrap().errors().clone();
+    errors.report("shader.wgsl", "expected expression, found ';'");
+    click(&mut app);
+
+    app.handle_key_press(KeyCode::Escape);
+    assert!(!app.camera_controller().mouse_look());
+    assert!(!errors.is_empty());
+
+    app.handle_key_press(KeyCode::Escape);
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn uploads_the_camera_with_each_frame() {
+    let mut app = headless_app();
+    assert!(app.camera_uniform().is_none());
+
+    send(&mut app, WindowEvent::Resized(PhysicalSize::new(32, 16)));
+    send(&mut app, WindowEvent::RedrawRequested);
+    assert_eq!(app.camera().aspect, 2.0);
+    let mut expected = CameraUniform::new();
+    expected.update_view_proj(app.camera());
+    let uniform = app.camera_uniform().expect("camera uniform");
+    assert_eq!(uniform.get().view_proj, expected.view_proj);
+    assert!(!uniform.is_dirty());
+}
@@ -0,0 +1,256 @@
+//! Packing images into texture atlas pages.
+
+mod common;
+
+use image::{Rgba, RgbaImage};
+use render_rs::{
+    capture::read_texture,
+    graphics::Graphics,
+    texture::{AtlasError, AtlasLayout, AtlasOptions, AtlasRect, TextureAtlas},
+};
+
+fn atlas(gfx: &Graphics, page_size: u32, max_pages: Option<u32>) -> TextureAtlas {
+    let options = AtlasOptions {
+        page_size,
+        max_pages,
+        srgb: false,
+        ..Default::default()
+    };
+    TextureAtlas::new(gfx.device(), Some("test atlas"), options).unwrap()
+}
+
+/// An image whose texels encode their position.
+fn gradient(width: u32, height: u32, blue: u8) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([(x * 16) as u8, (y * 16) as u8, blue, 255])
+    })
+}
+
+fn read_page(gfx: &Graphics, atlas: &TextureAtlas, page: u32) -> RgbaImage {
+    read_texture(gfx.device(), gfx.queue(), atlas.page_texture(page).unwrap()).unwrap()
+}
+
+#[test]
+fn packs_images_with_padding_and_extrusion() {
+    let gfx = common::headless(4, 4);
+    let mut atlas = atlas(&gfx, 64, None);
+    let a = atlas
+        .insert(gfx.device(), gfx.queue(), "a", &gradient(8, 4, 10))
+        .unwrap();
+    let b = atlas
+        .insert(gfx.device(), gfx.queue(), "b", &gradient(5, 6, 20))
+        .unwrap();
+    assert_eq!(atlas.len(), 2);
+    assert_eq!(atlas.page_count(), 1);
+
+    // Padding and extrusion put two texels between each image and its cell
+    assert_eq!(
+        a.rect,
+        AtlasRect {
+            x: 2,
+            y: 2,
+            width: 8,
+            height: 4
+        }
+    );
+    assert!(b.rect.x >= a.rect.x + a.rect.width + 4 || b.rect.y >= a.rect.y + 8);
+    let uv = atlas.uv("a").unwrap();
+    assert_eq!(uv.min, [2.0 / 64.0, 2.0 / 64.0]);
+    assert_eq!(uv.max, [10.0 / 64.0, 6.0 / 64.0]);
+
+    let page = read_page(&gfx, &atlas, 0);
+    for (name, image) in [("a", gradient(8, 4, 10)), ("b", gradient(5, 6, 20))] {
+        let rect = atlas.get(name).unwrap().rect;
+        for (x, y, texel) in image.enumerate_pixels() {
+            assert_eq!(
+                page.get_pixel(rect.x + x, rect.y + y),
+                texel,
+                "{name} {x},{y}"
+            );
+        }
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        // Edges are repeated once, corners included
+        assert_eq!(
+            page.get_pixel(rect.x - 1, rect.y - 1),
+            image.get_pixel(0, 0)
+        );
+        assert_eq!(
+            page.get_pixel(right, bottom - 1),
+            image.get_pixel(rect.width - 1, rect.height - 1)
+        );
+        assert_eq!(
+            page.get_pixel(rect.x + 1, bottom),
+            image.get_pixel(1, rect.height - 1)
+        );
+        // Then comes transparent padding
+        assert_eq!(page.get_pixel(rect.x - 2, rect.y), &Rgba([0; 4]));
+        assert_eq!(page.get_pixel(right + 1, rect.y), &Rgba([0; 4]));
+    }
+}
+
+#[test]
+fn adds_pages_until_the_limit() {
+    let gfx = common::headless(4, 4);
+    let mut atlas = atlas(&gfx, 32, Some(2));
+    let image = gradient(20, 20, 0);
+    assert_eq!(
+        atlas
+            .insert(gfx.device(), gfx.queue(), "a", &image)
+            .unwrap()
+            .page,
+        0
+    );
+    assert_eq!(
+        atlas
+            .insert(gfx.device(), gfx.queue(), "b", &image)
+            .unwrap()
+            .page,
+        1
+    );
+    assert_eq!(atlas.page_count(), 2);
+    assert!(matches!(
+        atlas.insert(gfx.device(), gfx.queue(), "c", &image),
+        Err(AtlasError::Full)
+    ));
+    assert!(matches!(
+        atlas.insert(gfx.device(), gfx.queue(), "d", &gradient(31, 2, 0)),
+        Err(AtlasError::TooLarge { width: 31, .. })
+    ));
+    assert!(matches!(
+        atlas.insert(gfx.device(), gfx.queue(), "e", &gradient(0, 4, 0)),
+        Err(AtlasError::Empty(name)) if name == "e"
+    ));
+    assert!(matches!(
+        TextureAtlas::new(
+            gfx.device(),
+            None,
+            AtlasOptions {
+                page_size: gfx.device().limits().max_texture_dimension_2d + 1,
+                ..Default::default()
+            }
+        ),
+        Err(AtlasError::PageSize { .. })
+    ));
+}
+
+#[test]
+fn reuses_the_space_of_removed_images() {
+    let gfx = common::headless(4, 4);
+    let mut atlas = atlas(&gfx, 32, Some(1));
+    let a = atlas
+        .insert(gfx.device(), gfx.queue(), "a", &gradient(12, 12, 0))
+        .unwrap();
+    atlas
+        .insert(gfx.device(), gfx.queue(), "b", &gradient(12, 12, 0))
+        .unwrap();
+    assert!(matches!(
+        atlas.insert(gfx.device(), gfx.queue(), "a", &gradient(2, 2, 0)),
+        Err(AtlasError::Duplicate(name)) if name == "a"
+    ));
+
+    assert_eq!(atlas.remove("a"), Some(a));
+    assert!(!atlas.contains("a"));
+    let c = atlas
+        .insert(gfx.device(), gfx.queue(), "c", &gradient(10, 12, 40))
+        .unwrap();
+    assert_eq!((c.page, c.rect.x, c.rect.y), (0, a.rect.x, a.rect.y));
+    let page = read_page(&gfx, &atlas, 0);
+    assert_eq!(
+        page.get_pixel(c.rect.x + 9, c.rect.y),
+        &Rgba([144, 0, 40, 255])
+    );
+
+    atlas.clear();
+    assert!(atlas.is_empty());
+    for name in ["a", "b", "c", "d"] {
+        atlas
+            .insert(gfx.device(), gfx.queue(), name, &gradient(12, 12, 0))
+            .unwrap();
+    }
+}
+
+#[test]
+fn restores_saved_layouts() {
+    let gfx = common::headless(4, 4);
+    let mut atlas = atlas(&gfx, 32, None);
+    for (name, width) in [("icons/close", 6), ("icons/open file", 9), ("cursor", 3)] {
+        atlas
+            .insert(gfx.device(), gfx.queue(), name, &gradient(width, 5, 60))
+            .unwrap();
+    }
+    let layout = atlas.layout();
+    let text = layout.to_string();
+    assert!(text.starts_with("atlas 32 1 1 1\n"));
+    let parsed: AtlasLayout = text.parse().unwrap();
+    assert_eq!(parsed, layout);
+
+    let page = read_page(&gfx, &atlas, 0);
+    let mut restored = TextureAtlas::from_layout(
+        gfx.device(),
+        gfx.queue(),
+        None,
+        &parsed,
+        std::slice::from_ref(&page),
+        false,
+    )
+    .unwrap();
+    assert_eq!(restored.len(), 3);
+    assert_eq!(
+        restored.get("icons/open file"),
+        atlas.get("icons/open file")
+    );
+    assert_eq!(read_page(&gfx, &restored, 0), page);
+    // Restored images keep their space
+    let entry = restored
+        .insert(gfx.device(), gfx.queue(), "new", &gradient(4, 4, 0))
+        .unwrap();
+    assert!(parsed.entries.iter().all(|(_, placed)| {
+        let (a, b) = (placed.rect, entry.rect);
+        a.x + a.width <= b.x
+            || b.x + b.width <= a.x
+            || a.y + a.height <= b.y
+            || b.y + b.height <= a.y
+    }));
+
+    assert!(matches!(
+        TextureAtlas::from_layout(gfx.device(), gfx.queue(), None, &parsed, &[], false),
+        Err(AtlasError::PageCount {
+            layout: 1,
+            images: 0
+        })
+    ));
+    // Layouts that weren't parsed are checked with the lines they would be
+    // written to
+    let mut moved = parsed.clone();
+    moved.entries[1].1.rect.x = 0;
+    assert!(matches!(
+        TextureAtlas::from_layout(
+            gfx.device(),
+            gfx.queue(),
+            None,
+            &moved,
+            std::slice::from_ref(&page),
+            false
+        ),
+        Err(AtlasError::Layout { line: 3, .. })
+    ));
+    for (text, line) in [
+        ("atlas 32 1 1\n", 1),
+        ("atlas 32 1 1 1\n0 2 2 4 x name\n", 2),
+        ("atlas 32 1 1 1\n0 2 2 4 4\n", 2),
+        ("atlas 32 1 1 1\n\n1 2 2 4 4 name\n", 3),
+        ("atlas 32 4294967295 1 1\n", 1),
+        // Margins reaching past the top left corner or u32::MAX
+        ("atlas 32 1 1 1\n\n0 0 2 4 4 name\n", 3),
+        ("atlas 32 1 1 1\n0 2 2 4294967295 4 name\n", 2),
+        ("atlas 32 1 1 1\n0 2 4294967294 4 4 name\n", 2),
+        ("atlas 32 1 1 1\n0 2 2 0 4 name\n", 2),
+        ("atlas 32 1 1 1\n0 2 2 4 4 a\n\n\n0 4 4 4 4 b\n", 5),
+        ("atlas 32 1 1 1\n0 2 2 4 4 a\n0 12 2 4 4 a\n", 3),
+    ] {
+        assert!(
+            matches!(text.parse::<AtlasLayout>(), Err(AtlasError::Layout { line: l, .. }) if l == line),
+            "{text:?}"
+        );
+    }
+}
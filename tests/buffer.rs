@@ -0,0 +1,293 @@
+//! Typed uniform and storage buffers.
+
+mod common;
+
+use std::sync::mpsc;
+
+use bytemuck::{Pod, Zeroable};
+use render_rs::{
+    graphics::Graphics,
+    render::{
+        BufferError, LayoutMismatch, ShaderPreprocessor, ShaderReflection, StorageBuffer,
+        UniformBuffer,
+    },
+};
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, DeviceDescriptor,
+    Maintain, MapMode, Queue, ShaderStages,
+};
+
+const SHADER: &str = "\
+struct Light {
+    position: vec3<f32>,
+    intensity: f32,
+}
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> lights: array<Light, 4>;
+@group(0) @binding(2) var<storage, read> colors: array<vec3<f32>>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * vec4<f32>(lights[index].position, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(colors[0], lights[0].intensity);
+}
+";
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct Light {
+    position: [f32; 3],
+    intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct Camera {
+    view_proj: [[f32; 4]; 4],
+}
+
+/// A device with the adapter's storage buffer limits, which the WebGL2
+/// limits of `Graphics` lack.
+fn storage_device(gfx: &Graphics) -> Option<(Device, Queue)> {
+    let limits = gfx.adapter().limits();
+    if limits.max_storage_buffers_per_shader_stage == 0 {
+        return None;
+    }
+    let descriptor = DeviceDescriptor {
+        required_limits: limits,
+        ..Default::default()
+    };
+    pollster::block_on(gfx.adapter().request_device(&descriptor, None)).ok()
+}
+
+fn reflect() -> ShaderReflection {
+    ShaderPreprocessor::new()
+        .process("lights.wgsl", SHADER)
+        .and_then(|shader| shader.reflect("vs_main", Some("fs_main")))
+        .unwrap()
+}
+
+fn read_buffer(device: &Device, queue: &Queue, buffer: &Buffer) -> Vec<u8> {
+    let readback = device.create_buffer(&BufferDescriptor {
+        label: Some("buffer readback"),
+        size: buffer.size(),
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, buffer.size());
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = mpsc::channel();
+    readback.slice(..).map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(Maintain::Wait);
+    receiver.recv().unwrap().unwrap();
+    let bytes = readback.slice(..).get_mapped_range().to_vec();
+    bytes
+}
+
+#[test]
+fn uploads_only_changed_values() {
+    let gfx = common::headless(4, 4);
+    let light = Light {
+        position: [1.0, 2.0, 3.0],
+        intensity: 0.5,
+    };
+    let mut buffer =
+        UniformBuffer::new(gfx.device(), Some("light"), ShaderStages::FRAGMENT, light).unwrap();
+    assert!(!buffer.is_dirty());
+    assert!(!buffer.write(gfx.queue()));
+
+    buffer.set(light);
+    assert!(!buffer.is_dirty());
+    buffer.get_mut().intensity = 2.0;
+    assert!(buffer.is_dirty());
+    assert!(buffer.write(gfx.queue()));
+    assert!(!buffer.write(gfx.queue()));
+
+    let bytes = read_buffer(gfx.device(), gfx.queue(), buffer.buffer());
+    let uploaded: Vec<Light> = bytemuck::cast_slice(&bytes).to_vec();
+    assert_eq!(
+        uploaded,
+        [Light {
+            intensity: 2.0,
+            ..light
+        }]
+    );
+}
+
+#[test]
+fn storage_buffers_follow_their_length() {
+    let gfx = common::headless(4, 4);
+    let stages = ShaderStages::FRAGMENT;
+    assert_eq!(
+        StorageBuffer::new(gfx.device(), None, stages, true, &[1u32]).unwrap_err(),
+        BufferError::StorageUnsupported
+    );
+    let Some((device, queue)) = storage_device(&gfx) else {
+        return;
+    };
+
+    let mut buffer = StorageBuffer::new(&device, None, stages, true, &[1u32, 2]).unwrap();
+    buffer.set_values(&device, &[1, 2]).unwrap();
+    assert!(!buffer.is_dirty());
+    buffer.set_values(&device, &[3, 4]).unwrap();
+    assert!(buffer.is_dirty());
+    buffer.write(&queue);
+    assert_eq!(
+        read_buffer(&device, &queue, buffer.buffer()),
+        bytemuck::bytes_of(&[3u32, 4])
+    );
+
+    buffer.set_values(&device, &[5, 6, 7]).unwrap();
+    assert_eq!(buffer.len(), 3);
+    assert_eq!(buffer.buffer().size(), 12);
+    assert_eq!(
+        read_buffer(&device, &queue, buffer.buffer()),
+        bytemuck::bytes_of(&[5u32, 6, 7])
+    );
+    assert_eq!(buffer.set_values(&device, &[]), Err(BufferError::Empty));
+}
+
+#[test]
+fn checks_wgsl_padding() {
+    let gfx = common::headless(4, 4);
+    let device = gfx.device();
+    let stages = ShaderStages::VERTEX_FRAGMENT;
+
+    // Uniform array elements are aligned to 16 bytes, scalars to 4
+    assert_eq!(
+        UniformBuffer::new_array(device, None, stages, &[[0.0f32; 3]; 4]).unwrap_err(),
+        BufferError::Alignment {
+            size: 12,
+            align: 16
+        }
+    );
+    assert_eq!(
+        UniformBuffer::new(device, None, stages, 0u8).unwrap_err(),
+        BufferError::Alignment { size: 1, align: 4 }
+    );
+    assert_eq!(
+        UniformBuffer::<u32>::new_array(device, None, stages, &[]).unwrap_err(),
+        BufferError::Empty
+    );
+
+    let reflection = reflect();
+    let camera = UniformBuffer::new(device, None, stages, Camera::zeroed()).unwrap();
+    camera.check_shader(&reflection, 0, 0).unwrap();
+    assert_eq!(
+        camera.check_shader(&reflection, 0, 3),
+        Err(BufferError::Layout(LayoutMismatch::MissingBinding {
+            group: 0,
+            binding: 3
+        }))
+    );
+    assert!(matches!(
+        camera.check_shader(&reflection, 0, 2),
+        Err(BufferError::Layout(LayoutMismatch::BindingType { .. }))
+    ));
+
+    let lights = UniformBuffer::new_array(device, None, stages, &[Light::zeroed(); 4]).unwrap();
+    lights.check_shader(&reflection, 0, 1).unwrap();
+    let too_few = UniformBuffer::new_array(device, None, stages, &[Light::zeroed(); 2]).unwrap();
+    assert_eq!(
+        too_few.check_shader(&reflection, 0, 1),
+        Err(BufferError::ShaderSize {
+            group: 0,
+            binding: 1,
+            shader: 64,
+            buffer: 32
+        })
+    );
+
+    let Some((device, _)) = storage_device(&gfx) else {
+        return;
+    };
+    let device = &device;
+    // vec3<f32> has a stride of 16 bytes in arrays
+    let unpadded = StorageBuffer::new(device, None, stages, true, &[[0.0f32; 3]]).unwrap();
+    assert_eq!(
+        unpadded.check_shader(&reflection, 0, 2),
+        Err(BufferError::ArrayStride {
+            group: 0,
+            binding: 2,
+            shader: 16,
+            size: 12
+        })
+    );
+    let padded = StorageBuffer::new(device, None, stages, true, &[[0.0f32; 4]; 3]).unwrap();
+    padded.check_shader(&reflection, 0, 2).unwrap();
+}
+
+#[test]
+fn struct_padding_is_left_to_the_shader_check() {
+    let gfx = common::headless(4, 4);
+    let device = gfx.device();
+    let stages = ShaderStages::VERTEX_FRAGMENT;
+    let reflection = ShaderPreprocessor::new()
+        .process(
+            "sun.wgsl",
+            "\
+struct Sun {
+    direction: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> sun: Sun;
+
+@vertex
+fn vs_main() -> @builtin(position) vec4<f32> {
+    return vec4<f32>(sun.direction, 1.0);
+}
+",
+        )
+        .and_then(|shader| shader.reflect("vs_main", None))
+        .unwrap();
+
+    // The struct is rounded up to the 16 byte alignment of its vec3
+    let unpadded = UniformBuffer::new(device, None, stages, [0.0f32; 3]).unwrap();
+    assert_eq!(
+        unpadded.check_shader(&reflection, 0, 0),
+        Err(BufferError::ShaderSize {
+            group: 0,
+            binding: 0,
+            shader: 16,
+            buffer: 12
+        })
+    );
+    let padded = UniformBuffer::new(device, None, stages, [0.0f32; 4]).unwrap();
+    padded.check_shader(&reflection, 0, 0).unwrap();
+}
+
+#[test]
+fn values_must_fit_in_a_binding() {
+    let gfx = common::headless(4, 4);
+    let device = gfx.device();
+    let max = u64::from(device.limits().max_uniform_buffer_binding_size);
+    let len = max as usize / 16 + 1;
+    assert_eq!(
+        UniformBuffer::new_array(device, None, ShaderStages::VERTEX, &vec![[0.0f32; 4]; len])
+            .unwrap_err(),
+        BufferError::TooLarge {
+            size: len as u64 * 16,
+            max
+        }
+    );
+    UniformBuffer::new_array(
+        device,
+        None,
+        ShaderStages::VERTEX,
+        &vec![[0.0f32; 4]; len - 1],
+    )
+    .unwrap();
+}
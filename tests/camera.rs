@@ -0,0 +1,240 @@
+//! The fly camera controller, driven by synthetic input.
+
+use std::time::Duration;
+
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use render_rs::camera::{Camera, CameraController};
+use winit::{
+    event::{DeviceEvent, ElementState, WindowEvent},
+    keyboard::KeyCode,
+};
+
+fn camera() -> Camera {
+    Camera {
+        eye: Point3::new(0.0, 1.0, 5.0),
+        target: Point3::new(0.0, 1.0, 4.0),
+        up: Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 60.0,
+        znear: 0.1,
+        zfar: 100.0,
+        reverse_z: false,
+    }
+}
+
+fn press(controller: &mut CameraController, key: KeyCode) {
+    assert!(controller.process_key(key, ElementState::Pressed));
+}
+
+fn release(controller: &mut CameraController, key: KeyCode) {
+    assert!(controller.process_key(key, ElementState::Released));
+}
+
+/// Runs `frames` updates spread evenly over `seconds`.
+fn run(controller: &mut CameraController, camera: &mut Camera, seconds: f32, frames: u32) {
+    let frame = Duration::from_secs_f32(seconds / frames as f32);
+    for _ in 0..frames {
+        controller.update_camera(camera, frame);
+    }
+}
+
+fn direction(camera: &Camera) -> Vector3<f32> {
+    (camera.target - camera.eye).normalize()
+}
+
+#[test]
+fn starts_looking_where_the_camera_looks() {
+    let mut camera = camera();
+    camera.target = Point3::new(1.0, 2.0, 4.0);
+    let mut controller = CameraController::new(&camera);
+    let before = direction(&camera);
+    assert!((controller.pitch().0 - (1.0 / 3f32.sqrt()).asin()).abs() < 1e-5);
+
+    controller.update_camera(&mut camera, Duration::ZERO);
+    assert!((direction(&camera) - before).magnitude() < 1e-5);
+    assert_eq!(camera.eye, Point3::new(0.0, 1.0, 5.0));
+}
+
+#[test]
+fn movement_does_not_depend_on_the_frame_rate() {
+    let mut slow = camera();
+    let mut fast = camera();
+    let mut slow_controller = CameraController::new(&slow);
+    let mut fast_controller = CameraController::new(&fast);
+    for controller in [&mut slow_controller, &mut fast_controller] {
+        press(controller, KeyCode::KeyW);
+        press(controller, KeyCode::KeyD);
+    }
+
+    run(&mut slow_controller, &mut slow, 0.5, 15);
+    run(&mut fast_controller, &mut fast, 0.5, 120);
+    assert!((slow.eye - fast.eye).magnitude() < 1e-3);
+    assert!((slow_controller.velocity() - fast_controller.velocity()).magnitude() < 1e-3);
+
+    for controller in [&mut slow_controller, &mut fast_controller] {
+        release(controller, KeyCode::KeyW);
+        release(controller, KeyCode::KeyD);
+    }
+    run(&mut slow_controller, &mut slow, 0.5, 5);
+    run(&mut fast_controller, &mut fast, 0.5, 200);
+    assert!((slow.eye - fast.eye).magnitude() < 1e-3);
+}
+
+#[test]
+fn accelerates_to_speed_and_damps_to_a_stop() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    controller.speed = 2.0;
+    press(&mut controller, KeyCode::KeyW);
+
+    controller.update_camera(&mut camera, Duration::from_millis(50));
+    let early = controller.velocity().magnitude();
+    assert!(early > 0.0 && early < 2.0);
+
+    run(&mut controller, &mut camera, 3.0, 60);
+    assert!((controller.velocity() - Vector3::new(0.0, 0.0, -2.0)).magnitude() < 1e-3);
+    assert!(camera.eye.z < 0.0);
+
+    press(&mut controller, KeyCode::ShiftLeft);
+    run(&mut controller, &mut camera, 3.0, 60);
+    assert!((controller.velocity().magnitude() - 6.0).abs() < 1e-3);
+
+    release(&mut controller, KeyCode::KeyW);
+    run(&mut controller, &mut camera, 0.1, 2);
+    assert!(controller.velocity().magnitude() > 0.0);
+    run(&mut controller, &mut camera, 5.0, 60);
+    assert!(controller.velocity().magnitude() < 1e-3);
+    // Height never changed
+    assert_eq!(camera.eye.y, 1.0);
+}
+
+#[test]
+fn instant_acceleration_moves_at_full_speed() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    controller.acceleration = f32::INFINITY;
+    controller.damping = f32::INFINITY;
+    press(&mut controller, KeyCode::Space);
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert!((camera.eye.y - 1.0 - controller.speed).abs() < 1e-5);
+
+    release(&mut controller, KeyCode::Space);
+    let stopped = camera.eye;
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert_eq!(camera.eye, stopped);
+}
+
+#[test]
+fn empty_frames_with_instant_acceleration_stay_put() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    controller.acceleration = f32::INFINITY;
+    controller.damping = f32::INFINITY;
+    press(&mut controller, KeyCode::KeyW);
+    controller.set_mouse_look(true);
+    controller.process_device_event(&DeviceEvent::MouseMotion {
+        delta: (900.0, 0.0),
+    });
+    let eye = camera.eye;
+    controller.update_camera(&mut camera, Duration::ZERO);
+    assert_eq!(camera.eye, eye);
+    assert_eq!(controller.velocity(), Vector3::new(0.0, 0.0, 0.0));
+    // Turning doesn't wait for time to pass
+    assert!((direction(&camera) - Vector3::unit_x()).magnitude() < 1e-5);
+
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert!((camera.eye.x - eye.x - controller.speed).abs() < 1e-4);
+}
+
+#[test]
+fn rates_of_zero_or_less_keep_the_velocity() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    controller.acceleration = 0.0;
+    press(&mut controller, KeyCode::KeyW);
+    run(&mut controller, &mut camera, 1.0, 10);
+    assert_eq!(controller.velocity(), Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(camera.eye, Point3::new(0.0, 1.0, 5.0));
+
+    controller.acceleration = f32::INFINITY;
+    controller.update_camera(&mut camera, Duration::from_millis(100));
+    let velocity = controller.velocity();
+    release(&mut controller, KeyCode::KeyW);
+    for damping in [0.0, -3.0] {
+        controller.damping = damping;
+        let eye = camera.eye;
+        controller.update_camera(&mut camera, Duration::from_secs(1));
+        assert_eq!(controller.velocity(), velocity);
+        assert!((camera.eye - eye - velocity).magnitude() < 1e-5);
+        assert!(camera.eye.z.is_finite());
+    }
+}
+
+#[test]
+fn pitch_stays_under_straight_up() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    controller.set_mouse_look(true);
+    let look = |controller: &mut CameraController, camera: &mut Camera, y: f64| {
+        controller.process_device_event(&DeviceEvent::MouseMotion { delta: (0.0, y) });
+        controller.update_camera(camera, Duration::from_millis(16));
+    };
+
+    for max_pitch in [90.0, 120.0, 720.0] {
+        controller.max_pitch = Deg(max_pitch);
+        look(&mut controller, &mut camera, -10_000.0);
+        assert!(controller.pitch() < Rad::from(Deg(90.0)), "{max_pitch}");
+        assert!(controller.pitch() > Rad::from(Deg(89.9)), "{max_pitch}");
+        // Still facing forward, rather than flipping over
+        assert!(direction(&camera).z < 0.0, "{max_pitch}");
+        look(&mut controller, &mut camera, 10_000.0);
+        assert!(controller.pitch() > Rad::from(Deg(-90.0)), "{max_pitch}");
+        assert!(direction(&camera).z < 0.0, "{max_pitch}");
+    }
+
+    // A negative limit keeps the view level
+    controller.max_pitch = Deg(-10.0);
+    look(&mut controller, &mut camera, -100.0);
+    assert_eq!(controller.pitch(), Rad(0.0));
+}
+
+#[test]
+fn mouse_looks_around_only_when_enabled() {
+    let mut camera = camera();
+    let mut controller = CameraController::new(&camera);
+    let motion = DeviceEvent::MouseMotion {
+        delta: (900.0, 0.0),
+    };
+    assert!(!controller.process_device_event(&motion));
+    controller.update_camera(&mut camera, Duration::from_millis(16));
+    assert!((direction(&camera) - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+
+    // 900 counts at 0.1 degrees each turn right by 90 degrees
+    controller.set_mouse_look(true);
+    assert!(controller.process_device_event(&motion));
+    controller.update_camera(&mut camera, Duration::from_millis(16));
+    assert!((direction(&camera) - Vector3::unit_x()).magnitude() < 1e-5);
+
+    // Looking up stops short of straight up
+    controller.process_device_event(&DeviceEvent::MouseMotion {
+        delta: (0.0, -5000.0),
+    });
+    controller.update_camera(&mut camera, Duration::from_millis(16));
+    assert_eq!(controller.pitch(), Rad::from(Deg(89.0)));
+    assert!(direction(&camera).y < 1.0);
+
+    // Losing focus releases keys and the mouse
+    press(&mut controller, KeyCode::KeyS);
+    assert!(!controller.process_window_event(&WindowEvent::Focused(false)));
+    assert!(!controller.mouse_look());
+    let eye = camera.eye;
+    controller.update_camera(&mut camera, Duration::from_secs(1));
+    assert_eq!(camera.eye, eye);
+}
+
+#[test]
+fn ignores_other_keys() {
+    let mut controller = CameraController::new(&camera());
+    assert!(!controller.process_key(KeyCode::KeyV, ElementState::Pressed));
+    assert!(controller.process_key(KeyCode::ArrowLeft, ElementState::Pressed));
+}
@@ -0,0 +1,68 @@
+//! Reading frames and textures back into images.
+
+mod common;
+
+use std::error::Error;
+
+use render_rs::{
+    capture::{read_texture, CaptureError},
+    graphics::Graphics,
+};
+use wgpu::{
+    Extent3d, SurfaceError, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
+
+fn texture(gfx: &Graphics, format: TextureFormat, usage: TextureUsages) -> Texture {
+    gfx.device().create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    })
+}
+
+#[test]
+fn reports_textures_that_cant_be_read() {
+    let gfx = common::headless(4, 4);
+    let hidden = texture(
+        &gfx,
+        TextureFormat::Rgba8Unorm,
+        TextureUsages::TEXTURE_BINDING,
+    );
+    assert!(matches!(
+        read_texture(gfx.device(), gfx.queue(), &hidden),
+        Err(CaptureError::NotCopyable)
+    ));
+    let float = texture(&gfx, TextureFormat::Rgba16Float, TextureUsages::COPY_SRC);
+    assert!(matches!(
+        read_texture(gfx.device(), gfx.queue(), &float),
+        Err(CaptureError::UnsupportedFormat(TextureFormat::Rgba16Float))
+    ));
+
+    let readable = texture(&gfx, TextureFormat::Bgra8Unorm, TextureUsages::COPY_SRC);
+    let image = read_texture(gfx.device(), gfx.queue(), &readable).expect("image");
+    assert_eq!(image.dimensions(), (4, 4));
+}
+
+#[test]
+fn keeps_the_cause_of_errors() {
+    let err = CaptureError::Surface(SurfaceError::Lost);
+    let source = err.source().expect("source");
+    assert_eq!(
+        source.downcast_ref::<SurfaceError>(),
+        Some(&SurfaceError::Lost)
+    );
+    assert_eq!(source.to_string(), SurfaceError::Lost.to_string());
+
+    assert!(CaptureError::NotCopyable.source().is_none());
+    assert!(CaptureError::MapPending.source().is_none());
+}
@@ -0,0 +1,31 @@
+//! Fixtures shared by the integration test suites.
+//!
+//! Graphics are created with [`create_headless_graphics`], which falls back
+//! to a software adapter on machines without a GPU. The usual `WGPU_*` env
+//! vars pick another adapter.
+
+// Each suite only uses some of the fixtures
+#![allow(dead_code)]
+
+use render_rs::{
+    config::GraphicsConfig,
+    graphics::{create_headless_graphics, Graphics},
+};
+
+/// The headless defaults overridden by env vars, to adjust before
+/// [`headless_with`].
+pub fn config() -> GraphicsConfig {
+    let mut config = GraphicsConfig::headless();
+    config.apply_env().expect("graphics config");
+    config
+}
+
+/// Headless graphics rendering `width` x `height` frames.
+pub fn headless(width: u32, height: u32) -> Graphics {
+    headless_with(&config(), width, height)
+}
+
+/// Headless graphics with `config`, usually adjusted from [`config`].
+pub fn headless_with(config: &GraphicsConfig, width: u32, height: u32) -> Graphics {
+    pollster::block_on(create_headless_graphics(config, width, height)).expect("headless graphics")
+}
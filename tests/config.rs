@@ -0,0 +1,223 @@
+//! Graphics options from code, env vars and the command line.
+
+use render_rs::config::{
+    choose_alpha_mode, choose_present_mode, list_adapters, AdapterSelector, ConfigError,
+    GraphicsConfig,
+};
+use wgpu::{
+    Backends, CompositeAlphaMode, Instance, InstanceDescriptor, PowerPreference, PresentMode,
+};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn parses_boolean_flags() {
+    let mut config = GraphicsConfig::default();
+    // Flags only take inline values
+    let rest = config
+        .apply_args(args(&["--reverse-z", "false"]))
+        .expect("valid args");
+    assert_eq!(rest, ["false"]);
+    assert!(config.reverse_z);
+
+    for (value, on) in [
+        ("", true),
+        ("1", true),
+        ("true", true),
+        ("True", true),
+        ("YES", true),
+        ("on", true),
+        ("0", false),
+        ("false", false),
+        ("False", false),
+        ("no", false),
+        ("OFF", false),
+    ] {
+        config.reverse_z = !on;
+        let arg = format!("--reverse-z={value}");
+        config.apply_args([arg.clone()]).expect("valid args");
+        assert_eq!(config.reverse_z, on, "{arg}");
+    }
+
+    for flag in ["--hot-reload", "--error-overlay", "--fallback-adapter"] {
+        let arg = format!("{flag}=maybe");
+        assert_eq!(
+            config.apply_args([arg]),
+            Err(ConfigError::InvalidValue {
+                arg: flag.to_string(),
+                value: "maybe".to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn parses_backends() {
+    let mut config = GraphicsConfig::default();
+    config
+        .apply_args(args(&["--backend", "Vulkan, gl"]))
+        .expect("valid args");
+    assert_eq!(config.backends, Backends::VULKAN | Backends::GL);
+    assert!(!config.force_fallback_adapter);
+
+    // Any backend can provide a software adapter
+    config
+        .apply_args(args(&["--backend=software"]))
+        .expect("valid args");
+    assert_eq!(config.backends, Backends::all());
+    assert!(config.force_fallback_adapter);
+
+    assert_eq!(
+        config.apply_args(args(&["--backend", "vulkan,glide"])),
+        Err(ConfigError::UnknownBackend("glide".to_string()))
+    );
+}
+
+#[test]
+fn parses_adapter_options() {
+    let mut config = GraphicsConfig::default();
+    let rest = config
+        .apply_args(args(&[
+            "run",
+            "--power-preference",
+            "high",
+            "--adapter=1",
+            "--fallback-adapter",
+        ]))
+        .expect("valid args");
+    assert_eq!(rest, ["run"]);
+    assert_eq!(config.power_preference, PowerPreference::HighPerformance);
+    assert_eq!(config.adapter, Some(AdapterSelector::Index(1)));
+    assert!(config.force_fallback_adapter);
+
+    config
+        .apply_args(args(&["--power-preference=low", "--adapter", "llvmpipe"]))
+        .expect("valid args");
+    assert_eq!(config.power_preference, PowerPreference::LowPower);
+    assert_eq!(
+        config.adapter,
+        Some(AdapterSelector::Name("llvmpipe".to_string()))
+    );
+
+    assert_eq!(
+        config.apply_args(args(&["--power-preference", "max"])),
+        Err(ConfigError::UnknownPowerPreference("max".to_string()))
+    );
+    assert_eq!(
+        config.apply_args(args(&["--adapter"])),
+        Err(ConfigError::MissingValue("--adapter".to_string()))
+    );
+    assert_eq!(
+        config.apply_args(args(&["--backend"])),
+        Err(ConfigError::MissingValue("--backend".to_string()))
+    );
+}
+
+#[test]
+fn selects_adapters_by_index_or_name() {
+    assert_eq!(AdapterSelector::parse("2"), AdapterSelector::Index(2));
+    assert_eq!(
+        AdapterSelector::parse("llvm"),
+        AdapterSelector::Name("llvm".to_string())
+    );
+
+    let instance = Instance::new(&InstanceDescriptor::default());
+    let adapters = list_adapters(&instance, Backends::all());
+    let adapter = adapters.first().expect("an adapter");
+    assert!(AdapterSelector::Index(0).matches(0, adapter));
+    assert!(!AdapterSelector::Index(1).matches(0, adapter));
+
+    // Any part of the name, whatever its case
+    let name = adapter.get_info().name;
+    let part: String = name.chars().skip(1).take(3).collect();
+    assert!(AdapterSelector::Name(part.to_uppercase()).matches(5, adapter));
+    assert!(!AdapterSelector::Name(format!("{name} but not")).matches(0, adapter));
+}
+
+/// The only test touching the env vars, so other tests don't see them change.
+#[test]
+fn applies_env_vars() {
+    let vars = [
+        ("WGPU_BACKEND", "gl"),
+        ("WGPU_POWER_PREF", "high"),
+        ("WGPU_ADAPTER_NAME", "llvmpipe"),
+        ("WGPU_FORCE_FALLBACK_ADAPTER", "TRUE"),
+    ];
+    let saved: Vec<_> = vars
+        .iter()
+        .map(|(name, _)| (name, std::env::var_os(name)))
+        .collect();
+    let set = |vars: &[(&str, &str)]| {
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+    };
+
+    set(&vars);
+    let config = GraphicsConfig::from_env().expect("valid env");
+    assert_eq!(config.backends, Backends::GL);
+    assert_eq!(config.power_preference, PowerPreference::HighPerformance);
+    assert_eq!(
+        config.adapter,
+        Some(AdapterSelector::Name("llvmpipe".to_string()))
+    );
+    assert!(config.force_fallback_adapter);
+
+    set(&[("WGPU_FORCE_FALLBACK_ADAPTER", "off")]);
+    assert!(!GraphicsConfig::from_env().unwrap().force_fallback_adapter);
+    set(&[("WGPU_FORCE_FALLBACK_ADAPTER", "sometimes")]);
+    assert_eq!(
+        GraphicsConfig::from_env(),
+        Err(ConfigError::InvalidValue {
+            arg: "WGPU_FORCE_FALLBACK_ADAPTER".to_string(),
+            value: "sometimes".to_string()
+        })
+    );
+    set(&[
+        ("WGPU_FORCE_FALLBACK_ADAPTER", "0"),
+        ("WGPU_BACKEND", "gpu"),
+    ]);
+    assert_eq!(
+        GraphicsConfig::from_env(),
+        Err(ConfigError::UnknownBackend("gpu".to_string()))
+    );
+
+    for (name, value) in saved {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+}
+
+#[test]
+fn present_modes_fall_back_towards_fifo() {
+    use PresentMode::*;
+    let all = [Fifo, FifoRelaxed, Mailbox, Immediate];
+    for mode in all {
+        assert_eq!(choose_present_mode(mode, &all), mode);
+    }
+
+    // Without vsync, the other mode without vsync is next
+    assert_eq!(choose_present_mode(Mailbox, &[Fifo, Immediate]), Immediate);
+    assert_eq!(choose_present_mode(Immediate, &[Fifo, Mailbox]), Mailbox);
+    assert_eq!(choose_present_mode(Mailbox, &[Fifo]), Fifo);
+    assert_eq!(choose_present_mode(FifoRelaxed, &[Fifo, Mailbox]), Fifo);
+    // Every surface supports Fifo, even when it isn't listed
+    assert_eq!(choose_present_mode(Immediate, &[]), Fifo);
+
+    // wgpu resolves the automatic modes
+    assert_eq!(choose_present_mode(AutoNoVsync, &[Fifo]), AutoNoVsync);
+    assert_eq!(choose_present_mode(AutoVsync, &[]), AutoVsync);
+}
+
+#[test]
+fn unsupported_alpha_modes_are_left_to_wgpu() {
+    use CompositeAlphaMode::*;
+    let supported = [Opaque, PreMultiplied];
+    assert_eq!(choose_alpha_mode(PreMultiplied, &supported), PreMultiplied);
+    assert_eq!(choose_alpha_mode(PostMultiplied, &supported), Auto);
+    assert_eq!(choose_alpha_mode(Inherit, &[]), Auto);
+}
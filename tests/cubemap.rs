@@ -0,0 +1,379 @@
+//! Array textures, cube maps and the skybox drawn from them.
+
+mod common;
+
+use cgmath::{Point3, Vector3};
+use image::{DynamicImage, GenericImage, Rgba, RgbaImage};
+use render_rs::{
+    camera::Camera,
+    capture::read_texture,
+    graphics::{Graphics, OFFSCREEN_FORMAT},
+    render::{
+        PipelineBuilder, RenderGraphBuilder, ShaderPreprocessor, ShaderSource, SkyboxPass,
+        TextureDesc,
+    },
+    texture::{Texture, TextureData, TextureError, TextureOptions},
+};
+use wgpu::{
+    Color, CommandEncoderDescriptor, Extent3d, LoadOp, Operations, Origin3d,
+    RenderPassColorAttachment, RenderPassDescriptor, ShaderStages, StoreOp, TexelCopyTextureInfo,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension,
+};
+
+/// One color per face, in layer order.
+const FACE_COLORS: [[u8; 4]; 6] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 255],
+    [0, 0, 255, 255],
+    [255, 255, 0, 255],
+    [0, 255, 255, 255],
+    [255, 0, 255, 255],
+];
+
+fn linear() -> TextureOptions {
+    TextureOptions {
+        srgb: false,
+        ..Default::default()
+    }
+}
+
+fn solid(size: u32, color: [u8; 4]) -> DynamicImage {
+    RgbaImage::from_pixel(size, size, Rgba(color)).into()
+}
+
+fn faces(size: u32) -> [DynamicImage; 6] {
+    FACE_COLORS.map(|color| solid(size, color))
+}
+
+/// Reads back the first mip of a layer by copying it into a 2D texture.
+fn read_layer(gfx: &Graphics, texture: &Texture, layer: u32) -> RgbaImage {
+    let size = Extent3d {
+        depth_or_array_layers: 1,
+        ..texture.texture.size()
+    };
+    let copy = gfx.device().create_texture(&TextureDescriptor {
+        label: Some("layer"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: texture.texture.format(),
+        usage: TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let mut encoder = gfx
+        .device()
+        .create_command_encoder(&CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_texture(
+        TexelCopyTextureInfo {
+            texture: &texture.texture,
+            mip_level: 0,
+            origin: Origin3d {
+                z: layer,
+                ..Origin3d::ZERO
+            },
+            aspect: TextureAspect::All,
+        },
+        copy.as_image_copy(),
+        size,
+    );
+    gfx.queue().submit(Some(encoder.finish()));
+    read_texture(gfx.device(), gfx.queue(), &copy).unwrap()
+}
+
+/// Samples a cube map face by face, as copies out of cube maps aren't
+/// implemented on GL.
+const READ_FACE: &str = r#"
+#include "cube.wgsl"
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) face: u32,
+}
+
+@group(0) @binding(0) var cube: texture_cube<f32>;
+@group(0) @binding(1) var cube_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corner = index % 3u;
+    let uv = vec2<f32>(f32((corner << 1u) & 2u), f32(corner & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.face = index / 3u;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(cube, cube_sampler, cube_direction(in.face, in.uv), 0.0);
+}
+"#;
+
+fn read_face(gfx: &Graphics, cube: &Texture, face: u32) -> RgbaImage {
+    let device = gfx.device();
+    let size = cube.texture.width();
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("face"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_source("cube.wgsl", include_str!("../src/render/cube.wgsl"));
+    let layout = cube.bind_group_layout(device, ShaderStages::FRAGMENT);
+    let pipeline = PipelineBuilder::new(device)
+        .set_preprocessor(&preprocessor)
+        .set_shader_module(ShaderSource::Wgsl(READ_FACE), "vs_main", Some("fs_main"))
+        .add_bind_group_layout(&layout)
+        .set_pixel_format(TextureFormat::Rgba8Unorm)
+        .build()
+        .unwrap();
+    let bind_group = cube.bind_group(device, &layout);
+
+    let view = target.create_view(&TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+    {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(face * 3..face * 3 + 3, 0..1);
+    }
+    gfx.queue().submit(Some(encoder.finish()));
+    read_texture(device, gfx.queue(), &target).unwrap()
+}
+
+fn assert_close(found: &Rgba<u8>, expected: [u8; 4], tolerance: u8, context: &str) {
+    let close = (0..4).all(|c| found[c].abs_diff(expected[c]) <= tolerance);
+    assert!(close, "{context}: found {found:?}, expected {expected:?}");
+}
+
+#[test]
+fn uploads_arrays_and_cube_faces() {
+    let gfx = common::headless(4, 4);
+    let (device, queue, cache, mipmaps) = (
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+    );
+
+    let layers = [solid(8, [10, 20, 30, 255]), solid(8, [40, 50, 60, 255])];
+    let array =
+        Texture::from_layers(device, queue, cache, mipmaps, &layers, None, &linear()).unwrap();
+    assert_eq!(array.view_dimension, TextureViewDimension::D2Array);
+    assert_eq!(array.layer_count(), 2);
+    assert_eq!(array.mip_level_count(), 4);
+    assert_eq!(
+        read_layer(&gfx, &array, 1).get_pixel(3, 3),
+        &Rgba([40, 50, 60, 255])
+    );
+
+    let cube =
+        Texture::from_faces(device, queue, cache, mipmaps, &faces(16), None, &linear()).unwrap();
+    assert_eq!(cube.view_dimension, TextureViewDimension::Cube);
+    assert_eq!(cube.layer_count(), 6);
+    assert_eq!(cube.mip_level_count(), 5);
+    for (layer, color) in FACE_COLORS.into_iter().enumerate() {
+        let face = read_face(&gfx, &cube, layer as u32);
+        assert_eq!(face.get_pixel(5, 9), &Rgba(color), "face {layer}");
+    }
+
+    let mut mismatched = faces(16);
+    mismatched[3] = solid(8, [0; 4]);
+    assert!(matches!(
+        Texture::from_faces(device, queue, cache, mipmaps, &mismatched, None, &linear()),
+        Err(TextureError::LayerMismatch { layer: 3 })
+    ));
+    let wide = TextureData::from_image(&RgbaImage::new(8, 4).into(), false);
+    assert!(matches!(
+        Texture::from_face_data(
+            device,
+            queue,
+            cache,
+            mipmaps,
+            &[0; 6].map(|_| wide.clone()),
+            None,
+            &linear()
+        ),
+        Err(TextureError::NotSquare {
+            width: 8,
+            height: 4
+        })
+    ));
+    assert!(matches!(
+        Texture::from_layers(device, queue, cache, mipmaps, &[], None, &linear()),
+        Err(TextureError::NoLayers)
+    ));
+}
+
+#[test]
+fn splits_crosses_into_faces() {
+    let gfx = common::headless(4, 4);
+    let size = 8;
+    let paint = |cross: &mut RgbaImage, (column, row): (u32, u32), color: [u8; 4]| {
+        let face = RgbaImage::from_pixel(size, size, Rgba(color));
+        cross.copy_from(&face, column * size, row * size).unwrap();
+    };
+    let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1)];
+
+    let mut horizontal = RgbaImage::new(size * 4, size * 3);
+    let mut vertical = RgbaImage::new(size * 3, size * 4);
+    for (cell, color) in cells.into_iter().zip(FACE_COLORS) {
+        paint(&mut horizontal, cell, color);
+        paint(&mut vertical, cell, color);
+    }
+    paint(&mut horizontal, (3, 1), FACE_COLORS[5]);
+    paint(&mut vertical, (1, 3), FACE_COLORS[5]);
+    // -Z is upside down in vertical crosses, its last texel is its first
+    let marker = [1, 2, 3, 255];
+    vertical.put_pixel(size * 2 - 1, size * 4 - 1, Rgba(marker));
+
+    for (cross, marked) in [(horizontal, false), (vertical, true)] {
+        let cube = Texture::from_cross(
+            gfx.device(),
+            gfx.queue(),
+            gfx.pipeline_cache(),
+            gfx.mipmap_generator(),
+            &cross.into(),
+            None,
+            &linear(),
+        )
+        .unwrap();
+        assert_eq!(cube.texture.width(), size);
+        for (layer, color) in FACE_COLORS.into_iter().enumerate() {
+            let face = read_face(&gfx, &cube, layer as u32);
+            assert_eq!(face.get_pixel(4, 4), &Rgba(color), "face {layer}");
+        }
+        if marked {
+            assert_close(read_face(&gfx, &cube, 5).get_pixel(0, 0), marker, 1, "-Z");
+        }
+    }
+
+    assert!(matches!(
+        Texture::from_cross(
+            gfx.device(),
+            gfx.queue(),
+            gfx.pipeline_cache(),
+            gfx.mipmap_generator(),
+            &solid(16, [0; 4]),
+            None,
+            &linear()
+        ),
+        Err(TextureError::CrossLayout {
+            width: 16,
+            height: 16
+        })
+    ));
+}
+
+#[test]
+fn converts_panoramas_on_the_gpu() {
+    let gfx = common::headless(4, 4);
+    // Red grows from left to right and green from top to bottom
+    let panorama = RgbaImage::from_fn(256, 128, |x, y| Rgba([x as u8, (y * 2) as u8, 0, 255]));
+    let cube = Texture::from_equirect(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        &panorama.into(),
+        32,
+        None,
+        &linear(),
+    )
+    .unwrap();
+    assert_eq!(cube.view_dimension, TextureViewDimension::Cube);
+    assert_eq!((cube.texture.width(), cube.mip_level_count()), (32, 6));
+
+    let center = |layer| *read_face(&gfx, &cube, layer).get_pixel(16, 16);
+    // Side faces look at the horizon, a quarter turn apart
+    assert_close(&center(5), [128, 128, 0, 255], 4, "-Z");
+    assert_close(&center(0), [192, 128, 0, 255], 4, "+X");
+    assert_close(&center(1), [64, 128, 0, 255], 4, "-X");
+    assert!(center(2)[1] < 8, "+Y looks at the top row");
+    assert!(center(3)[1] > 247, "-Y looks at the bottom row");
+}
+
+#[test]
+fn skybox_shows_the_side_the_camera_faces() {
+    let mut gfx = common::headless(16, 16);
+    let cube = Texture::from_faces(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        &faces(4),
+        None,
+        &linear(),
+    )
+    .unwrap();
+    let camera = |target: Vector3<f32>| Camera {
+        eye: Point3::new(3.0, -2.0, 5.0),
+        target: Point3::new(3.0, -2.0, 5.0) + target,
+        up: if target.y == 0.0 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_z()
+        },
+        aspect: 1.0,
+        fovy: 60.0,
+        znear: 0.1,
+        zfar: 100.0,
+        reverse_z: false,
+    };
+
+    let directions = [
+        Vector3::unit_x(),
+        -Vector3::unit_x(),
+        Vector3::unit_y(),
+        -Vector3::unit_y(),
+        Vector3::unit_z(),
+        -Vector3::unit_z(),
+    ];
+    for (face, direction) in directions.into_iter().enumerate() {
+        let mut graph = RenderGraphBuilder::new();
+        let color = graph.create_texture(TextureDesc::surface("sky", OFFSCREEN_FORMAT));
+        graph
+            .add_pass(SkyboxPass::new(
+                gfx.device(),
+                gfx.pipeline_cache(),
+                OFFSCREEN_FORMAT,
+                color,
+                &cube,
+                &camera(direction),
+            ))
+            .set_output(color);
+        gfx.set_render_graph(graph.build().unwrap());
+        let frame = gfx.capture_frame().unwrap();
+        assert_eq!(
+            frame.get_pixel(8, 8),
+            &Rgba(FACE_COLORS[face]),
+            "face {face}"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! The depth buffer kept by `Graphics` and pipelines testing against it.
+
+mod common;
+
+use std::ops::Range;
+
+use cgmath::{Point3, Vector3};
+use image::Rgba;
+use render_rs::{
+    camera::{Camera, CameraUniform},
+    config::{ConfigError, GraphicsConfig},
+    graphics::Graphics,
+    render::{
+        DepthBuffer, Pass, PassBuilder, PassContext, PipelineBuilder, PipelineError,
+        RenderGraphBuilder, ShaderSource, TextureDesc, TextureHandle, UniformBuffer,
+    },
+};
+use wgpu::{
+    Color, CompareFunction, Device, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, ShaderStages, StoreOp, TextureFormat,
+};
+use winit::dpi::PhysicalSize;
+
+/// A red quad close to the camera covering the left of the view, then a blue
+/// one further away covering the right, overlapping in the middle.
+const QUADS: &str = "
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) quad: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    var out: VertexOutput;
+    if quad == 0u {
+        let position = vec3<f32>(mix(-3.0, 0.5, corner.x), mix(-3.0, 3.0, corner.y), -2.0);
+        out.position = camera.view_proj * vec4<f32>(position, 1.0);
+        out.color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    } else {
+        let position = vec3<f32>(mix(-1.0, 8.0, corner.x), mix(-6.0, 6.0, corner.y), -4.0);
+        out.position = camera.view_proj * vec4<f32>(position, 1.0);
+        out.color = vec4<f32>(0.0, 0.0, 1.0, 1.0);
+    }
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+";
+
+const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+fn headless(depth_format: Option<TextureFormat>, reverse_z: bool) -> Graphics {
+    let mut config = common::config();
+    config.depth_format = depth_format;
+    config.reverse_z = reverse_z;
+    common::headless_with(&config, 64, 64)
+}
+
+fn camera(reverse_z: bool) -> Camera {
+    Camera {
+        eye: Point3::new(0.0, 0.0, 0.0),
+        target: Point3::new(0.0, 0.0, -1.0),
+        up: Vector3::unit_y(),
+        aspect: 1.0,
+        fovy: 90.0,
+        znear: 0.1,
+        zfar: 100.0,
+        reverse_z,
+    }
+}
+
+/// Draws `quads` of [`QUADS`], clearing the target when starting with the
+/// first one.
+struct QuadsPass {
+    target: TextureHandle,
+    quads: Range<u32>,
+    pipeline: RenderPipeline,
+    camera: UniformBuffer<CameraUniform>,
+}
+
+impl QuadsPass {
+    fn new(
+        device: &Device,
+        format: TextureFormat,
+        depth: &DepthBuffer,
+        target: TextureHandle,
+        quads: Range<u32>,
+    ) -> Self {
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera(depth.reverse_z()));
+        let camera = UniformBuffer::new(device, Some("camera"), ShaderStages::VERTEX, uniform)
+            .expect("camera uniform");
+        let pipeline = PipelineBuilder::new(device)
+            .set_shader_module(ShaderSource::Wgsl(QUADS), "vs_main", Some("fs_main"))
+            .add_bind_group_layouts(&[camera.bind_group_layout()])
+            .set_pixel_format(format)
+            .set_depth_buffer(depth, true, CompareFunction::Less)
+            .build()
+            .expect("quads pipeline");
+        Self {
+            target,
+            quads,
+            pipeline,
+            camera,
+        }
+    }
+}
+
+impl Pass for QuadsPass {
+    fn name(&self) -> &str {
+        "quads"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.write_texture(self.target);
+    }
+
+    fn execute(&mut self, ctx: &mut PassContext) {
+        self.camera.write(ctx.queue);
+        let view = ctx.texture_view(self.target);
+        let mut r_pass = ctx.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("quads"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: if self.quads.start == 0 {
+                        LoadOp::Clear(Color::GREEN)
+                    } else {
+                        LoadOp::Load
+                    },
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: ctx.depth_attachment(),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        r_pass.set_pipeline(&self.pipeline);
+        r_pass.set_bind_group(0, self.camera.bind_group(), &[]);
+        r_pass.draw(0..6, self.quads.clone());
+    }
+}
+
+#[test]
+fn parses_depth_options() {
+    let mut config = GraphicsConfig::default();
+    assert_eq!(config.depth_format, Some(TextureFormat::Depth32Float));
+    assert!(!config.reverse_z);
+
+    let rest = config
+        .apply_args(["--depth=depth24plus-stencil8", "--reverse-z", "run"].map(String::from))
+        .expect("valid args");
+    assert_eq!(rest, ["run"]);
+    assert_eq!(
+        config.depth_format,
+        Some(TextureFormat::Depth24PlusStencil8)
+    );
+    assert!(config.reverse_z);
+
+    config
+        .apply_args(["--depth", "none"].map(String::from))
+        .expect("valid args");
+    assert_eq!(config.depth_format, None);
+    assert_eq!(
+        config.apply_args(["--depth", "depth16"].map(String::from)),
+        Err(ConfigError::UnknownDepthFormat("depth16".to_string()))
+    );
+}
+
+#[test]
+fn depth_buffer_follows_the_frame_size() {
+    let mut gfx = headless(Some(TextureFormat::Depth24PlusStencil8), true);
+    let depth = gfx.depth_buffer().expect("depth buffer");
+    assert_eq!(depth.format(), TextureFormat::Depth24PlusStencil8);
+    assert!(depth.reverse_z());
+    assert_eq!(depth.clear_value(), 0.0);
+    assert_eq!(
+        depth.compare(CompareFunction::Less),
+        CompareFunction::Greater
+    );
+    assert_eq!(
+        (depth.texture().width(), depth.texture().height()),
+        (64, 64)
+    );
+
+    gfx.resize(PhysicalSize::new(40, 24));
+    let depth = gfx.depth_buffer().expect("depth buffer");
+    assert_eq!(
+        (depth.texture().width(), depth.texture().height()),
+        (40, 24)
+    );
+
+    // Minimizing keeps a 1x1 texture instead of recreating it every time
+    let mut depth = DepthBuffer::new(gfx.device(), TextureFormat::Depth32Float, 0, 0, 1, false);
+    assert_eq!((depth.texture().width(), depth.texture().height()), (1, 1));
+    let texture = depth.texture().clone();
+    depth.resize(gfx.device(), 0, 0);
+    depth.resize(gfx.device(), 1, 0);
+    assert_eq!(depth.texture(), &texture);
+    depth.resize(gfx.device(), 0, 2);
+    assert_ne!(depth.texture(), &texture);
+    assert_eq!((depth.texture().width(), depth.texture().height()), (1, 2));
+
+    assert!(headless(None, false).depth_buffer().is_none());
+}
+
+#[test]
+fn depth_options_need_a_depth_state() {
+    let gfx = headless(None, false);
+    let result = PipelineBuilder::new(gfx.device())
+        .set_shader_module(ShaderSource::Wgsl(QUADS), "vs_main", Some("fs_main"))
+        .set_pixel_format(gfx.format())
+        .set_depth_compare(CompareFunction::Always)
+        .build();
+    assert_eq!(result.err(), Some(PipelineError::NoDepthStencil));
+}
+
+#[test]
+fn nearer_fragments_hide_further_ones() {
+    for format in [
+        TextureFormat::Depth32Float,
+        TextureFormat::Depth24PlusStencil8,
+    ] {
+        for reverse_z in [false, true] {
+            let mut gfx = headless(Some(format), reverse_z);
+            let mut graph = RenderGraphBuilder::new();
+            let color = graph.create_texture(TextureDesc::surface("scene color", gfx.format()));
+            let depth = gfx.depth_buffer().expect("depth buffer");
+            graph
+                .add_pass(QuadsPass::new(
+                    gfx.device(),
+                    gfx.format(),
+                    depth,
+                    color,
+                    0..2,
+                ))
+                .set_output(color);
+            gfx.set_render_graph(graph.build().expect("graph"));
+
+            // The buffer is cleared every frame, equal depths would fail `Less`
+            for _ in 0..2 {
+                let frame = gfx.capture_frame().expect("frame");
+                let case = format!("{format:?}, reverse-Z {reverse_z}");
+                assert_eq!(*frame.get_pixel(8, 32), RED, "{case}");
+                assert_eq!(*frame.get_pixel(32, 32), RED, "{case}");
+                assert_eq!(*frame.get_pixel(56, 32), BLUE, "{case}");
+            }
+        }
+    }
+}
+
+#[test]
+fn later_passes_keep_the_depth_of_earlier_ones() {
+    let mut gfx = headless(Some(TextureFormat::Depth32Float), false);
+    let mut graph = RenderGraphBuilder::new();
+    let color = graph.create_texture(TextureDesc::surface("scene color", gfx.format()));
+    let depth = gfx.depth_buffer().expect("depth buffer");
+    let (device, format) = (gfx.device(), gfx.format());
+    graph
+        .add_pass(QuadsPass::new(device, format, depth, color, 0..1))
+        .add_pass(QuadsPass::new(device, format, depth, color, 1..2))
+        .set_output(color);
+    gfx.set_render_graph(graph.build().expect("graph"));
+
+    // Only the first pass clears, the blue quad stays behind the red one
+    for _ in 0..2 {
+        let frame = gfx.capture_frame().expect("frame");
+        assert_eq!(*frame.get_pixel(8, 32), RED);
+        assert_eq!(*frame.get_pixel(32, 32), RED);
+        assert_eq!(*frame.get_pixel(56, 32), BLUE);
+    }
+}
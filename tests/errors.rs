@@ -0,0 +1,120 @@
+//! Capturing wgpu errors and showing them in the error overlay.
+
+mod common;
+
+use render_rs::{
+    config::GraphicsConfig,
+    graphics::{Graphics, OFFSCREEN_FORMAT},
+    render::{ErrorLog, PipelineBuilder, PipelineError, ShaderSource},
+};
+use wgpu::{BindGroupLayoutDescriptor, ShaderModuleDescriptor};
+
+const UNIFORM: &str = "\
+@group(0) @binding(0) var<uniform> color: vec4<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(i32(index) - 1), f32(i32(index & 1u) * 2 - 1), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return color;
+}
+";
+
+fn headless(error_overlay: bool) -> Graphics {
+    let mut config = common::config();
+    config.error_overlay = error_overlay;
+    common::headless_with(&config, 64, 64)
+}
+
+#[test]
+fn invalid_pipelines_are_returned_as_errors() {
+    let gfx = headless(false);
+    let device = gfx.device();
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("uniform"),
+        source: wgpu::ShaderSource::Wgsl(UNIFORM.into()),
+    });
+    // Entries of layouts created outside the builder aren't checked against
+    // the shader, so only wgpu notices the missing binding
+    let empty = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[],
+    });
+    let result = PipelineBuilder::new(device)
+        .set_label("missing binding")
+        .set_shader_module(ShaderSource::Module(&module), "vs_main", Some("fs_main"))
+        .add_bind_group_layout(&empty)
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build();
+    let Err(PipelineError::Device { label, message }) = result else {
+        panic!("expected a device error, got {result:?}");
+    };
+    assert_eq!(label.as_deref(), Some("missing binding"));
+    assert!(!message.is_empty());
+    assert!(gfx.errors().is_empty());
+
+    // The device is still usable afterwards
+    PipelineBuilder::new(device)
+        .set_shader_module(ShaderSource::Wgsl(UNIFORM), "vs_main", Some("fs_main"))
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build()
+        .unwrap();
+}
+
+#[test]
+fn errors_are_kept_per_source() {
+    let errors = ErrorLog::new();
+    let generation = errors.generation();
+    errors.report("a.wgsl", "first");
+    errors.report("b.wgsl", "second");
+    errors.report("a.wgsl", "third");
+    assert_eq!(
+        errors.errors(),
+        [
+            ("b.wgsl".to_string(), "second".to_string()),
+            ("a.wgsl".to_string(), "third".to_string()),
+        ]
+    );
+    assert!(errors.generation() > generation);
+
+    // Clones share the log
+    let generation = errors.generation();
+    errors.clone().resolve("b.wgsl");
+    assert_eq!(errors.errors().len(), 1);
+    assert!(errors.generation() > generation);
+
+    let generation = errors.generation();
+    errors.resolve("b.wgsl");
+    assert_eq!(errors.generation(), generation);
+    errors.clear();
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn overlay_shows_reported_errors() {
+    let mut gfx = headless(true);
+    let clean = gfx.capture_frame().unwrap();
+
+    gfx.errors()
+        .report("shader.wgsl", "expected expression, found ';'");
+    let failing = gfx.capture_frame().unwrap();
+    assert_eq!(failing.get_pixel(0, 0), clean.get_pixel(0, 0));
+    assert_ne!(failing.get_pixel(10, 10), clean.get_pixel(10, 10));
+
+    gfx.errors().clear();
+    assert_eq!(gfx.capture_frame().unwrap(), clean);
+}
+
+#[test]
+fn overlay_is_opt_in() {
+    let mut config = GraphicsConfig::default();
+    assert!(!config.error_overlay);
+    let rest = config
+        .apply_args(["--error-overlay".to_string(), "run".to_string()])
+        .unwrap();
+    assert!(config.error_overlay);
+    assert_eq!(rest, ["run"]);
+}
@@ -0,0 +1,323 @@
+//! Golden-image regression tests.
+//!
+//! Every test renders a named scene offscreen and compares it against
+//! `tests/golden/<name>.png`. On failure the rendered image and a diff image
+//! are written to `target/golden/`.
+//!
+//! Rendering goes through [`create_headless_graphics`], which falls back to a
+//! software adapter (llvmpipe, lavapipe, WARP) on machines without a GPU. The
+//! usual `WGPU_*` env vars can be used to pick another adapter.
+//! Run with `UPDATE_GOLDEN=1` to (re)generate the reference images.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+use render_rs::{capture::read_texture, render::ShaderPreprocessor};
+use wgpu::util::DeviceExt;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// How far a rendered image may drift from its reference.
+#[derive(Clone, Copy, Debug)]
+struct Tolerance {
+    /// Largest per-channel difference for a pixel to count as identical.
+    channel: u8,
+    /// Largest perceptual colour difference, from 0 (identical) to 1 (black
+    /// vs white), for a pixel outside the channel tolerance to still pass.
+    perceptual: f32,
+    /// Fraction of failing pixels allowed before the comparison fails.
+    max_failing: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            perceptual: 0.05,
+            max_failing: 0.001,
+        }
+    }
+}
+
+struct Comparison {
+    failing: usize,
+    diff: RgbaImage,
+}
+
+/// Perceptual difference between two pixels, based on the YIQ colour space
+/// distance used by pixelmatch, normalised to 0..=1.
+fn perceptual_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    // Blend on white so that differences in alpha are visible too
+    let blend = |p: &Rgba<u8>| -> [f32; 3] {
+        let alpha = p[3] as f32 / 255.0;
+        [0, 1, 2].map(|i| 255.0 + (p[i] as f32 - 255.0) * alpha)
+    };
+    let [r1, g1, b1] = blend(a);
+    let [r2, g2, b2] = blend(b);
+
+    let y = |r: f32, g: f32, b: f32| r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2;
+    let i = |r: f32, g: f32, b: f32| r * 0.595_978 - g * 0.274_176_1 - b * 0.321_801_9;
+    let q = |r: f32, g: f32, b: f32| r * 0.211_470_2 - g * 0.522_617_2 + b * 0.311_147;
+
+    let dy = y(r1, g1, b1) - y(r2, g2, b2);
+    let di = i(r1, g1, b1) - i(r2, g2, b2);
+    let dq = q(r1, g1, b1) - q(r2, g2, b2);
+    // 35215 is the largest possible weighted distance (black vs white)
+    (0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0
+}
+
+fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: Tolerance) -> Comparison {
+    let mut failing = 0;
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    for ((a, e), d) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let channel_diff = (0..4).map(|i| a[i].abs_diff(e[i])).max().unwrap_or(0);
+        let fails =
+            channel_diff > tolerance.channel && perceptual_delta(a, e) > tolerance.perceptual;
+        *d = if fails {
+            failing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // Faded greyscale of the reference gives context around failures
+            let luma = (e[0] as u32 + e[1] as u32 + e[2] as u32) / 3;
+            let faded = (255 - (255 - luma) / 4) as u8;
+            Rgba([faded, faded, faded, 255])
+        };
+    }
+    Comparison { failing, diff }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden")
+}
+
+fn assert_matches_golden(name: &str, actual: &RgbaImage, tolerance: Tolerance) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&path) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(err) => panic!(
+            "missing reference image {} ({err}), run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        ),
+    };
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "{name}: rendered size differs from the reference"
+    );
+
+    let comparison = compare(actual, &expected, tolerance);
+    let total = (actual.width() * actual.height()) as f32;
+    if comparison.failing as f32 / total > tolerance.max_failing {
+        let dir = output_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let actual_path = dir.join(format!("{name}.actual.png"));
+        let diff_path = dir.join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+        panic!(
+            "{name}: {} of {total} pixels differ from {}, see {} and {}",
+            comparison.failing,
+            path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+/// The red triangle from `assets/shaders/triangle.wgsl` drawn by `Graphics::draw`.
+fn triangle_scene() -> RgbaImage {
+    let mut gfx = common::headless(WIDTH, HEIGHT);
+    gfx.capture_frame().unwrap()
+}
+
+/// The textured pentagon of the old `State` scene, without the camera.
+fn textured_pentagon_scene() -> RgbaImage {
+    #[rustfmt::skip]
+    const VERTICES: &[[f32; 5]] = &[
+        [-0.0868241, 0.49240386, 0.0, 0.4131759, 0.00759614],
+        [-0.49513406, 0.06958647, 0.0, 0.0048659444, 0.43041354],
+        [-0.21918549, -0.44939706, 0.0, 0.28081453, 0.949397],
+        [0.35966998, -0.3473291, 0.0, 0.85967, 0.84732914],
+        [0.44147372, 0.2347359, 0.0, 0.9414737, 0.2652641],
+    ];
+    const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
+
+    let gfx = common::headless(WIDTH, HEIGHT);
+    let device = gfx.device();
+    let queue = gfx.queue();
+
+    let logo = image::load_from_memory(include_bytes!("../assets/logo.png"))
+        .unwrap()
+        .to_rgba8();
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("logo"),
+            size: wgpu::Extent3d {
+                width: logo.width(),
+                height: logo.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &logo,
+    );
+    let view = texture.create_view(&Default::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        mag_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let vertex_bytes: Vec<u8> = VERTICES
+        .iter()
+        .flatten()
+        .flat_map(|f| f.to_ne_bytes())
+        .collect();
+    let index_bytes: Vec<u8> = INDICES.iter().flat_map(|i| i.to_ne_bytes()).collect();
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("pentagon vertices"),
+        contents: &vertex_bytes,
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("pentagon indices"),
+        contents: &index_bytes,
+        usage: wgpu::BufferUsages::INDEX,
+    });
+
+    let image_wgsl = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders/image.wgsl");
+    let image_wgsl = ShaderPreprocessor::new().process_file(&image_wgsl).unwrap();
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("image.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(image_wgsl.source().into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("textured pentagon"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(gfx.format().into())],
+            compilation_options: Default::default(),
+        }),
+        primitive: Default::default(),
+        depth_stencil: None,
+        multisample: Default::default(),
+        multiview: None,
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("logo"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    });
+
+    let target = gfx.offscreen_texture().unwrap();
+    let target_view = target.create_view(&Default::default());
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("textured pentagon"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.2,
+                        b: 0.3,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..9, 0, 0..1);
+    }
+    queue.submit(Some(encoder.finish()));
+
+    read_texture(device, queue, target).unwrap()
+}
+
+#[test]
+fn triangle() {
+    assert_matches_golden("triangle", &triangle_scene(), Tolerance::default());
+}
+
+#[test]
+fn textured_pentagon() {
+    // Texture filtering differs slightly between implementations
+    let tolerance = Tolerance {
+        channel: 8,
+        max_failing: 0.01,
+        ..Default::default()
+    };
+    assert_matches_golden("textured_pentagon", &textured_pentagon_scene(), tolerance);
+}
+
+#[test]
+fn comparison_flags_changed_pixels() {
+    let expected = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+    actual.put_pixel(2, 2, Rgba([1, 1, 1, 255]));
+
+    let comparison = compare(&actual, &expected, Tolerance::default());
+    assert_eq!(comparison.failing, 1);
+    assert_eq!(*comparison.diff.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+    assert_ne!(*comparison.diff.get_pixel(2, 2), Rgba([255, 0, 0, 255]));
+}
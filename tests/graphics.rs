@@ -0,0 +1,125 @@
+//! Errors reported while creating graphics.
+
+mod common;
+
+use std::error::Error;
+
+use render_rs::{
+    config::AdapterSelector,
+    graphics::{create_headless_graphics, GraphicsError},
+};
+use wgpu::{
+    rwh::{RawDisplayHandle, RawWindowHandle, XlibDisplayHandle, XlibWindowHandle},
+    Backends, DeviceDescriptor, Instance, InstanceDescriptor, SurfaceTargetUnsafe,
+};
+
+/// Every variant but the ones wrapping errors only wgpu can create.
+fn plain_errors() -> Vec<GraphicsError> {
+    vec![
+        GraphicsError::AdapterNotFound {
+            force_fallback_adapter: false,
+        },
+        GraphicsError::AdapterNotFound {
+            force_fallback_adapter: true,
+        },
+        GraphicsError::AdapterNotMatched(AdapterSelector::Index(3)),
+        GraphicsError::AdapterNotMatched(AdapterSelector::Name("radeon".to_string())),
+        GraphicsError::SurfaceUnsupported {
+            adapter: "llvmpipe".to_string(),
+        },
+        GraphicsError::ConfigureSurface(wgpu::Error::Validation {
+            source: "invalid size".into(),
+            description: "surface size is zero".to_string(),
+        }),
+    ]
+}
+
+/// An error from an instance without backends, which can't create surfaces.
+fn create_surface_error() -> GraphicsError {
+    let instance = Instance::new(&InstanceDescriptor {
+        backends: Backends::empty(),
+        ..Default::default()
+    });
+    // SAFETY: no backend is enabled, so nothing ever reads the handles
+    let surface = unsafe {
+        instance.create_surface_unsafe(SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle: RawDisplayHandle::Xlib(XlibDisplayHandle::new(None, 0)),
+            raw_window_handle: RawWindowHandle::Xlib(XlibWindowHandle::new(1)),
+        })
+    };
+    GraphicsError::CreateSurface(surface.expect_err("no backend creates the surface"))
+}
+
+/// An error from asking the adapter for features it doesn't have.
+fn request_device_error() -> GraphicsError {
+    let gfx = common::headless(4, 4);
+    let adapter = gfx.adapter();
+    let request = adapter.request_device(
+        &DeviceDescriptor {
+            required_features: !adapter.features(),
+            ..Default::default()
+        },
+        None,
+    );
+    GraphicsError::RequestDevice(pollster::block_on(request).expect_err("missing features"))
+}
+
+#[test]
+fn describes_every_error() {
+    let messages: Vec<_> = plain_errors().iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        [
+            "no graphics adapter found, make sure up to date GPU drivers are installed",
+            "no software adapter found, install Mesa (llvmpipe/lavapipe) or WARP",
+            "no graphics adapter matches #3, run with --list-adapters to see the available ones",
+            "no graphics adapter matches \"radeon\", run with --list-adapters to see the available ones",
+            "adapter \"llvmpipe\" can't present to the window",
+            "failed to configure the window surface: surface size is zero",
+        ]
+    );
+
+    let err = create_surface_error();
+    assert!(err
+        .to_string()
+        .starts_with("failed to create a surface for the window: "));
+    let err = request_device_error();
+    assert!(err
+        .to_string()
+        .starts_with("the graphics adapter failed to open a device: "));
+}
+
+#[test]
+fn keeps_the_cause_of_wgpu_errors() {
+    for err in [create_surface_error(), request_device_error()] {
+        let source = err.source().expect("wrapped error");
+        assert!(err.to_string().ends_with(&source.to_string()), "{err}");
+    }
+    let errors = plain_errors();
+    let (configure, rest) = errors.split_last().unwrap();
+    assert!(configure.source().is_some());
+    assert!(rest.iter().all(|err| err.source().is_none()));
+}
+
+#[test]
+fn only_missing_adapters_retry_with_a_fallback() {
+    let retried: Vec<_> = plain_errors()
+        .iter()
+        .chain(&[create_surface_error(), request_device_error()])
+        .map(GraphicsError::can_retry_with_fallback)
+        .collect();
+    assert_eq!(
+        retried,
+        [true, false, false, false, false, false, false, false]
+    );
+}
+
+#[test]
+fn unmatched_adapters_are_reported() {
+    let mut config = common::config();
+    config.adapter = Some(AdapterSelector::Name("no such adapter".to_string()));
+    let result = pollster::block_on(create_headless_graphics(&config, 4, 4));
+    let err = result.expect_err("no adapter matches");
+    assert!(matches!(err, GraphicsError::AdapterNotMatched(_)), "{err}");
+    assert!(!err.can_retry_with_fallback());
+}
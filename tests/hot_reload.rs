@@ -0,0 +1,185 @@
+//! Rebuilding pipelines when their shader files change.
+
+mod common;
+
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use render_rs::{
+    config::GraphicsConfig,
+    graphics::{Graphics, OFFSCREEN_FORMAT},
+    render::{PipelineBuilder, ShaderHotReload, ShaderSource},
+};
+
+const TRIANGLE: &str = "\
+#include \"color.wgsl\"
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(i32(index) - 1), f32(i32(index & 1u) * 2 - 1), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return COLOR;
+}
+";
+const RED: &str = "const COLOR = vec4<f32>(1.0, 0.0, 0.0, 1.0);\n";
+const BLUE: &str = "const COLOR = vec4<f32>(0.0, 0.0, 1.0, 1.0);\n";
+
+/// A temporary directory for the shaders of `test`.
+fn shader_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "render-rs-hot-reload-{}-{test}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Polls until a pipeline was rebuilt, or gives up after a few seconds.
+fn poll_until_reloaded(hot_reload: &ShaderHotReload, gfx: &Graphics) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if hot_reload.poll(gfx.device(), gfx.pipeline_cache()) > 0 {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn pipelines_follow_their_shader_files() {
+    let gfx = common::headless(4, 4);
+    let hot_reload = ShaderHotReload::new().unwrap();
+    let dir = shader_dir("pipelines");
+    let path = dir.join("triangle.wgsl");
+    let color = dir.join("color.wgsl");
+    fs::write(&path, TRIANGLE).unwrap();
+    fs::write(&color, RED).unwrap();
+
+    let pipeline = PipelineBuilder::new(gfx.device())
+        .set_shader_module(ShaderSource::Path(&path), "vs_main", Some("fs_main"))
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build_reloadable(gfx.pipeline_cache(), &hot_reload)
+        .unwrap();
+    let original = pipeline.get();
+    let built = gfx.pipeline_cache().stats().misses;
+    assert_eq!(hot_reload.poll(gfx.device(), gfx.pipeline_cache()), 0);
+
+    // Changing an included file rebuilds the pipelines including it
+    fs::write(&color, BLUE).unwrap();
+    assert!(poll_until_reloaded(&hot_reload, &gfx));
+    assert_ne!(pipeline.get(), original);
+    // Reloads go through the pipeline cache
+    assert_eq!(gfx.pipeline_cache().stats().misses, built + 1);
+
+    // Let late events of the write settle before breaking the shader
+    thread::sleep(Duration::from_millis(200));
+    hot_reload.poll(gfx.device(), gfx.pipeline_cache());
+    let reloaded = pipeline.get();
+
+    fs::write(&path, TRIANGLE.replace("return COLOR", "return true")).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(hot_reload.poll(gfx.device(), gfx.pipeline_cache()), 0);
+    assert_eq!(pipeline.get(), reloaded);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn broken_shaders_are_watched_until_fixed() {
+    let gfx = common::headless(4, 4);
+    let hot_reload = ShaderHotReload::new().unwrap();
+    let dir = shader_dir("broken");
+    let path = dir.join("triangle.wgsl");
+    fs::write(&path, TRIANGLE).unwrap();
+    // The included file is missing
+    let _ = fs::remove_file(dir.join("color.wgsl"));
+
+    let mut fallback = None;
+    let pipeline = PipelineBuilder::new(gfx.device())
+        .set_shader_module(ShaderSource::Path(&path), "vs_main", Some("fs_main"))
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build_reloadable_or(gfx.pipeline_cache(), &hot_reload, || {
+            let pipeline = PipelineBuilder::new(gfx.device())
+                .set_shader_module(
+                    ShaderSource::Wgsl(&TRIANGLE.replace("#include \"color.wgsl\"", RED)),
+                    "vs_main",
+                    Some("fs_main"),
+                )
+                .set_pixel_format(OFFSCREEN_FORMAT)
+                .build()
+                .unwrap();
+            fallback = Some(pipeline.clone());
+            pipeline
+        })
+        .unwrap();
+    assert_eq!(Some(pipeline.get()), fallback);
+
+    fs::write(&path, TRIANGLE.replace("#include \"color.wgsl\"", BLUE)).unwrap();
+    assert!(poll_until_reloaded(&hot_reload, &gfx));
+    assert_ne!(Some(pipeline.get()), fallback);
+
+    // Invalid builder state is still an error
+    let err = PipelineBuilder::new(gfx.device())
+        .set_shader_module(ShaderSource::Path(&path), "vs_main", Some("fs_main"))
+        .build_reloadable_or(gfx.pipeline_cache(), &hot_reload, || unreachable!());
+    assert!(err.is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn graphics_read_shaders_from_the_shader_dir() {
+    let mut config = common::config();
+    config
+        .apply_args(["--hot-reload", "--shader-dir=shaders"].map(String::from))
+        .unwrap();
+    assert!(config.hot_reload);
+    assert_eq!(config.shader_dir, PathBuf::from("shaders"));
+    // Tests run from the project, where the default directory is found
+    config.shader_dir = GraphicsConfig::default().shader_dir;
+    assert_eq!(
+        config.shader_path("triangle.wgsl"),
+        PathBuf::from("assets/shaders/triangle.wgsl")
+    );
+
+    let dir = shader_dir("graphics");
+    fs::write(dir.join("triangle.wgsl"), TRIANGLE).unwrap();
+    fs::write(dir.join("color.wgsl"), BLUE).unwrap();
+    config.shader_dir = dir.clone();
+    let mut gfx = common::headless_with(&config, 8, 8);
+    let frame = gfx.capture_frame().unwrap();
+    assert_eq!(frame.get_pixel(4, 5).0, [0, 0, 255, 255]);
+
+    fs::write(dir.join("color.wgsl"), RED).unwrap();
+    let start = Instant::now();
+    while gfx.capture_frame().unwrap().get_pixel(4, 5).0 != [255, 0, 0, 255] {
+        assert!(start.elapsed() < Duration::from_secs(5), "not reloaded");
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(gfx.errors().is_empty());
+
+    // A shader broken at startup is replaced by the built-in one until fixed
+    fs::write(dir.join("color.wgsl"), "const COLOR = 1.0;\n").unwrap();
+    let mut gfx = common::headless_with(&config, 8, 8);
+    let frame = gfx.capture_frame().unwrap();
+    assert_eq!(frame.get_pixel(4, 5).0, [255, 0, 0, 255]);
+    assert!(!gfx.errors().is_empty());
+
+    fs::write(dir.join("color.wgsl"), BLUE).unwrap();
+    let start = Instant::now();
+    while gfx.capture_frame().unwrap().get_pixel(4, 5).0 != [0, 0, 255, 255] {
+        assert!(start.elapsed() < Duration::from_secs(5), "not reloaded");
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(gfx.errors().is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,113 @@
+//! Multisampled frames resolved into the render graph's textures.
+
+mod common;
+
+use image::RgbaImage;
+use render_rs::{
+    config::{choose_sample_count, ConfigError, GraphicsConfig},
+    graphics::Graphics,
+    render::{RenderGraphBuilder, TextureDesc, TrianglePass},
+};
+use wgpu::TextureFormat;
+use winit::dpi::PhysicalSize;
+
+fn headless(sample_count: u32) -> Graphics {
+    let mut config = common::config();
+    config.sample_count = sample_count;
+    common::headless_with(&config, 64, 64)
+}
+
+/// Pixels of the triangle frame that are neither the red triangle nor the
+/// green background.
+fn blended_pixels(frame: &RgbaImage) -> usize {
+    frame
+        .pixels()
+        .filter(|pixel| ![[255, 0, 0, 255], [0, 255, 0, 255]].contains(&pixel.0))
+        .count()
+}
+
+#[test]
+fn parses_sample_count() {
+    let mut config = GraphicsConfig::default();
+    assert_eq!(config.sample_count, 1);
+    config
+        .apply_args(["--msaa", "4"].map(String::from))
+        .expect("valid args");
+    assert_eq!(config.sample_count, 4);
+    assert_eq!(
+        config.apply_args(["--msaa=lots"].map(String::from)),
+        Err(ConfigError::InvalidNumber {
+            arg: "--msaa".to_string(),
+            value: "lots".to_string()
+        })
+    );
+}
+
+#[test]
+fn chooses_supported_sample_counts() {
+    let gfx = headless(1);
+    let formats = [gfx.format(), TextureFormat::Depth32Float];
+    assert_eq!(choose_sample_count(1, gfx.adapter(), &formats), 1);
+    assert_eq!(choose_sample_count(0, gfx.adapter(), &formats), 1);
+    // 4 samples are guaranteed for both formats
+    assert_eq!(choose_sample_count(4, gfx.adapter(), &formats), 4);
+    assert!(choose_sample_count(3, gfx.adapter(), &formats) <= 2);
+    let highest = choose_sample_count(64, gfx.adapter(), &formats);
+    assert!(highest.is_power_of_two() && (4..=16).contains(&highest));
+}
+
+#[test]
+fn single_sampled_frames_have_hard_edges() {
+    let mut gfx = headless(1);
+    assert_eq!(gfx.sample_count(), 1);
+    let frame = gfx.capture_frame().expect("frame");
+    assert_eq!(blended_pixels(&frame), 0);
+}
+
+#[test]
+fn multisampled_frames_blend_edges() {
+    let mut gfx = headless(4);
+    assert_eq!(gfx.sample_count(), 4);
+    assert_eq!(gfx.pipeline_cache().sample_count(), 4);
+    assert_eq!(gfx.depth_buffer().expect("depth buffer").sample_count(), 4);
+
+    let frame = gfx.capture_frame().expect("frame");
+    assert!(blended_pixels(&frame) > 0);
+    // The inside of the triangle and the background are untouched
+    assert_eq!(frame.get_pixel(32, 40).0, [255, 0, 0, 255]);
+    assert_eq!(frame.get_pixel(2, 2).0, [0, 255, 0, 255]);
+
+    gfx.resize(PhysicalSize::new(48, 32));
+    let frame = gfx.capture_frame().expect("frame");
+    assert_eq!(frame.dimensions(), (48, 32));
+    assert!(gfx.errors().is_empty());
+}
+
+#[test]
+fn multisampled_passes_draw_into_textures_of_any_format() {
+    let mut gfx = headless(4);
+    // Either texture is the output, the other one is drawn all the same
+    for output in [0, 1] {
+        let mut graph = RenderGraphBuilder::new();
+        let textures = [
+            graph.create_texture(TextureDesc::surface("hdr", TextureFormat::Rgba16Float)),
+            graph.create_texture(TextureDesc::surface("color", gfx.format())),
+        ];
+        let formats = [TextureFormat::Rgba16Float, gfx.format()];
+        for (texture, format) in textures.into_iter().zip(formats) {
+            let pass = TrianglePass::new(gfx.device(), gfx.pipeline_cache(), format, texture);
+            graph.add_pass(pass);
+        }
+        graph.set_output(textures[output]);
+        let graph = graph.build().expect("graph");
+        // The formats differ, so the textures can't share a multisampled one
+        assert_eq!(graph.multisampled_texture_count(), 2);
+        gfx.set_render_graph(graph);
+
+        let frame = gfx.capture_frame().expect("frame");
+        assert!(blended_pixels(&frame) > 0, "output {output}");
+        assert_eq!(frame.get_pixel(32, 40).0, [255, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(2, 2).0, [0, 255, 0, 255]);
+    }
+    assert!(gfx.errors().is_empty());
+}
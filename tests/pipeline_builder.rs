@@ -0,0 +1,179 @@
+//! Pipeline state validation of `PipelineBuilder`.
+
+mod common;
+
+use render_rs::{
+    graphics::OFFSCREEN_FORMAT,
+    render::{PipelineBuilder, PipelineError, RenderPipelineCache, ShaderSource},
+};
+use wgpu::{
+    BlendState, CompareFunction, Face, IndexFormat, PolygonMode, PrimitiveTopology, TextureFormat,
+    VertexBufferLayout, VertexStepMode,
+};
+
+const SHADER: &str = include_str!("../assets/shaders/triangle.wgsl");
+
+fn triangle<'a, 'b>(builder: &'b mut PipelineBuilder<'a>) -> &'b mut PipelineBuilder<'a> {
+    builder.set_shader_module(ShaderSource::Wgsl(SHADER), "vs_main", Some("fs_main"))
+}
+
+#[test]
+fn builds_full_pipeline_state() {
+    let gfx = common::headless(4, 4);
+    let mut builder = PipelineBuilder::new(gfx.device());
+    triangle(&mut builder)
+        .set_label("full state")
+        .set_topology(PrimitiveTopology::TriangleStrip)
+        .set_strip_index_format(Some(IndexFormat::Uint16))
+        .set_cull_mode(Some(Face::Back))
+        .add_color_target(OFFSCREEN_FORMAT)
+        .set_blend(Some(BlendState::ALPHA_BLENDING))
+        .add_color_target(TextureFormat::Rgba16Float)
+        .set_depth(TextureFormat::Depth32Float, true, CompareFunction::Less)
+        .set_sample_count(4);
+    assert!(builder.build().is_ok());
+}
+
+#[test]
+fn reports_invalid_state() {
+    let gfx = common::headless(4, 4);
+    let mut builder = PipelineBuilder::new(gfx.device());
+
+    assert_eq!(
+        builder.set_pixel_format(OFFSCREEN_FORMAT).build().err(),
+        Some(PipelineError::MissingShader)
+    );
+    assert_eq!(
+        triangle(&mut builder).build().err(),
+        Some(PipelineError::NoTargets)
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .set_blend(Some(BlendState::REPLACE))
+            .build()
+            .err(),
+        Some(PipelineError::NoColorTarget)
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .add_color_target(TextureFormat::Depth32Float)
+            .build()
+            .err(),
+        Some(PipelineError::NotColorFormat(TextureFormat::Depth32Float))
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .add_color_target(TextureFormat::Rgba32Float)
+            .set_blend(Some(BlendState::ALPHA_BLENDING))
+            .build()
+            .err(),
+        Some(PipelineError::NotBlendable(TextureFormat::Rgba32Float))
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .set_pixel_format(OFFSCREEN_FORMAT)
+            .set_sample_count(3)
+            .build()
+            .err(),
+        Some(PipelineError::UnsupportedSampleCount {
+            count: 3,
+            format: OFFSCREEN_FORMAT
+        })
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .set_pixel_format(OFFSCREEN_FORMAT)
+            .set_depth(OFFSCREEN_FORMAT, true, CompareFunction::Less)
+            .build()
+            .err(),
+        Some(PipelineError::NotDepthFormat(OFFSCREEN_FORMAT))
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .set_pixel_format(OFFSCREEN_FORMAT)
+            .set_strip_index_format(Some(IndexFormat::Uint32))
+            .build()
+            .err(),
+        Some(PipelineError::StripIndexFormat(
+            PrimitiveTopology::TriangleList
+        ))
+    );
+    assert_eq!(
+        triangle(&mut builder)
+            .set_pixel_format(OFFSCREEN_FORMAT)
+            .set_polygon_mode(PolygonMode::Line)
+            .build()
+            .err(),
+        Some(PipelineError::MissingFeature(
+            wgpu::Features::POLYGON_MODE_LINE
+        ))
+    );
+    // Every build starts over from the defaults, the shader included
+    assert_eq!(builder.build().err(), Some(PipelineError::MissingShader));
+}
+
+#[test]
+fn identical_pipelines_are_shared() {
+    let gfx = common::headless(4, 4);
+    let cache = RenderPipelineCache::new();
+    let mut builder = PipelineBuilder::new(gfx.device());
+
+    let first = triangle(&mut builder)
+        .set_label("first")
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build_cached(&cache)
+        .unwrap();
+    let second = triangle(&mut builder)
+        .set_label("second")
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build_cached(&cache)
+        .unwrap();
+    assert_eq!(first, second);
+
+    let culled = triangle(&mut builder)
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .set_cull_mode(Some(Face::Back))
+        .build_cached(&cache)
+        .unwrap();
+    assert_ne!(first, culled);
+
+    // Unused vertex buffers are still part of the description
+    let buffered = triangle(&mut builder)
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .add_vertex_buffer_layout(VertexBufferLayout {
+            array_stride: 16,
+            step_mode: VertexStepMode::Instance,
+            attributes: &[],
+        })
+        .build_cached(&cache)
+        .unwrap();
+    assert_ne!(first, buffered);
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 3));
+    // All misses share the shader module
+    assert_eq!((stats.shader_hits, stats.shader_misses), (2, 1));
+    assert_eq!(cache.len(), 3);
+}
+
+#[test]
+fn shaders_compiled_on_several_threads_are_shared() {
+    let gfx = common::headless(4, 4);
+    let (device, cache) = (gfx.device(), RenderPipelineCache::new());
+    let modules: Vec<_> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| cache.shader_module(device, None, SHADER)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap().expect("valid shader"))
+            .collect()
+    });
+    // Threads racing to compile it all get the module cached first
+    let cached = cache.shader_module(device, None, SHADER).unwrap();
+    assert!(modules.iter().all(|module| *module == cached));
+
+    let stats = cache.stats();
+    assert_eq!(stats.shader_hits + stats.shader_misses, 5);
+    assert!(stats.shader_misses >= 1);
+}
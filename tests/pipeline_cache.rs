@@ -0,0 +1,92 @@
+//! Pipeline cache files kept on disk between runs.
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use render_rs::render::{
+    cache_file_identity, decode_cache_file, encode_cache_file, write_cache_file,
+};
+
+/// A temporary directory for the cache files of `test`.
+fn cache_dir(test: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "render-rs-pipeline-cache-{}-{test}",
+        std::process::id()
+    ))
+}
+
+#[test]
+fn cache_files_round_trip() {
+    let gfx = common::headless(4, 4);
+    let identity = cache_file_identity(&gfx.adapter().get_info());
+    assert!(!identity.contains('\n'), "{identity}");
+
+    for data in [&b""[..], b"\0\x01driver\ndata\n"] {
+        let file = encode_cache_file(&identity, data);
+        assert_eq!(decode_cache_file(&file, &identity), Some(data));
+    }
+}
+
+#[test]
+fn cache_files_of_other_drivers_are_ignored() {
+    let file = encode_cache_file("Vulkan llvmpipe 0x10005 0x0 llvmpipe Mesa 24.0", b"data");
+    for identity in [
+        "Vulkan llvmpipe 0x10005 0x0 llvmpipe Mesa 24.1",
+        // Neither a prefix nor an extension of the identity matches
+        "Vulkan llvmpipe 0x10005 0x0 llvmpipe Mesa 24",
+        "Vulkan llvmpipe 0x10005 0x0 llvmpipe Mesa 24.0 (git)",
+        "",
+    ] {
+        assert_eq!(decode_cache_file(&file, identity), None, "{identity}");
+    }
+}
+
+#[test]
+fn damaged_cache_files_are_ignored() {
+    let identity = "Gl llvmpipe 0x0 0x0  4.5 (Core Profile) Mesa 24.0";
+    let file = encode_cache_file(identity, b"data");
+    let header = file.len() - b"data".len();
+
+    // Cut anywhere in the header
+    for len in 0..header {
+        assert_eq!(decode_cache_file(&file[..len], identity), None, "{len}");
+    }
+    assert_eq!(decode_cache_file(&file[..header], identity), Some(&b""[..]));
+
+    // Without the magic, or with the one of another format version
+    let bare = [identity.as_bytes(), b"\ndata"].concat();
+    assert_eq!(decode_cache_file(&bare, identity), None);
+    let mut other = file.clone();
+    let version = file.iter().position(|&byte| byte == b'1').unwrap();
+    other[version] = b'2';
+    assert_eq!(decode_cache_file(&other, identity), None);
+    assert_eq!(decode_cache_file(b"driver data", identity), None);
+}
+
+#[test]
+fn cache_files_are_replaced_atomically() {
+    let dir = cache_dir("replace");
+    let path = dir.join("cache").join("gl_llvmpipe");
+    let _ = fs::remove_dir_all(&dir);
+
+    // Creates missing directories
+    write_cache_file(&path, b"first").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    write_cache_file(&path, b"second").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+
+    // Only the cache itself is left, the temporary file was renamed over it
+    let files: Vec<_> = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, ["gl_llvmpipe"]);
+
+    // A failed write leaves the previous cache untouched
+    fs::create_dir(path.with_extension("tmp")).unwrap();
+    assert!(write_cache_file(&path, b"third").is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"second");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,178 @@
+//! Includes, defines and line mapping of the WGSL preprocessor.
+
+mod common;
+
+use std::path::Path;
+
+use render_rs::{
+    graphics::OFFSCREEN_FORMAT,
+    render::{
+        PipelineBuilder, PipelineError, RenderPipelineCache, ShaderError, ShaderPreprocessor,
+        ShaderSource, SourceLocation,
+    },
+};
+
+const COLOR: &str = "\
+#ifdef BLUE
+const COLOR = vec4<f32>(0.0, 0.0, 1.0, 1.0);
+#else
+const COLOR = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+#endif
+";
+
+const TRIANGLE: &str = "\
+#include \"color.wgsl\"
+#include \"color.wgsl\"
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(i32(index) - 1), f32(i32(index & 1u) * 2 - 1), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return COLOR;
+}
+";
+
+fn location(file: &str, line: u32, column: u32) -> SourceLocation {
+    SourceLocation {
+        file: file.into(),
+        line,
+        column,
+    }
+}
+
+#[test]
+fn expands_includes_and_conditionals() {
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_source("color.wgsl", COLOR);
+
+    let red = preprocessor.process("triangle.wgsl", TRIANGLE).unwrap();
+    assert!(red.source().contains("vec4<f32>(1.0, 0.0, 0.0, 1.0)"));
+    // Included once, the second include is skipped
+    assert_eq!(red.source().matches("const COLOR").count(), 1);
+    assert!(red.validate().is_ok());
+
+    let blue = preprocessor
+        .define("BLUE", "")
+        .process("triangle.wgsl", TRIANGLE)
+        .unwrap();
+    assert!(blue.source().contains("vec4<f32>(0.0, 0.0, 1.0, 1.0)"));
+    assert_eq!(blue.map_location(1, 1), Some(location("color.wgsl", 2, 1)));
+    assert_eq!(
+        blue.map_location(3, 5),
+        Some(location("triangle.wgsl", 4, 5))
+    );
+}
+
+#[test]
+fn defines_replace_identifiers() {
+    let source = "\
+#define SIZE 4u
+#ifndef UNUSED
+var<private> values: array<f32, SIZE>; // SIZE stays in comments
+const SIZE_2 = 2.0;
+#endif
+";
+    let shader = ShaderPreprocessor::new().process("sizes", source).unwrap();
+    assert_eq!(
+        shader.source(),
+        "var<private> values: array<f32, 4u>; // SIZE stays in comments\nconst SIZE_2 = 2.0;\n"
+    );
+}
+
+#[test]
+fn compile_errors_point_into_included_files() {
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_source(
+        "color.wgsl",
+        "const COLOR = vec4<f32>(1.0, 0.0, 0.0 1.0);\n",
+    );
+
+    let shader = preprocessor.process("triangle.wgsl", TRIANGLE).unwrap();
+    let Err(ShaderError::Compile { labels, .. }) = shader.validate() else {
+        panic!("shader should not compile");
+    };
+    assert_eq!(labels[0].0.file, "color.wgsl");
+    assert_eq!(labels[0].0.line, 1);
+}
+
+#[test]
+fn reports_malformed_directives() {
+    let preprocessor = ShaderPreprocessor::new();
+    let error = |source| preprocessor.process("bad.wgsl", source).unwrap_err();
+
+    assert_eq!(
+        error("\n  #pragma once\n"),
+        ShaderError::UnknownDirective {
+            directive: "pragma".into(),
+            location: location("bad.wgsl", 2, 3)
+        }
+    );
+    assert_eq!(
+        error("#ifdef A\n#ifndef B\n#endif\n"),
+        ShaderError::UnterminatedConditional(location("bad.wgsl", 1, 1))
+    );
+    assert_eq!(
+        error("#ifdef A\n#else\n#else\n#endif\n"),
+        ShaderError::UnmatchedDirective {
+            directive: "else".into(),
+            location: location("bad.wgsl", 3, 1)
+        }
+    );
+    assert_eq!(
+        error("#define 2D\n"),
+        ShaderError::InvalidDirective {
+            directive: "define".into(),
+            location: location("bad.wgsl", 1, 1)
+        }
+    );
+    assert_eq!(
+        error("#include \"missing.wgsl\"\n"),
+        ShaderError::IncludeNotFound {
+            name: "missing.wgsl".into(),
+            location: location("bad.wgsl", 1, 1)
+        }
+    );
+}
+
+#[test]
+fn asset_shaders_share_their_declarations() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders");
+    let preprocessor = ShaderPreprocessor::new();
+    for name in ["camera.wgsl", "image.wgsl"] {
+        let shader = preprocessor.process_file(&dir.join(name)).unwrap();
+        assert!(shader.validate().is_ok(), "{name} is invalid");
+        assert_eq!(shader.paths().count(), 2);
+    }
+}
+
+#[test]
+fn shader_defs_build_permutations() {
+    let gfx = common::headless(4, 4);
+    let cache = RenderPipelineCache::new();
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_source("color.wgsl", COLOR);
+
+    let mut builder = PipelineBuilder::new(gfx.device());
+    let mut build = |defs: &[(&'static str, &'static str)]| {
+        builder
+            .set_preprocessor(&preprocessor)
+            .set_shader_module(ShaderSource::Wgsl(TRIANGLE), "vs_main", Some("fs_main"))
+            .set_pixel_format(OFFSCREEN_FORMAT);
+        for &(name, value) in defs {
+            builder.add_shader_def(name, value);
+        }
+        builder.build_cached(&cache)
+    };
+
+    let red = build(&[]).unwrap();
+    let blue = build(&[("BLUE", "")]).unwrap();
+    assert_ne!(red, blue);
+    assert_eq!(build(&[("BLUE", "")]).unwrap(), blue);
+    assert!(matches!(
+        build(&[("COLOR", "1.0")]),
+        Err(PipelineError::Shader(ShaderError::Compile { .. }))
+    ));
+}
@@ -0,0 +1,202 @@
+//! Layouts generated from and checked against shader reflection.
+
+mod common;
+
+use std::{num::NonZeroU64, path::Path};
+
+use render_rs::{
+    graphics::OFFSCREEN_FORMAT,
+    render::{
+        LayoutMismatch, PipelineBuilder, PipelineError, ShaderPreprocessor, ShaderReflection,
+        ShaderSource,
+    },
+};
+use wgpu::{
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType,
+    SamplerBindingType, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension,
+    VertexBufferLayout, VertexFormat, VertexStepMode,
+};
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+
+fn camera_wgsl() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders/camera.wgsl");
+    ShaderPreprocessor::new()
+        .process_file(&path)
+        .unwrap()
+        .source()
+        .to_string()
+}
+
+fn camera_reflection() -> ShaderReflection {
+    ShaderPreprocessor::new()
+        .process("camera.wgsl", &camera_wgsl())
+        .unwrap()
+        .reflect("vs_main", Some("fs_main"))
+        .unwrap()
+}
+
+fn vertex_buffer(attributes: &[wgpu::VertexAttribute]) -> VertexBufferLayout<'_> {
+    VertexBufferLayout {
+        array_stride: 20,
+        step_mode: VertexStepMode::Vertex,
+        attributes,
+    }
+}
+
+#[test]
+fn reflects_bindings_of_used_resources() {
+    let reflection = camera_reflection();
+    assert_eq!(reflection.group_count(), 2);
+    assert_eq!(
+        reflection.bind_group_layout_entries(0),
+        [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    );
+    assert_eq!(
+        reflection.bind_group_layout_entries(1),
+        [BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(64),
+            },
+            count: None,
+        }]
+    );
+    assert_eq!(reflection.bindings()[2].name.as_deref(), Some("camera"));
+}
+
+#[test]
+fn generates_layouts_without_explicit_ones() {
+    let gfx = common::headless(4, 4);
+    let source = camera_wgsl();
+    let pipeline = PipelineBuilder::new(gfx.device())
+        .set_shader_module(ShaderSource::Wgsl(&source), "vs_main", Some("fs_main"))
+        .add_vertex_buffer_layout(vertex_buffer(&VERTEX_ATTRIBUTES))
+        .set_pixel_format(OFFSCREEN_FORMAT)
+        .build()
+        .unwrap();
+    // Groups of generated layouts are available for bind groups
+    pipeline.get_bind_group_layout(1);
+}
+
+#[test]
+fn reports_mismatching_layouts() {
+    let gfx = common::headless(4, 4);
+    let device = gfx.device();
+    let source = camera_wgsl();
+    let reflection = camera_reflection();
+    let texture_entries = reflection.bind_group_layout_entries(0);
+    let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &texture_entries,
+    });
+    let mut camera_entries = reflection.bind_group_layout_entries(1);
+    let camera_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &camera_entries,
+    });
+
+    let build = |camera_entries: &[BindGroupLayoutEntry],
+                 attributes: &[wgpu::VertexAttribute],
+                 target: TextureFormat| {
+        PipelineBuilder::new(device)
+            .set_shader_module(ShaderSource::Wgsl(&source), "vs_main", Some("fs_main"))
+            .add_vertex_buffer_layout(vertex_buffer(attributes))
+            .add_bind_group_layout_with_entries(&texture_layout, &texture_entries)
+            .add_bind_group_layout_with_entries(&camera_layout, camera_entries)
+            .set_pixel_format(target)
+            .build()
+            .err()
+    };
+
+    assert_eq!(
+        build(&camera_entries, &VERTEX_ATTRIBUTES, OFFSCREEN_FORMAT),
+        None
+    );
+    assert_eq!(
+        build(&[], &VERTEX_ATTRIBUTES, OFFSCREEN_FORMAT),
+        Some(PipelineError::LayoutMismatch(
+            LayoutMismatch::MissingBinding {
+                group: 1,
+                binding: 0
+            }
+        ))
+    );
+    assert_eq!(
+        build(&camera_entries, &VERTEX_ATTRIBUTES[..1], OFFSCREEN_FORMAT),
+        Some(PipelineError::LayoutMismatch(
+            LayoutMismatch::MissingVertexAttribute(1)
+        ))
+    );
+    assert_eq!(
+        build(
+            &camera_entries,
+            &wgpu::vertex_attr_array![0 => Float32x3, 1 => Uint32x2],
+            OFFSCREEN_FORMAT
+        ),
+        Some(PipelineError::LayoutMismatch(
+            LayoutMismatch::VertexFormat {
+                location: 1,
+                format: VertexFormat::Uint32x2
+            }
+        ))
+    );
+    assert_eq!(
+        build(
+            &camera_entries,
+            &VERTEX_ATTRIBUTES,
+            TextureFormat::Rgba8Uint
+        ),
+        Some(PipelineError::LayoutMismatch(
+            LayoutMismatch::FragmentOutput {
+                location: 0,
+                format: TextureFormat::Rgba8Uint
+            }
+        ))
+    );
+
+    camera_entries[0].visibility = ShaderStages::FRAGMENT;
+    assert_eq!(
+        build(&camera_entries, &VERTEX_ATTRIBUTES, OFFSCREEN_FORMAT),
+        Some(PipelineError::LayoutMismatch(LayoutMismatch::Visibility {
+            group: 1,
+            binding: 0,
+            shader: ShaderStages::VERTEX,
+            layout: ShaderStages::FRAGMENT
+        }))
+    );
+    camera_entries[0].visibility = ShaderStages::VERTEX;
+    camera_entries[0].ty = BindingType::Buffer {
+        ty: BufferBindingType::Storage { read_only: true },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    };
+    assert!(matches!(
+        build(&camera_entries, &VERTEX_ATTRIBUTES, OFFSCREEN_FORMAT),
+        Some(PipelineError::LayoutMismatch(LayoutMismatch::BindingType {
+            group: 1,
+            ..
+        }))
+    ));
+}
@@ -0,0 +1,227 @@
+//! Ordering, validation and aliasing of render graphs.
+
+mod common;
+
+use render_rs::{
+    graphics::OFFSCREEN_FORMAT,
+    render::{
+        GraphError, Pass, PassBuilder, PassContext, RenderGraphBuilder, TextureDesc, TextureHandle,
+        TrianglePass,
+    },
+};
+use wgpu::{Origin3d, TexelCopyTextureInfo, TextureAspect, TextureFormat, TextureUsages};
+use winit::dpi::PhysicalSize;
+
+/// A pass that only declares accesses.
+struct Stub {
+    name: &'static str,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+}
+
+impl Stub {
+    fn new(name: &'static str, reads: &[TextureHandle], writes: &[TextureHandle]) -> Self {
+        Self {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        }
+    }
+}
+
+impl Pass for Stub {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        for &texture in &self.reads {
+            builder.read_texture(texture);
+        }
+        for &texture in &self.writes {
+            builder.write_texture(texture);
+        }
+    }
+
+    fn execute(&mut self, _ctx: &mut PassContext) {}
+}
+
+fn color(graph: &mut RenderGraphBuilder, label: &'static str) -> TextureHandle {
+    graph.create_texture(TextureDesc::surface(label, TextureFormat::Rgba8Unorm))
+}
+
+#[test]
+fn passes_run_after_their_inputs_are_written() {
+    let mut graph = RenderGraphBuilder::new();
+    let scene = color(&mut graph, "scene");
+    let post = color(&mut graph, "post");
+    let out = color(&mut graph, "out");
+    graph
+        .add_pass(Stub::new("ui", &[post], &[out]))
+        .add_pass(Stub::new("post", &[scene], &[post]))
+        .add_pass(Stub::new("scene", &[], &[scene]))
+        .set_output(out);
+
+    let graph = graph.build().unwrap();
+    assert_eq!(graph.pass_names(), ["scene", "post", "ui"]);
+}
+
+#[test]
+fn independent_passes_keep_their_order() {
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    let b = color(&mut graph, "b");
+    let out = color(&mut graph, "out");
+    graph
+        .add_pass(Stub::new("b", &[], &[b]))
+        .add_pass(Stub::new("a", &[], &[a]))
+        .add_pass(Stub::new("combine", &[a, b], &[out]))
+        .set_output(out);
+
+    let graph = graph.build().unwrap();
+    assert_eq!(graph.pass_names(), ["b", "a", "combine"]);
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    let b = color(&mut graph, "b");
+    graph
+        .add_pass(Stub::new("first", &[b], &[a]))
+        .add_pass(Stub::new("second", &[a], &[b]))
+        .set_output(b);
+
+    assert_eq!(
+        graph.build().unwrap_err(),
+        GraphError::Cycle(vec!["first".into(), "second".into()])
+    );
+}
+
+#[test]
+fn reading_unwritten_textures_is_rejected() {
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    let out = color(&mut graph, "out");
+    graph
+        .add_pass(Stub::new("post", &[a], &[out]))
+        .set_output(out);
+
+    assert_eq!(
+        graph.build().unwrap_err(),
+        GraphError::UninitializedRead {
+            pass: "post".into(),
+            resource: "a".into()
+        }
+    );
+}
+
+#[test]
+fn output_is_required() {
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    graph.add_pass(Stub::new("scene", &[], &[a]));
+    assert_eq!(graph.build().unwrap_err(), GraphError::NoOutput);
+
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    graph.set_output(a);
+    assert_eq!(
+        graph.build().unwrap_err(),
+        GraphError::OutputNotWritten("a".into())
+    );
+}
+
+#[test]
+fn textures_with_disjoint_lifetimes_are_aliased() {
+    let mut graph = RenderGraphBuilder::new();
+    let a = color(&mut graph, "a");
+    let b = color(&mut graph, "b");
+    let c = color(&mut graph, "c");
+    let hdr = graph.create_texture(TextureDesc::surface("hdr", TextureFormat::Rgba16Float));
+    graph
+        .add_pass(Stub::new("scene", &[], &[a]))
+        .add_pass(Stub::new("blur", &[a], &[b]))
+        .add_pass(Stub::new("tonemap", &[b], &[c, hdr]))
+        .set_output(c);
+
+    let graph = graph.build().unwrap();
+    // `a` is dead once `blur` ran, `b` is still read while `c` is written
+    assert!(graph.textures_aliased(a, c));
+    assert!(!graph.textures_aliased(b, c));
+    // Different formats never share memory
+    assert!(!graph.textures_aliased(a, hdr));
+    assert_eq!(graph.physical_texture_count(), 3);
+}
+
+/// Copies one texture into another.
+struct Copy {
+    from: TextureHandle,
+    to: TextureHandle,
+}
+
+impl Pass for Copy {
+    fn name(&self) -> &str {
+        "copy"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.read_texture(self.from).write_texture(self.to);
+    }
+
+    fn execute(&mut self, ctx: &mut PassContext) {
+        let from = ctx.texture(self.from);
+        let to = ctx.texture(self.to);
+        let copy = |texture| TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        };
+        ctx.encoder
+            .copy_texture_to_texture(copy(from), copy(to), from.size());
+    }
+}
+
+#[test]
+fn graph_output_is_drawn_to_the_frame() {
+    let mut gfx = common::headless(32, 32);
+
+    let mut graph = RenderGraphBuilder::new();
+    let scene = graph.create_texture(TextureDesc {
+        usage: TextureUsages::COPY_SRC,
+        ..TextureDesc::surface("scene", OFFSCREEN_FORMAT)
+    });
+    let copy = graph.create_texture(TextureDesc {
+        usage: TextureUsages::COPY_DST,
+        ..TextureDesc::surface("copy", OFFSCREEN_FORMAT)
+    });
+    graph
+        .add_pass(Copy {
+            from: scene,
+            to: copy,
+        })
+        .add_pass(TrianglePass::new(
+            gfx.device(),
+            gfx.pipeline_cache(),
+            OFFSCREEN_FORMAT,
+            scene,
+        ))
+        .set_output(copy);
+    gfx.set_render_graph(graph.build().unwrap());
+    assert_eq!(gfx.render_graph().pass_names(), ["triangle", "copy"]);
+
+    for _ in 0..2 {
+        let image = gfx.capture_frame().unwrap();
+        assert_eq!(image.get_pixel(16, 20).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 1).0, [0, 255, 0, 255]);
+    }
+
+    // The output is recreated with the frame, and drawn from then on
+    gfx.resize(PhysicalSize::new(64, 16));
+    let image = gfx.capture_frame().unwrap();
+    assert_eq!(image.dimensions(), (64, 16));
+    assert_eq!(image.get_pixel(32, 10).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(1, 1).0, [0, 255, 0, 255]);
+    assert!(gfx.errors().is_empty());
+}
@@ -0,0 +1,114 @@
+//! Offline validation of WGSL shaders.
+
+use std::{fs, path::Path};
+
+use render_rs::render::{ShaderError, ShaderPreprocessor, ShaderValidator};
+use wgpu::ShaderStages;
+
+const TRIANGLE: &str = "\
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(i32(index) - 1), f32(i32(index & 1u) * 2 - 1), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+";
+
+const STORAGE: &str = "\
+@group(0) @binding(0) var<storage, read> colors: array<vec4<f32>>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(0.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return colors[0];
+}
+";
+
+fn validate(validator: &ShaderValidator, source: &str) -> Vec<ShaderError> {
+    let shader = ShaderPreprocessor::new()
+        .process("shader.wgsl", source)
+        .unwrap();
+    validator.validate(&shader)
+}
+
+#[test]
+fn project_shaders_are_valid() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let reports =
+        ShaderValidator::new().validate_files([root.join("assets/shaders"), root.join("src")]);
+    for report in &reports {
+        assert!(report.is_ok(), "{report}");
+    }
+    // textured.wgsl and cube.wgsl are only checked as part of the shaders
+    // including them
+    assert!(reports.iter().all(|report| {
+        !report.path.ends_with("textured.wgsl") && !report.path.ends_with("cube.wgsl")
+    }));
+    assert_eq!(reports.len(), 8);
+}
+
+#[test]
+fn requires_entry_points() {
+    let mut validator = ShaderValidator::new();
+    assert_eq!(validate(&validator, TRIANGLE), []);
+
+    validator.require_entry_point("vs_main", ShaderStages::FRAGMENT);
+    assert_eq!(
+        validate(&validator, TRIANGLE),
+        [ShaderError::MissingEntryPoint("vs_main".into())]
+    );
+    validator
+        .clear_entry_points()
+        .require_entry_point("cs_main", ShaderStages::COMPUTE);
+    assert_eq!(
+        validate(&validator, TRIANGLE),
+        [ShaderError::MissingEntryPoint("cs_main".into())]
+    );
+}
+
+#[test]
+fn reports_what_webgl2_lacks() {
+    let mut validator = ShaderValidator::new();
+    let errors = validate(&validator, STORAGE);
+    let Some(ShaderError::Unsupported { target, labels, .. }) = errors.first() else {
+        panic!("storage buffers should be reported, got {errors:?}");
+    };
+    assert_eq!(*target, "WebGL2");
+    assert_eq!(labels[0].0.line, 1);
+
+    validator.set_webgl2(false);
+    assert_eq!(validate(&validator, STORAGE), []);
+}
+
+#[test]
+fn diagnostics_quote_the_faulty_line() {
+    let dir = std::env::temp_dir().join(format!(
+        "render-rs-shader-validation-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("broken.wgsl"),
+        TRIANGLE.replace("vec4<f32>(1.0)", "true"),
+    )
+    .unwrap();
+
+    let reports = ShaderValidator::new().validate_files([dir.clone()]);
+    assert_eq!(reports.len(), 1);
+    assert!(!reports[0].is_ok());
+    let diagnostics = reports[0].to_string();
+    assert!(diagnostics.contains("broken.wgsl:8:12"), "{diagnostics}");
+    assert!(
+        diagnostics.contains(" 8 |     return true;\n   |            ^"),
+        "{diagnostics}"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
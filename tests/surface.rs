@@ -0,0 +1,93 @@
+//! Recovering from frames that can't be acquired.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use image::RgbaImage;
+use render_rs::{
+    app::{App, Control},
+    capture::read_texture,
+    graphics::{Graphics, SurfaceEvent, SurfaceRecovery},
+};
+use wgpu::SurfaceError;
+use winit::{dpi::PhysicalSize, event::WindowEvent};
+
+/// What the offscreen frame currently holds.
+fn frame(gfx: &Graphics) -> RgbaImage {
+    let texture = gfx.offscreen_texture().expect("offscreen target");
+    read_texture(gfx.device(), gfx.queue(), texture).expect("frame")
+}
+
+#[test]
+fn recovers_according_to_the_error() {
+    let mut gfx = common::headless(16, 16);
+    for (error, recovery) in [
+        (SurfaceError::Lost, SurfaceRecovery::Reconfigured),
+        (SurfaceError::Outdated, SurfaceRecovery::Reconfigured),
+        (SurfaceError::Timeout, SurfaceRecovery::SkippedFrame),
+        (SurfaceError::Other, SurfaceRecovery::SkippedFrame),
+        (SurfaceError::OutOfMemory, SurfaceRecovery::Exit),
+    ] {
+        gfx.draw().expect("frame");
+        let drawn = frame(&gfx);
+        assert_eq!(
+            gfx.recover(error.clone()),
+            SurfaceEvent {
+                error: error.clone(),
+                recovery
+            }
+        );
+        // Only reconfiguring recreates the frame
+        let reconfigured = frame(&gfx) != drawn;
+        assert_eq!(
+            reconfigured,
+            recovery == SurfaceRecovery::Reconfigured,
+            "{error:?}"
+        );
+        assert_eq!(gfx.size(), PhysicalSize::new(16, 16));
+    }
+}
+
+#[test]
+fn notifies_the_app_of_surface_events() {
+    let mut app = App::with_graphics(common::headless(16, 16));
+    let events = Rc::new(RefCell::new(Vec::new()));
+    app.on_surface_event({
+        let events = Rc::clone(&events);
+        move |event| events.borrow_mut().push(event.clone())
+    });
+
+    assert_eq!(
+        app.handle_window_event(WindowEvent::RedrawRequested),
+        Control::Continue
+    );
+    assert!(events.borrow().is_empty());
+
+    assert_eq!(
+        app.handle_surface_error(SurfaceError::Outdated),
+        Control::Continue
+    );
+    assert_eq!(
+        app.handle_surface_error(SurfaceError::Timeout),
+        Control::Continue
+    );
+    assert_eq!(
+        app.handle_surface_error(SurfaceError::OutOfMemory),
+        Control::Exit
+    );
+    let recoveries: Vec<_> = events.borrow().iter().map(|event| event.recovery).collect();
+    assert_eq!(
+        recoveries,
+        [
+            SurfaceRecovery::Reconfigured,
+            SurfaceRecovery::SkippedFrame,
+            SurfaceRecovery::Exit
+        ]
+    );
+    assert_eq!(app.frames_drawn(), 1);
+
+    // Drawing carries on after a recoverable error
+    app.handle_window_event(WindowEvent::RedrawRequested);
+    assert_eq!(app.frames_drawn(), 2);
+}
@@ -0,0 +1,210 @@
+//! Uploading textures with their mip chains.
+
+mod common;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use render_rs::{
+    capture::read_texture_level,
+    graphics::Graphics,
+    render::MipFilter,
+    texture::{generate_mipmaps, Texture, TextureData, TextureError, TextureOptions},
+};
+use wgpu::{ErrorFilter, Features, ShaderStages, TextureFormat, TextureSampleType, TextureUsages};
+
+/// Black and white texels alternating, averaging to linear grey.
+fn checkerboard(size: u32) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(size, size, |x, y| {
+        if (x + y) % 2 == 0 {
+            Rgba([0, 0, 0, 255])
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    }))
+}
+
+fn upload(gfx: &Graphics, image: &DynamicImage, options: &TextureOptions) -> Texture {
+    Texture::from_image(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        image,
+        Some("test"),
+        options,
+    )
+    .unwrap()
+}
+
+fn smallest_level(gfx: &Graphics, texture: &Texture) -> RgbaImage {
+    let level = texture.mip_level_count() - 1;
+    read_texture_level(gfx.device(), gfx.queue(), &texture.texture, level).unwrap()
+}
+
+#[test]
+fn generates_mips_on_the_gpu_and_cpu_alike() {
+    let gfx = common::headless(4, 4);
+    let image = checkerboard(8);
+    let gpu = upload(&gfx, &image, &TextureOptions::default());
+    let cpu = upload(
+        &gfx,
+        &image,
+        &TextureOptions {
+            cpu_mipmaps: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(gpu.mip_level_count(), 4);
+    assert!(gpu
+        .texture
+        .usage()
+        .contains(TextureUsages::RENDER_ATTACHMENT));
+    assert!(!cpu
+        .texture
+        .usage()
+        .contains(TextureUsages::RENDER_ATTACHMENT));
+
+    // Averaged in linear space, 0.5 is 188 in sRGB
+    for texture in [&gpu, &cpu] {
+        let level = smallest_level(&gfx, texture);
+        assert_eq!(level.dimensions(), (1, 1));
+        let Rgba([r, g, b, a]) = *level.get_pixel(0, 0);
+        assert!(r.abs_diff(188) <= 2, "{r}");
+        assert_eq!((r, g, a), (b, b, 255));
+    }
+}
+
+#[test]
+fn gpu_mips_reuse_their_pipelines() {
+    let gfx = common::headless(4, 4);
+    upload(&gfx, &checkerboard(8), &TextureOptions::default());
+    let misses = gfx.pipeline_cache().stats().misses;
+    let hits = gfx.pipeline_cache().stats().hits;
+    upload(&gfx, &checkerboard(4), &TextureOptions::default());
+    let stats = gfx.pipeline_cache().stats();
+    assert_eq!((stats.hits, stats.misses), (hits + 1, misses));
+}
+
+#[test]
+fn filters_are_configurable() {
+    let gfx = common::headless(4, 4);
+    let image = checkerboard(4);
+
+    let nearest = upload(
+        &gfx,
+        &image,
+        &TextureOptions {
+            mip_filter: MipFilter::Nearest,
+            ..Default::default()
+        },
+    );
+    let Rgba([r, ..]) = *smallest_level(&gfx, &nearest).get_pixel(0, 0);
+    assert!(r == 0 || r == 255, "{r}");
+
+    // Lanczos is only available on the CPU
+    let lanczos = upload(
+        &gfx,
+        &image,
+        &TextureOptions {
+            mip_filter: MipFilter::Lanczos3,
+            ..Default::default()
+        },
+    );
+    assert!(!lanczos
+        .texture
+        .usage()
+        .contains(TextureUsages::RENDER_ATTACHMENT));
+    assert_eq!(lanczos.mip_level_count(), 3);
+
+    let single = upload(
+        &gfx,
+        &image,
+        &TextureOptions {
+            mipmaps: false,
+            ..Default::default()
+        },
+    );
+    assert_eq!(single.mip_level_count(), 1);
+}
+
+#[test]
+fn uploads_precomputed_mips() {
+    let gfx = common::headless(4, 4);
+    let red = Rgba([255, 0, 0, 255]);
+    let levels = [
+        RgbaImage::from_pixel(4, 2, Rgba([0, 0, 255, 255])),
+        RgbaImage::from_pixel(2, 1, red),
+    ];
+    let texture = Texture::from_mips(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        &levels,
+        None,
+        &TextureOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(texture.mip_level_count(), 3);
+    let level =
+        |level| read_texture_level(gfx.device(), gfx.queue(), &texture.texture, level).unwrap();
+    assert_eq!(level(1), levels[1]);
+    // Generated from the last level given
+    assert_eq!(level(2), RgbaImage::from_pixel(1, 1, red));
+
+    let result = Texture::from_mips(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        &[levels[0].clone(), RgbaImage::new(2, 2)],
+        None,
+        &TextureOptions::default(),
+    );
+    assert!(matches!(
+        result,
+        Err(TextureError::MipSize {
+            level: 1,
+            expected: (2, 1),
+            found: (2, 2)
+        })
+    ));
+}
+
+#[test]
+fn cpu_mips_halve_down_to_one_texel() {
+    let base = RgbaImage::from_pixel(5, 3, Rgba([10, 20, 30, 40]));
+    let levels = generate_mipmaps(&base, 2, MipFilter::Linear, true);
+    let sizes: Vec<_> = levels.iter().map(RgbaImage::dimensions).collect();
+    assert_eq!(sizes, [(2, 1), (1, 1)]);
+    assert_eq!(*levels[1].get_pixel(0, 0), Rgba([10, 20, 30, 40]));
+}
+
+#[test]
+fn float32_textures_bind_with_a_sampler_they_allow() {
+    let gfx = common::headless(4, 4);
+    let device = gfx.device();
+    let data = TextureData {
+        format: TextureFormat::Rgba32Float,
+        width: 4,
+        height: 4,
+        levels: vec![vec![0; 4 * 4 * 16]],
+    };
+    let texture = Texture::from_data(
+        device,
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        &data,
+        None,
+        &TextureOptions::default(),
+    )
+    .unwrap();
+    let filterable = device.features().contains(Features::FLOAT32_FILTERABLE);
+    assert_eq!(texture.sample_type, TextureSampleType::Float { filterable });
+
+    device.push_error_scope(ErrorFilter::Validation);
+    let layout = texture.bind_group_layout(device, ShaderStages::FRAGMENT);
+    texture.bind_group(device, &layout);
+    let error = pollster::block_on(device.pop_error_scope());
+    assert!(error.is_none(), "{error:?}");
+}
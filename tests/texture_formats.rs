@@ -0,0 +1,492 @@
+//! Loading float, grey and block compressed textures.
+
+mod common;
+
+use std::io::Cursor;
+
+use ddsfile::{AlphaMode, D3D10ResourceDimension, Dds, DxgiFormat, NewDxgiParams};
+use half::f16;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb, Rgba, RgbaImage};
+use ktx2::{Format, Header, Index, LevelIndex, SupercompressionScheme};
+use render_rs::{
+    capture::read_texture,
+    graphics::Graphics,
+    render::{PipelineBuilder, ShaderPreprocessor, ShaderSource, UniformBuffer},
+    texture::{Texture, TextureData, TextureError, TextureOptions},
+};
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindingResource, CommandEncoderDescriptor, Extent3d,
+    LoadOp, Operations, RenderPassColorAttachment, RenderPassDescriptor, ShaderStages, StoreOp,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor,
+};
+
+fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, format).unwrap();
+    bytes.into_inner()
+}
+
+fn load(gfx: &Graphics, bytes: &[u8], options: &TextureOptions) -> Result<Texture, TextureError> {
+    Texture::from_bytes(
+        gfx.device(),
+        gfx.queue(),
+        gfx.pipeline_cache(),
+        gfx.mipmap_generator(),
+        bytes,
+        "test",
+        options,
+    )
+}
+
+fn halves(bytes: &[u8]) -> Vec<f32> {
+    bytemuck::pod_collect_to_vec::<u8, f16>(bytes)
+        .into_iter()
+        .map(f32::from)
+        .collect()
+}
+
+/// Bytes pseudo random enough to cover every block mode.
+fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// A KTX2 file with its levels, optionally supercompressed.
+fn ktx2(
+    format: Format,
+    (width, height): (u32, u32),
+    levels: &[Vec<u8>],
+    scheme: Option<SupercompressionScheme>,
+) -> Vec<u8> {
+    let stored: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|level| match scheme {
+            Some(SupercompressionScheme::Zstandard) => ruzstd::encoding::compress_to_vec(
+                level.as_slice(),
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ),
+            Some(SupercompressionScheme::ZLIB) => {
+                miniz_oxide::deflate::compress_to_vec_zlib(level, 6)
+            }
+            _ => level.clone(),
+        })
+        .collect();
+    // The data format descriptor only holds its size, readers don't need
+    // more to load the levels
+    let dfd_offset = Header::LENGTH + levels.len() * LevelIndex::LENGTH;
+    let mut offset = dfd_offset + 4;
+    let header = Header {
+        format: Some(format),
+        type_size: 1,
+        pixel_width: width,
+        pixel_height: height,
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 1,
+        level_count: levels.len() as u32,
+        supercompression_scheme: scheme,
+        index: Index {
+            dfd_byte_offset: dfd_offset as u32,
+            dfd_byte_length: 4,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: 0,
+            sgd_byte_length: 0,
+        },
+    };
+    let mut bytes = header.as_bytes().to_vec();
+    for (level, data) in levels.iter().zip(&stored) {
+        let index = LevelIndex {
+            byte_offset: offset as u64,
+            byte_length: data.len() as u64,
+            uncompressed_byte_length: level.len() as u64,
+        };
+        bytes.extend(index.as_bytes());
+        offset += data.len();
+    }
+    bytes.extend(4u32.to_le_bytes());
+    for data in &stored {
+        bytes.extend(data);
+    }
+    bytes
+}
+
+fn dds(format: DxgiFormat, (width, height): (u32, u32), levels: &[Vec<u8>]) -> Vec<u8> {
+    let mut dds = Dds::new_dxgi(NewDxgiParams {
+        height,
+        width,
+        depth: None,
+        format,
+        mipmap_levels: Some(levels.len() as u32),
+        array_layers: None,
+        caps2: None,
+        is_cubemap: false,
+        resource_dimension: D3D10ResourceDimension::Texture2D,
+        alpha_mode: AlphaMode::Straight,
+    })
+    .unwrap();
+    dds.data = levels.concat();
+    let mut bytes = Vec::new();
+    dds.write(&mut bytes).unwrap();
+    bytes
+}
+
+/// Renders every texel of the first level of `texture` into an 8-bit
+/// target, mapping snorm values to unorm ones and squashing floats into
+/// the unorm range.
+fn render_texels(gfx: &Graphics, texture: &Texture) -> RgbaImage {
+    const SHADER: &str = "\
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> remap: u32;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = textureLoad(source, vec2<i32>(position.xy), 0);
+    if remap == 1u {
+        return texel * 0.5 + 0.5;
+    }
+    if remap == 2u {
+        return texel / (1.0 + abs(texel)) * 0.5 + 0.5;
+    }
+    return texel;
+}
+";
+    let device = gfx.device();
+    let reflection = ShaderPreprocessor::new()
+        .process("texels.wgsl", SHADER)
+        .and_then(|shader| shader.reflect("vs_main", Some("fs_main")))
+        .unwrap();
+    let layouts = reflection.create_bind_group_layouts(device, Some("texels"));
+    let pipeline = PipelineBuilder::new(device)
+        .set_shader_module(ShaderSource::Wgsl(SHADER), "vs_main", Some("fs_main"))
+        .add_bind_group_layout(&layouts[0])
+        .set_pixel_format(TextureFormat::Rgba8Unorm)
+        .build()
+        .unwrap();
+
+    let format = texture.texture.format();
+    let snorm = matches!(
+        format,
+        TextureFormat::Bc4RSnorm
+            | TextureFormat::Bc5RgSnorm
+            | TextureFormat::EacR11Snorm
+            | TextureFormat::EacRg11Snorm
+            | TextureFormat::R8Snorm
+            | TextureFormat::Rg8Snorm
+    );
+    let float = matches!(
+        format,
+        TextureFormat::Bc6hRgbUfloat | TextureFormat::Bc6hRgbFloat | TextureFormat::Rgba16Float
+    );
+    let uniform = UniformBuffer::new(
+        device,
+        None,
+        ShaderStages::FRAGMENT,
+        [if float { 2 } else { snorm as u32 }, 0, 0, 0],
+    )
+    .unwrap();
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &layouts[0],
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&texture.view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: uniform.buffer().as_entire_binding(),
+            },
+        ],
+    });
+    let size = texture.texture.size();
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("texels"),
+        size: Extent3d {
+            depth_or_array_layers: 1,
+            ..size
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+    {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+    gfx.queue().submit(Some(encoder.finish()));
+    read_texture(device, gfx.queue(), &target).unwrap()
+}
+
+#[test]
+fn loads_16_bit_hdr_and_grey_images() {
+    let gfx = common::headless(4, 4);
+    let linear = TextureOptions {
+        srgb: false,
+        ..Default::default()
+    };
+
+    let grey = ImageBuffer::<Luma<u16>, _>::from_fn(4, 4, |x, _| Luma([x as u16 * 21845]));
+    let png = encode(DynamicImage::ImageLuma16(grey.clone()), ImageFormat::Png);
+    let texture = load(&gfx, &png, &linear).unwrap();
+    assert_eq!(texture.texture.format(), TextureFormat::R16Float);
+    assert_eq!(texture.mip_level_count(), 3);
+    let data = TextureData::from_image(&DynamicImage::ImageLuma16(grey), false);
+    assert_eq!(
+        halves(&data.levels[0])[..4],
+        [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0].map(|v| f16::from_f32(v).into())
+    );
+
+    // sRGB grey is linearized into all color channels
+    let grey = ImageBuffer::from_pixel(2, 2, Luma([32768u16]));
+    let data = TextureData::from_image(&DynamicImage::ImageLuma16(grey), true);
+    assert_eq!(data.format, TextureFormat::Rgba16Float);
+    let texel = halves(&data.levels[0]);
+    assert!((texel[0] - 0.214).abs() < 1e-3, "{texel:?}");
+    assert_eq!(texel[3], 1.0);
+
+    let grey = ImageBuffer::from_pixel(2, 2, Luma([128u8]));
+    let data = TextureData::from_image(&DynamicImage::ImageLuma8(grey.clone()), false);
+    assert_eq!(data.format, TextureFormat::R8Unorm);
+    assert_eq!(data.levels[0], [128; 4]);
+    let data = TextureData::from_image(&DynamicImage::ImageLuma8(grey), true);
+    assert_eq!(data.format, TextureFormat::Rgba8UnormSrgb);
+
+    // Values above 1 survive, whatever the sRGB option
+    let bright = ImageBuffer::from_pixel(8, 4, Rgb([4.0f32, 0.5, 0.0]));
+    let hdr = encode(DynamicImage::ImageRgb32F(bright), ImageFormat::Hdr);
+    let texture = load(&gfx, &hdr, &TextureOptions::default()).unwrap();
+    assert_eq!(texture.texture.format(), TextureFormat::Rgba16Float);
+    assert_eq!(texture.mip_level_count(), 4);
+    let bright = ImageBuffer::from_pixel(4, 4, Rgba([16.0f32, 0.25, 1.0, 0.5]));
+    let exr = encode(DynamicImage::ImageRgba32F(bright), ImageFormat::OpenExr);
+    let data = TextureData::from_image(&image::load_from_memory(&exr).unwrap(), true);
+    assert_eq!(data.format, TextureFormat::Rgba16Float);
+    assert_eq!(halves(&data.levels[0])[..4], [16.0, 0.25, 1.0, 0.5]);
+    let texture = load(&gfx, &exr, &TextureOptions::default()).unwrap();
+    assert_eq!(texture.mip_level_count(), 3);
+}
+
+#[test]
+fn reads_ktx2_and_dds_containers() {
+    let gfx = common::headless(4, 4);
+    let base: Vec<u8> = [255, 0, 0, 255].repeat(16);
+    let level1: Vec<u8> = [0, 255, 0, 255].repeat(4);
+    let levels = [base, level1];
+
+    for scheme in [
+        None,
+        Some(SupercompressionScheme::Zstandard),
+        Some(SupercompressionScheme::ZLIB),
+    ] {
+        let file = ktx2(Format::R8G8B8A8_UNORM, (4, 4), &levels, scheme);
+        // Levels in the file are kept, the rest generated
+        let texture = load(&gfx, &file, &TextureOptions::default()).unwrap();
+        assert_eq!(texture.texture.format(), TextureFormat::Rgba8Unorm);
+        assert_eq!(texture.mip_level_count(), 3);
+        let texels = render_texels(&gfx, &texture);
+        assert_eq!(
+            *texels.get_pixel(3, 3),
+            Rgba([255, 0, 0, 255]),
+            "{scheme:?}"
+        );
+    }
+
+    let file = dds(DxgiFormat::R8G8B8A8_UNorm_sRGB, (4, 4), &levels);
+    let texture = load(&gfx, &file, &TextureOptions::default()).unwrap();
+    assert_eq!(texture.texture.format(), TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(texture.mip_level_count(), 3);
+
+    let truncated = dds(DxgiFormat::R8G8B8A8_UNorm, (4, 4), &levels[..1]);
+    let mut truncated = Dds::read(truncated.as_slice()).unwrap();
+    truncated.data.truncate(60);
+    let mut file = Vec::new();
+    truncated.write(&mut file).unwrap();
+    assert!(matches!(
+        load(&gfx, &file, &TextureOptions::default()),
+        Err(TextureError::LevelSize {
+            level: 0,
+            expected: 64,
+            found: 60
+        })
+    ));
+
+    let cube = Dds::new_dxgi(NewDxgiParams {
+        height: 4,
+        width: 4,
+        depth: None,
+        format: DxgiFormat::R8G8B8A8_UNorm,
+        mipmap_levels: None,
+        array_layers: Some(6),
+        caps2: None,
+        is_cubemap: true,
+        resource_dimension: D3D10ResourceDimension::Texture2D,
+        alpha_mode: AlphaMode::Straight,
+    })
+    .unwrap();
+    let mut file = Vec::new();
+    cube.write(&mut file).unwrap();
+    assert!(matches!(
+        load(&gfx, &file, &TextureOptions::default()),
+        Err(TextureError::UnsupportedLayout("cube maps"))
+    ));
+}
+
+#[test]
+fn decompresses_blocks_like_the_gpu() {
+    let gfx = common::headless(4, 4);
+    let cases = [
+        (Format::BC1_RGBA_UNORM_BLOCK, 8, (4, 4)),
+        (Format::BC2_UNORM_BLOCK, 16, (4, 4)),
+        (Format::BC3_UNORM_BLOCK, 16, (4, 4)),
+        (Format::BC4_UNORM_BLOCK, 8, (4, 4)),
+        (Format::BC4_SNORM_BLOCK, 8, (4, 4)),
+        (Format::BC5_UNORM_BLOCK, 16, (4, 4)),
+        (Format::BC5_SNORM_BLOCK, 16, (4, 4)),
+        (Format::BC6H_UFLOAT_BLOCK, 16, (4, 4)),
+        (Format::BC6H_SFLOAT_BLOCK, 16, (4, 4)),
+        (Format::BC7_UNORM_BLOCK, 16, (4, 4)),
+        (Format::ETC2_R8G8B8_UNORM_BLOCK, 8, (4, 4)),
+        (Format::ETC2_R8G8B8A1_UNORM_BLOCK, 8, (4, 4)),
+        (Format::ETC2_R8G8B8A8_UNORM_BLOCK, 16, (4, 4)),
+        (Format::EAC_R11_UNORM_BLOCK, 8, (4, 4)),
+        (Format::EAC_R11_SNORM_BLOCK, 8, (4, 4)),
+        (Format::EAC_R11G11_UNORM_BLOCK, 16, (4, 4)),
+        (Format::EAC_R11G11_SNORM_BLOCK, 16, (4, 4)),
+        (Format::ASTC_4x4_UNORM_BLOCK, 16, (4, 4)),
+        (Format::ASTC_6x5_UNORM_BLOCK, 16, (6, 5)),
+        (Format::ASTC_8x6_UNORM_BLOCK, 16, (8, 6)),
+        (Format::ASTC_10x10_UNORM_BLOCK, 16, (10, 10)),
+        (Format::ASTC_12x12_UNORM_BLOCK, 16, (12, 12)),
+    ];
+    // A multiple of every block size
+    let size = 120;
+    for (seed, (format, block_size, (block_width, block_height))) in cases.into_iter().enumerate() {
+        let blocks = (size / block_width) * (size / block_height);
+        let mut texels = noise(blocks as usize * block_size, seed as u32 + 1);
+        let astc = matches!(
+            format,
+            Format::ASTC_4x4_UNORM_BLOCK
+                | Format::ASTC_6x5_UNORM_BLOCK
+                | Format::ASTC_8x6_UNORM_BLOCK
+                | Format::ASTC_10x10_UNORM_BLOCK
+                | Format::ASTC_12x12_UNORM_BLOCK
+        );
+        if astc {
+            // Most random ASTC blocks have more weights than texels or HDR
+            // endpoints. Give them a grid 4 weights wide and 2 to 5 high, and
+            // LDR endpoints unless they have several partitions with
+            // different endpoint modes.
+            for block in texels.chunks_exact_mut(16) {
+                block[0] &= !0x8c;
+                block[0] |= u8::from(block[0] & 3 == 0);
+                block[1] &= !0x41;
+                block[3] &= !0x04;
+            }
+        }
+        let file = ktx2(format, (size, size), &[texels], None);
+        let cpu = load(
+            &gfx,
+            &file,
+            &TextureOptions {
+                cpu_decompression: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!cpu.texture.format().is_compressed());
+        assert_eq!(cpu.mip_level_count(), 1);
+
+        let gpu = load(&gfx, &file, &TextureOptions::default()).unwrap();
+        if !gpu.texture.format().is_compressed() {
+            // The adapter lacks the feature
+            continue;
+        }
+        let (cpu, gpu) = (render_texels(&gfx, &cpu), render_texels(&gfx, &gpu));
+        for (x, y, expected) in gpu.enumerate_pixels() {
+            let found = cpu.get_pixel(x, y);
+            let close = found
+                .0
+                .iter()
+                .zip(expected.0)
+                .all(|(&a, b)| a.abs_diff(b) <= 2);
+            assert!(
+                close,
+                "{format:?} texel ({x}, {y}) is {found:?}, expected {expected:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn reports_what_cant_be_loaded() {
+    let gfx = common::headless(4, 4);
+    let astc = ktx2(Format::ASTC_4x4_SFLOAT_BLOCK, (4, 4), &[vec![0; 16]], None);
+    let options = TextureOptions {
+        cpu_decompression: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        load(&gfx, &astc, &options),
+        Err(TextureError::NoDecoder(TextureFormat::Astc { .. }))
+    ));
+
+    let basis = ktx2(
+        Format::R8G8B8A8_UNORM,
+        (4, 4),
+        &[vec![0; 64]],
+        Some(SupercompressionScheme::BasisLZ),
+    );
+    assert!(matches!(
+        load(&gfx, &basis, &options),
+        Err(TextureError::UnsupportedSupercompression(_))
+    ));
+
+    // Sizes that aren't whole blocks are decompressed
+    let odd = ktx2(Format::BC1_RGBA_UNORM_BLOCK, (6, 3), &[vec![0; 16]], None);
+    let texture = load(&gfx, &odd, &TextureOptions::default()).unwrap();
+    assert_eq!(texture.texture.format(), TextureFormat::Rgba8Unorm);
+
+    let short = ktx2(Format::BC1_RGBA_UNORM_BLOCK, (8, 8), &[vec![0; 16]], None);
+    assert!(matches!(
+        load(&gfx, &short, &TextureOptions::default()),
+        Err(TextureError::LevelSize {
+            level: 0,
+            expected: 32,
+            found: 16
+        })
+    ));
+}